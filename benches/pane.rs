@@ -0,0 +1,69 @@
+/***
+Drawing throughput on each torture corpus: `cargo bench --features bench`. Feeding a pane times
+the whole output path - parse, update the pane, draw its frame - and redrawing times drawing its
+lines alone.
+***/
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use decker::decker::terminal::{Pane, ScrollMode};
+use decker::parser::Torture;
+
+// Read the way the output loops read
+const READ_SIZE: usize = 1024;
+// (width, height): a small widget, the usual terminal, and a big monitor's worth
+const SIZES: [(u16, u16); 3] = [(40, 10), (80, 24), (240, 70)];
+
+fn pane(size: (u16, u16)) -> Pane {
+    let mut pane = Pane::new("bench", 1, 1, size.1, size.0);
+    pane.set_scroll_mode(ScrollMode::Scroll);
+    pane
+}
+
+fn feed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("feed");
+    for size in SIZES {
+        for torture in Torture::ALL {
+            let corpus = torture.generate(64 * 1024, 1, size);
+            group.throughput(Throughput::Bytes(corpus.len() as u64));
+            let id = BenchmarkId::new(torture.name(), format!("{}x{}", size.0, size.1));
+            group.bench_with_input(id, &corpus, |b, corpus| {
+                b.iter(|| {
+                    // One frame per read, the way the render loop would see a busy task
+                    let (mut pane, mut frame) = (pane(size), Vec::new());
+                    for read in corpus.chunks(READ_SIZE) {
+                        pane.push(read).unwrap();
+                        frame.clear();
+                        pane.write(&mut frame).unwrap();
+                    }
+                    frame.len()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn redraw(c: &mut Criterion) {
+    let mut group = c.benchmark_group("redraw");
+    for size in SIZES {
+        for torture in Torture::ALL {
+            let mut pane = pane(size);
+            pane.push(torture.generate(64 * 1024, 1, size)).unwrap();
+            let mut frame = Vec::new();
+            pane.write(&mut frame).unwrap();
+
+            let id = BenchmarkId::new(torture.name(), format!("{}x{}", size.0, size.1));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    pane.invalidate();
+                    frame.clear();
+                    pane.write(&mut frame).unwrap();
+                    frame.len()
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, feed, redraw);
+criterion_main!(benches);
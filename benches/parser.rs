@@ -0,0 +1,27 @@
+/***
+Parser throughput on each torture corpus: `cargo bench --features bench`. Drawing what's parsed
+is benches/pane.rs.
+***/
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use decker::parser::{StreamState, Torture};
+
+// Read the way the output loops read
+const READ_SIZE: usize = 1024;
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for torture in Torture::ALL {
+        let corpus = torture.generate(256 * 1024, 1, (80, 24));
+        group.throughput(Throughput::Bytes(corpus.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(torture.name()), &corpus, |b, corpus| {
+            b.iter(|| {
+                let mut stream = StreamState::new();
+                corpus.chunks(READ_SIZE).map(|read| stream.feed(read).len()).sum::<usize>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+#![no_main]
+use decker::parser::{StreamState, TerminalOutput};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // Split the input in two, so sequences and characters cut across reads get exercised too
+    let split = data.first().map(|b| *b as usize % (data.len() + 1)).unwrap_or(0);
+    let mut stream = StreamState::new();
+    let mut tokens = stream.feed(&data[..split]);
+    tokens.extend(stream.feed(&data[split..]));
+
+    for token in tokens {
+        if let TerminalOutput::CSI(vt100) = token {
+            let seq = vt100.seq();
+            let _ = seq.param(0, 0);
+            let _ = seq.to_string();
+        }
+    }
+});
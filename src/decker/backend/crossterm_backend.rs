@@ -0,0 +1,151 @@
+use crate::decker::backend::{Goto, Keyboard, RawStdout};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{stdout, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// crossterm's raw mode is for the whole terminal, so it's only left once nothing needs it
+static RAW_USERS: AtomicUsize = AtomicUsize::new(0);
+
+pub fn terminal_size() -> std::io::Result<(u16, u16)> {
+    terminal::size()
+}
+
+pub fn raw_stdout() -> std::io::Result<RawStdout> {
+    terminal::enable_raw_mode()?;
+    RAW_USERS.fetch_add(1, Ordering::SeqCst);
+    Ok(RawStdout { inner: stdout() })
+}
+
+/***
+Where keystrokes come from. crossterm reads the terminal itself, whatever stdin is.
+ */
+pub fn keyboard(_stdin_spent: bool) -> std::io::Result<Box<dyn Read>> {
+    Ok(Box::new(Keyboard { pending: VecDeque::new() }))
+}
+
+impl RawStdout {
+    /***
+    Put the terminal back into raw mode, e.g. after something else took it out
+     */
+    pub fn reactivate(&self) -> std::io::Result<()> {
+        terminal::enable_raw_mode()
+    }
+}
+
+impl Write for RawStdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Drop for RawStdout {
+    fn drop(&mut self) {
+        if RAW_USERS.fetch_sub(1, Ordering::SeqCst) == 1 {
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+}
+
+impl Display for Goto {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\x1b[{};{}H", self.1, self.0)
+    }
+}
+
+impl Read for Keyboard {
+    /***
+    Block until there's a key or paste to hand over. Other events, like resizes, are skipped -
+    the panes notice those for themselves.
+     */
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.is_empty() {
+            match event::read()? {
+                Event::Key(key) => { self.pending.extend(encode_key(&key)) }
+                Event::Paste(text) => { self.pending.extend(text.into_bytes()) }
+                _ => {}
+            }
+        }
+
+        let count = buf.len().min(self.pending.len());
+        for (slot, byte) in buf.iter_mut().zip(self.pending.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+/***
+The bytes an xterm sends for a key press. Releases, and keys it has no bytes for, send nothing.
+ */
+fn encode_key(key: &KeyEvent) -> Vec<u8> {
+    if key.kind == KeyEventKind::Release {
+        return Vec::new();
+    }
+
+    let bytes = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            match c {
+                ' ' | '@' => { vec![0] }
+                'a'..='z' | 'A'..='Z' | '['..='_' => { vec![c.to_ascii_uppercase() as u8 & 0x1f] }
+                _ => { c.to_string().into_bytes() }
+            }
+        }
+        KeyCode::Char(c) => { c.to_string().into_bytes() }
+        KeyCode::Enter => { vec![b'\r'] }
+        KeyCode::Tab => { vec![b'\t'] }
+        KeyCode::BackTab => { b"\x1b[Z".to_vec() }
+        KeyCode::Backspace => { vec![0x7f] }
+        KeyCode::Esc => { vec![0x1b] }
+        KeyCode::Null => { vec![0] }
+        KeyCode::Up => { b"\x1b[A".to_vec() }
+        KeyCode::Down => { b"\x1b[B".to_vec() }
+        KeyCode::Right => { b"\x1b[C".to_vec() }
+        KeyCode::Left => { b"\x1b[D".to_vec() }
+        KeyCode::Home => { b"\x1b[H".to_vec() }
+        KeyCode::End => { b"\x1b[F".to_vec() }
+        KeyCode::Insert => { b"\x1b[2~".to_vec() }
+        KeyCode::Delete => { b"\x1b[3~".to_vec() }
+        KeyCode::PageUp => { b"\x1b[5~".to_vec() }
+        KeyCode::PageDown => { b"\x1b[6~".to_vec() }
+        KeyCode::F(n @ 1..=4) => { vec![0x1b, b'O', b'P' + n - 1] }
+        KeyCode::F(n @ 5..=12) => {
+            let code = [15, 17, 18, 19, 20, 21, 23, 24][(n - 5) as usize];
+            format!("\x1b[{}~", code).into_bytes()
+        }
+        _ => { Vec::new() }
+    };
+
+    // Alt sends an escape first
+    if key.modifiers.contains(KeyModifiers::ALT) && !bytes.is_empty() {
+        std::iter::once(0x1b).chain(bytes).collect()
+    } else {
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Vec<u8> {
+        encode_key(&KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn it_encodes_keys_like_a_terminal() {
+        assert_eq!(key(KeyCode::Char('b'), KeyModifiers::CONTROL), vec![2]);
+        assert_eq!(key(KeyCode::Char('é'), KeyModifiers::NONE), "é".as_bytes().to_vec());
+        assert_eq!(key(KeyCode::Char('x'), KeyModifiers::ALT), b"\x1bx".to_vec());
+        assert_eq!(key(KeyCode::Up, KeyModifiers::NONE), b"\x1b[A".to_vec());
+        assert_eq!(key(KeyCode::F(2), KeyModifiers::NONE), b"\x1bOQ".to_vec());
+        assert_eq!(key(KeyCode::F(12), KeyModifiers::NONE), b"\x1b[24~".to_vec());
+        assert_eq!(format!("{}", Goto(3, 7)), "\x1b[7;3H");
+    }
+}
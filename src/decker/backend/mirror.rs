@@ -0,0 +1,126 @@
+use crate::decker::backend::{MirroredStdout, RawStdout, RenderMirror};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use log::{error, info};
+use anyhow::bail;
+
+impl RenderMirror {
+    /***
+    Mirror into `path` if it's a FIFO. Otherwise into a new file beside it, with the time in its
+    name, e.g. log/render-1700000000.raw and its timing in log/render-1700000000.tm
+     */
+    pub fn open(path: &Path) -> anyhow::Result<RenderMirror> {
+        let is_fifo = std::fs::metadata(path).map(|m| m.file_type().is_fifo()).unwrap_or(false);
+        if is_fifo {
+            // Without a reader, this fails rather than waiting for one. Writes never wait either -
+            // a reader that falls behind loses the mirror instead of holding up drawing.
+            let capture = match OpenOptions::new().write(true).custom_flags(libc::O_NONBLOCK).open(path) {
+                Ok(capture) => { capture }
+                Err(e) if e.raw_os_error() == Some(libc::ENXIO) => {
+                    bail!("Nothing is reading {} - start its reader before decker", path.display())
+                }
+                Err(e) => { return Err(e.into()) }
+            };
+            return Ok(RenderMirror { capture, timing: None, last_write: Instant::now() });
+        }
+
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        let capture_path = Self::stamped(path, started, None);
+        info!("main: Mirroring what's drawn to {}", capture_path.display());
+        Ok(RenderMirror {
+            capture: File::create(&capture_path)?,
+            timing: Some(File::create(Self::stamped(path, started, Some("tm")))?),
+            last_write: Instant::now(),
+        })
+    }
+
+    // log/render.raw => log/render-<time>.raw, or log/render-<time>.<extension>
+    fn stamped(path: &Path, time: u64, extension: Option<&str>) -> PathBuf {
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let extension = extension.map(String::from).or_else(|| path.extension().map(|e| e.to_string_lossy().to_string()));
+        let name = match extension {
+            None => { format!("{}-{}", stem, time) }
+            Some(extension) => { format!("{}-{}.{}", stem, time, extension) }
+        };
+        path.with_file_name(name)
+    }
+
+    /***
+    Add what was just drawn, and how long after the last write it was
+     */
+    fn record(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.capture.write_all(bytes)?;
+        if let Some(timing) = &mut self.timing {
+            writeln!(timing, "{:.6} {}", self.last_write.elapsed().as_secs_f64(), bytes.len())?;
+        }
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+impl MirroredStdout {
+    pub fn new(stdout: RawStdout, mirror: Option<Arc<Mutex<RenderMirror>>>) -> MirroredStdout {
+        MirroredStdout { stdout, mirror }
+    }
+
+    pub fn reactivate(&self) -> std::io::Result<()> {
+        self.stdout.reactivate()
+    }
+}
+
+impl Write for MirroredStdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.stdout.write(buf)?;
+        let recorded = match &self.mirror {
+            None => { Ok(()) }
+            Some(mirror) => { mirror.lock().map(|mut m| m.record(&buf[..written])).unwrap_or(Ok(())) }
+        };
+        if let Err(e) = recorded {
+            error!("main: Could not mirror what's drawn, so no longer mirroring: {}", e);
+            self.mirror = None;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stdout.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn it_names_mirrors_for_when_they_were_opened() {
+        assert_eq!(RenderMirror::stamped(Path::new("log/render.raw"), 1700, None), PathBuf::from("log/render-1700.raw"));
+        assert_eq!(RenderMirror::stamped(Path::new("log/render.raw"), 1700, Some("tm")), PathBuf::from("log/render-1700.tm"));
+        assert_eq!(RenderMirror::stamped(Path::new("render"), 1700, None), PathBuf::from("render-1700"));
+    }
+
+    #[test]
+    fn it_records_timing_alongside() {
+        let dir = std::env::temp_dir().join(format!("decker-mirror-{}", std::process::id()));
+        let mut mirror = RenderMirror::open(&dir.join("render.raw")).unwrap();
+        mirror.record(b"\x1b[2J").unwrap();
+        mirror.record(b"hello").unwrap();
+
+        let mut files = std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect::<Vec<_>>();
+        files.sort();
+        let mut capture = String::new();
+        File::open(&files[0]).unwrap().read_to_string(&mut capture).unwrap();
+        let timing = std::fs::read_to_string(&files[1]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(capture, "\x1b[2Jhello");
+        assert_eq!(timing.lines().map(|l| l.split(' ').nth(1).unwrap()).collect::<Vec<_>>(), vec!["4", "5"]);
+    }
+}
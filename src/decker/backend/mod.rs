@@ -0,0 +1,59 @@
+/***
+* Terminal backend
+* The real terminal: raw mode, its size, cursor addressing and reading keys from it. termion by
+* default, or crossterm when built with `--features crossterm`, which also reads input as events.
+***/
+#[cfg(not(feature = "crossterm"))]
+mod termion_backend;
+#[cfg(feature = "crossterm")]
+mod crossterm_backend;
+mod mirror;
+
+#[cfg(not(feature = "crossterm"))]
+pub use termion_backend::{keyboard, raw_stdout, terminal_size, Goto};
+#[cfg(feature = "crossterm")]
+pub use crossterm_backend::{keyboard, raw_stdout, terminal_size};
+
+/***
+Stdout, with the terminal in raw mode until it's dropped
+ */
+pub struct RawStdout {
+    #[cfg(not(feature = "crossterm"))]
+    inner: termion::raw::RawTerminal<std::io::Stdout>,
+    #[cfg(feature = "crossterm")]
+    inner: std::io::Stdout,
+}
+
+/***
+A copy of every byte drawn to the terminal, for seeing exactly what decker sent when a pane looks
+wrong. Into a FIFO as it's drawn, or a file named for when it was opened, with a timing file
+alongside so `decker replay` can play it back.
+ */
+pub struct RenderMirror {
+    capture: std::fs::File,
+    timing: Option<std::fs::File>,
+    last_write: std::time::Instant,
+}
+
+/***
+Stdout, mirrored if there's a mirror. A mirror that can't be written to is given up on, rather
+than getting in the way of drawing.
+ */
+pub struct MirroredStdout {
+    stdout: RawStdout,
+    mirror: Option<std::sync::Arc<std::sync::Mutex<RenderMirror>>>,
+}
+
+/***
+Moves the cursor to (x, y), 1-based, when displayed
+ */
+#[cfg(feature = "crossterm")]
+pub struct Goto(pub u16, pub u16);
+
+/***
+Keyboard events, turned back into the bytes a terminal would have sent for them
+ */
+#[cfg(feature = "crossterm")]
+pub struct Keyboard {
+    pending: std::collections::VecDeque<u8>,
+}
@@ -0,0 +1,43 @@
+use crate::decker::backend::RawStdout;
+use std::io::{stdin, stdout, Read, Write};
+use termion::raw::IntoRawMode;
+
+pub use termion::cursor::Goto;
+
+pub fn terminal_size() -> std::io::Result<(u16, u16)> {
+    termion::terminal_size()
+}
+
+pub fn raw_stdout() -> std::io::Result<RawStdout> {
+    Ok(RawStdout { inner: stdout().into_raw_mode()? })
+}
+
+/***
+Where keystrokes come from: stdin, or the terminal itself if stdin's been used for something else
+ */
+pub fn keyboard(stdin_spent: bool) -> std::io::Result<Box<dyn Read>> {
+    if stdin_spent {
+        Ok(Box::new(termion::get_tty()?))
+    } else {
+        Ok(Box::new(stdin()))
+    }
+}
+
+impl RawStdout {
+    /***
+    Put the terminal back into raw mode, e.g. after something else took it out
+     */
+    pub fn reactivate(&self) -> std::io::Result<()> {
+        self.inner.activate_raw_mode()
+    }
+}
+
+impl Write for RawStdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -1,24 +1,330 @@
 use crate::decker::child::ChildProcess;
+use crate::decker::{Task, TaskKind};
 use portable_pty::CommandBuilder;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{anyhow, bail};
+
+// Decker's emulator is an xterm-alike with 256 and 24-bit color
+const DEFAULT_TERM: &str = "xterm-256color";
+const DEFAULT_COLORTERM: &str = "truecolor";
 
 impl ChildProcess {
     pub fn new(command: &str, path: &str, size: (u16,u16)) -> ChildProcess {
         ChildProcess {
             command: command.to_owned(),
             path: path.to_owned(),
-            size: size,
+            size,
+            argv: command.split_ascii_whitespace().map(|s| s.to_owned()).collect(),
+            local_cwd: true,
+            teardown: None,
+            env: Vec::new(),
+            env_removed: Vec::new(),
+        }
+    }
+
+    /***
+    Build the child process for a task, taking its kind into account
+    @arg interactive: Whether the child is attached to a PTY we forward input to
+     */
+    pub fn for_task(task: &Task, size: (u16,u16), interactive: bool) -> anyhow::Result<ChildProcess> {
+        let mut child = Self::for_kind(task, size, interactive)?;
+        child.set_terminal_env(task, interactive);
+        Ok(child)
+    }
+
+    fn for_kind(task: &Task, size: (u16,u16), interactive: bool) -> anyhow::Result<ChildProcess> {
+        match task.kind {
+            TaskKind::Local => {
+                let mut child = ChildProcess::new(&task.command, &task.path, size);
+                if let Some(argv) = &task.argv {
+                    child.argv = argv.clone();
+                }
+                child.argv = Self::limited_argv(task, child.argv);
+                Ok(child)
+            }
+            TaskKind::Ssh => {
+                Ok(ChildProcess {
+                    command: task.command.clone(),
+                    path: task.path.clone(),
+                    size,
+                    argv: Self::ssh_argv(task, interactive)?,
+                    local_cwd: false,
+                    teardown: None,
+                    env: Vec::new(),
+                    env_removed: Vec::new(),
+                })
+            }
+            TaskKind::Docker | TaskKind::Podman => {
+                let runtime = if task.kind == TaskKind::Docker { "docker" } else { "podman" };
+                let (argv, teardown) = Self::container_argv(task, runtime, interactive)?;
+                Ok(ChildProcess {
+                    command: task.command.clone(),
+                    path: task.path.clone(),
+                    size,
+                    argv,
+                    local_cwd: false,
+                    teardown,
+                    env: Vec::new(),
+                    env_removed: Vec::new(),
+                })
+            }
+            TaskKind::SysStats => { Err(anyhow!("{}: sysstats tasks are built in - there's no command to run", task.id)) }
+        }
+    }
+
+    /***
+    A line marking the end of a run, e.g. "[exited: code 0 in 1.2s]", to print after its output.
+    The run's output is left in place above it.
+     */
+    pub fn exit_banner(status: &str, success: bool, elapsed: Duration) -> Vec<u8> {
+        let color = if success { "30;42" } else { "37;41" };
+        format!("\r\n\x1b[0;{}m[exited: {} in {:.1}s]\x1b[0m", color, status, elapsed.as_secs_f64()).into_bytes()
+    }
+
+    /***
+    Tell the child what it's drawing on: decker's emulator, and outside a PTY (where there's no
+    terminal to ask), the size of its pane
+     */
+    fn set_terminal_env(&mut self, task: &Task, interactive: bool) {
+        let env = &mut self.env;
+        env.push((String::from("TERM"), task.term.clone().unwrap_or_else(|| String::from(DEFAULT_TERM))));
+        match task.colorterm.as_deref() {
+            None => { env.push((String::from("COLORTERM"), String::from(DEFAULT_COLORTERM))) }
+            // Not even whatever decker's own terminal set
+            Some("") => { self.env_removed.push(String::from("COLORTERM")) }
+            Some(colorterm) => { env.push((String::from("COLORTERM"), colorterm.to_string())) }
+        }
+        if !interactive {
+            env.push((String::from("COLUMNS"), self.size.1.to_string()));
+            env.push((String::from("LINES"), self.size.0.to_string()));
+        }
+    }
+
+    /***
+    Wrap a local command so it runs with the task's priority and resource limits. Limits are
+    set by the shell's `ulimit`, so work wherever `sh` supports them.
+     */
+    fn limited_argv(task: &Task, argv: Vec<String>) -> Vec<String> {
+        let mut argv = argv;
+        if let Some(nice) = task.nice {
+            argv.splice(0..0, vec![String::from("nice"), String::from("-n"), nice.to_string()]);
+        }
+
+        let mut limits = Vec::new();
+        if let Some(secs) = task.max_cpu_secs {
+            limits.push(format!("ulimit -t {}", secs));
+        }
+        if let Some(mb) = task.max_memory_mb {
+            limits.push(format!("ulimit -v {}", mb * 1024));
+        }
+        if !limits.is_empty() {
+            // `sh -c 'limits; exec "$@"' sh <argv>` passes the command through untouched
+            let script = format!("{} && exec \"$@\"", limits.join(" && "));
+            argv.splice(0..0, vec![String::from("sh"), String::from("-c"), script, String::from("sh")]);
+        }
+
+        argv
+    }
+
+    /***
+    Run the command in an existing container (`exec`), or a fresh one from an image (`run`).
+    Fresh containers are named so they can be removed if we shut down while they're running.
+     */
+    fn container_argv(task: &Task, runtime: &str, interactive: bool) -> anyhow::Result<(Vec<String>, Option<Vec<String>>)> {
+        let mut argv = vec![String::from(runtime)];
+        let mut teardown = None;
+
+        let target = match (&task.container, &task.image) {
+            (Some(container), None) => {
+                argv.push(String::from("exec"));
+                container.clone()
+            }
+            (None, Some(image)) => {
+                let name = format!("decker-{}-{}", task.id, std::process::id());
+                argv.extend(vec![String::from("run"), String::from("--rm"), String::from("--name"), name.clone()]);
+                teardown = Some(vec![String::from(runtime), String::from("rm"), String::from("-f"), name]);
+                image.clone()
+            }
+            _ => { bail!("Task '{}' is a {} task, and needs exactly one of `container` or `image`", task.id, runtime) }
+        };
+
+        if interactive {
+            argv.extend(vec![String::from("-i"), String::from("-t")]);
+        }
+
+        if !task.path.is_empty() && task.path != "." {
+            argv.extend(vec![String::from("-w"), task.path.clone()]);
+        }
+
+        argv.extend(vec![target, String::from("sh"), String::from("-c"), task.command.clone()]);
+
+        Ok((argv, teardown))
+    }
+
+    fn ssh_argv(task: &Task, interactive: bool) -> anyhow::Result<Vec<String>> {
+        let host = match &task.host {
+            None => { bail!("Task '{}' is an ssh task, but has no host", task.id) }
+            Some(host) => { host.clone() }
+        };
+
+        // Share one connection per host between all of the tasks that use it, rather than
+        // paying for a fresh handshake every time a widget refreshes.
+        let control_path = std::env::temp_dir().join("decker-ssh-%C");
+        let mut argv = vec![
+            String::from("ssh"),
+            String::from("-o"), String::from("ControlMaster=auto"),
+            String::from("-o"), format!("ControlPath={}", control_path.display()),
+            String::from("-o"), String::from("ControlPersist=60"),
+        ];
+
+        if interactive {
+            argv.push(String::from("-t"));
+        } else {
+            // Widgets can't answer password prompts - fail instead of hanging
+            argv.extend(vec![String::from("-T"), String::from("-o"), String::from("BatchMode=yes")]);
+        }
+
+        if let Some(user) = &task.user {
+            argv.extend(vec![String::from("-l"), user.clone()]);
+        }
+
+        if let Some(key) = &task.key {
+            argv.extend(vec![String::from("-i"), key.clone()]);
         }
+
+        argv.push(host);
+
+        let remote_command = if task.path.is_empty() || task.path == "." {
+            task.command.clone()
+        } else {
+            format!("cd '{}' && {}", task.path.replace('\'', "'\\''"), task.command)
+        };
+        argv.push(remote_command);
+
+        Ok(argv)
     }
 
     pub fn command_for_pty(&self) -> CommandBuilder {
-        let mut cmd_and_args = self.command.split_ascii_whitespace();
-        let command = cmd_and_args.next().unwrap();
-        let args = cmd_and_args.collect::<Vec<_>>();
+        // A PTY's command can only add to the environment, so `env` takes things out of it
+        let mut argv = Vec::new();
+        if !self.env_removed.is_empty() {
+            argv.push(String::from("env"));
+            self.env_removed.iter().for_each(|key| argv.extend([String::from("-u"), key.clone()]));
+        }
+        argv.extend(self.argv.iter().cloned());
+
+        let mut cmd = CommandBuilder::new(&argv[0]);
+        if self.local_cwd { cmd.cwd(self.path.clone()); }
+        if argv.len() > 1 { cmd.args(&argv[1..]); }
+        for (key, value) in &self.env { cmd.env(key, value); }
+
+        cmd
+    }
 
-        let mut cmd = CommandBuilder::new(command);
-        cmd.cwd(self.path.clone());
-        if args.len() > 0 { cmd.args(args); }
+    /***
+    The command for running non-interactively. It leads its own process group, so stopping the
+    task reaches everything it started, e.g. both halves of `sh -c "a | b"`.
+    (PTY children get a whole session of their own, which does the same.)
+     */
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.argv[0]);
+        if self.local_cwd { cmd.current_dir(self.path.clone()); }
+        if self.argv.len() > 1 { cmd.args(&self.argv[1..]); }
+        cmd.envs(self.env.iter().map(|(key, value)| (key, value)));
+        for key in &self.env_removed { cmd.env_remove(key); }
+        cmd.process_group(0);
 
         cmd
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(toml_str: &str) -> Task {
+        toml::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn it_splits_local_commands() {
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime -p'");
+        let child = ChildProcess::for_task(&t, (1, 10), false).unwrap();
+        assert_eq!(child.argv, vec!["uptime", "-p"]);
+
+        let t = task("id = 'say'\nname = 'Say'\npath = '.'\ncommand = 'echo a b'\nargv = ['echo', 'a b']");
+        let child = ChildProcess::for_task(&t, (1, 10), false).unwrap();
+        assert_eq!(child.argv, vec!["echo", "a b"]);
+    }
+
+    #[test]
+    fn it_styles_exit_banners() {
+        assert_eq!(ChildProcess::exit_banner("code 0", true, Duration::from_millis(1234)),
+                   b"\r\n\x1b[0;30;42m[exited: code 0 in 1.2s]\x1b[0m".to_vec());
+        assert_eq!(ChildProcess::exit_banner("signal 9", false, Duration::from_secs(3)),
+                   b"\r\n\x1b[0;37;41m[exited: signal 9 in 3.0s]\x1b[0m".to_vec());
+    }
+
+    #[test]
+    fn it_describes_the_terminal_to_children() {
+        let env = |child: ChildProcess| child.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>();
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime'");
+        assert_eq!(env(ChildProcess::for_task(&t, (5, 40), false).unwrap()),
+                   vec!["TERM=xterm-256color", "COLORTERM=truecolor", "COLUMNS=40", "LINES=5"]);
+
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime'\nterm = 'vt100'\ncolorterm = ''");
+        let child = ChildProcess::for_task(&t, (5, 40), true).unwrap();
+        assert_eq!(child.env_removed, vec!["COLORTERM"]);
+        assert_eq!(child.command_for_pty().as_unix_command_line().unwrap(), "env -u COLORTERM uptime");
+        assert_eq!(env(child), vec!["TERM=vt100"]);
+    }
+
+    #[test]
+    fn it_applies_priority_and_limits_to_local_commands() {
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime -p'\nnice = 10");
+        let child = ChildProcess::for_task(&t, (1, 10), false).unwrap();
+        assert_eq!(child.argv, vec!["nice", "-n", "10", "uptime", "-p"]);
+
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime -p'\nnice = 5\nmax_cpu_secs = 30\nmax_memory_mb = 64");
+        let child = ChildProcess::for_task(&t, (1, 10), false).unwrap();
+        assert_eq!(child.argv, vec!["sh", "-c", "ulimit -t 30 && ulimit -v 65536 && exec \"$@\"", "sh", "nice", "-n", "5", "uptime", "-p"]);
+    }
+
+    #[test]
+    fn it_reads_stdin_from_text_or_a_file() {
+        let t = task("id = 'q'\nname = 'Query'\npath = '.'\ncommand = 'jq .name'\nstdin = { text = '{\"name\": \"pi\"}' }");
+        assert_eq!(t.stdin_bytes().unwrap(), Some(br#"{"name": "pi"}"#.to_vec()));
+
+        let t = task("id = 'q'\nname = 'Query'\npath = 'src'\ncommand = 'wc -l'\nstdin = 'main.rs'");
+        assert!(t.stdin_bytes().unwrap().unwrap().starts_with(b"use "));
+
+        let t = task("id = 'q'\nname = 'Query'\npath = '.'\ncommand = 'wc -l'\nstdin = 'no/such/file'");
+        assert!(t.stdin_bytes().is_err());
+    }
+
+    #[test]
+    fn it_wraps_ssh_commands() {
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '/srv'\ncommand = 'uptime -p'\nkind = 'ssh'\nhost = 'pi2'\nuser = 'lucas'");
+        let child = ChildProcess::for_task(&t, (1, 10), false).unwrap();
+        assert_eq!(child.argv.first().unwrap(), "ssh");
+        assert_eq!(&child.argv[child.argv.len() - 3..], &["lucas", "pi2", "cd '/srv' && uptime -p"][..]);
+        assert!(child.argv.contains(&String::from("BatchMode=yes")));
+    }
+
+    #[test]
+    fn it_runs_image_tasks_in_named_containers() {
+        let t = task("id = 'db'\nname = 'DB'\npath = '.'\ncommand = 'psql -l'\nkind = 'podman'\nimage = 'postgres'");
+        let child = ChildProcess::for_task(&t, (1, 10), true).unwrap();
+        let name = format!("decker-db-{}", std::process::id());
+        assert_eq!(child.argv, vec!["podman", "run", "--rm", "--name", &name, "-i", "-t", "postgres", "sh", "-c", "psql -l"]);
+        assert_eq!(child.teardown, Some(vec![String::from("podman"), String::from("rm"), String::from("-f"), name]));
+    }
+
+    #[test]
+    fn it_requires_a_host_for_ssh_tasks() {
+        let t = task("id = 'up'\nname = 'Uptime'\npath = '.'\ncommand = 'uptime'\nkind = 'ssh'");
+        assert!(ChildProcess::for_task(&t, (1, 10), false).is_err());
+    }
+}
@@ -3,9 +3,39 @@
 * Keeps track of all the things we need for trafficing I/O between processes
 ***/
 mod child_process;
+mod output_throttle;
+mod termination;
+
+use std::time::Instant;
+
+pub use termination::{exit_status, signal_group, terminate, wait_for_exit};
 
 pub struct ChildProcess {
     pub command: String,
     pub path: String,
     pub size: (u16,u16),
-}
\ No newline at end of file
+    // The program and arguments actually launched. Usually just `command`, split up,
+    // but remote tasks wrap it in e.g. an ssh invocation.
+    argv: Vec<String>,
+    // Remote commands don't run in a local directory
+    local_cwd: bool,
+    // Run at shutdown to clean up anything the child leaves behind
+    pub teardown: Option<Vec<String>>,
+    // Set in the child's environment, e.g. TERM, so it knows what it's drawing on
+    env: Vec<(String, String)>,
+    // Taken out of the child's environment, e.g. a COLORTERM it shouldn't inherit from decker's
+    env_removed: Vec<String>,
+}
+
+/***
+* Caps how many bytes of a child's output we forward each second, so a runaway
+* task can't flood the output channel and freeze every other pane.
+***/
+pub struct OutputThrottle {
+    // Zero means unlimited
+    max_bytes_per_sec: u64,
+    window_start: Instant,
+    window_bytes: u64,
+    // Whether we've already told the user this window was cut short
+    truncated: bool,
+}
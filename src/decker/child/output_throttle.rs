@@ -0,0 +1,105 @@
+use crate::decker::child::OutputThrottle;
+use std::time::{Duration, Instant};
+
+// Shown in the pane when output is dropped
+const TRUNCATED_MARKER: &[u8] = b"\x1B[0m\r\n\x1B[7m[decker: output truncated]\x1B[0m\r\n";
+
+// The limit for tasks which don't set one: 1MB/s
+const DEFAULT_OUTPUT_RATE_KB: u64 = 1024;
+
+impl OutputThrottle {
+    pub fn new(max_bytes_per_sec: u64) -> OutputThrottle {
+        OutputThrottle {
+            max_bytes_per_sec,
+            window_start: Instant::now(),
+            window_bytes: 0,
+            truncated: false,
+        }
+    }
+
+    /***
+    Build the throttle for a task's configured limit, in KB/s
+     */
+    pub fn for_rate_kb(rate_kb: Option<u64>) -> OutputThrottle {
+        Self::new(rate_kb.unwrap_or(DEFAULT_OUTPUT_RATE_KB).saturating_mul(1024))
+    }
+
+    pub fn max_bytes_per_sec(&self) -> u64 {
+        self.max_bytes_per_sec
+    }
+
+    pub fn set_max_bytes_per_sec(&mut self, max_bytes_per_sec: u64) {
+        self.max_bytes_per_sec = max_bytes_per_sec;
+    }
+
+    /***
+    Filter a chunk of output through the throttle.
+    Returns the bytes to forward - possibly cut short and followed by a truncation marker -
+    or None if the whole chunk should be dropped.
+     */
+    pub fn admit(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        self.admit_at(bytes, Instant::now())
+    }
+
+    fn admit_at(&mut self, bytes: &[u8], now: Instant) -> Option<Vec<u8>> {
+        if self.max_bytes_per_sec == 0 {
+            return Some(bytes.to_vec());
+        }
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.window_bytes = 0;
+            self.truncated = false;
+        }
+
+        let allowance = self.max_bytes_per_sec.saturating_sub(self.window_bytes) as usize;
+        if bytes.len() <= allowance {
+            self.window_bytes += bytes.len() as u64;
+            return Some(bytes.to_vec());
+        }
+
+        // Over the limit - forward what fits, then flag the rest as dropped once per window
+        self.window_bytes = self.max_bytes_per_sec;
+        let mut admitted = bytes[..allowance].to_vec();
+        if !self.truncated {
+            self.truncated = true;
+            admitted.extend_from_slice(TRUNCATED_MARKER);
+        }
+
+        if admitted.is_empty() { None } else { Some(admitted) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_forwards_output_under_the_limit() {
+        let mut throttle = OutputThrottle::new(10);
+        let now = Instant::now();
+        assert_eq!(throttle.admit_at(b"hello", now), Some(b"hello".to_vec()));
+        assert_eq!(throttle.admit_at(b"world", now), Some(b"world".to_vec()));
+    }
+
+    #[test]
+    fn it_truncates_output_over_the_limit_once_per_second() {
+        let mut throttle = OutputThrottle::new(4);
+        let now = Instant::now();
+
+        let mut expected = b"abcd".to_vec();
+        expected.extend_from_slice(TRUNCATED_MARKER);
+        assert_eq!(throttle.admit_at(b"abcdef", now), Some(expected));
+        assert_eq!(throttle.admit_at(b"ghi", now), None);
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(throttle.admit_at(b"ghi", later), Some(b"ghi".to_vec()));
+    }
+
+    #[test]
+    fn zero_means_unlimited() {
+        let mut throttle = OutputThrottle::for_rate_kb(Some(0));
+        let big = vec![b'x'; 10 * 1024 * 1024];
+        assert_eq!(throttle.admit(&big).map(|b| b.len()), Some(big.len()));
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use libc::c_int;
+use log::{info, warn};
+
+// How often to check whether the processes have gone yet
+const POLL: Duration = Duration::from_millis(50);
+// How long after SIGKILL to keep waiting for a process to be reaped
+const REAP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/***
+Stop processes politely: SIGTERM, plus SIGHUP for shells in a PTY that only listen for that. Any
+still running once their grace period is up get SIGKILL. Each pid's whole process group is
+signalled, and isn't done until everything in it has gone. Returns the pids that were never seen
+to be reaped.
+
+`held` signals a pid's group through the child we hold for it, as long as it hasn't been waited on
+yet, and says whether it could - a pid we've let go of may already belong to something else.
+Signal 0 just asks.
+ */
+pub fn terminate(targets: &[(u32, Duration)], mut held: impl FnMut(u32, c_int) -> bool) -> Vec<u32> {
+    let started = Instant::now();
+    for &(pid, _) in targets {
+        signal(&mut held, pid, libc::SIGTERM);
+        signal(&mut held, pid, libc::SIGHUP);
+    }
+
+    let give_up = targets.iter().map(|(_, grace)| *grace).max().unwrap_or_default() + REAP_TIMEOUT;
+    let mut killed = HashSet::new();
+    let mut left = targets.to_vec();
+    loop {
+        left.retain(|&(pid, _)| held(pid, 0) || group_alive(pid));
+        if left.is_empty() || started.elapsed() >= give_up {
+            break;
+        }
+
+        for &(pid, grace) in &left {
+            if started.elapsed() >= grace && killed.insert(pid) {
+                info!("main: pid {} outlived its {:?} grace period - killing it", pid, grace);
+                signal(&mut held, pid, libc::SIGKILL);
+            }
+        }
+        thread::sleep(POLL);
+    }
+
+    left.into_iter().map(|(pid, _)| pid).collect()
+}
+
+/***
+Signal the group `pid` led through its child if we still hold it. Once it's been waited on, what
+it started may still be running - and while they are, nothing else can be given its pid.
+ */
+fn signal(held: &mut impl FnMut(u32, c_int) -> bool, pid: u32, signal: c_int) {
+    if !held(pid, signal) && group_alive(pid) {
+        signal_group(pid, signal);
+    }
+}
+
+/***
+Send `signal` to the process group `pid` leads, or just to `pid` if it doesn't lead one. Only for
+pids that can't have been reused: a child that hasn't been waited on, or a group still running.
+ */
+pub fn signal_group(pid: u32, signal: c_int) {
+    // Safe: kill and killpg only take plain integers
+    let sent = unsafe { libc::killpg(pid as libc::pid_t, signal) == 0 || libc::kill(pid as libc::pid_t, signal) == 0 };
+    if !sent {
+        warn!("main: Could not send signal {} to pid {}: {}", signal, pid, std::io::Error::last_os_error());
+    }
+}
+
+fn group_alive(pid: u32) -> bool {
+    // Safe: signal 0 checks the group is there without touching it
+    if unsafe { libc::killpg(pid as libc::pid_t, 0) } != 0 {
+        return false;
+    }
+    // Zombies still count as the group's, but there's nothing left of them to stop - whoever
+    // inherited them will reap them in their own time
+    match fs::read_dir("/proc") {
+        Err(_) => { true }
+        Ok(procs) => { procs.flatten().any(|process| running_in_group(&process.path(), pid)) }
+    }
+}
+
+fn running_in_group(process: &Path, pgid: u32) -> bool {
+    let stat = match fs::read_to_string(process.join("stat")) {
+        Err(_) => { return false }
+        Ok(stat) => { stat }
+    };
+    // After the command name (which may hold spaces and parens of its own): state, ppid, pgrp...
+    let fields = stat.rsplit_once(')').map(|(_, rest)| rest.split_whitespace().collect::<Vec<_>>()).unwrap_or_default();
+    fields.first().is_some_and(|state| *state != "Z") && fields.get(2) == Some(&pgid.to_string().as_str())
+}
+
+/***
+Block until the child `pid` exits, without waiting on it - until whoever holds it does, it can
+still be signalled by pid
+ */
+pub fn wait_for_exit(pid: u32) -> std::io::Result<()> {
+    loop {
+        // Safe: an all-zero siginfo_t is valid, and waitid only fills it in
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let waited = unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) };
+        if waited == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/***
+How the child `pid` ended - "code 1", "signal 9" - without waiting on it, so whoever holds it can
+still reap it. None while it's still running.
+ */
+pub fn exit_status(pid: u32) -> std::io::Result<Option<String>> {
+    // Safe: an all-zero siginfo_t is valid, and waitid only fills it in
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let flags = libc::WEXITED | libc::WNOHANG | libc::WNOWAIT;
+    if unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // Safe: waitid filled these in. No pid means nothing has exited yet.
+    let (exited, status) = unsafe { (info.si_pid(), info.si_status()) };
+    match (exited, info.si_code) {
+        (0, _) => { Ok(None) }
+        (_, libc::CLD_EXITED) => { Ok(Some(format!("code {}", status))) }
+        _ => { Ok(Some(format!("signal {}", status))) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    #[test]
+    fn it_kills_processes_that_ignore_sigterm() {
+        let mut stubborn = Command::new("sh").arg("-c").arg("trap '' TERM HUP; sleep 5 | sleep 5").process_group(0).spawn().unwrap();
+        let mut polite = Command::new("sleep").arg("5").process_group(0).spawn().unwrap();
+        thread::sleep(Duration::from_millis(100)); // let the trap get set up
+
+        let started = Instant::now();
+        let targets = [(stubborn.id(), Duration::from_millis(200)), (polite.id(), Duration::from_secs(5))];
+        let stubborn_pid = stubborn.id();
+        let left = terminate(&targets, |pid, signal| {
+            let child = if pid == stubborn_pid { &mut stubborn } else { &mut polite };
+            let held = child.try_wait().unwrap().is_none();
+            if held { signal_group(pid, signal); }
+            held
+        });
+
+        assert!(left.is_empty());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(!stubborn.wait().unwrap().success());
+        assert!(!polite.wait().unwrap().success());
+        assert!(!group_alive(stubborn.id()));
+    }
+
+    #[test]
+    fn it_waits_for_exit_without_reaping() {
+        let mut child = Command::new("sh").arg("-c").arg("exit 3").spawn().unwrap();
+        wait_for_exit(child.id()).unwrap();
+        // Still ours to wait on
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+    }
+
+    #[test]
+    fn it_tells_how_a_child_ended_without_reaping_it() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert_eq!(exit_status(child.id()).unwrap(), None);
+        child.kill().unwrap();
+        wait_for_exit(child.id()).unwrap();
+        assert_eq!(exit_status(child.id()).unwrap(), Some(String::from("signal 9")));
+        assert!(!child.wait().unwrap().success());
+
+        let mut child = Command::new("sh").arg("-c").arg("exit 3").spawn().unwrap();
+        wait_for_exit(child.id()).unwrap();
+        assert_eq!(exit_status(child.id()).unwrap(), Some(String::from("code 3")));
+        assert_eq!(child.wait().unwrap().code(), Some(3));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::decker::cli::{CliArgs, Command};
+use crate::decker::terminal::PipeTarget;
+use crate::decker::config::ConfigSource;
+use crate::decker::control::GroupAction;
+use std::collections::HashMap;
+use anyhow::bail;
+
+// Options which take a value, e.g. `--socket /tmp/decker.sock`
+const VALUE_OPTIONS: [&str; 16] = ["--socket", "--file", "--command", "--config", "--profile", "--layout", "--mirror",
+                                    "--timing", "--speed", "--size", "--snapshot", "--pane", "--id", "--linger", "--task", "--kb"];
+
+impl CliArgs {
+    /***
+    Parse the command line, minus the program name
+     */
+    pub fn parse(args: &[String]) -> anyhow::Result<CliArgs> {
+        let mut options: HashMap<String, String> = HashMap::new();
+        let mut positional = Vec::new();
+        let mut flags = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                option if VALUE_OPTIONS.contains(&option) => {
+                    match args.next() {
+                        None => { bail!("{} requires a value", option) }
+                        Some(value) => { options.insert(option.to_string(), value.clone()); }
+                    }
+                }
+                // Everything after a bare -- is positional, e.g. a command with options of its own
+                "--" => { positional.extend(args.by_ref().cloned()) }
+                flag if flag.starts_with("--") => { flags.push(flag.to_string()) }
+                _ => { positional.push(arg.clone()) }
+            }
+        }
+
+        let socket = options.remove("--socket");
+        let config = match (options.remove("--config"), options.remove("--layout")) {
+            (None, None) => { ConfigSource::default() }
+            (Some(path), None) => { if path == "-" { ConfigSource::Stdin } else { ConfigSource::File(path) } }
+            (None, Some(layout)) => { ConfigSource::Inline(layout) }
+            (Some(_), Some(_)) => { bail!("--config and --layout can't be used together") }
+        };
+        let profile = options.remove("--profile");
+        let no_color = Self::take_flag(&mut flags, "--no-color");
+        let screen_reader = Self::take_flag(&mut flags, "--screen-reader");
+        let mirror = options.remove("--mirror");
+
+        let command = match positional.first().map(|s| s.as_str()) {
+            None => { Command::Dashboard { session: None, resume: Self::take_flag(&mut flags, "--resume") } }
+            Some("status") => { Command::Status { json: Self::take_flag(&mut flags, "--json") } }
+            Some("pipe-pane") => {
+                let pane = match positional.get(1) {
+                    None => { bail!("pipe-pane requires a pane id") }
+                    Some(pane) => { pane.clone() }
+                };
+                let target = match (options.remove("--file"), options.remove("--command")) {
+                    (None, None) => { None }
+                    (Some(path), None) => { Some(PipeTarget::File(path)) }
+                    (None, Some(command)) => { Some(PipeTarget::Command(command)) }
+                    (Some(_), Some(_)) => { bail!("pipe-pane takes --file or --command, not both") }
+                };
+                Command::PipePane { pane, target }
+            }
+            Some(command @ "enable") | Some(command @ "disable") => {
+                let task_id = match positional.get(1) {
+                    None => { bail!("{} requires a task id", command) }
+                    Some(task_id) => { task_id.clone() }
+                };
+                Command::SetEnabled { task_id, enabled: command == "enable" }
+            }
+            Some("cancel") => {
+                let task_id = match positional.get(1) {
+                    None => { bail!("cancel requires a task id") }
+                    Some(task_id) => { task_id.clone() }
+                };
+                Command::Cancel { task_id }
+            }
+            Some("repaint") => { Command::Repaint }
+            Some("export-html") => {
+                match (positional.get(1), positional.get(2)) {
+                    (Some(pane), Some(path)) => { Command::ExportHtml { pane: pane.clone(), path: path.clone() } }
+                    _ => { bail!("export-html requires a pane id and a file") }
+                }
+            }
+            Some("run") => {
+                if positional.len() < 2 {
+                    bail!("run requires a command")
+                }
+                let argv = positional[1..].to_vec();
+                Command::Run { argv, id: options.remove("--id"), pane: options.remove("--pane"), linger: options.remove("--linger") }
+            }
+            Some("session") => {
+                let path = match positional.get(2) {
+                    None => { bail!("session requires a file") }
+                    Some(path) => { path.clone() }
+                };
+                match positional.get(1).map(|s| s.as_str()) {
+                    Some("export") => { Command::ExportSession { path } }
+                    Some("import") => { Command::Dashboard { session: Some(path), resume: false } }
+                    _ => { bail!("session expects export or import") }
+                }
+            }
+            Some("attach") => {
+                match options.remove("--task") {
+                    None => { bail!("attach requires --task <id>") }
+                    Some(task_id) => { Command::Attach { task_id } }
+                }
+            }
+            Some("group") => {
+                let action = match positional.get(1).map(|s| s.as_str()) {
+                    Some("run") => { GroupAction::Run }
+                    Some("pause") => { GroupAction::Pause }
+                    Some("resume") => { GroupAction::Resume }
+                    Some("kill") => { GroupAction::Kill }
+                    Some(other) => { bail!("Unknown group action '{}' - expected run, pause, resume or kill", other) }
+                    None => { bail!("group requires an action: run, pause, resume or kill") }
+                };
+                let group = match positional.get(2) {
+                    None => { bail!("group requires a group name") }
+                    Some(group) => { group.clone() }
+                };
+                Command::Group { group, action }
+            }
+            Some("replay") => {
+                let capture = match positional.get(1) {
+                    None => { bail!("replay requires a capture file") }
+                    Some(capture) => { capture.clone() }
+                };
+                let speed = match options.remove("--speed") {
+                    None => { 1.0 }
+                    Some(speed) => {
+                        match speed.parse::<f64>() {
+                            Ok(speed) if speed >= 0.0 => { speed }
+                            _ => { bail!("--speed expects a number like 2 or 0.5, got '{}'", speed) }
+                        }
+                    }
+                };
+                let size = match options.remove("--size") {
+                    None => { (80, 24) }
+                    Some(size) => { Self::parse_size(&size)? }
+                };
+                Command::Replay { capture, timing: options.remove("--timing"), speed, size, snapshot: options.remove("--snapshot") }
+            }
+            Some("bench") => {
+                let size = match options.remove("--size") {
+                    None => { (80, 24) }
+                    Some(size) => { Self::parse_size(&size)? }
+                };
+                let kb = match options.remove("--kb") {
+                    None => { 1024 }
+                    Some(kb) => {
+                        match kb.parse::<usize>() {
+                            Ok(kb) if kb > 0 => { kb }
+                            _ => { bail!("--kb expects a number of KB, like 256, got '{}'", kb) }
+                        }
+                    }
+                };
+                Command::Bench { size, kb }
+            }
+            Some(other) => { bail!("Unknown command '{}'", other) }
+        };
+
+        if let Some(flag) = flags.first().or_else(|| options.keys().next()) {
+            bail!("Unknown option '{}'", flag);
+        }
+
+        Ok(CliArgs { command, socket, config, profile, no_color, screen_reader, mirror })
+    }
+
+    // "80x24" => (80, 24)
+    fn parse_size(size: &str) -> anyhow::Result<(u16, u16)> {
+        let parsed = size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+        match parsed {
+            Some((width, height)) if width > 0 && height > 0 => { Ok((width, height)) }
+            _ => { bail!("--size expects WIDTHxHEIGHT, like 80x24, got '{}'", size) }
+        }
+    }
+
+    fn take_flag(flags: &mut Vec<String>, flag: &str) -> bool {
+        let found = flags.iter().any(|f| f == flag);
+        flags.retain(|f| f != flag);
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<CliArgs> {
+        CliArgs::parse(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn it_runs_the_dashboard_by_default() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Dashboard { session: None, resume: false });
+        assert_eq!(parse(&["--resume"]).unwrap().command, Command::Dashboard { session: None, resume: true });
+        assert!(parse(&["--no-color"]).unwrap().no_color);
+        assert!(parse(&["--screen-reader"]).unwrap().screen_reader);
+        assert_eq!(parse(&["--mirror", "/tmp/render.fifo"]).unwrap().mirror, Some(String::from("/tmp/render.fifo")));
+    }
+
+    #[test]
+    fn it_parses_status_with_json() {
+        let args = parse(&["status", "--json", "--socket", "/tmp/d.sock"]).unwrap();
+        assert_eq!(args.command, Command::Status { json: true });
+        assert_eq!(args.socket, Some(String::from("/tmp/d.sock")));
+    }
+
+    #[test]
+    fn it_parses_pipe_pane_targets() {
+        let args = parse(&["pipe-pane", "time", "--command", "grep 12:00"]).unwrap();
+        assert_eq!(args.command, Command::PipePane { pane: String::from("time"), target: Some(PipeTarget::Command(String::from("grep 12:00"))) });
+
+        let args = parse(&["pipe-pane", "time"]).unwrap();
+        assert_eq!(args.command, Command::PipePane { pane: String::from("time"), target: None });
+    }
+
+    #[test]
+    fn it_parses_config_sources() {
+        assert_eq!(parse(&[]).unwrap().config, ConfigSource::default());
+        assert_eq!(parse(&["--config", "-"]).unwrap().config, ConfigSource::Stdin);
+        assert_eq!(parse(&["--layout", "{}"]).unwrap().config, ConfigSource::Inline(String::from("{}")));
+        assert!(parse(&["--layout", "{}", "--config", "a.toml"]).is_err());
+        assert_eq!(parse(&["--profile", "weekend"]).unwrap().profile, Some(String::from("weekend")));
+    }
+
+    #[test]
+    fn it_parses_enable_and_disable() {
+        assert_eq!(parse(&["disable", "weather"]).unwrap().command, Command::SetEnabled { task_id: String::from("weather"), enabled: false });
+        assert_eq!(parse(&["enable", "weather"]).unwrap().command, Command::SetEnabled { task_id: String::from("weather"), enabled: true });
+        assert!(parse(&["enable"]).is_err());
+        assert_eq!(parse(&["cancel", "weather"]).unwrap().command, Command::Cancel { task_id: String::from("weather") });
+        assert!(parse(&["cancel"]).is_err());
+        assert_eq!(parse(&["repaint"]).unwrap().command, Command::Repaint);
+        assert_eq!(parse(&["export-html", "weather", "w.html"]).unwrap().command,
+                   Command::ExportHtml { pane: String::from("weather"), path: String::from("w.html") });
+        assert!(parse(&["export-html", "weather"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_ad_hoc_runs() {
+        let args = parse(&["run", "--pane", "time", "--", "ls", "--color=never", "-l"]).unwrap();
+        assert_eq!(args.command, Command::Run { argv: vec![String::from("ls"), String::from("--color=never"), String::from("-l")], id: None, pane: Some(String::from("time")), linger: None });
+        assert!(parse(&["run"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_session_commands() {
+        assert_eq!(parse(&["session", "export", "s.json"]).unwrap().command, Command::ExportSession { path: String::from("s.json") });
+        assert_eq!(parse(&["session", "import", "s.json"]).unwrap().command, Command::Dashboard { session: Some(String::from("s.json")), resume: false });
+        assert!(parse(&["session", "save", "s.json"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_attach() {
+        assert_eq!(parse(&["attach", "--task", "htop"]).unwrap().command, Command::Attach { task_id: String::from("htop") });
+        assert!(parse(&["attach", "htop"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_group_commands() {
+        let args = parse(&["group", "pause", "network"]).unwrap();
+        assert_eq!(args.command, Command::Group { group: String::from("network"), action: GroupAction::Pause });
+
+        assert!(parse(&["group", "network"]).is_err());
+        assert!(parse(&["group", "explode", "network"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_replay_options() {
+        let args = parse(&["replay", "vim.raw", "--timing", "vim.tm", "--speed", "4", "--size", "100x30"]).unwrap();
+        assert_eq!(args.command, Command::Replay {
+            capture: String::from("vim.raw"), timing: Some(String::from("vim.tm")), speed: 4.0, size: (100, 30), snapshot: None
+        });
+
+        assert!(parse(&["replay"]).is_err());
+        assert!(parse(&["replay", "vim.raw", "--size", "huge"]).is_err());
+        assert!(parse(&["replay", "vim.raw", "--speed", "-1"]).is_err());
+    }
+
+    #[test]
+    fn it_parses_bench_options() {
+        assert_eq!(parse(&["bench"]).unwrap().command, Command::Bench { size: (80, 24), kb: 1024 });
+        assert_eq!(parse(&["bench", "--size", "132x50", "--kb", "64"]).unwrap().command, Command::Bench { size: (132, 50), kb: 64 });
+
+        assert!(parse(&["bench", "--kb", "0"]).is_err());
+        assert!(parse(&["bench", "--kb", "lots"]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_unknown_options() {
+        assert!(parse(&["status", "--yaml"]).is_err());
+        assert!(parse(&["status", "--file", "out.log"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+    }
+}
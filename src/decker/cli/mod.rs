@@ -0,0 +1,98 @@
+/***
+* Command line handling
+* Decker runs the dashboard by default. Subcommands talk to an already running
+* dashboard over its control socket.
+***/
+mod cli_args;
+
+use crate::decker::terminal::PipeTarget;
+use crate::decker::config::ConfigSource;
+use crate::decker::control::GroupAction;
+
+pub const USAGE: &str = "\
+Usage: decker [OPTIONS] [COMMAND]
+
+Commands:
+  (none) [--resume]
+                   Run the dashboard. With --resume, pick up where the last one left off, from
+                   the session it checkpointed.
+  status [--json]  Print the state of a running dashboard's tasks and panes
+  pipe-pane <pane> [--file <PATH> | --command <CMD>]
+                   Copy a pane's raw output to a file or command. With neither, stop copying.
+  enable <task>    Let a disabled task run again
+  disable <task>   Stop a task from running until it's enabled
+  cancel <task>    Drop a task's queued runs and skip its next periodic one
+  repaint          Clear the dashboard's terminal and draw everything again, e.g. after a stray
+                   escape sequence has scrambled it
+  run [--pane <pane>] [--id <id>] [--linger <period>] [--] <command...>
+                   Run a one-off command on the dashboard, from the current directory. Its
+                   output goes in a pane over the bottom half of the screen, which closes
+                   --linger (default 10s) after it finishes, or into an existing pane with
+                   --pane. Prints the new task's id.
+  export-html <pane> <file>
+                   Save everything a pane has, scrollback included, as an HTML page with its
+                   colors, to share exactly what a widget showed.
+  session export <file>
+                   Save the dashboard's tasks, panes, schedules and what each pane is showing.
+  session import <file>
+                   Run the dashboard from a saved session, in place of the config's tasks and
+                   panes. The rest of the config still applies.
+  attach --task <task>
+                   Make a task the main pane's interactive one, stopping whatever was running
+                   there (even the main shell), and start it.
+  group <run|pause|resume|kill> <group>
+                   Run every task in a group now, hold or restart its periodic runs, or kill
+                   whatever it's running.
+  replay <capture> [--timing <FILE>] [--speed <N>] [--size <WxH>] [--snapshot <FILE>]
+                   Play a recorded PTY stream (e.g. from `script --timing`) through a pane.
+                   --speed multiplies playback speed; 0 skips the waits. --size defaults to 80x24.
+                   With --snapshot, write the final screen's text to FILE instead of drawing it.
+  bench [--size <WxH>] [--kb <N>]
+                   Time the parser and renderer on made-up output that's hard on them: style
+                   changes, scrolling, cursor jumps and a mix. Each corpus is --kb (default 1024)
+                   KB, drawn into a --size (default 80x24) pane. Build with --release first.
+
+Options:
+  --socket <PATH>  Control socket to listen on / connect to
+  --config <PATH>  Read the dashboard config from PATH instead of config/tasks.toml.
+                   Use - to read it from stdin.
+  --profile <NAME> Launch the dashboard with the config's NAME profile.
+  --no-color       Draw the dashboard without colors. Also set by a non-empty NO_COLOR.
+  --screen-reader  Print what changes in each pane as lines labelled with its task, e.g.
+                   \"[weather] 12°C, cloudy\", instead of drawing the dashboard.
+  --mirror <PATH>  Copy every byte drawn to the terminal into PATH, if it's a FIFO, or else a new
+                   file named for the time beside it, with a timing file for `decker replay`.
+  --layout <CFG>   Use CFG (TOML or JSON) as the dashboard config";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // Optionally picking up from a saved session, or the last checkpoint
+    Dashboard { session: Option<String>, resume: bool },
+    ExportSession { path: String },
+    ExportHtml { pane: String, path: String },
+    Status { json: bool },
+    PipePane { pane: String, target: Option<PipeTarget> },
+    SetEnabled { task_id: String, enabled: bool },
+    Cancel { task_id: String },
+    Repaint,
+    Attach { task_id: String },
+    Run { argv: Vec<String>, id: Option<String>, pane: Option<String>, linger: Option<String> },
+    Group { group: String, action: GroupAction },
+    Replay { capture: String, timing: Option<String>, speed: f64, size: (u16, u16), snapshot: Option<String> },
+    Bench { size: (u16, u16), kb: usize },
+}
+
+#[derive(Debug)]
+pub struct CliArgs {
+    pub command: Command,
+    pub socket: Option<String>,
+    pub config: ConfigSource,
+    // Which of the config's profiles to launch with
+    pub profile: Option<String>,
+    // Draw without colors, whatever the terminal can do
+    pub no_color: bool,
+    // Print pane updates as lines for a screen reader
+    pub screen_reader: bool,
+    // Copy everything drawn to the terminal here
+    pub mirror: Option<String>,
+}
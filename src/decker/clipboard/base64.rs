@@ -0,0 +1,56 @@
+/***
+Decode standard base64, padded or not. Whitespace is skipped. None if there's anything else
+that isn't base64.
+ */
+pub fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits: u32 = 0;
+    let mut count = 0;
+    let mut padded = false;
+
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => { c - b'A' }
+            b'a'..=b'z' => { c - b'a' + 26 }
+            b'0'..=b'9' => { c - b'0' + 52 }
+            b'+' => { 62 }
+            b'/' => { 63 }
+            b'=' => {
+                padded = true;
+                continue;
+            }
+            _ => { return None }
+        };
+        if padded { return None; } // nothing comes after the padding
+
+        bits = (bits << 6) | value as u32;
+        count += 1;
+        if count == 4 {
+            out.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+            count = 0;
+        }
+    }
+
+    match count {
+        0 => {}
+        2 => { out.push((bits >> 4) as u8) }
+        3 => { out.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()) }
+        _ => { return None }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_base64() {
+        assert_eq!(decode_base64("aGVsbG8gd29ybGQ=").unwrap(), b"hello world");
+        assert_eq!(decode_base64("aGk").unwrap(), b"hi");
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("aGk=x"), None);
+        assert_eq!(decode_base64("a*"), None);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::decker::clipboard::decode_base64;
+use anyhow::bail;
+
+// The clipboard and selections OSC 52 may name: clipboard, primary, secondary, select, cut buffers
+const SELECTIONS: &str = "cpqs01234567";
+
+/***
+Check an OSC 52 clipboard write from a task before it's passed on to the real terminal: it
+names only real selections, its payload is base64 of no more than `max_bytes`, and it isn't
+trying to read the clipboard instead. Returns the write, tidied up, to send on.
+ */
+pub fn clipboard_write(code: &str, max_bytes: usize) -> anyhow::Result<String> {
+    let body = code.strip_prefix("\x1b]52;").unwrap_or(code);
+    let body = body.strip_suffix('\x07').or_else(|| body.strip_suffix("\x1b\\")).unwrap_or(body);
+    let (selections, data) = match body.split_once(';') {
+        None => { bail!("not an OSC 52 clipboard write") }
+        Some(parts) => { parts }
+    };
+
+    if !selections.chars().all(|c| SELECTIONS.contains(c)) {
+        bail!("unknown selection '{}'", selections);
+    }
+    if data == "?" {
+        bail!("tasks may not read the clipboard");
+    }
+    let data = data.chars().filter(|c| !c.is_ascii_whitespace()).collect::<String>();
+    match decode_base64(&data) {
+        None => { bail!("the payload isn't base64") }
+        Some(text) if text.len() > max_bytes => { bail!("{} bytes is over the {} byte limit", text.len(), max_bytes) }
+        Some(_) => { Ok(format!("\x1b]52;{};{}\x07", selections, data)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_checks_clipboard_writes() {
+        assert_eq!(clipboard_write("\x1b]52;c;aGVs\nbG8=\x1b\\", 5).unwrap(), "\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(clipboard_write("\x1b]52;;\x07", 5).unwrap(), "\x1b]52;;\x07");
+        assert!(clipboard_write("\x1b]52;c;aGVsbG8=\x07", 4).is_err());
+        assert!(clipboard_write("\x1b]52;c;?\x07", 5).is_err());
+        assert!(clipboard_write("\x1b]52;x;aGk=\x07", 5).is_err());
+        assert!(clipboard_write("\x1b]52;c;a*\x07", 5).is_err());
+    }
+}
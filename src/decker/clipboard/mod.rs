@@ -0,0 +1,36 @@
+/***
+* Clipboard
+* Reading the system clipboard for pasting into the main pane, either from a helper command or by
+* asking the terminal itself with OSC 52. Tasks that are allowed to can set it with OSC 52 too.
+***/
+mod base64;
+mod copy;
+mod paste;
+
+pub use base64::decode_base64;
+pub use copy::clipboard_write;
+pub use paste::{read_clipboard_reply, run_paste_command, OSC52_QUERY};
+
+/***
+Where pasted text comes from
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PasteSource {
+    // Ask the terminal for its clipboard with an OSC 52 query
+    Terminal,
+    // Run a helper like wl-paste or pbpaste through the shell and take what it prints
+    Command(String),
+}
+
+/***
+How far the terminal has got answering an OSC 52 query
+ */
+#[derive(Debug)]
+pub enum ClipboardReply {
+    // Started, but there's more to come
+    Incomplete,
+    // The terminal's clipboard, or why it couldn't be read
+    Clipboard(anyhow::Result<String>),
+    // Whatever was typed isn't an answer at all, so the terminal isn't going to answer
+    NotAReply,
+}
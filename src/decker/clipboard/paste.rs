@@ -0,0 +1,135 @@
+use crate::decker::clipboard::{decode_base64, ClipboardReply, PasteSource};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use anyhow::{anyhow, bail};
+
+// Ask the terminal what's on its clipboard. It answers with the same sequence, with the
+// clipboard in base64 instead of the ?.
+pub const OSC52_QUERY: &[u8] = b"\x1b]52;c;?\x07";
+const OSC52_REPLY: &[u8] = b"\x1b]52;";
+// How often to check whether a paste helper has finished
+const HELPER_POLL: Duration = Duration::from_millis(10);
+
+impl PasteSource {
+    /***
+    The configured paste_from: "osc52", or a command to run. Unset picks whichever helper suits
+    the desktop we're on, and falls back to asking the terminal.
+     */
+    pub fn new(configured: Option<&str>) -> PasteSource {
+        let set = |var: &str| std::env::var_os(var).map(|v| !v.is_empty()).unwrap_or(false);
+        let helper = match configured {
+            Some("osc52") => { None }
+            Some(command) => { Some(command) }
+            None if set("WAYLAND_DISPLAY") => { Some("wl-paste --no-newline") }
+            None if cfg!(target_os = "macos") => { Some("pbpaste") }
+            None if set("DISPLAY") => { Some("xclip -selection clipboard -o") }
+            None => { None }
+        };
+        match helper {
+            None => { PasteSource::Terminal }
+            Some(command) => { PasteSource::Command(command.to_string()) }
+        }
+    }
+}
+
+/***
+Run a paste helper through the shell, and take what it prints as the clipboard. One that's still
+going after `timeout` is killed.
+ */
+pub fn run_paste_command(command: &str, timeout: Duration) -> anyhow::Result<String> {
+    let mut child = Command::new("sh").arg("-c").arg(command).
+        stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().
+        map_err(|e| anyhow!("Could not run '{}': {}", command, e))?;
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("'{}' didn't finish within {:.1}s", command, timeout.as_secs_f64());
+        }
+        thread::sleep(HELPER_POLL);
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        bail!("'{}' failed ({}): {}", command, status, String::from_utf8_lossy(&stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&stdout).into_owned())
+}
+
+// Read a pipe to the end on another thread, so the helper can't stall on a full one
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut read = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut read);
+        }
+        read
+    })
+}
+
+/***
+Read the terminal's answer to OSC52_QUERY out of what's been typed since asking
+ */
+pub fn read_clipboard_reply(received: &[u8]) -> ClipboardReply {
+    if !received.starts_with(OSC52_REPLY) {
+        return if OSC52_REPLY.starts_with(received) { ClipboardReply::Incomplete } else { ClipboardReply::NotAReply };
+    }
+
+    // ESC ] 52 ; <selection> ; <base64> then BEL or ESC \
+    let body = &received[OSC52_REPLY.len()..];
+    let end = match body.iter().position(|&b| b == b'\x07' || b == b'\x1b') {
+        None => { return ClipboardReply::Incomplete }
+        Some(end) => { end }
+    };
+    if body[end] == b'\x1b' && body.len() == end + 1 {
+        return ClipboardReply::Incomplete; // wait for the \ of the ESC \
+    }
+
+    let body = String::from_utf8_lossy(&body[..end]);
+    let encoded = body.split_once(';').map(|(_, data)| data).unwrap_or("");
+    let text = decode_base64(encoded).
+        map(|bytes| String::from_utf8_lossy(&bytes).into_owned()).
+        ok_or_else(|| anyhow!("The terminal's clipboard reply wasn't valid base64"));
+    ClipboardReply::Clipboard(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_the_terminals_clipboard_reply() {
+        let reply = b"\x1b]52;c;aGVsbG8=\x1b\\";
+        assert!(matches!(read_clipboard_reply(&reply[..4]), ClipboardReply::Incomplete));
+        assert!(matches!(read_clipboard_reply(&reply[..14]), ClipboardReply::Incomplete));
+        assert!(matches!(read_clipboard_reply(&reply[..16]), ClipboardReply::Incomplete));
+        match read_clipboard_reply(reply) {
+            ClipboardReply::Clipboard(text) => { assert_eq!(text.unwrap(), "hello") }
+            other => { panic!("Expected the clipboard, got {:?}", other) }
+        }
+        assert!(matches!(read_clipboard_reply(b"ls\r"), ClipboardReply::NotAReply));
+        assert!(matches!(read_clipboard_reply(b"\x1b]52;c;!!\x07"), ClipboardReply::Clipboard(Err(_))));
+    }
+
+    #[test]
+    fn it_runs_paste_helpers() {
+        assert_eq!(PasteSource::new(Some("osc52")), PasteSource::Terminal);
+        assert_eq!(run_paste_command("printf 'a\\nb'", Duration::from_secs(2)).unwrap(), "a\nb");
+        assert!(run_paste_command("exit 3", Duration::from_secs(2)).is_err());
+
+        let started = Instant::now();
+        let err = run_paste_command("exec sleep 5", Duration::from_millis(100)).err().unwrap();
+        assert!(err.to_string().contains("didn't finish"), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
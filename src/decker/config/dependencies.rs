@@ -0,0 +1,57 @@
+use crate::decker::Task;
+use std::collections::HashSet;
+use anyhow::bail;
+
+/***
+Put tasks in the order they should first run: each after everything it depends_on, and otherwise
+in config order. Dependencies on tasks that don't exist are ignored. Fails if dependencies go
+round in a circle.
+ */
+pub fn start_order(tasks: Vec<Task>) -> anyhow::Result<Vec<Task>> {
+    let ids = tasks.iter().map(|t| t.id.clone()).collect::<HashSet<_>>();
+    let mut placed = HashSet::new();
+    let mut ordered = Vec::with_capacity(tasks.len());
+    let mut remaining = tasks;
+
+    while !remaining.is_empty() {
+        let ready = remaining.iter().position(|task| {
+            task.depends_on.iter().flatten().all(|dep| placed.contains(dep) || !ids.contains(dep))
+        });
+        match ready {
+            None => {
+                let stuck = remaining.iter().map(|t| t.id.as_str()).collect::<Vec<_>>();
+                bail!("depends_on goes round in a circle between tasks {}", stuck.join(", "))
+            }
+            Some(index) => {
+                let task = remaining.remove(index);
+                placed.insert(task.id.clone());
+                ordered.push(task);
+            }
+        }
+    }
+
+    Ok(ordered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, depends_on: &[&str]) -> Task {
+        let deps = depends_on.iter().map(|d| format!("'{}'", d)).collect::<Vec<_>>().join(", ");
+        toml::from_str(&format!("id = '{}'\nname = '{}'\npath = '.'\ncommand = 'true'\ndepends_on = [{}]", id, id, deps)).unwrap()
+    }
+
+    #[test]
+    fn it_starts_dependencies_first() {
+        let tasks = vec![task("status", &["vpn"]), task("time", &[]), task("vpn", &["missing"])];
+        let order = start_order(tasks).unwrap().into_iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(order, vec!["time", "vpn", "status"]);
+    }
+
+    #[test]
+    fn it_rejects_circular_dependencies() {
+        let tasks = vec![task("time", &[]), task("a", &["b"]), task("b", &["a"])];
+        assert_eq!(start_order(tasks).map(|_| ()).unwrap_err().to_string(), "depends_on goes round in a circle between tasks a, b");
+    }
+}
@@ -0,0 +1,355 @@
+use crate::decker::config::{start_order, ConfigErrors, ConfigSource, DeckerConfig};
+use crate::decker::{period_to_secs, TaskKind};
+use crate::decker::keys::parse_key;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use simplelog::LevelFilter;
+
+impl ConfigErrors {
+    pub fn new(source: &ConfigSource, problems: Vec<String>) -> ConfigErrors {
+        let source = match source {
+            ConfigSource::File(path) => { path.clone() }
+            ConfigSource::Stdin => { String::from("<stdin>") }
+            ConfigSource::Inline(_) => { String::from("--layout") }
+        };
+        ConfigErrors { source, problems }
+    }
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Configuration errors in {}:", self.source)?;
+        for problem in &self.problems {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/***
+Look for problems the parser can't catch: bad periods, duplicate ids and the like.
+ */
+pub fn check(config: &DeckerConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut ids = HashSet::new();
+    for task in &config.tasks {
+        if !ids.insert(&task.id) {
+            problems.push(format!("task '{}' is defined more than once", task.id));
+        }
+        if let Some(period) = &task.period {
+            if let Err(e) = period_to_secs(period) {
+                problems.push(format!("task '{}' period: {}", task.id, e));
+            }
+        }
+        if let Some(grace) = &task.stop_grace {
+            if let Err(e) = period_to_secs(grace) {
+                problems.push(format!("task '{}' stop_grace: {}", task.id, e));
+            }
+        }
+        for pattern in task.watch.iter().flatten() {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                problems.push(format!("task '{}' watch '{}': {}", task.id, pattern, e));
+            }
+        }
+        if let Some(trigger_on) = &task.trigger_on {
+            if trigger_on.task == task.id {
+                problems.push(format!("task '{}' trigger_on: a task can't trigger itself", task.id));
+            } else if !config.tasks.iter().any(|t| t.id == trigger_on.task) {
+                problems.push(format!("task '{}' trigger_on: there's no task '{}'", task.id, trigger_on.task));
+            }
+            if let Err(e) = regex::Regex::new(&trigger_on.pattern) {
+                problems.push(format!("task '{}' trigger_on pattern: {}", task.id, e));
+            }
+        }
+        for dep in task.depends_on.iter().flatten() {
+            if !config.tasks.iter().any(|t| &t.id == dep) {
+                problems.push(format!("task '{}' depends_on: there's no task '{}'", task.id, dep));
+            }
+        }
+        if let Some(nice) = task.nice {
+            if !(-20..=19).contains(&nice) {
+                problems.push(format!("task '{}' nice: {} is outside -20 (highest priority) to 19 (lowest)", task.id, nice));
+            }
+        }
+        if task.max_cpu_secs == Some(0) || task.max_memory_mb == Some(0) {
+            problems.push(format!("task '{}': resource limits must be more than 0", task.id));
+        }
+        let limited = task.nice.is_some() || task.max_cpu_secs.is_some() || task.max_memory_mb.is_some();
+        if task.kind == TaskKind::SysStats && !cfg!(feature = "sysinfo") {
+            problems.push(format!("task '{}': decker was built without system stats - rebuild with `--features sysinfo`", task.id));
+        }
+        if limited && task.kind != TaskKind::Local {
+            problems.push(format!("task '{}': nice and resource limits only apply to local tasks", task.id));
+        }
+        if let Some(pattern) = &task.healthy_when {
+            if let Err(e) = regex::Regex::new(pattern) {
+                problems.push(format!("task '{}' healthy_when: {}", task.id, e));
+            }
+        }
+        for filter in task.filters.iter().flatten() {
+            if let Err(e) = regex::bytes::Regex::new(&filter.pattern) {
+                problems.push(format!("task '{}' filters '{}': {}", task.id, filter.pattern, e));
+            }
+        }
+    }
+
+    if let Err(e) = start_order(config.tasks.clone()) {
+        problems.push(e.to_string());
+    }
+
+    match config.panes.iter().filter(|p| p.is_main()).count() {
+        0 => { problems.push(String::from("no 'main' pane! Make one of your panes' task_id = \"main\"")) }
+        1 => {} // perfect!
+        _ => { problems.push(String::from("more than one pane has task_id = \"main\"")) }
+    }
+
+    for pane in &config.panes {
+        if pane.width == 0 || pane.height == 0 {
+            problems.push(format!("pane '{}' has no room - width and height must be at least 1", pane.task_id));
+        }
+        if pane.title_row && pane.height < 2 {
+            problems.push(format!("pane '{}' needs a height of at least 2 for a title_row", pane.task_id));
+        }
+        if pane.tab_width == Some(0) {
+            problems.push(format!("pane '{}': tab_width must be at least 1", pane.task_id));
+        }
+        if pane.max_fps.is_some_and(|fps| !(fps > 0.0 && fps.is_finite())) {
+            problems.push(format!("pane '{}': max_fps must be more than 0", pane.task_id));
+        }
+    }
+
+    if LevelFilter::from_str(&config.logging.level).is_err() {
+        problems.push(format!("logging level: '{}' is not one of off, error, warn, info, debug or trace", config.logging.level));
+    }
+    if let Some(max_age) = &config.logging.max_age {
+        if let Err(e) = period_to_secs(max_age) {
+            problems.push(format!("logging max_age: {}", e));
+        }
+    }
+    if let Some(max_age) = &config.task_logs.max_age {
+        if let Err(e) = period_to_secs(max_age) {
+            problems.push(format!("task_logs max_age: {}", e));
+        }
+    }
+
+    if let Err(e) = period_to_secs(&config.session.every) {
+        problems.push(format!("session every: {}", e));
+    }
+    if config.output.queue_size == 0 {
+        problems.push(String::from("output queue_size must be at least 1"));
+    }
+    if config.concurrency.max_running == 0 {
+        problems.push(String::from("concurrency max_running must be at least 1"));
+    }
+    for (group, limit) in &config.concurrency.groups {
+        if *limit == 0 {
+            problems.push(format!("concurrency group '{}': limit must be at least 1", group));
+        }
+        if !config.tasks.iter().any(|t| t.group.as_ref() == Some(group)) {
+            problems.push(format!("concurrency group '{}': no task is in this group", group));
+        }
+    }
+
+    let keys = &config.keys;
+    let mut bound = HashSet::new();
+    for &(name, key) in &[("prefix", &keys.prefix), ("refresh", &keys.refresh), ("next_pane", &keys.next_pane),
+                          ("last_pane", &keys.last_pane), ("next_layout", &keys.next_layout),
+                          ("prompt", &keys.prompt),
+                          ("scroll_up", &keys.scroll_up), ("scroll_down", &keys.scroll_down),
+                          ("pan_left", &keys.pan_left), ("pan_right", &keys.pan_right),
+                          ("local_echo", &keys.local_echo),
+                          ("paste", &keys.paste),
+                          ("repaint", &keys.repaint)] {
+        match parse_key(key) {
+            Err(e) => { problems.push(format!("keys {}: {}", name, e)) }
+            Ok(byte) => {
+                if !bound.insert(byte) {
+                    problems.push(format!("keys {}: '{}' is already bound to something else", name, key));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::config::{load_task_config, parse_config};
+
+    #[test]
+    fn it_lists_every_problem() {
+        let cfg = parse_config(r#"
+            [[tasks]]
+            id = "time"
+            name = "Time"
+            path = "."
+            command = "date"
+            period = "soon"
+
+            [[panes]]
+            task_id = "time"
+            x = 1
+            y = 1
+            width = 0
+            height = 1
+
+            [logging]
+            level = "chatty"
+
+            [output]
+            queue_size = 0
+            "#).unwrap();
+
+        let problems = check(&cfg);
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        assert!(problems[0].starts_with("task 'time' period: 'soon' is not a valid period"), "{:?}", problems);
+        assert!(problems[1].starts_with("no 'main' pane!"), "{:?}", problems);
+        assert!(problems.contains(&String::from("output queue_size must be at least 1")), "{:?}", problems);
+    }
+
+    #[test]
+    fn it_reports_parse_errors_with_their_location() {
+        let source = ConfigSource::Inline(String::from("tasks = []\npanes = []\nlogging = {"));
+        let err = load_task_config(&source, None).err().unwrap().to_string();
+        assert!(err.starts_with("Configuration errors in --layout:"), "{}", err);
+        assert!(err.contains("line 3"), "{}", err);
+
+        let source = ConfigSource::Inline(String::from("tasks = []\npanes = []\nbogus = 1"));
+        let err = load_task_config(&source, None).err().unwrap().to_string();
+        assert!(err.contains("unknown field `bogus`"), "{}", err);
+    }
+
+    #[test]
+    fn it_judges_task_health() {
+        use crate::decker::TaskHealth;
+
+        let cfg = parse_config(r#"
+            [[tasks]]
+            id = "main"
+            name = "Checks"
+            path = "."
+            command = "./check.sh"
+            success_exit_codes = [0, 3]
+            healthy_when = "^OK"
+
+            [[panes]]
+            task_id = "main"
+            x = 1
+            y = 1
+            width = 10
+            height = 10
+            "#).unwrap();
+        assert!(check(&cfg).is_empty());
+
+        let task = &cfg.tasks[0];
+        assert_eq!(task.health_of(Some(3), b"OK: all good"), TaskHealth::Healthy);
+        assert_eq!(task.health_of(Some(0), b"FAIL: disk full"), TaskHealth::Unhealthy);
+        assert_eq!(task.health_of(Some(1), b"OK: all good"), TaskHealth::Unhealthy);
+        assert_eq!(task.health_of(None, b"OK: all good"), TaskHealth::Unhealthy);
+    }
+
+    #[test]
+    fn it_checks_output_triggers() {
+        let cfg = parse_config(r#"
+            [[tasks]]
+            id = "main"
+            name = "Deploy"
+            path = "."
+            command = "./deploy-status.sh"
+            trigger_on = { task = "main", pattern = "BUILD OK" }
+
+            [[tasks]]
+            id = "tests"
+            name = "Tests"
+            path = "."
+            command = "cargo test"
+            trigger_on = { task = "build", pattern = "(" }
+
+            [[panes]]
+            task_id = "main"
+            x = 1
+            y = 1
+            width = 10
+            height = 10
+            "#).unwrap();
+        let problems = check(&cfg);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert_eq!(problems[0], "task 'main' trigger_on: a task can't trigger itself");
+        assert_eq!(problems[1], "task 'tests' trigger_on: there's no task 'build'");
+        assert!(problems[2].starts_with("task 'tests' trigger_on pattern:"), "{:?}", problems);
+    }
+
+    #[test]
+    fn it_rejects_bad_patterns() {
+        let cfg = parse_config(r#"
+            [[tasks]]
+            id = "main"
+            name = "Checks"
+            path = "."
+            command = "./check.sh"
+            healthy_when = "(unclosed"
+            filters = [{ pattern = "token=\\w+", replace = "token=…" }, { pattern = "[" }]
+
+            [[panes]]
+            task_id = "main"
+            x = 1
+            y = 1
+            width = 10
+            height = 10
+            "#).unwrap();
+        let problems = check(&cfg);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("task 'main' healthy_when:"), "{:?}", problems);
+        assert!(problems[1].starts_with("task 'main' filters '[':"), "{:?}", problems);
+    }
+
+    #[test]
+    fn it_checks_key_bindings() {
+        let cfg = parse_config(r#"
+            [[tasks]]
+            id = "main"
+            name = "Shell"
+            path = "."
+            command = "bash"
+
+            [[panes]]
+            task_id = "main"
+            x = 1
+            y = 1
+            width = 10
+            height = 10
+
+            [keys]
+            prompt = "r"
+            scroll_up = "o"
+            scroll_down = ";"
+            pan_left = "Space"
+            pan_right = "C-b"
+            local_echo = "r"
+            paste = "o"
+            repaint = "C-b"
+            "#).unwrap();
+        let problems = check(&cfg);
+        assert_eq!(problems, vec![String::from("keys prompt: 'r' is already bound to something else"),
+                                  String::from("keys scroll_up: 'o' is already bound to something else"),
+                                  String::from("keys scroll_down: ';' is already bound to something else"),
+                                  String::from("keys pan_left: 'Space' is already bound to something else"),
+                                  String::from("keys pan_right: 'C-b' is already bound to something else"),
+                                  String::from("keys local_echo: 'r' is already bound to something else"),
+                                  String::from("keys paste: 'o' is already bound to something else"),
+                                  String::from("keys repaint: 'C-b' is already bound to something else")]);
+    }
+
+    #[test]
+    fn it_reports_missing_files() {
+        let source = ConfigSource::File(String::from("no/such/tasks.toml"));
+        let err = load_task_config(&source, None).err().unwrap().to_string();
+        assert_eq!(err, "Configuration errors in no/such/tasks.toml:\n  - file not found");
+    }
+}
@@ -0,0 +1,122 @@
+use crate::decker::config::DeckerConfig;
+use regex::{Captures, Regex};
+use lazy_static::lazy_static;
+use anyhow::bail;
+
+lazy_static! {
+    // $${VAR} is an escaped, literal ${VAR}
+    static ref ENV_VAR_REGEX: Regex = Regex::new(r"\$?\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+}
+
+/***
+Replace ${VAR} references in the config's task commands, paths, remote options and pane titles
+with values from the environment. Fails listing every variable that isn't set.
+ */
+pub fn interpolate_env(config: &mut DeckerConfig) -> anyhow::Result<()> {
+    interpolate_with(config, &|name| std::env::var(name).ok())
+}
+
+fn interpolate_with(config: &mut DeckerConfig, lookup: &dyn Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+
+    for task in config.tasks.iter_mut() {
+        let context = format!("task '{}'", task.id);
+        resolve(&mut task.command, &context, "command", lookup, &mut errors);
+        resolve(&mut task.path, &context, "path", lookup, &mut errors);
+        for (field, value) in [("host", &mut task.host), ("user", &mut task.user), ("key", &mut task.key),
+                               ("container", &mut task.container), ("image", &mut task.image)] {
+            if let Some(value) = value {
+                resolve(value, &context, field, lookup, &mut errors);
+            }
+        }
+    }
+
+    for pane in config.panes.iter_mut() {
+        if let Some(title) = pane.title.as_mut() {
+            resolve(title, &format!("pane '{}'", pane.task_id), "title", lookup, &mut errors);
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+
+    Ok(())
+}
+
+fn resolve(value: &mut String, context: &str, field: &str, lookup: &dyn Fn(&str) -> Option<String>, errors: &mut Vec<String>) {
+    let mut missing = Vec::new();
+
+    let resolved = ENV_VAR_REGEX.replace_all(value, |caps: &Captures| {
+        let reference = caps.get(0).unwrap().as_str();
+        let name = caps.get(1).unwrap().as_str();
+
+        if reference.starts_with("$$") {
+            return reference[1..].to_string();
+        }
+
+        match lookup(name) {
+            Some(v) => { v }
+            None => {
+                missing.push(name.to_string());
+                String::new()
+            }
+        }
+    }).to_string();
+
+    if missing.is_empty() {
+        *value = resolved;
+    } else {
+        errors.push(format!("{} {}: undefined environment variable(s) {}", context, field, missing.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::config::parse_config;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => { Some(String::from("pi2")) }
+            "HOME" => { Some(String::from("/home/lucas")) }
+            _ => { None }
+        }
+    }
+
+    fn config() -> DeckerConfig {
+        parse_config(r#"
+            [[tasks]]
+            id = "up"
+            name = "Uptime"
+            path = "${HOME}/bin"
+            command = "ping -c1 ${HOST} $${HOST}"
+
+            [[panes]]
+            task_id = "main"
+            title = "${HOST} shell"
+            x = 1
+            y = 1
+            width = 80
+            height = 24"#).unwrap()
+    }
+
+    #[test]
+    fn it_replaces_variables() {
+        let mut cfg = config();
+        interpolate_with(&mut cfg, &lookup).unwrap();
+
+        assert_eq!(cfg.tasks[0].path, "/home/lucas/bin");
+        assert_eq!(cfg.tasks[0].command, "ping -c1 pi2 ${HOST}");
+        assert_eq!(cfg.panes[0].title, Some(String::from("pi2 shell")));
+    }
+
+    #[test]
+    fn it_reports_every_missing_variable() {
+        let mut cfg = config();
+        let err = interpolate_with(&mut cfg, &|_| None).unwrap_err().to_string();
+
+        assert!(err.contains("task 'up' path: undefined environment variable(s) HOME"), "{}", err);
+        assert!(err.contains("pane 'main' title: undefined environment variable(s) HOST"), "{}", err);
+    }
+}
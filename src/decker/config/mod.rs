@@ -1,18 +1,312 @@
-use crate::decker::Task;
+use crate::decker::{Task, TaskId};
 use std::fs::File;
-use std::io::Read;
-use serde::Deserialize;
-use toml::de::Error;
+use std::io::{ErrorKind, Read};
+use serde::{Deserialize, Serialize};
+use crate::decker::output::BackpressurePolicy;
+use crate::decker::terminal::ColorDepth;
+use crate::decker::terminal::Overflow;
+use crate::decker::terminal::{Align, VAlign};
+use crate::decker::theme::Theme;
+use std::collections::HashMap;
+
+mod interpolate;
+mod diagnostics;
+mod templates;
+mod dependencies;
+mod profiles;
+
+pub use interpolate::interpolate_env;
+pub use dependencies::start_order;
 
 #[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct DeckerConfig {
     pub tasks: Vec<Task>,
-    pub panes: Vec<PaneDefinition>
+    pub panes: Vec<PaneDefinition>,
+    // Expanded into more tasks and panes when the config is loaded
+    #[serde(default)]
+    pub templates: Vec<TaskTemplate>,
+    #[serde(default)]
+    pub logging: LogConfig,
+    #[serde(default)]
+    pub task_logs: TaskLogConfig,
+    #[serde(default)]
+    pub output: OutputConfig,
+    #[serde(default)]
+    pub keys: KeysConfig,
+    #[serde(default)]
+    pub terminal: TerminalConfig,
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    #[serde(default)]
+    pub session: SessionConfig,
+    // Alternative task sets and layouts, picked with --profile
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub path: String,
+    pub level: String,
+    // Rotation policy - rotate by size (in KB) and/or age (a period string, e.g. "1d")
+    pub max_size_kb: Option<u64>,
+    pub max_age: Option<String>,
+    pub keep: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            path: String::from("log/decker.log"),
+            level: String::from("info"),
+            max_size_kb: Some(10 * 1024),
+            max_age: None,
+            keep: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TaskLogConfig {
+    // Where each task's output is logged. Nothing is logged unless this is set.
+    pub dir: Option<String>,
+    // Rotation policy, as for the main log
+    pub max_size_kb: Option<u64>,
+    pub max_age: Option<String>,
+    pub keep: usize,
+}
+
+impl Default for TaskLogConfig {
+    fn default() -> Self {
+        TaskLogConfig {
+            dir: None,
+            max_size_kb: Some(1024),
+            max_age: None,
+            keep: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    // What to do when task output arrives faster than we can draw it
+    pub backpressure: BackpressurePolicy,
+    // How many chunks of output can wait to be drawn
+    pub queue_size: usize,
+    // Show the queue's counters on the bottom row of the screen
+    pub status_bar: bool,
+    // The real terminal can draw sixel graphics, so the focused pane's may be passed through
+    pub sixel: bool,
+    // Likewise for iTerm2 (OSC 1337) and kitty graphics protocol images
+    pub inline_images: bool,
+    // How many colors the real terminal can show. Worked out from $TERM and friends if unset.
+    pub colors: Option<ColorDepth>,
+    // Print each pane's changes as labelled lines for a screen reader, rather than painting panes
+    pub screen_reader: bool,
+    // Copy everything drawn to the terminal here, a FIFO or a file that's stamped with the time
+    pub mirror: Option<String>,
+    // How tasks with highlight_changes mark what changed since their last run: SGR parameters,
+    // and how long for (a period string)
+    pub highlight_style: String,
+    pub highlight_fade: String,
+}
+
+/***
+What tasks are told about the terminal they're drawing on, unless they say otherwise
+ */
+#[derive(Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TerminalConfig {
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+}
+
+/***
+Where pastes come from, and how much tasks may copy
+ */
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    // "osc52" asks the terminal, anything else is a command that prints the clipboard. Unset
+    // picks a helper for the desktop we're on.
+    pub paste_from: Option<String>,
+    // The most a pane's task may put on the clipboard in one go, for panes allowed to set it
+    pub max_copy_kb: usize,
 }
 
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        ClipboardConfig { paste_from: None, max_copy_kb: 100 }
+    }
+}
+
+/***
+Colors to swap to by day and by night, and when to swap them
+ */
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    pub day: Option<Theme>,
+    pub night: Option<Theme>,
+    // Local times, "HH:MM"
+    pub day_starts: String,
+    pub night_starts: String,
+    // A command that prints "day" or "night", to go by instead of the clock
+    pub follow: Option<String>,
+    // How often to check whether it's time to swap, a period string
+    pub check_every: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            day: None,
+            night: None,
+            day_starts: String::from("07:00"),
+            night_starts: String::from("21:00"),
+            follow: None,
+            check_every: String::from("1m"),
+        }
+    }
+}
+
+/***
+How many background tasks may run at once. Runs past a limit wait for a running task to finish.
+ */
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConcurrencyConfig {
+    pub max_running: usize,
+    // Limits for tasks in particular groups, on top of the overall one
+    pub groups: HashMap<String, usize>,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        ConcurrencyConfig {
+            max_running: 32,
+            groups: HashMap::new(),
+        }
+    }
+}
+
+/***
+Where and how often the session is saved, for `--resume` to pick up after a restart
+ */
 #[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    // Nothing is saved unless this is set
+    pub checkpoint: Option<String>,
+    // A period string, e.g. "1m"
+    pub every: String,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            checkpoint: None,
+            every: String::from("1m"),
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            backpressure: BackpressurePolicy::Block,
+            queue_size: 50,
+            status_bar: false,
+            sixel: false,
+            inline_images: false,
+            colors: None,
+            screen_reader: false,
+            mirror: None,
+            highlight_style: String::from("30;43"),
+            highlight_fade: String::from("5s"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeysConfig {
+    // Pressed before any of decker's own keys, e.g. "C-b" for Ctrl-B
+    pub prefix: String,
+    pub refresh: String,
+    pub next_pane: String,
+    pub last_pane: String,
+    pub next_layout: String,
+    pub scroll_up: String,
+    pub scroll_down: String,
+    pub pan_left: String,
+    pub pan_right: String,
+    pub local_echo: String,
+    pub prompt: String,
+    pub paste: String,
+    pub repaint: String,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        KeysConfig {
+            prefix: String::from("C-b"),
+            refresh: String::from("r"),
+            next_pane: String::from("o"),
+            last_pane: String::from(";"),
+            next_layout: String::from("Space"),
+            scroll_up: String::from("["),
+            scroll_down: String::from("]"),
+            pan_left: String::from("<"),
+            pan_right: String::from(">"),
+            local_echo: String::from("e"),
+            prompt: String::from(":"),
+            paste: String::from("p"),
+            repaint: String::from("C-l"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct PaneDefinition {
     pub task_id: String,
+    pub title: Option<String>,
+    // Lines of history to keep once output scrolls off the top
+    pub scrollback: Option<usize>,
+    // Keep history past `scrollback` compressed in this file instead, up to scrollback_file_mb
+    pub scrollback_file: Option<String>,
+    pub scrollback_file_mb: Option<u64>,
+    // Columns between the default tab stops
+    pub tab_width: Option<u16>,
+    // Draw the pane at most this many times a second, however fast its task prints
+    pub max_fps: Option<f64>,
+    // Give the pane a header row with its name, the title its task sets, and status markers
+    #[serde(default)]
+    pub title_row: bool,
+    // Scroll output up as it reaches the bottom, rather than starting over at the top
+    pub scroll: Option<bool>,
+    // Wrap long lines onto the next row, or cut them off. Main wraps by default, others truncate.
+    pub overflow: Option<Overflow>,
+    // Draw typing straight away, ahead of the task echoing it
+    #[serde(default)]
+    pub local_echo: bool,
+    // Let the task set the clipboard with OSC 52, e.g. vim over ssh
+    #[serde(default)]
+    pub set_clipboard: bool,
+    // Where content smaller than the pane sits in it, e.g. a centered clock
+    #[serde(default)]
+    pub align: Align,
+    #[serde(default)]
+    pub valign: VAlign,
     pub x: u16,
     pub y: u16,
     pub height: u16,
@@ -25,27 +319,153 @@ impl PaneDefinition {
     }
 }
 
-pub fn load_task_config() -> Option<DeckerConfig> {
-    let mut tasks_file = File::open("config/tasks.toml").unwrap();
-    let mut toml_tasks = String::new();
-    tasks_file.read_to_string(&mut toml_tasks).unwrap();
-    let config: Result<DeckerConfig, Error> = toml::from_str(&toml_tasks);
+/***
+A task and pane stamped out once per instance, with each instance's values filled in for
+{name} placeholders. Each pane is placed `offset` further along than the last.
+ */
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TaskTemplate {
+    // A task, in the same shape as [[tasks]]. Left untyped until it's been filled in.
+    pub task: serde_json::Value,
+    pub pane: TemplatePane,
+    pub instances: Vec<HashMap<String, String>>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TemplatePane {
+    pub title: Option<String>,
+    pub x: u16,
+    pub y: u16,
+    pub height: u16,
+    pub width: u16,
+    // (x, y) between one instance's pane and the next. Defaults to stacking them downwards.
+    pub offset: Option<(u16, u16)>,
+    pub max_fps: Option<f64>,
+}
+
+/***
+A named variation on the shared config. The shared tasks are narrowed to those in `include` (all
+of them if unset), the profile's own tasks are added, and its panes replace the shared layout.
+ */
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub include: Option<Vec<TaskId>>,
+    #[serde(default)]
+    pub tasks: Vec<Task>,
+    pub panes: Option<Vec<PaneDefinition>>,
+}
+
+/***
+Where to read the configuration from
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ConfigSource {
+    File(String),
+    Stdin,
+    // TOML or JSON passed directly on the command line
+    Inline(String),
+}
 
-    match config {
-        Ok(conf) => {
-            match how_many_mains(&conf.panes) {
-                0 => { panic!("No 'main' layout! Make one of your panes' task_id = \"main\""); },
-                1 => { Some(conf) }, // perfect!
-                _ => { panic!("More than one pane with 'main' task_id in tasks.toml!"); }
-            }
-        },
-        Err(err) => {
-            println!("Configuration error: {}", err);
-            None
+/***
+Everything wrong with a config, collected so it can all be fixed in one pass
+ */
+#[derive(Debug)]
+pub struct ConfigErrors {
+    // Where the config came from, e.g. its path
+    pub source: String,
+    pub problems: Vec<String>,
+}
+
+impl Default for ConfigSource {
+    fn default() -> Self {
+        ConfigSource::File(String::from("config/tasks.toml"))
+    }
+}
+
+/***
+Read, parse and check the config, switched over to `profile` if one is given. Fails with a
+ConfigErrors listing every problem found.
+ */
+pub fn load_task_config(source: &ConfigSource, profile: Option<&str>) -> anyhow::Result<DeckerConfig> {
+    let mut cfg_text = String::new();
+    let read = match source {
+        ConfigSource::File(path) => { File::open(path).and_then(|mut f| f.read_to_string(&mut cfg_text)) }
+        ConfigSource::Stdin => { std::io::stdin().read_to_string(&mut cfg_text) }
+        ConfigSource::Inline(cfg) => { cfg_text.push_str(cfg); Ok(cfg.len()) }
+    };
+
+    if let Err(e) = read {
+        let problem = match e.kind() {
+            ErrorKind::NotFound => { String::from("file not found") }
+            _ => { e.to_string() }
+        };
+        return Err(ConfigErrors::new(source, vec![problem]).into());
+    }
+
+    let mut config = match parse_config(&cfg_text) {
+        Ok(config) => { config }
+        Err(e) => { return Err(ConfigErrors::new(source, vec![e.to_string()]).into()) }
+    };
+
+    let mut problems = Vec::new();
+    if let Err(e) = templates::expand(&mut config) {
+        problems.extend(e.to_string().lines().map(String::from));
+    }
+    if let Some(name) = profile {
+        if let Err(e) = profiles::select(&mut config, name) {
+            problems.push(e.to_string());
         }
     }
+    if let Err(e) = interpolate_env(&mut config) {
+        problems.extend(e.to_string().lines().map(String::from));
+    }
+    problems.extend(diagnostics::check(&config));
+
+    if problems.is_empty() {
+        Ok(config)
+    } else {
+        Err(ConfigErrors::new(source, problems).into())
+    }
+}
+
+/***
+Configs are TOML, but JSON is accepted too since it's easier for scripts to generate
+ */
+fn parse_config(cfg: &str) -> anyhow::Result<DeckerConfig> {
+    if cfg.trim_start().starts_with('{') {
+        Ok(serde_json::from_str(cfg)?)
+    } else {
+        Ok(toml::from_str(cfg)?)
+    }
 }
 
-fn how_many_mains(panes: &Vec<PaneDefinition>) -> usize {
-    panes.iter().filter(|p| p.is_main()).count()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_toml_configs() {
+        let cfg = parse_config("tasks = []\n[[panes]]\ntask_id = 'main'\nx = 1\ny = 1\nwidth = 80\nheight = 24").unwrap();
+        assert!(cfg.panes[0].is_main());
+    }
+
+    #[test]
+    fn it_parses_json_configs() {
+        let cfg = parse_config(r#"{"tasks": [], "panes": [{"task_id": "main", "x": 1, "y": 1, "width": 80, "height": 24}]}"#).unwrap();
+        assert!(cfg.panes[0].is_main());
+        assert_eq!(cfg.logging.level, "info");
+    }
+
+    #[test]
+    fn it_parses_a_backpressure_policy() {
+        let cfg = parse_config("tasks = []\npanes = []\n[output]\nbackpressure = 'drop-oldest'").unwrap();
+        assert_eq!(cfg.output.backpressure, BackpressurePolicy::DropOldest);
+        assert_eq!(cfg.output.queue_size, 50);
+        assert_eq!(cfg.output.colors, None);
+        let cfg = parse_config("tasks = []\npanes = []\n[output]\ncolors = '256'").unwrap();
+        assert_eq!(cfg.output.colors, Some(ColorDepth::TwoFiftySix));
+    }
+}
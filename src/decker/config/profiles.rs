@@ -0,0 +1,110 @@
+use crate::decker::config::DeckerConfig;
+use std::collections::HashSet;
+use anyhow::bail;
+
+/***
+Switch the config over to the named profile. Without panes of its own, the shared panes stay,
+less any whose task was left out.
+ */
+pub fn select(config: &mut DeckerConfig, name: &str) -> anyhow::Result<()> {
+    let profile = match config.profiles.get(name) {
+        Some(profile) => { profile.clone() }
+        None => {
+            let mut known = config.profiles.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+            known.sort_unstable();
+            if known.is_empty() {
+                bail!("no profile named '{}' - the config doesn't define any", name);
+            }
+            bail!("no profile named '{}' - expected one of {}", name, known.join(", "));
+        }
+    };
+
+    if let Some(include) = &profile.include {
+        let ids = config.tasks.iter().map(|t| &t.id).collect::<HashSet<_>>();
+        let unknown = include.iter().filter(|id| !ids.contains(id)).map(|id| format!("'{}'", id)).collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            bail!("profile '{}' includes tasks that don't exist: {}", name, unknown.join(", "));
+        }
+        config.tasks.retain(|task| include.contains(&task.id));
+        let kept = config.tasks.iter().map(|t| t.id.clone()).collect::<HashSet<_>>();
+        config.panes.retain(|pane| pane.is_main() || kept.contains(&pane.task_id));
+    }
+
+    config.tasks.extend(profile.tasks);
+    if let Some(panes) = profile.panes {
+        config.panes = panes;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::config::parse_config;
+
+    const CONFIG: &str = r#"
+        [[tasks]]
+        id = "time"
+        name = "Time"
+        path = "."
+        command = "date"
+
+        [[tasks]]
+        id = "build"
+        name = "Build"
+        path = "."
+        command = "make"
+
+        [[panes]]
+        task_id = "time"
+        x = 1
+        y = 1
+        width = 30
+        height = 2
+
+        [[panes]]
+        task_id = "build"
+        x = 1
+        y = 3
+        width = 30
+        height = 10
+
+        [profiles.weekend]
+        include = ["time"]
+
+        [[profiles.weekend.tasks]]
+        id = "music"
+        name = "Music"
+        path = "."
+        command = "cmus"
+
+        [profiles.office]
+        panes = [{ task_id = "build", x = 1, y = 1, width = 80, height = 24 }]
+        "#;
+
+    #[test]
+    fn it_narrows_and_adds_tasks() {
+        let mut cfg = parse_config(CONFIG).unwrap();
+        select(&mut cfg, "weekend").unwrap();
+
+        assert_eq!(cfg.tasks.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["time", "music"]);
+        assert_eq!(cfg.panes.iter().map(|p| p.task_id.as_str()).collect::<Vec<_>>(), vec!["time"]);
+    }
+
+    #[test]
+    fn it_replaces_the_layout() {
+        let mut cfg = parse_config(CONFIG).unwrap();
+        select(&mut cfg, "office").unwrap();
+
+        assert_eq!(cfg.tasks.len(), 2);
+        assert_eq!(cfg.panes.len(), 1);
+        assert_eq!(cfg.panes[0].width, 80);
+    }
+
+    #[test]
+    fn it_rejects_unknown_profiles() {
+        let mut cfg = parse_config(CONFIG).unwrap();
+        let err = select(&mut cfg, "holiday").err().unwrap().to_string();
+        assert_eq!(err, "no profile named 'holiday' - expected one of office, weekend");
+    }
+}
@@ -0,0 +1,169 @@
+use crate::decker::config::{DeckerConfig, PaneDefinition};
+use crate::decker::Task;
+use std::collections::HashMap;
+use regex::{Captures, Regex};
+use lazy_static::lazy_static;
+use anyhow::bail;
+
+lazy_static! {
+    // ${VAR} belongs to environment interpolation, so it's left alone
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"(\$?)\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+}
+
+/***
+Add a task and pane to the config for every instance of every template. Fails listing every
+instance that couldn't be filled in.
+ */
+pub fn expand(config: &mut DeckerConfig) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+
+    for (index, template) in config.templates.iter().enumerate() {
+        let offset = template.pane.offset.unwrap_or((0, template.pane.height));
+
+        for (n, vars) in template.instances.iter().enumerate() {
+            let context = format!("template {} instance {}", index + 1, n + 1);
+            let task = match fill_task(&template.task, vars) {
+                Ok(task) => { task }
+                Err(e) => {
+                    errors.push(format!("{}: {}", context, e));
+                    continue;
+                }
+            };
+
+            let title = match &template.pane.title {
+                None => { None }
+                Some(title) => {
+                    match fill(title, vars) {
+                        Ok(title) => { Some(title) }
+                        Err(e) => {
+                            errors.push(format!("{} pane title: {}", context, e));
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let n = n as u16;
+            config.panes.push(PaneDefinition {
+                task_id: task.id.clone(),
+                title,
+                scrollback: None,
+                scrollback_file: None,
+                scrollback_file_mb: None,
+                tab_width: None,
+                max_fps: template.pane.max_fps,
+                title_row: false,
+                scroll: None,
+                overflow: None,
+                local_echo: false,
+                set_clipboard: false,
+                align: Default::default(),
+                valign: Default::default(),
+                x: template.pane.x.saturating_add(n.saturating_mul(offset.0)),
+                y: template.pane.y.saturating_add(n.saturating_mul(offset.1)),
+                height: template.pane.height,
+                width: template.pane.width,
+            });
+            config.tasks.push(task);
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+
+    Ok(())
+}
+
+fn fill_task(template: &serde_json::Value, vars: &HashMap<String, String>) -> anyhow::Result<Task> {
+    let filled = fill_value(template, vars)?;
+    Ok(serde_json::from_value(filled)?)
+}
+
+// Fill in every string in the task, however deeply it's nested
+fn fill_value(value: &serde_json::Value, vars: &HashMap<String, String>) -> anyhow::Result<serde_json::Value> {
+    use serde_json::Value;
+
+    match value {
+        Value::String(s) => { Ok(Value::String(fill(s, vars)?)) }
+        Value::Array(items) => {
+            Ok(Value::Array(items.iter().map(|v| fill_value(v, vars)).collect::<anyhow::Result<_>>()?))
+        }
+        Value::Object(fields) => {
+            let mut filled = serde_json::Map::new();
+            for (name, v) in fields {
+                filled.insert(name.clone(), fill_value(v, vars)?);
+            }
+            Ok(Value::Object(filled))
+        }
+        _ => { Ok(value.clone()) }
+    }
+}
+
+fn fill(s: &str, vars: &HashMap<String, String>) -> anyhow::Result<String> {
+    let mut missing = Vec::new();
+
+    let filled = PLACEHOLDER_REGEX.replace_all(s, |caps: &Captures| {
+        let placeholder = caps.get(0).unwrap().as_str();
+        if !caps[1].is_empty() {
+            return placeholder.to_string();
+        }
+
+        match vars.get(&caps[2]) {
+            Some(value) => { value.clone() }
+            None => {
+                missing.push(caps[2].to_string());
+                placeholder.to_string()
+            }
+        }
+    }).to_string();
+
+    if !missing.is_empty() {
+        bail!("no value for {}", missing.join(", "));
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::config::parse_config;
+
+    #[test]
+    fn it_stamps_out_a_task_and_pane_per_instance() {
+        let mut cfg = parse_config(r#"
+            tasks = []
+            panes = []
+
+            [[templates]]
+            task = { id = "ping-{host}", name = "Ping {host}", path = "${HOME}", command = "ping -c1 {host}", period = "30s" }
+            pane = { title = "{host}", x = 1, y = 10, width = 30, height = 2 }
+            instances = [{ host = "alpha" }, { host = "beta" }]
+            "#).unwrap();
+        expand(&mut cfg).unwrap();
+
+        assert_eq!(cfg.tasks.len(), 2);
+        assert_eq!(cfg.tasks[1].id, "ping-beta");
+        assert_eq!(cfg.tasks[1].command, "ping -c1 beta");
+        assert_eq!(cfg.tasks[1].path, "${HOME}");
+        assert_eq!(cfg.panes[1].title.as_deref(), Some("beta"));
+        assert_eq!((cfg.panes[0].y, cfg.panes[1].y), (10, 12));
+    }
+
+    #[test]
+    fn it_reports_placeholders_without_values() {
+        let mut cfg = parse_config(r#"
+            tasks = []
+            panes = []
+
+            [[templates]]
+            task = { id = "ping-{host}", name = "Ping", path = ".", command = "ping -c{count} {host}" }
+            pane = { x = 1, y = 1, width = 30, height = 2, offset = [30, 0] }
+            instances = [{ host = "alpha", count = "1" }, { host = "beta" }]
+            "#).unwrap();
+
+        let err = expand(&mut cfg).err().unwrap().to_string();
+        assert_eq!(err, "template 1 instance 2: no value for count");
+        assert_eq!(cfg.tasks.len(), 1);
+    }
+}
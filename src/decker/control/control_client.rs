@@ -0,0 +1,24 @@
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use anyhow::{anyhow, bail};
+
+/***
+Send a single request to a running decker and return its response
+ */
+pub fn request(path: &Path, command: &str, data: &str) -> anyhow::Result<String> {
+    let mut stream = UnixStream::connect(path).
+        map_err(|e| anyhow!("Could not connect to decker at {}: {}", path.display(), e))?;
+
+    writeln!(stream, "{}: {}", command, data)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+
+    match response.strip_prefix("Error - ") {
+        None => { Ok(response) }
+        Some(reason) => { bail!("{}", reason) }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::decker::control::{ControlServer, GroupAction, GroupCommand, PaneStatus, PipePane, RunTask, Status};
+use crate::decker::{period_to_secs, MasterControl, Task, TaskId};
+use crate::decker::output::OutputStats;
+use crate::decker::terminal::{Pane, PanePipes, PaneRequest};
+use crate::decker::config::PaneDefinition;
+use crate::decker::session::Session;
+use crossbeam_channel::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use log::{info, warn, error};
+use anyhow::{anyhow, bail};
+
+// How long a `decker run` pane stays open after its command finishes, and how often to check
+const DEFAULT_LINGER: &str = "10s";
+const RUN_POLL: Duration = Duration::from_millis(500);
+// How long the panes get to answer an export or open a pane, which waits for the next frame
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl ControlServer {
+    /***
+    Listen for control requests at `path`, replacing any stale socket left by a previous run.
+     */
+    pub fn bind(path: &Path, mcp: MasterControl, pane_definitions: Vec<PaneDefinition>, pipes: Arc<Mutex<PanePipes>>, output_stats: Arc<OutputStats>,
+                pane_requests: Sender<PaneRequest>) -> anyhow::Result<ControlServer> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("Another decker is already listening on {}", path.display());
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        info!("control: Listening on {}", path.display());
+
+        let panes = pane_definitions.iter().map(|p| PaneStatus {
+            task_id: p.task_id.clone(), title: p.title.clone(), x: p.x, y: p.y, width: p.width, height: p.height, main: p.is_main()
+        }).collect();
+        Ok(ControlServer { listener, mcp, panes, pane_definitions, pipes, output_stats, pane_requests, runs: 0 })
+    }
+
+    /***
+    Serve requests, one connection at a time. Doesn't return.
+     */
+    pub fn run(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = self.handle(stream) {
+                        warn!("control: Failed to handle request: {}", e);
+                    }
+                }
+                Err(e) => { error!("control: Failed to accept connection: {}", e); }
+            }
+        }
+    }
+
+    fn handle(&mut self, stream: UnixStream) -> anyhow::Result<()> {
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+
+        let (command, data) = match request.split_once(':') {
+            None => { (request.trim(), "") }
+            Some((command, data)) => { (command.trim(), data.trim()) }
+        };
+        info!("control: Received {}: {}", command, data);
+
+        let response = match self.dispatch(command, data) {
+            Ok(resp) => { resp }
+            Err(e) => { format!("Error - {}", e) }
+        };
+
+        let mut stream = stream;
+        stream.write_all(response.as_bytes())?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn dispatch(&mut self, command: &str, data: &str) -> anyhow::Result<String> {
+        match command {
+            "status" => {
+                let status = Status { tasks: self.mcp.status()?, panes: self.panes.clone(), output: self.output_stats.snapshot() };
+                Ok(serde_json::to_string(&status)?)
+            }
+            "pipe-pane" => {
+                let request: PipePane = serde_json::from_str(data)?;
+                if !self.panes.iter().any(|p| p.task_id == request.pane) {
+                    bail!("No pane named '{}'", request.pane);
+                }
+
+                let mut pipes = self.pipes.lock().map_err(|_| anyhow!("Pane pipes are unavailable"))?;
+                match request.target {
+                    Some(target) => { pipes.open(&request.pane, &target)?; }
+                    None => {
+                        if !pipes.close(&request.pane) { bail!("Pane '{}' isn't being piped", request.pane) }
+                    }
+                }
+                Ok(String::from("Success"))
+            }
+            "enable" | "disable" => {
+                self.mcp.set_enabled(data, command == "enable")?;
+                Ok(String::from("Success"))
+            }
+            "cancel" => {
+                self.mcp.cancel(data)?;
+                Ok(String::from("Success"))
+            }
+            "repaint" => {
+                self.pane_requests.send(PaneRequest::Repaint)?;
+                Ok(String::from("Success"))
+            }
+            "export-html" => {
+                let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
+                self.pane_requests.send(PaneRequest::ExportHtml(data.to_string(), reply_tx))?;
+                match reply_rx.recv_timeout(EXPORT_TIMEOUT).map_err(|_| anyhow!("The panes didn't answer"))? {
+                    None => { bail!("No pane named '{}'", data) }
+                    Some(html) => { Ok(html) }
+                }
+            }
+            "session" => {
+                let session = Session::capture(&self.mcp, &self.pane_definitions, &self.pane_requests)?;
+                Ok(serde_json::to_string(&session)?)
+            }
+            "attach" => {
+                if !self.mcp.status()?.iter().any(|t| t.id == data) {
+                    bail!("No task '{}'", data);
+                }
+                let main = self.panes.iter().find(|p| p.main).map(|p| (p.width, p.height));
+                self.mcp.attach(&data.to_string(), main)?;
+                Ok(String::from("Success"))
+            }
+            "run" => {
+                let request: RunTask = serde_json::from_str(data)?;
+                self.run_ad_hoc(request)
+            }
+            "group" => {
+                let request: GroupCommand = serde_json::from_str(data)?;
+                match request.action {
+                    GroupAction::Run => { self.mcp.run_group(&request.group)? }
+                    GroupAction::Pause => { self.mcp.pause_group(&request.group)? }
+                    GroupAction::Resume => { self.mcp.resume_group(&request.group)? }
+                    GroupAction::Kill => { self.mcp.kill_group(&request.group)? }
+                }
+                Ok(String::from("Success"))
+            }
+            _ => { bail!("Unsupported command: {}", command) }
+        }
+    }
+
+    /***
+    Register and start a one-off task, returning its id. Its own pane closes a while after it
+    finishes; an existing pane it borrowed is left be.
+     */
+    fn run_ad_hoc(&mut self, request: RunTask) -> anyhow::Result<String> {
+        let linger = Duration::from_secs(period_to_secs(request.linger.as_deref().unwrap_or(DEFAULT_LINGER))?);
+        self.runs += 1;
+        let task_id = request.id.unwrap_or_else(|| format!("run-{}", self.runs));
+        if self.mcp.status()?.iter().any(|t| t.id == task_id) {
+            bail!("There's already a task '{}'", task_id);
+        }
+        let command = request.argv.join(" ");
+        let task: Task = serde_json::from_value(serde_json::json!({
+            "id": task_id, "name": command, "command": command, "argv": request.argv, "path": request.path
+        }))?;
+
+        match &request.pane {
+            Some(pane) => {
+                if !self.panes.iter().any(|p| &p.task_id == pane) {
+                    bail!("No pane named '{}'", pane);
+                }
+                self.mcp.register_in(task, pane)?;
+            }
+            None => {
+                let pane = Pane::scratch(&task_id);
+                let size = Some((pane.width(), pane.height()));
+                // Output sent before the pane is up would be dropped, so wait for it
+                let (opened_tx, opened_rx) = crossbeam_channel::bounded(1);
+                self.pane_requests.send(PaneRequest::Open(task_id.clone(), Box::new(pane), opened_tx))?;
+                opened_rx.recv_timeout(EXPORT_TIMEOUT).map_err(|_| anyhow!("The panes didn't open one for '{}'", task_id))?;
+                self.mcp.register(task, size)?;
+                self.close_when_done(&task_id, linger);
+            }
+        }
+
+        self.mcp.execute(&task_id)?;
+        Ok(task_id)
+    }
+
+    /***
+    Close a task's pane `linger` after its run is over, and forget the task so its id can be
+    used again
+     */
+    fn close_when_done(&self, task_id: &TaskId, linger: Duration) {
+        let (mut mcp, requests, task_id) = (self.mcp.clone(), self.pane_requests.clone(), task_id.clone());
+        thread::spawn(move || {
+            loop {
+                thread::sleep(RUN_POLL);
+                let busy = mcp.status().map(|tasks| tasks.iter().any(|t| t.id == task_id && (t.running || t.queued)));
+                if let Ok(false) = busy {
+                    break;
+                }
+            }
+            thread::sleep(linger);
+            if let Err(e) = requests.send(PaneRequest::Close(task_id.clone())) {
+                warn!("{}: Could not close its pane: {}", task_id, e);
+            }
+            if let Err(e) = mcp.unregister(&task_id) {
+                warn!("{}: Could not unregister it: {}", task_id, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::control::request;
+    use crossbeam_channel::unbounded;
+    use std::thread;
+
+    #[test]
+    fn it_serves_status_over_the_socket() {
+        let (cmd_tx, cmd_rx) = unbounded::<String>();
+        let (resp_tx, resp_rx) = unbounded();
+
+        // Stand in for the orchestrator
+        thread::spawn(move || {
+            while let Ok(cmd) = cmd_rx.recv() {
+                if cmd.starts_with("status") { resp_tx.send(String::from("status: []")).unwrap(); }
+                if cmd.starts_with("kill_group") { resp_tx.send(String::from("kill_group: Error - no tasks in group 'nope'")).unwrap(); }
+            }
+        });
+
+        let path = std::env::temp_dir().join(format!("decker-test-{}.sock", uuid::Uuid::new_v4()));
+        let pane = serde_json::from_str(r#"{"task_id": "main", "x": 1, "y": 1, "width": 80, "height": 24}"#).unwrap();
+        let mut server = ControlServer::bind(&path, MasterControl::new(cmd_tx, resp_rx), vec![pane], Arc::new(Mutex::new(PanePipes::new())), Arc::new(OutputStats::default()), unbounded().0).unwrap();
+        thread::spawn(move || server.run());
+
+        let status: Status = serde_json::from_str(&request(&path, "status", "").unwrap()).unwrap();
+        assert!(status.tasks.is_empty());
+        assert_eq!(status.panes[0].task_id, "main");
+
+        let err = request(&path, "group", r#"{"group": "nope", "action": "kill"}"#).err().unwrap();
+        assert!(err.to_string().contains("no tasks in group 'nope'"), "{}", err);
+
+        assert!(request(&path, "bogus", "").is_err());
+        assert!(request(&path, "pipe-pane", r#"{"pane": "nope", "target": null}"#).is_err());
+        let err = request(&path, "run", r#"{"argv": ["ls"], "path": ".", "id": null, "pane": "nope", "linger": null}"#).err().unwrap();
+        assert!(err.to_string().contains("No pane named 'nope'"), "{}", err);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+/***
+* Control socket
+* Lets other processes (e.g. `decker status`) query and command a running dashboard.
+* A request is a single line in the same "<command>: <data>" format MasterControl speaks.
+* The response is the payload on success, or "Error - <reason>".
+***/
+mod control_server;
+mod control_client;
+
+pub use control_client::request;
+
+use crate::decker::{MasterControl, TaskStatus};
+use crate::decker::output::{OutputStats, OutputStatsSnapshot};
+use crate::decker::config::PaneDefinition;
+use crate::decker::terminal::{PanePipes, PaneRequest, PipeTarget};
+use crossbeam_channel::Sender;
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+
+pub struct ControlServer {
+    listener: UnixListener,
+    mcp: MasterControl,
+    panes: Vec<PaneStatus>,
+    // The panes as configured, for saving sessions
+    pane_definitions: Vec<PaneDefinition>,
+    pipes: Arc<Mutex<PanePipes>>,
+    output_stats: Arc<OutputStats>,
+    // Opens and closes panes for `decker run`, and how many it's run so far
+    pane_requests: Sender<PaneRequest>,
+    runs: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PaneStatus {
+    pub task_id: String,
+    pub title: Option<String>,
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    pub main: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PipePane {
+    pub pane: String,
+    // None stops piping
+    pub target: Option<PipeTarget>,
+}
+
+/***
+A one-off command to run on the dashboard. It gets a pane of its own unless `pane` names one.
+ */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunTask {
+    // The command and its arguments, as they were given
+    pub argv: Vec<String>,
+    // Where to run it
+    pub path: String,
+    // Defaults to "run-1", "run-2" and so on
+    pub id: Option<String>,
+    pub pane: Option<String>,
+    // How long its own pane stays open after it finishes, e.g. "30s"
+    pub linger: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupAction {
+    Run,
+    Pause,
+    Resume,
+    Kill,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GroupCommand {
+    pub group: String,
+    pub action: GroupAction,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Status {
+    pub tasks: Vec<TaskStatus>,
+    pub panes: Vec<PaneStatus>,
+    #[serde(default)]
+    pub output: OutputStatsSnapshot,
+}
+
+// TODO: Named sessions (`decker attach -s work`), each with its own orchestrator, panes and config,
+//       wait on a daemon mode: one server process that runs them all and that clients attach to.
+//       Until then each dashboard is its own process, reached through its own --socket.
+/***
+Where the control socket lives unless told otherwise
+ */
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => { PathBuf::from(dir).join("decker.sock") }
+        _ => {
+            let user = std::env::var("USER").unwrap_or_else(|_| String::from("default"));
+            std::env::temp_dir().join(format!("decker-{}.sock", user))
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use crate::decker::display::DisplayFilter;
+use crate::decker::display::json::pretty_json;
+use crate::decker::display::markdown::render_markdown;
+
+impl DisplayFilter {
+    /***
+    Reformat one run's output for its pane. Anything the filter doesn't understand is left alone.
+     */
+    pub fn apply(&self, output: &[u8]) -> Vec<u8> {
+        let text = match std::str::from_utf8(output) {
+            Ok(text) => { text }
+            Err(_) => { return output.to_vec() }
+        };
+
+        match self {
+            DisplayFilter::Json => {
+                // A single document, or failing that one per line, as APIs that stream do
+                if let Some(pretty) = pretty_json(text) {
+                    return pretty.into_bytes();
+                }
+                text.split_inclusive('\n').
+                    map(|line| match pretty_json(line) {
+                        Some(pretty) => { pretty }
+                        None => { line.to_string() }
+                    }).
+                    collect::<String>().
+                    into_bytes()
+            }
+            DisplayFilter::Markdown => { render_markdown(text).into_bytes() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_pretty_prints_json_a_line_at_a_time() {
+        let shown = DisplayFilter::Json.apply(b"fetching...\n{\"up\":1}\n");
+        assert_eq!(String::from_utf8(shown).unwrap(), "fetching...\n{\n  \x1b[1;34m\"up\"\x1b[0m: \x1b[36m1\x1b[0m\n}\n");
+        assert_eq!(DisplayFilter::Json.apply(b"\xff{}"), b"\xff{}".to_vec());
+    }
+}
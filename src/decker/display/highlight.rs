@@ -0,0 +1,143 @@
+use crate::decker::display::ChangeHighlighter;
+use crate::decker::TaskId;
+use anyhow::bail;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    static ref SGR_REGEX: Regex = Regex::new("\x1b\\[[0-9;:]*m").unwrap();
+}
+
+impl Default for ChangeHighlighter {
+    fn default() -> Self {
+        // Black on yellow, for five seconds
+        ChangeHighlighter { style: String::from("30;43"), fade_after: Duration::from_secs(5), previous: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl ChangeHighlighter {
+    /***
+    Highlight changes with the SGR parameters in `style`, e.g. "30;43" for black on yellow, for
+    `fade_after` after each run
+     */
+    pub fn new(style: &str, fade_after: Duration) -> anyhow::Result<ChangeHighlighter> {
+        if style.is_empty() || !style.chars().all(|c| c.is_ascii_digit() || c == ';' || c == ':') {
+            bail!("'{}' is not a highlight style - expected SGR parameters, e.g. \"30;43\"", style);
+        }
+        Ok(ChangeHighlighter { style: style.to_string(), fade_after, previous: Mutex::new(HashMap::new()) })
+    }
+
+    /***
+    Remember what a task's run is drawing, and mark where it differs from its last run. None
+    for a first run, or one that's just the same.
+     */
+    pub fn highlight(&self, task_id: &TaskId, shown: &[u8]) -> Option<Vec<u8>> {
+        let previous = self.previous.lock().ok()?.insert(task_id.clone(), shown.to_vec())?;
+        if previous == shown {
+            return None;
+        }
+        match (std::str::from_utf8(&previous), std::str::from_utf8(shown)) {
+            (Ok(previous), Ok(shown)) => { Some(highlight_changes(previous, shown, &self.style).into_bytes()) }
+            _ => { None }
+        }
+    }
+
+    /***
+    Whether `shown` is still what the task's latest run drew, so its highlights should fade
+     */
+    pub fn is_latest(&self, task_id: &TaskId, shown: &[u8]) -> bool {
+        self.previous.lock().map(|previous| previous.get(task_id).map(|p| p.as_slice()) == Some(shown)).unwrap_or(false)
+    }
+
+    pub fn fade_after(&self) -> Duration {
+        self.fade_after
+    }
+}
+
+/***
+Draw `current` with whatever's changed since `previous` in the `sgr` style, line by line. Within
+plain lines only the words that changed are highlighted; lines with styles of their own are
+highlighted whole.
+ */
+pub fn highlight_changes(previous: &str, current: &str, sgr: &str) -> String {
+    let before = previous.split('\n').collect::<Vec<_>>();
+    current.split('\n').enumerate().
+        map(|(n, line)| match before.get(n) {
+            Some(old) if *old == line => { line.to_string() }
+            old => { highlight_line(old.copied().unwrap_or(""), line, sgr) }
+        }).
+        collect::<Vec<_>>().
+        join("\n")
+}
+
+fn highlight_line(old: &str, line: &str, sgr: &str) -> String {
+    let on = format!("\x1b[{}m", sgr);
+    if line.contains('\x1b') {
+        // Put the highlight back after each of the line's own style changes
+        let styled = SGR_REGEX.replace_all(line, |c: &regex::Captures| format!("{}{}", &c[0], on));
+        return format!("{}{}\x1b[0m", on, styled);
+    }
+
+    let old = SGR_REGEX.replace_all(old, "").chars().collect::<Vec<_>>();
+    let chars = line.chars().collect::<Vec<_>>();
+    let mut changed = chars.iter().enumerate().map(|(n, c)| old.get(n) != Some(c)).collect::<Vec<_>>();
+    // Grow each change out to the whole word it's in, so 42% -> 45% lights up "45%", not "5"
+    let mut start = 0;
+    while start < chars.len() {
+        let end = (start..chars.len()).find(|&n| chars[n].is_whitespace()).unwrap_or(chars.len());
+        if changed[start..end].iter().any(|&c| c) {
+            changed[start..end].iter_mut().for_each(|c| *c = true);
+        }
+        start = end + 1;
+    }
+
+    let mut out = String::with_capacity(line.len());
+    let mut lit = false;
+    for (c, changed) in chars.into_iter().zip(changed) {
+        if changed != lit {
+            out.push_str(if changed { &on } else { "\x1b[0m" });
+            lit = changed;
+        }
+        out.push(c);
+    }
+    if lit {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::display::ChangeHighlighter;
+    use crate::decker::display::highlight::highlight_changes;
+    use crate::decker::TaskId;
+    use std::time::Duration;
+
+    #[test]
+    fn it_highlights_the_words_that_changed() {
+        let shown = highlight_changes("cpu 42%\nmem 3.1G\n", "cpu 45%\nmem 3.1G\nswap 0\n", "7");
+        assert_eq!(shown, "cpu \x1b[7m45%\x1b[0m\nmem 3.1G\n\x1b[7mswap 0\x1b[0m\n");
+    }
+
+    #[test]
+    fn it_highlights_styled_lines_whole() {
+        let shown = highlight_changes("\x1b[32mok\x1b[0m", "\x1b[31mdown\x1b[0m", "7");
+        assert_eq!(shown, "\x1b[7m\x1b[31m\x1b[7mdown\x1b[0m\x1b[7m\x1b[0m");
+    }
+
+    #[test]
+    fn it_only_highlights_runs_that_changed_something() {
+        let highlighter = ChangeHighlighter::new("30;43", Duration::from_secs(5)).unwrap();
+        let task_id = TaskId::from("load");
+        assert_eq!(highlighter.highlight(&task_id, b"0.5"), None);
+        assert_eq!(highlighter.highlight(&task_id, b"0.5"), None);
+        assert_eq!(highlighter.highlight(&task_id, b"0.7").unwrap(), b"\x1b[30;43m0.7\x1b[0m");
+        assert!(highlighter.is_latest(&task_id, b"0.7"));
+        assert!(!highlighter.is_latest(&task_id, b"0.5"));
+
+        assert!(ChangeHighlighter::new("1;\x1b[2J", Duration::from_secs(5)).is_err());
+    }
+}
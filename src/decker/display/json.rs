@@ -0,0 +1,118 @@
+use serde::de::IgnoredAny;
+
+const INDENT: &str = "  ";
+const KEY: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const LITERAL: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/***
+`text` indented and colored, if it's a JSON object or array. Keys keep the order they came in.
+ */
+pub fn pretty_json(text: &str) -> Option<String> {
+    let text = text.trim();
+    if !(text.starts_with('{') || text.starts_with('[')) || serde_json::from_str::<IgnoredAny>(text).is_err() {
+        return None;
+    }
+
+    let mut out = String::with_capacity(text.len() * 2);
+    let mut depth = 0;
+    let mut chars = text.chars().peekable();
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.push_str(&INDENT.repeat(depth));
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '[' => {
+                out.push(c);
+                skip_whitespace(&mut chars);
+                if let Some('}' | ']') = chars.peek() {
+                    out.push(chars.next().unwrap_or(c)); // nothing inside - keep it on one line
+                } else {
+                    depth += 1;
+                    newline(&mut out, depth);
+                }
+            }
+            '}' | ']' => {
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(c);
+                newline(&mut out, depth);
+            }
+            ':' => { out.push_str(": ") }
+            '"' => {
+                let string = read_string(&mut chars);
+                skip_whitespace(&mut chars);
+                let color = if chars.peek() == Some(&':') { KEY } else { STRING };
+                out.push_str(&format!("{}\"{}\"{}", color, string, RESET));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut literal = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || ",:]}".contains(next) { break; }
+                    literal.push(next);
+                    chars.next();
+                }
+                let color = if c == '-' || c.is_ascii_digit() { NUMBER } else { LITERAL };
+                out.push_str(&format!("{}{}{}", color, literal, RESET));
+            }
+        }
+    }
+    out.push('\n');
+    Some(out)
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+        chars.next();
+    }
+}
+
+/***
+The rest of a string whose opening quote has been read, escapes and all, without the closing quote
+ */
+fn read_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => { break }
+            '\\' => {
+                string.push(c);
+                string.extend(chars.next());
+            }
+            c => { string.push(c) }
+        }
+    }
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> String {
+        regex::Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(text, "").to_string()
+    }
+
+    #[test]
+    fn it_pretty_prints_json() {
+        let pretty = pretty_json(r#"{"status":"ok","up":true,"load":[0.5, -1e3],"tags":{}, "note":"a \"quoted\" , word"}"#).unwrap();
+        assert_eq!(plain(&pretty), "{\n  \"status\": \"ok\",\n  \"up\": true,\n  \"load\": [\n    0.5,\n    -1e3\n  ],\n  \"tags\": {},\n  \"note\": \"a \\\"quoted\\\" , word\"\n}\n");
+        assert!(pretty.contains("\x1b[1;34m\"status\"\x1b[0m: \x1b[32m\"ok\"\x1b[0m"));
+        assert!(pretty.contains("\x1b[35mtrue\x1b[0m"));
+    }
+
+    #[test]
+    fn it_leaves_everything_else_alone() {
+        assert_eq!(pretty_json("Sunny, 21°C"), None);
+        assert_eq!(pretty_json("{\"unfinished\": "), None);
+        assert_eq!(pretty_json("42"), None);
+    }
+}
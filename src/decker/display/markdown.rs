@@ -0,0 +1,92 @@
+use crate::decker::terminal::{Color, PrintStyle};
+
+/***
+`text` with a little Markdown drawn as styles: # headers in bold (underlined at the top level),
+- and * list items as bullets (or boxes, for [ ] and [x] to-dos), **bold** and `code`.
+ */
+pub fn render_markdown(text: &str) -> String {
+    text.split_inclusive('\n').map(render_line).collect()
+}
+
+fn render_line(line: &str) -> String {
+    let body = line.trim_end_matches(['\r', '\n']);
+    let ending = &line[body.len()..];
+    let trimmed = body.trim_start();
+    let indent = &body[..body.len() - trimmed.len()];
+
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+        let header = PrintStyle { bold: true, underline: level == 1, ..Default::default() };
+        return format!("{}{}{}", indent, styled(trimmed[level..].trim(), header, PrintStyle::default()), ending);
+    }
+
+    let (bullet, item) = match trimmed.strip_prefix("- ").or_else(|| trimmed.strip_prefix("* ")) {
+        None => { ("", trimmed) }
+        Some(item) => {
+            if let Some(todo) = item.strip_prefix("[ ] ") {
+                ("☐ ", todo)
+            } else if let Some(done) = item.strip_prefix("[x] ").or_else(|| item.strip_prefix("[X] ")) {
+                ("☑ ", done)
+            } else {
+                ("• ", item)
+            }
+        }
+    };
+    format!("{}{}{}{}", indent, bullet, inline(item, PrintStyle::default()), ending)
+}
+
+/***
+Text with **bold** and `code` spans, drawn over the `base` style. Markers without a partner on
+the same line are left as they are.
+ */
+fn inline(text: &str, base: PrintStyle) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some((span, after)) = rest.strip_prefix("**").and_then(|r| r.split_once("**")) {
+            out.push_str(&styled(span, PrintStyle { bold: true, ..base }, base));
+            rest = after;
+        } else if let Some((span, after)) = rest.strip_prefix('`').and_then(|r| r.split_once('`')) {
+            // Code is shown as written, ** and all
+            let code = PrintStyle { foreground: Color::Cyan, ..base };
+            out.push_str(&format!("{}{}\x1b[0m{}", code.to_str(), span, restore(base)));
+            rest = after;
+        } else {
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+fn styled(text: &str, style: PrintStyle, base: PrintStyle) -> String {
+    format!("{}{}\x1b[0m{}", style.to_str(), inline(text, style), restore(base))
+}
+
+/***
+What gets back to `base` after a reset
+ */
+fn restore(base: PrintStyle) -> String {
+    if base == PrintStyle::default() { String::new() } else { base.to_str() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> String {
+        regex::Regex::new("\x1b\\[[0-9;]*m").unwrap().replace_all(text, "").to_string()
+    }
+
+    #[test]
+    fn it_renders_a_little_markdown() {
+        let notes = "# TODO\n- [ ] call **the** `vet`\n  * [x] done\n- plain **unpaired\nnot#a header\n";
+        let rendered = render_markdown(notes);
+        assert_eq!(plain(&rendered), "TODO\n☐ call the vet\n  ☑ done\n• plain **unpaired\nnot#a header\n");
+
+        let bold = PrintStyle { bold: true, ..Default::default() };
+        assert!(rendered.starts_with(&format!("{}TODO\x1b[0m\n", PrintStyle { underline: true, ..bold }.to_str())));
+        assert!(rendered.contains(&format!("{}the\x1b[0m", bold.to_str())));
+        assert!(rendered.contains(&format!("{}vet\x1b[0m", PrintStyle { foreground: Color::Cyan, ..Default::default() }.to_str())));
+    }
+}
@@ -0,0 +1,84 @@
+/***
+* Display filters
+* Ways of reformatting a widget task's output before it reaches its pane, so tasks can print
+* something machine-readable and still look good on the dashboard, of tidying up what other
+* tools print, and of marking what changed since a task last ran.
+***/
+mod filter;
+mod highlight;
+mod json;
+mod markdown;
+mod rewrite;
+mod strip;
+
+use crate::decker::TaskId;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayFilter {
+    // Pretty-print and color JSON. Output that isn't JSON is shown as it is.
+    Json,
+    // Draw a little Markdown - headers, lists, bold and code - with styles
+    Markdown,
+}
+
+/***
+A regex replacement run over a task's output before it's drawn, to strip timestamps, redact
+tokens or drop noisy lines
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OutputFilter {
+    pub pattern: String,
+    // What matches become. $1, ${name} and so on are the pattern's groups. Empty drops them.
+    #[serde(default)]
+    pub replace: String,
+}
+
+/***
+A task's filters, compiled
+ */
+#[derive(Clone, Debug, Default)]
+pub struct OutputFilters {
+    filters: Vec<(Regex, String)>,
+}
+
+/***
+Strips escape sequences out of output, for tools whose colors and cursor movement fight their pane
+ */
+#[derive(Clone, Debug, Default)]
+pub struct AnsiStripper {
+    state: StripState,
+}
+
+/***
+How far into an escape sequence the stripper is
+ */
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+enum StripState {
+    #[default]
+    Text,
+    // Just after an ESC
+    Escape,
+    // In a CSI, e.g. ESC [ 1 ; 3 1 m
+    Csi,
+    // In an OSC, DCS or the like, and just after an ESC inside one
+    String,
+    StringEscape,
+}
+
+/***
+Marks what changed between runs of the tasks that ask for it, and for how long
+ */
+pub struct ChangeHighlighter {
+    // SGR parameters, e.g. "30;43"
+    style: String,
+    fade_after: Duration,
+    // What each task's last run drew, before highlighting
+    previous: Mutex<HashMap<TaskId, Vec<u8>>>,
+}
@@ -0,0 +1,57 @@
+use crate::decker::display::{OutputFilter, OutputFilters};
+use regex::bytes::Regex;
+
+impl OutputFilters {
+    /***
+    Compile a task's filters, to run in the order they're given
+     */
+    pub fn new(filters: &[OutputFilter]) -> anyhow::Result<OutputFilters> {
+        let filters = filters.iter().
+            map(|f| Ok((Regex::new(&f.pattern)?, f.replace.clone()))).
+            collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(OutputFilters { filters })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /***
+    Run every filter over `output` in turn. Output that no filter matches comes back as it is.
+     */
+    pub fn apply(&self, output: &[u8]) -> Vec<u8> {
+        let mut output = output.to_vec();
+        for (pattern, replace) in &self.filters {
+            if let std::borrow::Cow::Owned(replaced) = pattern.replace_all(&output, replace.as_bytes()) {
+                output = replaced;
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::display::{OutputFilter, OutputFilters};
+
+    fn filter(pattern: &str, replace: &str) -> OutputFilter {
+        OutputFilter { pattern: pattern.to_string(), replace: replace.to_string() }
+    }
+
+    #[test]
+    fn it_rewrites_output_filter_by_filter() {
+        let filters = OutputFilters::new(&[
+            filter(r"^\d{4}-\d\d-\d\dT[\d:.]+Z ", ""),
+            filter(r"(?m)^DEBUG .*\n", ""),
+            filter(r"token=(\w{4})\w+", "token=$1…"),
+        ]).unwrap();
+        let shown = filters.apply(b"2024-01-02T03:04:05.678Z login ok\nDEBUG retrying\ntoken=abcd1234efgh\n");
+        assert_eq!(String::from_utf8_lossy(&shown), "login ok\ntoken=abcd…\n");
+    }
+
+    #[test]
+    fn it_refuses_bad_patterns() {
+        assert!(OutputFilters::new(&[filter("(unclosed", "")]).is_err());
+        assert!(OutputFilters::new(&[]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+use crate::decker::display::{AnsiStripper, StripState};
+
+impl AnsiStripper {
+    /***
+    Drop every escape sequence and control character from the next piece of output, leaving the
+    text, newlines, carriage returns, tabs and backspaces. A sequence cut off at the end is
+    remembered, and the rest of it dropped from the next piece.
+     */
+    pub fn feed(&mut self, output: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity(output.len());
+        for &b in output {
+            self.state = match (self.state, b) {
+                (StripState::Text, 0x1b) => { StripState::Escape }
+                (StripState::Text, b'\n' | b'\r' | b'\t' | 0x08) => {
+                    text.push(b);
+                    StripState::Text
+                }
+                (StripState::Text, 0..=0x1f | 0x7f) => { StripState::Text }
+                (StripState::Text, _) => {
+                    text.push(b);
+                    StripState::Text
+                }
+                (StripState::Escape, b'[') => { StripState::Csi }
+                (StripState::Escape, b']' | b'P' | b'X' | b'^' | b'_') => { StripState::String }
+                (StripState::Escape, 0x20..=0x2f) => { StripState::Escape } // e.g. the ( of ESC ( B
+                (StripState::Escape, _) => { StripState::Text }
+                (StripState::Csi, 0x40..=0x7e) => { StripState::Text }
+                (StripState::Csi, _) => { StripState::Csi }
+                // Strings end with BEL, or ESC \
+                (StripState::String, 0x07) => { StripState::Text }
+                (StripState::String, 0x1b) => { StripState::StringEscape }
+                (StripState::String, _) => { StripState::String }
+                (StripState::StringEscape, b'\\') => { StripState::Text }
+                (StripState::StringEscape, _) => { StripState::String }
+            };
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::display::AnsiStripper;
+
+    #[test]
+    fn it_leaves_only_the_text() {
+        let mut stripper = AnsiStripper::default();
+        let text = stripper.feed(b"\x1b[1;31mFAIL\x1b[0m\tdisk\x1b]8;;http://x\x1b\\ link\x1b]8;;\x07\x1b(B\x07\r\n");
+        assert_eq!(text, b"FAIL\tdisk link\r\n");
+    }
+
+    #[test]
+    fn it_drops_sequences_split_between_pieces() {
+        let mut stripper = AnsiStripper::default();
+        assert_eq!(stripper.feed(b"up \x1b[3"), b"up ");
+        assert_eq!(stripper.feed(b"2m12d\x1b]0;ti"), b"12d");
+        assert_eq!(stripper.feed(b"tle\x07!"), b"!");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::decker::keys::{KeyAction, KeyBindings, Keystroke};
+use crate::decker::config::KeysConfig;
+use std::collections::HashMap;
+use anyhow::anyhow;
+
+/***
+Turn a key name into the byte the terminal sends for it: "r" => b'r', "C-b" => 0x02
+ */
+pub fn parse_key(key: &str) -> anyhow::Result<u8> {
+    let ctrl = key.strip_prefix("C-");
+    let mut chars = ctrl.unwrap_or(key).chars();
+    match (chars.next(), chars.next(), ctrl.is_some()) {
+        (Some(c), None, false) if c.is_ascii_graphic() => { Ok(c as u8) }
+        (Some(c), None, true) if c.is_ascii_alphabetic() => { Ok(c.to_ascii_lowercase() as u8 & 0x1f) }
+        _ if key == "Space" => { Ok(b' ') }
+        _ => { Err(anyhow!("'{}' is not a key decker can bind - use a character like \"r\" or a control key like \"C-b\"", key)) }
+    }
+}
+
+impl KeyBindings {
+    pub fn new(config: &KeysConfig) -> anyhow::Result<KeyBindings> {
+        let prefix = parse_key(&config.prefix)?;
+        let mut bindings = HashMap::new();
+        for (key, action) in [(&config.refresh, KeyAction::Refresh), (&config.next_pane, KeyAction::NextPane),
+                              (&config.last_pane, KeyAction::LastPane), (&config.next_layout, KeyAction::NextLayout),
+                              (&config.scroll_up, KeyAction::ScrollUp), (&config.scroll_down, KeyAction::ScrollDown),
+                              (&config.pan_left, KeyAction::PanLeft), (&config.pan_right, KeyAction::PanRight),
+                              (&config.local_echo, KeyAction::ToggleEcho), (&config.prompt, KeyAction::Prompt),
+                              (&config.paste, KeyAction::Paste), (&config.repaint, KeyAction::Repaint)] {
+            let byte = parse_key(key)?;
+            if byte == prefix || bindings.insert(byte, action).is_some() {
+                return Err(anyhow!("'{}' is bound to more than one key command", key));
+            }
+        }
+
+        Ok(KeyBindings { prefix, bindings, prefixed: false })
+    }
+
+    /***
+    Sort a chunk of input into bound commands and bytes for the active process. A prefix at the
+    end of a chunk carries over to the next one.
+     */
+    pub fn feed(&mut self, input: &[u8]) -> Vec<Keystroke> {
+        let mut strokes = Vec::new();
+        let mut forward = Vec::new();
+
+        for &byte in input {
+            if self.prefixed {
+                self.prefixed = false;
+                if byte == self.prefix {
+                    // Pressing the prefix twice sends it through
+                    forward.push(byte);
+                } else if let Some(action) = self.bindings.get(&byte) {
+                    if !forward.is_empty() {
+                        strokes.push(Keystroke::Forward(std::mem::take(&mut forward)));
+                    }
+                    strokes.push(Keystroke::Action(*action));
+                }
+                // Anything else after the prefix is swallowed
+            } else if byte == self.prefix {
+                self.prefixed = true;
+            } else {
+                forward.push(byte);
+            }
+        }
+
+        if !forward.is_empty() {
+            strokes.push(Keystroke::Forward(forward));
+        }
+        strokes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_key_names() {
+        assert_eq!(parse_key("r").unwrap(), b'r');
+        assert_eq!(parse_key("C-b").unwrap(), 2);
+        assert_eq!(parse_key("C-A").unwrap(), 1);
+        assert!(parse_key("C-").is_err());
+        assert!(parse_key("rr").is_err());
+        assert!(parse_key(" ").is_err());
+        assert_eq!(parse_key("Space").unwrap(), b' ');
+    }
+
+    #[test]
+    fn it_separates_commands_from_input() {
+        let mut keys = KeyBindings::new(&KeysConfig::default()).unwrap();
+
+        assert_eq!(keys.feed(b"ls"), vec![Keystroke::Forward(b"ls".to_vec())]);
+        assert_eq!(keys.feed(b"a\x02rb"), vec![
+            Keystroke::Forward(b"a".to_vec()),
+            Keystroke::Action(KeyAction::Refresh),
+            Keystroke::Forward(b"b".to_vec()),
+        ]);
+        assert_eq!(keys.feed(b"\x02\x02"), vec![Keystroke::Forward(vec![2])]);
+        assert_eq!(keys.feed(b"\x02z"), vec![]);
+
+        // The prefix and its command can arrive in separate reads
+        assert_eq!(keys.feed(b"\x02"), vec![]);
+        assert_eq!(keys.feed(b"o"), vec![Keystroke::Action(KeyAction::NextPane)]);
+    }
+
+    #[test]
+    fn it_refuses_a_key_bound_twice() {
+        let config = KeysConfig { paste: String::from("r"), ..KeysConfig::default() };
+        assert!(KeyBindings::new(&config).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::decker::keys::KeypadModes;
+use std::sync::atomic::Ordering;
+
+/***
+What a keypad key sends in numeric mode, given the final byte of its application mode sequence
+(ESC O p is keypad 0, and so on)
+ */
+fn numeric_key(app_key: u8) -> Option<u8> {
+    match app_key {
+        b'p'..=b'y' => { Some(b'0' + (app_key - b'p')) }
+        b'n' => { Some(b'.') }
+        b'M' => { Some(b'\r') }
+        b'l' => { Some(b',') }
+        b'm' => { Some(b'-') }
+        b'j' => { Some(b'*') }
+        b'k' => { Some(b'+') }
+        b'o' => { Some(b'/') }
+        b'X' => { Some(b'=') }
+        _ => { None }
+    }
+}
+
+impl KeypadModes {
+    pub fn new() -> KeypadModes {
+        Default::default()
+    }
+
+    pub fn host_app(&self) -> bool {
+        self.host_app.load(Ordering::Relaxed)
+    }
+
+    pub fn set_host_app(&self, app: bool) {
+        self.host_app.store(app, Ordering::Relaxed)
+    }
+
+    pub fn set_main_app(&self, app: bool) {
+        self.main_app.store(app, Ordering::Relaxed)
+    }
+
+    pub fn set_main_paste(&self, bracketed: bool) {
+        self.main_paste.store(bracketed, Ordering::Relaxed)
+    }
+
+    /***
+    What to send the main pane to paste `text`, as a terminal would. Newlines become Enter, and
+    the paste is bracketed if the main pane asked for that - with any end-of-paste marker inside
+    it taken out, so the paste can't break out early.
+     */
+    pub fn paste(&self, text: &str) -> Vec<u8> {
+        let text = text.replace("\r\n", "\r").replace('\n', "\r");
+        if !self.main_paste.load(Ordering::Relaxed) {
+            return text.into_bytes();
+        }
+        format!("\x1b[200~{}\x1b[201~", text.replace("\x1b[201~", "")).into_bytes()
+    }
+
+    /***
+    Rewrite keypad input for the main pane. While the terminal is in application mode on another
+    pane's behalf, keypad keys arrive as ESC O sequences the main pane didn't ask for.
+     */
+    pub fn translate(&self, input: Vec<u8>) -> Vec<u8> {
+        if !self.host_app() || self.main_app.load(Ordering::Relaxed) {
+            return input;
+        }
+
+        let mut out = Vec::with_capacity(input.len());
+        let mut i = 0;
+        while i < input.len() {
+            let numeric = match input.get(i..i + 3) {
+                Some([0x1b, b'O', key]) => { numeric_key(*key) }
+                _ => { None }
+            };
+            match numeric {
+                Some(key) => {
+                    out.push(key);
+                    i += 3;
+                }
+                None => {
+                    out.push(input[i]);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_translates_app_keypad_keys_only_when_main_wants_numbers() {
+        let modes = KeypadModes::new();
+        let keys = b"\x1bOq\x1bOk\x1bOA".to_vec();
+        assert_eq!(modes.translate(keys.clone()), keys);
+
+        modes.set_host_app(true);
+        assert_eq!(modes.translate(keys.clone()), b"1+\x1bOA".to_vec());
+
+        modes.set_main_app(true);
+        assert_eq!(modes.translate(keys.clone()), keys);
+    }
+
+    #[test]
+    fn it_brackets_pastes_only_when_main_asks() {
+        let modes = KeypadModes::new();
+        assert_eq!(modes.paste("ls\npwd"), b"ls\rpwd".to_vec());
+
+        modes.set_main_paste(true);
+        assert_eq!(modes.paste("ls\r\necho hi\x1b[201~\n"), b"\x1b[200~ls\recho hi\r\x1b[201~".to_vec());
+    }
+}
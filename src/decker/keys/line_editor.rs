@@ -0,0 +1,208 @@
+use crate::decker::keys::{Edit, LineEditor};
+
+impl LineEditor {
+    pub fn new() -> LineEditor {
+        Default::default()
+    }
+
+    /***
+    Start over on an empty line. History is kept.
+     */
+    pub fn clear(&mut self) {
+        self.line.clear();
+        self.cursor = 0;
+        self.recalled = None;
+        self.partial.clear();
+    }
+
+    /***
+    Apply typed keys to the line. Left and right (or ^B and ^F) move the cursor, ^A and ^E (or Home
+    and End) jump to either end, backspace and Delete remove characters, ^U and ^K cut everything
+    before or after the cursor, and up and down go through earlier lines.
+     */
+    pub fn feed(&mut self, input: &[u8]) -> Edit {
+        for &byte in input {
+            if !self.partial.is_empty() || byte == 0x1b || byte >= 0x80 {
+                self.partial.push(byte);
+                self.take_partial();
+                continue;
+            }
+
+            match byte {
+                b'\r' | b'\n' => {
+                    let line = self.line.iter().collect::<String>();
+                    if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+                    self.clear();
+                    return Edit::Submit(line);
+                }
+                0x03 | 0x07 => {
+                    self.clear();
+                    return Edit::Cancel;
+                }
+                0x7f | 0x08 => {
+                    if self.cursor > 0 {
+                        self.cursor -= 1;
+                        self.line.remove(self.cursor);
+                    }
+                }
+                0x01 => { self.cursor = 0 }
+                0x05 => { self.cursor = self.line.len() }
+                0x02 => { self.cursor = self.cursor.saturating_sub(1) }
+                0x06 => { self.cursor = (self.cursor + 1).min(self.line.len()) }
+                0x04 => { self.delete() }
+                0x15 => {
+                    self.line.drain(..self.cursor);
+                    self.cursor = 0;
+                }
+                0x0b => { self.line.truncate(self.cursor) }
+                byte if byte.is_ascii_control() => {}
+                byte => { self.insert(byte as char) }
+            }
+        }
+        Edit::Editing
+    }
+
+    /***
+    Act on a finished escape sequence or UTF-8 character. Unfinished ones wait for more input,
+    and ones we don't know are dropped.
+     */
+    fn take_partial(&mut self) {
+        if self.partial[0] != 0x1b {
+            match std::str::from_utf8(&self.partial) {
+                Ok(s) => {
+                    let c = s.chars().next().unwrap_or(' ');
+                    self.insert(c);
+                    self.partial.clear();
+                }
+                Err(e) if e.error_len().is_some() => { self.partial.clear() }
+                Err(_) => {}
+            }
+            return;
+        }
+
+        let seq = std::mem::take(&mut self.partial);
+        match seq.as_slice() {
+            [0x1b] | [0x1b, b'['] | [0x1b, b'O'] => { self.partial = seq; }
+            [0x1b, b'[' | b'O', b'D'] => { self.cursor = self.cursor.saturating_sub(1) }
+            [0x1b, b'[' | b'O', b'C'] => { self.cursor = (self.cursor + 1).min(self.line.len()) }
+            [0x1b, b'[' | b'O', b'H'] | [0x1b, b'[', b'1', b'~'] => { self.cursor = 0 }
+            [0x1b, b'[' | b'O', b'F'] | [0x1b, b'[', b'4', b'~'] => { self.cursor = self.line.len() }
+            [0x1b, b'[' | b'O', b'A'] => { self.recall_older() }
+            [0x1b, b'[' | b'O', b'B'] => { self.recall_newer() }
+            [0x1b, b'[', b'3', b'~'] => { self.delete() }
+            [0x1b, b'[', rest @ ..] if rest.last().map(|b| !(0x40..=0x7e).contains(b)).unwrap_or(false) => {
+                // Parameters - the final byte is still to come
+                self.partial = seq;
+            }
+            _ => {}
+        }
+    }
+
+    fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+        }
+    }
+
+    fn recall_older(&mut self) {
+        let index = match self.recalled {
+            None if self.history.is_empty() => { return }
+            None => { self.history.len() - 1 }
+            Some(index) => { index.saturating_sub(1) }
+        };
+        self.recall(Some(index));
+    }
+
+    fn recall_newer(&mut self) {
+        match self.recalled {
+            None => {}
+            Some(index) if index + 1 >= self.history.len() => { self.recall(None) }
+            Some(index) => { self.recall(Some(index + 1)) }
+        }
+    }
+
+    /***
+    Swap the line for one from history, or an empty one once we're back past the newest
+     */
+    fn recall(&mut self, index: Option<usize>) {
+        self.recalled = index;
+        self.line = index.map(|i| self.history[i].chars().collect()).unwrap_or_default();
+        self.cursor = self.line.len();
+    }
+
+    /***
+    What to send a one-row pane to show the line after `prompt`, with its cursor in place
+     */
+    pub fn render(&self, prompt: &str) -> Vec<u8> {
+        let text = self.line.iter().collect::<String>();
+        let mut out = format!("\r\x1b[K{}{}\r", prompt, text);
+        let column = prompt.chars().count() + self.cursor;
+        if column > 0 {
+            out.push_str(&format!("\x1b[{}C", column));
+        }
+        out.into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(editor: &LineEditor) -> (String, usize) {
+        (editor.line.iter().collect(), editor.cursor)
+    }
+
+    #[test]
+    fn it_edits_the_line() {
+        let mut editor = LineEditor::new();
+        assert_eq!(editor.feed(b"wexther\x1b[D\x1b[D\x1b[D\x1b[D\x1b[D\x1b[3~a"), Edit::Editing);
+        assert_eq!(typed(&editor), (String::from("weather"), 3));
+
+        editor.feed(b"\x01x\x05\x7fy");
+        assert_eq!(typed(&editor), (String::from("xweathey"), 8));
+
+        editor.feed(b"\x1b[H\x1b[C\x0b");
+        assert_eq!(typed(&editor), (String::from("x"), 1));
+        editor.feed(b"abc\x02\x02\x15");
+        assert_eq!(typed(&editor), (String::from("bc"), 0));
+
+        // Sequences and characters can be split across reads
+        editor.feed(b"\x1b[");
+        editor.feed(b"F\xc3");
+        editor.feed(b"\xa9");
+        assert_eq!(typed(&editor), (String::from("bcé"), 3));
+        assert_eq!(editor.feed(b"\x07"), Edit::Cancel);
+        assert_eq!(typed(&editor), (String::new(), 0));
+    }
+
+    #[test]
+    fn it_recalls_earlier_lines() {
+        let mut editor = LineEditor::new();
+        assert_eq!(editor.feed(b"time\r"), Edit::Submit(String::from("time")));
+        editor.feed(b"weather\rnews");
+        editor.clear();
+
+        editor.feed(b"\x1b[A");
+        assert_eq!(typed(&editor).0, "weather");
+        editor.feed(b"\x1b[A\x1b[A");
+        assert_eq!(typed(&editor).0, "time");
+        editor.feed(b"\x1bOB");
+        assert_eq!(typed(&editor).0, "weather");
+        editor.feed(b"\x1b[B");
+        assert_eq!(typed(&editor), (String::new(), 0));
+    }
+
+    #[test]
+    fn it_renders_with_the_cursor_in_place() {
+        let mut editor = LineEditor::new();
+        editor.feed(b"abc\x1b[D");
+        assert_eq!(editor.render(": "), b"\r\x1b[K: abc\r\x1b[4C".to_vec());
+    }
+}
@@ -0,0 +1,27 @@
+use crate::decker::keys::{KeypadModes, MainInput};
+use crate::decker::clipboard::PasteSource;
+use crate::decker::ProcInput;
+use crossbeam_channel::{SendError, Sender};
+use std::sync::Arc;
+use std::time::Instant;
+
+impl MainInput {
+    pub fn new(input_tx: Sender<ProcInput>, keypad: Arc<KeypadModes>, paste_from: PasteSource) -> MainInput {
+        MainInput { input_tx, keypad, paste_from }
+    }
+
+    pub fn paste_from(&self) -> &PasteSource {
+        &self.paste_from
+    }
+
+    /***
+    Send keystrokes typed at `typed_at` on, fixing up keypad keys the main pane isn't expecting
+     */
+    pub fn type_keys(&self, input: Vec<u8>, typed_at: Instant) -> Result<(), SendError<ProcInput>> {
+        self.input_tx.send(ProcInput { input: self.keypad.translate(input), typed_at: Some(typed_at) })
+    }
+
+    pub fn paste(&self, text: &str) -> Result<(), SendError<ProcInput>> {
+        self.input_tx.send(ProcInput { input: self.keypad.paste(text), typed_at: Some(Instant::now()) })
+    }
+}
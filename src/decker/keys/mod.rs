@@ -0,0 +1,109 @@
+/***
+* Key bindings
+* Decker's own commands are reached through a prefix key, a la tmux: press the prefix, then
+* the command's key. Everything else is forwarded to the active process untouched.
+***/
+mod key_bindings;
+mod keypad;
+mod line_editor;
+mod main_input;
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use crossbeam_channel::Sender;
+use crate::decker::clipboard::PasteSource;
+use crate::decker::ProcInput;
+
+pub use key_bindings::parse_key;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeyAction {
+    // Run the focused pane's task right now
+    Refresh,
+    // Move focus to the next pane
+    NextPane,
+    // Move focus back to the pane that had it before
+    LastPane,
+    // Re-tile the panes into the next layout
+    NextLayout,
+    // Scroll the focused pane back through its scrollback, or forward towards its live output
+    ScrollUp,
+    ScrollDown,
+    // Pan the focused pane across lines too wide for it
+    PanLeft,
+    PanRight,
+    // Turn local echo of typing on or off for the focused pane
+    ToggleEcho,
+    // Open decker's prompt, for running a task by its id
+    Prompt,
+    // Paste the system clipboard into the main pane
+    Paste,
+    // Clear the screen and draw everything again
+    Repaint,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Keystroke {
+    // Input for the active process
+    Forward(Vec<u8>),
+    Action(KeyAction),
+}
+
+pub struct KeyBindings {
+    prefix: u8,
+    bindings: HashMap<u8, KeyAction>,
+    // Was the last byte we saw the prefix?
+    prefixed: bool,
+}
+
+/***
+A line being typed at decker's prompt, with readline-style editing
+ */
+#[derive(Default)]
+pub struct LineEditor {
+    line: Vec<char>,
+    // Where in the line the cursor is
+    cursor: usize,
+    // Lines entered before, oldest first, and which one up and down have got to
+    history: Vec<String>,
+    recalled: Option<usize>,
+    // The start of an escape sequence or UTF-8 character split across reads
+    partial: Vec<u8>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Edit {
+    // Still typing
+    Editing,
+    // Enter was pressed on this line
+    Submit(String),
+    // Given up on with ^C or ^G
+    Cancel,
+}
+
+/***
+Numeric keypad modes (DECKPAM/DECKPNM). The real terminal's keypad follows the focused pane, but
+keystrokes always go to the main pane, which may want plain digits instead. Pastes go there too,
+bracketed if it asked for them to be.
+ */
+#[derive(Default)]
+pub struct KeypadModes {
+    // Is the real terminal's keypad in application mode?
+    host_app: AtomicBool,
+    // Does the main pane's process want application mode?
+    main_app: AtomicBool,
+    // Does the main pane's process want pastes bracketed?
+    main_paste: AtomicBool,
+}
+
+/***
+Where typing and pastes for the main pane go
+ */
+#[derive(Clone)]
+pub struct MainInput {
+    input_tx: Sender<ProcInput>,
+    keypad: Arc<KeypadModes>,
+    // Where to read the clipboard from when pasting
+    paste_from: PasteSource,
+}
@@ -0,0 +1,40 @@
+/***
+* Log file handling
+* A Write target for simplelog that creates its parent directories and
+* rotates itself once it grows too large or too old, plus per-task logs of
+* everything each task printed.
+***/
+mod rotating_file;
+mod task_logs;
+
+use crate::decker::TaskId;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+
+    // How much we've written and when we started writing to the current file
+    bytes_written: u64,
+    opened_at: SystemTime,
+
+    // Rotation policy
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+}
+
+/***
+* A rotating log of each task's raw output, in `<dir>/<task id>.log`
+***/
+pub struct TaskLogs {
+    dir: PathBuf,
+    max_bytes: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+    // Opened the first time a task prints something
+    files: HashMap<TaskId, RotatingFile>,
+}
@@ -0,0 +1,144 @@
+use crate::decker::logging::RotatingFile;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+impl RotatingFile {
+    /***
+    Open (or create) the log file at `path`, creating any missing parent directories.
+    @arg max_bytes: Rotate once the file grows past this size
+    @arg max_age: Rotate once the file has been open for this long
+    @arg keep: How many rotated files (path.1, path.2, ...) to retain
+     */
+    pub fn new(path: &Path, max_bytes: Option<u64>, max_age: Option<Duration>, keep: usize) -> anyhow::Result<RotatingFile> {
+        let file = Self::open(path)?;
+        let bytes_written = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            bytes_written,
+            opened_at: SystemTime::now(),
+            max_bytes,
+            max_age,
+            keep,
+        })
+    }
+
+    fn open(path: &Path) -> anyhow::Result<File> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        Ok(OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let too_big = match self.max_bytes {
+            None => { false }
+            Some(max) => { self.bytes_written >= max }
+        };
+
+        let too_old = match self.max_age {
+            None => { false }
+            Some(max) => { self.opened_at.elapsed().map(|age| age >= max).unwrap_or(false) }
+        };
+
+        too_big || too_old
+    }
+
+    fn rotated_path(&self, generation: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", generation));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+
+        if self.keep == 0 {
+            // Nothing to retain - just start over
+            std::fs::remove_file(&self.path)?;
+        } else {
+            // Shuffle path.1 -> path.2, etc. The oldest falls off the end.
+            let _ = std::fs::remove_file(self.rotated_path(self.keep));
+            for generation in (1..self.keep).rev() {
+                let from = self.rotated_path(generation);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(generation + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = Self::open(&self.path).map_err(|e| std::io::Error::other(e.to_string()))?;
+        self.bytes_written = 0;
+        self.opened_at = SystemTime::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log_path() -> PathBuf {
+        std::env::temp_dir().
+            join(format!("decker-test-{}", uuid::Uuid::new_v4())).
+            join("nested").
+            join("decker.log")
+    }
+
+    #[test]
+    fn it_creates_missing_parent_directories() {
+        let path = temp_log_path();
+        let mut log = RotatingFile::new(&path, None, None, 1).unwrap();
+        log.write_all(b"hello").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+    }
+
+    #[test]
+    fn it_rotates_when_the_file_is_too_big() {
+        let path = temp_log_path();
+        let mut log = RotatingFile::new(&path, Some(5), None, 2).unwrap();
+        log.write_all(b"first").unwrap();
+        log.write_all(b"second").unwrap();
+        log.write_all(b"third").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third");
+        assert_eq!(std::fs::read_to_string(log.rotated_path(1)).unwrap(), "second");
+        assert_eq!(std::fs::read_to_string(log.rotated_path(2)).unwrap(), "first");
+    }
+
+    #[test]
+    fn it_only_keeps_the_configured_number_of_files() {
+        let path = temp_log_path();
+        let mut log = RotatingFile::new(&path, Some(1), None, 1).unwrap();
+        log.write_all(b"a").unwrap();
+        log.write_all(b"b").unwrap();
+        log.write_all(b"c").unwrap();
+
+        assert_eq!(std::fs::read_to_string(log.rotated_path(1)).unwrap(), "b");
+        assert!(!log.rotated_path(2).exists());
+    }
+}
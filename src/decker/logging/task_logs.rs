@@ -0,0 +1,61 @@
+use crate::decker::logging::{RotatingFile, TaskLogs};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use log::warn;
+
+impl TaskLogs {
+    pub fn new(dir: &Path, max_bytes: Option<u64>, max_age: Option<Duration>, keep: usize) -> TaskLogs {
+        TaskLogs { dir: dir.to_path_buf(), max_bytes, max_age, keep, files: HashMap::new() }
+    }
+
+    /***
+    Append output to a task's log. A log we can't write to is reported, not fatal - the
+    dashboard matters more than the record of it.
+     */
+    pub fn write(&mut self, task_id: &str, output: &[u8]) {
+        if !self.files.contains_key(task_id) {
+            match RotatingFile::new(&self.path_for(task_id), self.max_bytes, self.max_age, self.keep) {
+                Ok(file) => { self.files.insert(task_id.to_string(), file); }
+                Err(e) => {
+                    warn!("{}: Could not open output log: {}", task_id, e);
+                    return;
+                }
+            }
+        }
+
+        if let Some(file) = self.files.get_mut(task_id) {
+            if let Err(e) = file.write_all(output).and_then(|_| file.flush()) {
+                warn!("{}: Could not write output log: {}", task_id, e);
+            }
+        }
+    }
+
+    // Task ids come from the config, so keep them from wandering out of the directory
+    fn path_for(&self, task_id: &str) -> PathBuf {
+        let name = task_id.chars().
+            map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).
+            collect::<String>();
+        self.dir.join(format!("{}.log", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_keeps_a_log_per_task() {
+        let dir = std::env::temp_dir().join(format!("decker-test-{}", uuid::Uuid::new_v4()));
+        let mut logs = TaskLogs::new(&dir.join("tasks"), None, None, 1);
+
+        logs.write("weather", b"sunny\n");
+        logs.write("../escape", b"nope\n");
+        logs.write("weather", b"rain\n");
+
+        assert_eq!(std::fs::read_to_string(dir.join("tasks/weather.log")).unwrap(), "sunny\nrain\n");
+        assert!(dir.join("tasks/___escape.log").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
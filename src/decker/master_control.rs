@@ -1,4 +1,5 @@
-use crate::decker::{MasterControl, Task, TaskId};
+use crate::decker::{MasterControl, Task, TaskHealth, TaskId};
+use crate::decker::supervisor::Heartbeat;
 use log::{info, warn};
 use std::time::Duration;
 use std::ops::Deref;
@@ -6,13 +7,22 @@ use simple_error::bail;
 use serde::{Serialize, Deserialize};
 use crossbeam_channel::{Sender, Receiver};
 use crate::decker::terminal::Pane;
+use std::sync::{Arc, Mutex, MutexGuard};
 
 pub type PaneSize = Option<(u16, u16)>;
 
+// How long the orchestrator may go without answering before we call it unresponsive
+const BACKEND_DEADLINE: Duration = Duration::from_secs(5);
+// Shutting down waits for tasks' grace periods, so it can take a while to answer
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
 #[derive(Serialize, Deserialize)]
 pub struct RegisterTask {
     pub(crate) task: Task,
-    pub(crate) size: PaneSize
+    pub(crate) size: PaneSize,
+    // Show the task's output in this task's pane instead of its own
+    #[serde(default)]
+    pub(crate) pane: Option<TaskId>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -21,11 +31,44 @@ pub struct ResizeTask {
     pub(crate) size: PaneSize
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TaskStatus {
+    pub id: TaskId,
+    pub name: String,
+    pub command: String,
+    pub period: Option<String>,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub disabled: bool,
+    pub size: PaneSize,
+    pub active: bool,
+    pub running: bool,
+    // Waiting for fewer tasks to be running, or for its dependencies' first runs
+    #[serde(default)]
+    pub queued: bool,
+    // Asked to run again while running, which it will once this run is done
+    #[serde(default)]
+    pub follow_up: bool,
+    // Its next periodic run was cancelled
+    #[serde(default)]
+    pub skip_next: bool,
+    // Seconds since the Unix epoch
+    pub last_run: Option<u64>,
+    // None until a run finishes
+    #[serde(default)]
+    pub health: Option<TaskHealth>,
+}
+
 impl MasterControl {
     pub fn new(cmd_tx: Sender<String>, resp_rx: Receiver<String>) -> MasterControl {
         MasterControl {
             proc_orc_cmd_tx: cmd_tx,
             proc_orc_resp_rx: resp_rx,
+            in_flight: Arc::new(Mutex::new(())),
+            backend: Arc::new(Heartbeat::new()),
         }
     }
 
@@ -33,8 +76,19 @@ impl MasterControl {
     Register a new task with the orchestrator
      */
     pub fn register(&mut self, task: Task, size: PaneSize) -> anyhow::Result<()> {
-        let metadata = RegisterTask { task, size };
+        self.register_task(RegisterTask { task, size, pane: None })
+    }
+
+    /***
+    Register a task that shows its output in another task's pane, at that pane's size
+     */
+    pub fn register_in(&mut self, task: Task, pane: &TaskId) -> anyhow::Result<()> {
+        self.register_task(RegisterTask { task, size: None, pane: Some(pane.clone()) })
+    }
 
+    fn register_task(&mut self, metadata: RegisterTask) -> anyhow::Result<()> {
+
+        let _guard = self.lock();
         self.send_command("register", &serde_json::to_string(&metadata)?)?;
         let resp = self.await_response("register")?;
         if resp.trim() == "Success" {
@@ -44,19 +98,31 @@ impl MasterControl {
         }
     }
 
-    pub fn resize(&mut self, task_id: &TaskId, size: PaneSize) -> anyhow::Result<()> {
-        let metadata = ResizeTask { task_id: task_id.to_owned(), size };
-
-        self.send_command("resize", &serde_json::to_string(&metadata)?)?;
-        let resp = self.await_response("resize")?;
+    /***
+    Forget a task that isn't running, e.g. a finished `decker run`, so its id is free again
+     */
+    pub fn unregister(&mut self, task_id: &TaskId) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("unregister", task_id)?;
+        let resp = self.await_response("unregister")?;
         if resp.trim() == "Success" {
             Ok(())
         } else {
-            bail!(simple_error::simple_error!(resp));
+            bail!(simple_error::simple_error!(resp.trim()));
         }
     }
 
+    /***
+    Tell a task its pane's new size, without waiting to hear back - the render thread resizes
+    panes, and mustn't stall on the orchestrator. Failures are logged there.
+     */
+    pub fn resize(&self, task_id: &TaskId, size: PaneSize) -> anyhow::Result<()> {
+        let metadata = ResizeTask { task_id: task_id.to_owned(), size };
+        self.send_command("local_resize", &serde_json::to_string(&metadata)?)
+    }
+
     pub fn running(&self) -> anyhow::Result<bool> {
+        let _guard = self.lock();
         self.send_command("running", "")?;
         let resp = self.await_response("running").unwrap();
         info!("main: Running response {}", resp.trim());
@@ -75,6 +141,7 @@ impl MasterControl {
         // TODO: Finish wiring this up.
         //  Probably need to track tasks within ProcessOrchestrator again
         let resize_task = ResizeTask { task_id: task_id.clone(), size: Some((pane.width(), pane.height())) };
+        let _guard = self.lock();
         self.send_command("resize", &serde_json::to_string(&resize_task)?)?;
         self.await_response("resize")?;
 
@@ -84,16 +151,189 @@ impl MasterControl {
         Ok(())
     }
 
+    /***
+    Make a task the main pane's interactive one at `size`, in place of whichever was, and start it
+     */
+    pub fn attach(&mut self, task_id: &TaskId, size: PaneSize) -> anyhow::Result<()> {
+        let resize_task = ResizeTask { task_id: task_id.clone(), size };
+        let _guard = self.lock();
+        for (command, data) in &[("resize", serde_json::to_string(&resize_task)?), ("activate", task_id.clone()), ("execute", task_id.clone())] {
+            self.send_command(command, data)?;
+            let resp = self.await_response(command)?;
+            if resp.trim() != "Success" {
+                bail!(simple_error::simple_error!(resp.trim()));
+            }
+        }
+        Ok(())
+    }
+
     /***
     Execute a task by name
      */
     pub fn execute(&mut self, name: &str) -> anyhow::Result<()> {
+        let _guard = self.lock();
         while let Err(_) = self.await_response("execute") {
             self.send_command("execute", name)?;
         }
         Ok(())
     }
 
+    /***
+    Run a task right away, restarting its period
+     */
+    pub fn refresh(&mut self, task_id: &TaskId) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("execute", task_id)?;
+        self.await_response("execute")?;
+        Ok(())
+    }
+
+    /***
+    Let a task run again, or stop it from running at all
+     */
+    pub fn set_enabled(&mut self, task_id: &str, enabled: bool) -> anyhow::Result<()> {
+        let command = if enabled { "enable" } else { "disable" };
+        let _guard = self.lock();
+        self.send_command(command, task_id)?;
+        let resp = self.await_response(command)?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp.trim()));
+        }
+    }
+
+    /***
+    Drop a task's queued runs and skip its next periodic one, leaving it enabled
+     */
+    pub fn cancel(&mut self, task_id: &str) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("cancel", task_id)?;
+        let resp = self.await_response("cancel")?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp.trim()));
+        }
+    }
+
+    /***
+    Run every task in a group now
+     */
+    pub fn run_group(&mut self, group: &str) -> anyhow::Result<()> {
+        self.group_command("run_group", group)
+    }
+
+    /***
+    Hold a group's periodic runs until it's resumed
+     */
+    pub fn pause_group(&mut self, group: &str) -> anyhow::Result<()> {
+        self.group_command("pause_group", group)
+    }
+
+    pub fn resume_group(&mut self, group: &str) -> anyhow::Result<()> {
+        self.group_command("resume_group", group)
+    }
+
+    /***
+    Kill any running processes belonging to a group
+     */
+    pub fn kill_group(&mut self, group: &str) -> anyhow::Result<()> {
+        self.group_command("kill_group", group)
+    }
+
+    fn group_command(&mut self, command: &str, group: &str) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command(command, group)?;
+        let resp = self.await_response(command)?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp.trim()));
+        }
+    }
+
+    /***
+    Has the orchestrator answered anything lately? Keep asking with `ping` to find out.
+     */
+    pub fn responsive(&self) -> bool {
+        self.backend.since_last_beat() < BACKEND_DEADLINE
+    }
+
+    /***
+    Check that the orchestrator is still answering commands
+     */
+    pub fn ping(&self) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("ping", "")?;
+        let resp = self.await_response("ping")?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp));
+        }
+    }
+
+    /***
+    Stop the orchestrator, cleaning up any children it started
+     */
+    pub fn shutdown(&mut self) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("shutdown", "")?;
+        let resp = self.await_response_within("shutdown", SHUTDOWN_DEADLINE)?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp));
+        }
+    }
+
+    /***
+    Report the state of every registered task
+     */
+    pub fn status(&self) -> anyhow::Result<Vec<TaskStatus>> {
+        let _guard = self.lock();
+        self.send_command("status", "")?;
+        let resp = self.await_response("status")?;
+        match serde_json::from_str(resp.trim()) {
+            Ok(statuses) => { Ok(statuses) }
+            Err(_) => { bail!(simple_error::simple_error!(resp.trim())); }
+        }
+    }
+
+    /***
+    Every registered task, as it was defined
+     */
+    pub fn tasks(&self) -> anyhow::Result<Vec<Task>> {
+        let _guard = self.lock();
+        self.send_command("tasks", "")?;
+        let resp = self.await_response("tasks")?;
+        match serde_json::from_str(resp.trim()) {
+            Ok(tasks) => { Ok(tasks) }
+            Err(_) => { bail!(simple_error::simple_error!(resp.trim())); }
+        }
+    }
+
+    /***
+    Pick tasks' schedules up where a saved session left them: when they last ran, and whether
+    they were paused or disabled
+     */
+    pub fn restore(&mut self, schedules: &[TaskStatus]) -> anyhow::Result<()> {
+        let _guard = self.lock();
+        self.send_command("restore", &serde_json::to_string(schedules)?)?;
+        let resp = self.await_response("restore")?;
+        if resp.trim() == "Success" {
+            Ok(())
+        } else {
+            bail!(simple_error::simple_error!(resp.trim()));
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        // A panic while holding the lock doesn't leave anything inconsistent - carry on.
+        self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     fn send_command(&self, command: &str, metadata: &str) -> anyhow::Result<()>{
         let data = format!("{}: {}", command, metadata);
         info!("MCP Sending command {}", data);
@@ -102,14 +342,18 @@ impl MasterControl {
     }
 
     fn await_response(&self, expected_response_type: &str) -> anyhow::Result<String> {
-        let half_sec = Duration::new(0, 500_000_000);
+        self.await_response_within(expected_response_type, Duration::from_millis(500))
+    }
+
+    fn await_response_within(&self, expected_response_type: &str, timeout: Duration) -> anyhow::Result<String> {
         let mut received_response = String::new();
         loop {
-            let resp = self.proc_orc_resp_rx.recv_timeout(half_sec)?;
+            let resp = self.proc_orc_resp_rx.recv_timeout(timeout)?;
             let parts = resp.split(":").collect::<Vec<&str>>();
             match parts.first() {
                 None => { break; } // empty string?! Shouldn't happen.
                 Some(response_type) => {
+                    self.backend.beat();
                     if response_type.deref() == expected_response_type {
                         received_response = parts[1..].join(":");
                         break;
@@ -1,63 +1,307 @@
 use crossbeam_channel::{Sender, Receiver};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 
 
-pub(crate) mod child;
+pub mod child;
 mod process_orchestrator;
 mod master_control;
-pub(crate) mod terminal;
-pub(crate) mod config;
+pub mod terminal;
+pub mod backend;
+pub mod config;
+pub mod logging;
+pub mod control;
+pub mod cli;
+pub mod systemd;
+pub mod output;
+pub mod supervisor;
+pub mod keys;
+pub mod trigger;
+pub mod sysstats;
+pub mod session;
+pub mod clipboard;
+pub mod display;
+pub mod theme;
 
 use serde::{Deserialize, Serialize};
-use crate::decker::master_control::PaneSize;
+pub use crate::decker::master_control::{PaneSize, TaskStatus};
 use lazy_static::lazy_static;
+use crate::decker::sysstats::SysStats;
 use portable_pty::{PtyPair, Child};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use crate::decker::output::OutputSender;
+use crate::decker::output::Encoding;
+use crate::decker::trigger::OutputTrigger;
+use crate::decker::logging::TaskLogs;
+use crate::decker::supervisor::Heartbeat;
+use crate::decker::display::{ChangeHighlighter, DisplayFilter, OutputFilter, OutputFilters};
 
-pub struct ProcOutput { pub name: String, pub output: String }
+pub struct ProcOutput { pub name: String, pub output: Vec<u8> }
 
+/***
+Bytes for the main pane's PTY, and when they were typed - the same moment the pane was told of
+them for its predictions. `None` if they weren't typed, e.g. answers to terminal queries.
+ */
+pub struct ProcInput { pub input: Vec<u8>, pub typed_at: Option<Instant> }
+
+/***
+How the main pane's current task's output is handled
+ */
+pub struct MainOutput {
+    // The output rate (bytes/sec) allowed through
+    rate: AtomicU64,
+    encoding: RwLock<Encoding>,
+    strip_ansi: AtomicBool,
+    filters: RwLock<OutputFilters>,
+    // When the typing last written to the main pane's PTY was typed. Output soon after is likely
+    // its echo.
+    typed_at: Mutex<Option<Instant>>,
+}
+
+#[derive(Clone)]
 pub struct MasterControl {
     // For sending commands/responses to ProcOrc
     proc_orc_cmd_tx: Sender<String>,
     proc_orc_resp_rx: Receiver<String>,
+    // Clones share the response channel, so only one command may be in flight at a time
+    in_flight: Arc<Mutex<()>>,
+    // Beats whenever the orchestrator answers anything. Shared by every clone.
+    backend: Arc<Heartbeat>,
 }
 
 pub type TaskId = String;
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Task {
     pub id: TaskId,
     pub name: String,
+    pub description: Option<String>,
     pub command: String,
+    // The command's words when they were given already split, e.g. by `decker run`. Local
+    // tasks run these as-is instead of splitting `command` on whitespace.
+    pub argv: Option<Vec<String>>,
     pub path: String,
     pub period: Option<String>,
-    period_secs: Option<u64>
+    period_secs: Option<u64>,
+    // Tasks sharing a group can be run, paused or killed together
+    pub group: Option<String>,
+    // Disabled tasks are registered, but never run
+    #[serde(default)]
+    pub disabled: bool,
+    // Glob patterns. The task runs again whenever a matching file changes.
+    pub watch: Option<Vec<String>>,
+    // Run whenever another task's output matches a pattern
+    pub trigger_on: Option<TriggerOn>,
+    // Tasks whose first run must finish before this one's first run starts
+    pub depends_on: Option<Vec<TaskId>>,
+
+    // Where the command runs, and the options for running it there
+    #[serde(default)]
+    pub kind: TaskKind,
+    pub host: Option<String>,
+    pub user: Option<String>,
+    pub key: Option<String>,
+    pub container: Option<String>,
+    pub image: Option<String>,
+
+    // Fed to the task's stdin when it isn't running interactively
+    pub stdin: Option<TaskInput>,
+
+    // Scheduling priority and resource limits for local tasks, so heavy widgets can't
+    // starve the main pane
+    pub nice: Option<i32>,
+    pub max_cpu_secs: Option<u64>,
+    pub max_memory_mb: Option<u64>,
+
+    // Most output (in KB) forwarded to the pane each second. Zero is unlimited.
+    pub output_rate_kb: Option<u64>,
+    // Most output (in KB) drawn in the task's pane per frame. Past that, the middle is dropped.
+    pub output_buffer_kb: Option<usize>,
+    // Drop every escape sequence from the output, so it's drawn as plain text
+    #[serde(default)]
+    pub strip_ansi: bool,
+    // Regex replacements run over the output before it's drawn (and before `display`)
+    pub filters: Option<Vec<OutputFilter>>,
+    // `filters`, compiled once the task's registered
+    #[serde(skip)]
+    compiled_filters: OutputFilters,
+    // Reformat each run's output before it's drawn, e.g. pretty-print JSON
+    pub display: Option<DisplayFilter>,
+    // What the task's output is encoded as, if not UTF-8
+    pub encoding: Option<Encoding>,
+    // Highlight what's changed since the task's last run, for a few seconds
+    #[serde(default)]
+    pub highlight_changes: bool,
+
+    // What counts as a successful run. Defaults to exiting 0; a healthy_when regex must also
+    // match the task's output.
+    pub success_exit_codes: Option<Vec<i32>>,
+    pub healthy_when: Option<String>,
+
+    // How long the task gets to exit after SIGTERM before it's killed outright, e.g. "5s"
+    pub stop_grace: Option<String>,
+
+    // TERM and COLORTERM for the task. An empty colorterm leaves COLORTERM unset.
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+}
+
+/***
+What to feed a non-interactive task on stdin: the contents of a file, read fresh each run
+(relative to the task's path), or a literal string.
+ */
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum TaskInput {
+    File(String),
+    Text { text: String },
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerOn {
+    // The task whose output to watch
+    pub task: TaskId,
+    // A regex, matched against each line of that output
+    pub pattern: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskHealth {
+    Healthy,
+    Unhealthy,
+}
+
+// The health of every task that's finished a run, shared with whoever displays it
+pub type HealthMap = Arc<RwLock<HashMap<TaskId, TaskHealth>>>;
+
+// Tasks that won't be run until they're enabled again
+pub type DisabledSet = Arc<RwLock<HashSet<TaskId>>>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskKind {
+    #[default]
+    Local,
+    Ssh,
+    Docker,
+    Podman,
+    // Not a command at all: decker's own readout of this machine's CPU, memory, load and disks
+    SysStats,
+}
+
+// How long a task gets to exit after SIGTERM, unless it says otherwise
+const DEFAULT_STOP_GRACE: Duration = Duration::from_secs(3);
+
 lazy_static! {
     static ref DIGITS_REGEX: regex::Regex = regex::Regex::new("([0-9]+).*").unwrap();
 }
 
 impl Task {
     pub fn cache_period(&mut self) {
-        let period = self.period.clone().unwrap_or(String::new());
-
-        if self.period_secs.is_none() && self.period.is_some() {
-            // Determine the number of seconds
-            let base = DIGITS_REGEX.
-                captures(&period).unwrap().
-                get(1).unwrap().
-                as_str().to_string().
-                parse::<u64>().unwrap();
-            let period_seconds = match period.chars().last() {
-                Some('h') => base * 3600,
-                Some('m') => base * 60,
-                _ => base
-            };
-
-            self.period_secs = Some(period_seconds)
+        if let (None, Some(period)) = (self.period_secs, &self.period) {
+            self.period_secs = Some(period_to_secs(period).unwrap())
         }
     }
+
+    /***
+    The bytes to feed this task on stdin, if any
+     */
+    pub fn stdin_bytes(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        match &self.stdin {
+            None => { Ok(None) }
+            Some(TaskInput::Text { text }) => { Ok(Some(text.clone().into_bytes())) }
+            Some(TaskInput::File(path)) => {
+                let path = std::path::Path::new(&self.path).join(path);
+                match std::fs::read(&path) {
+                    Ok(bytes) => { Ok(Some(bytes)) }
+                    Err(e) => { Err(anyhow::anyhow!("Could not read stdin for {} from {}: {}", self.id, path.display(), e)) }
+                }
+            }
+        }
+    }
+
+    /***
+    How long to wait after asking the task to stop before killing it
+     */
+    pub fn stop_grace(&self) -> Duration {
+        match self.stop_grace.as_deref().map(period_to_secs) {
+            Some(Ok(secs)) => { Duration::from_secs(secs) }
+            _ => { DEFAULT_STOP_GRACE }
+        }
+    }
+
+    /***
+    Compile the task's output filters. Bad patterns are caught when the config's loaded, so any
+    that get this far are logged and the filters skipped.
+     */
+    pub fn compile_filters(&mut self) {
+        self.compiled_filters = OutputFilters::new(self.filters.as_deref().unwrap_or_default()).unwrap_or_else(|e| {
+            log::error!("{}: Not filtering output: {}", self.id, e);
+            OutputFilters::default()
+        })
+    }
+
+    /***
+    The task's output filters, as compiled by `compile_filters`
+     */
+    pub fn output_filters(&self) -> &OutputFilters {
+        &self.compiled_filters
+    }
+
+    /***
+    Judge a finished run by its exit code (None if it was killed by a signal) and output
+     */
+    pub fn health_of(&self, exit_code: Option<i32>, output: &[u8]) -> TaskHealth {
+        let exit_ok = match (exit_code, &self.success_exit_codes) {
+            (None, _) => { false }
+            (Some(code), None) => { code == 0 }
+            (Some(code), Some(codes)) => { codes.contains(&code) }
+        };
+
+        let output_ok = match &self.healthy_when {
+            None => { true }
+            Some(pattern) => {
+                match regex::Regex::new(pattern) {
+                    Ok(re) => { re.is_match(&String::from_utf8_lossy(output)) }
+                    Err(_) => { false }
+                }
+            }
+        };
+
+        if exit_ok && output_ok { TaskHealth::Healthy } else { TaskHealth::Unhealthy }
+    }
+}
+
+/***
+Convert a period string into a number of seconds.
+Expects a digit plus an optional unit character (s, m, h or d). Ex: "10m" for ten minutes.
+ */
+pub fn period_to_secs(period: &str) -> anyhow::Result<u64> {
+    let base = match DIGITS_REGEX.captures(period).and_then(|c| c.get(1)) {
+        None => { anyhow::bail!("'{}' is not a valid period - expected e.g. \"10m\"", period) }
+        Some(digits) => { digits.as_str().parse::<u64>()? }
+    };
+
+    let seconds = match period.chars().last() {
+        Some('d') => base * 86400,
+        Some('h') => base * 3600,
+        Some('m') => base * 60,
+        _ => base
+    };
+
+    Ok(seconds)
+}
+
+// Held by a capture thread. Dropping it, even while unwinding from a panic, tells the orchestrator
+// the capture is over.
+struct CaptureDone {
+    commander: Sender<String>,
+    task_id: TaskId,
 }
 
 //  All of the threaded functionality lives in the process orchestrator class
@@ -68,7 +312,44 @@ pub struct ProcessOrchestrator {
     // Track all of our registered tasks
     tasks: HashMap<String, Task>,
     sizes: HashMap<String, PaneSize>,
+    // Tasks showing their output in another task's pane, e.g. from `decker run --pane`
+    routes: HashMap<TaskId, TaskId>,
     periodic_tasks: Arc<RwLock<HashMap<TaskId, u64>>>,
+    watched_tasks: Arc<RwLock<HashMap<TaskId, Vec<String>>>>,
+    output_triggers: Arc<Mutex<Vec<OutputTrigger>>>,
+
+    // Where every task's raw output is recorded, if anywhere
+    task_logs: Option<Arc<Mutex<TaskLogs>>>,
+
+    // Commands that clean up after children (e.g. containers) when we shut down
+    teardowns: HashMap<TaskId, Vec<String>>,
+
+    // When each task last started, and how its last run went, for status reporting.
+    // A run of any sort restarts a periodic task's countdown.
+    last_runs: Arc<RwLock<HashMap<TaskId, SystemTime>>>,
+    health: HealthMap,
+
+    // Tasks whose periodic runs are on hold, and the children of non-interactive runs in progress.
+    // A child is let go of (and waited on) with the lock held, so its pid is never signalled after.
+    paused: HashSet<TaskId>,
+    disabled: DisabledSet,
+    running_children: Arc<Mutex<HashMap<TaskId, std::process::Child>>>,
+    // Marks what changed since their last run in the panes of tasks that ask for it
+    changes: Arc<ChangeHighlighter>,
+    // The threads capturing non-interactive runs' output, at most one per task
+    captures: HashMap<TaskId, JoinHandle<()>>,
+    // Runs waiting for fewer tasks to be running, oldest first, and the limits they're waiting on
+    pending: VecDeque<TaskId>,
+    // Tasks asked to run again while already running. They get one more run once they finish.
+    follow_ups: HashSet<TaskId>,
+    // Tasks whose next periodic run was cancelled
+    skip_next: HashSet<TaskId>,
+    // Tasks holding their first run until their dependencies' first runs are done, and the tasks
+    // that have finished a run
+    awaiting_dependencies: HashSet<TaskId>,
+    finished: HashSet<TaskId>,
+    max_running: usize,
+    group_limits: HashMap<String, usize>,
 
     // Should we keep running?
     shutdown: bool,
@@ -79,13 +360,17 @@ pub struct ProcessOrchestrator {
     resp_tx: Sender<String>,
 
     // Channels for aggregated STDIN/OUT forwarding
-    output_tx: Sender<ProcOutput>,
-    input_rx: Receiver<String>,
+    output_tx: OutputSender,
+    input_rx: Receiver<ProcInput>,
 
-    // The PTY for the main window
+    // The PTY for the main window, and how its output is handled
     main_pty: PtyPair,
-    // the name and child process of the activated task
+    main_output: Arc<MainOutput>,
+    // the name and child process of the activated task. The main output loop reads main_task.
     active_proc: Option<String>,
-    active_child: Option<Box<dyn Child + Send>>,
+    main_task: Arc<RwLock<Option<TaskId>>>,
+    // Read by sysstats tasks. Made on first use.
+    sys_stats: Option<SysStats>,
+    active_child: Arc<Mutex<Option<Box<dyn Child + Send>>>>,
     has_active_task: bool // convenience field
 }
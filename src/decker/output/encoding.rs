@@ -0,0 +1,106 @@
+use crate::decker::output::{Decoder, Encoding};
+use encoding_rs::SHIFT_JIS;
+use std::borrow::Cow;
+
+// What CP437's top half draws, from 0x80 on. The bottom half is ASCII, control codes included,
+// so escape sequences still work.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+impl Encoding {
+    /***
+    A run's whole output as UTF-8, ready to be parsed
+     */
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut decoder = Decoder::new(*self);
+        match decoder.feed(bytes) {
+            Cow::Borrowed(bytes) => { Cow::Borrowed(bytes) }
+            Cow::Owned(mut text) => {
+                text.extend(decoder.finish());
+                Cow::Owned(text)
+            }
+        }
+    }
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding) -> Decoder {
+        let stream = match encoding {
+            Encoding::ShiftJis => { Some(SHIFT_JIS.new_decoder_without_bom_handling()) }
+            Encoding::Utf8 | Encoding::Latin1 | Encoding::Cp437 => { None }
+        };
+        Decoder { encoding, stream }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /***
+    The next piece of output as UTF-8. The end of a character cut off by the end of `bytes` is
+    held back until the rest of it arrives.
+     */
+    pub fn feed<'a>(&mut self, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+        let text = match self.encoding {
+            // The parser puts UTF-8 split across reads back together itself
+            Encoding::Utf8 => { return Cow::Borrowed(bytes) }
+            Encoding::Latin1 => { bytes.iter().map(|&b| b as char).collect::<String>() }
+            Encoding::Cp437 => {
+                bytes.iter().
+                    map(|&b| if b < 0x80 { b as char } else { CP437_HIGH[b as usize - 0x80] }).
+                    collect::<String>()
+            }
+            Encoding::ShiftJis => { self.decode_stream(bytes, false) }
+        };
+        Cow::Owned(text.into_bytes())
+    }
+
+    /***
+    What's left once the output's over: a character that never got finished
+     */
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.decode_stream(b"", true).into_bytes()
+    }
+
+    // Anything that isn't valid in the encoding becomes U+FFFD
+    fn decode_stream(&mut self, bytes: &[u8], last: bool) -> String {
+        let decoder = match self.stream.as_mut() {
+            None => { return String::new() }
+            Some(decoder) => { decoder }
+        };
+        // Sized so the whole of `bytes` always fits
+        let mut text = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap_or(bytes.len() * 3 + 4));
+        let _ = decoder.decode_to_string(bytes, &mut text, last);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_decodes_cp437() {
+        let banner = b"\x1b[31m\xc9\xcd\xbb\x1b[0m\r\n\xb0\xb1\xb2\xdb 80\xf8F";
+        assert_eq!(String::from_utf8(Encoding::Cp437.decode(banner).into_owned()).unwrap(), "\x1b[31m╔═╗\x1b[0m\r\n░▒▓█ 80°F");
+        assert_eq!(Encoding::Utf8.decode("½".as_bytes()).as_ref(), "½".as_bytes());
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9").as_ref(), "café".as_bytes());
+    }
+
+    #[test]
+    fn it_decodes_characters_split_across_reads() {
+        let mut decoder = Decoder::new(Encoding::ShiftJis);
+        assert_eq!(decoder.feed(b"\x93\xfa\x96").as_ref(), "日".as_bytes());
+        assert_eq!(decoder.feed(b"\x7b!").as_ref(), "本!".as_bytes());
+        assert_eq!(decoder.feed(b"\x82").as_ref(), b"");
+        assert_eq!(decoder.finish(), "\u{fffd}".as_bytes());
+    }
+}
@@ -0,0 +1,107 @@
+/***
+* Output queue
+* Carries task output from the orchestrator to the render thread, and decides what
+* happens when rendering falls behind: wait for it, shed the oldest output, or merge
+* each pane's backlog into a single update. The echo of typing skips the queue.
+***/
+mod encoding;
+mod output_queue;
+
+pub use output_queue::output_queue;
+
+use crate::decker::{ProcOutput, TaskId};
+use crossbeam_channel::{Receiver, Sender};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicU64;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackpressurePolicy {
+    // Stall the task until the renderer catches up
+    #[default]
+    Block,
+    // Throw away the oldest queued output to make room
+    DropOldest,
+    // Hold output aside and deliver it to its pane in one piece once there's room
+    Coalesce,
+}
+
+/***
+How a task's output is encoded. It's turned into UTF-8 before its pane parses it.
+ */
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+pub enum Encoding {
+    #[default]
+    #[serde(rename = "utf-8")]
+    Utf8,
+    // ISO-8859-1, as older Unix tools print
+    #[serde(rename = "latin-1")]
+    Latin1,
+    // Japanese, as older Windows and some embedded tools print
+    #[serde(rename = "shift-jis")]
+    ShiftJis,
+    // The IBM PC's character set, for ANSI art and BBS-style banners
+    #[serde(rename = "cp437")]
+    Cp437,
+}
+
+/***
+Turns a task's output into UTF-8 as it arrives, holding on to the start of any character split
+between reads
+ */
+pub struct Decoder {
+    encoding: Encoding,
+    // For multi-byte encodings, made once for the whole stream. It holds split characters itself.
+    stream: Option<encoding_rs::Decoder>,
+}
+
+#[derive(Clone)]
+pub struct OutputSender {
+    tx: Sender<ProcOutput>,
+    // Only used to shed the oldest output under DropOldest
+    rx: Receiver<ProcOutput>,
+    policy: BackpressurePolicy,
+    pending: Arc<Mutex<HashMap<TaskId, Vec<u8>>>>,
+    stats: Arc<OutputStats>,
+    // Echoes of typing, which go ahead of the queue. How many bytes each task has waiting in the
+    // queue (or held aside), since an echo mustn't overtake output printed before it.
+    echo_tx: Sender<ProcOutput>,
+    waiting: Arc<Mutex<HashMap<TaskId, usize>>>,
+}
+
+pub struct OutputReceiver {
+    rx: Receiver<ProcOutput>,
+    pending: Arc<Mutex<HashMap<TaskId, Vec<u8>>>>,
+    stats: Arc<OutputStats>,
+    echo_rx: Receiver<ProcOutput>,
+    waiting: Arc<Mutex<HashMap<TaskId, usize>>>,
+    // Output taken off the queue just as an echo printed before it arrived, to deliver next
+    deferred: Cell<Option<ProcOutput>>,
+    // Whether what was received last was an echo
+    echoed: Cell<bool>,
+}
+
+/***
+Running totals of what the queue did when it filled up
+ */
+#[derive(Default, Debug)]
+pub struct OutputStats {
+    // Sends that had to wait for room
+    pub blocked: AtomicU64,
+    // Chunks (and their bytes) thrown away
+    pub dropped: AtomicU64,
+    pub dropped_bytes: AtomicU64,
+    // Chunks merged into a pane's pending output
+    pub coalesced: AtomicU64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct OutputStatsSnapshot {
+    pub blocked: u64,
+    pub dropped: u64,
+    pub dropped_bytes: u64,
+    pub coalesced: u64,
+}
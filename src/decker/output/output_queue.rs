@@ -0,0 +1,326 @@
+use crate::decker::{ProcOutput, TaskId};
+use crate::decker::output::{BackpressurePolicy, OutputReceiver, OutputSender, OutputStats, OutputStatsSnapshot};
+use crossbeam_channel::{bounded, select, unbounded, RecvTimeoutError, TryRecvError, TrySendError};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use anyhow::anyhow;
+
+// The most output we'll hold aside for one pane under Coalesce before shedding the oldest of it
+const MAX_PENDING_BYTES: usize = 256 * 1024;
+
+/***
+Create a queue holding at most `capacity` chunks of output, which handles a full queue per `policy`
+ */
+pub fn output_queue(capacity: usize, policy: BackpressurePolicy) -> (OutputSender, OutputReceiver) {
+    let (tx, rx) = bounded(capacity);
+    let (echo_tx, echo_rx) = unbounded();
+    let pending = Arc::new(Mutex::new(HashMap::new()));
+    let stats = Arc::new(OutputStats::default());
+    let waiting = Arc::new(Mutex::new(HashMap::new()));
+
+    let sender = OutputSender { tx, rx: rx.clone(), policy, pending: pending.clone(), stats: stats.clone(), echo_tx, waiting: waiting.clone() };
+    let receiver = OutputReceiver { rx, pending, stats, echo_rx, waiting, deferred: Cell::new(None), echoed: Cell::new(false) };
+    (sender, receiver)
+}
+
+impl OutputSender {
+    pub fn send(&self, output: ProcOutput) -> anyhow::Result<()> {
+        let (name, len) = (output.name.clone(), output.output.len());
+        add_waiting(&self.waiting, &name, len);
+        let sent = self.enqueue(output);
+        if sent.is_err() {
+            remove_waiting(&self.waiting, &name, len);
+        }
+        sent
+    }
+
+    /***
+    Send the echo of typing ahead of everything queued, so it can be drawn straight away. Unless
+    the task has output still waiting, which the echo mustn't overtake, or an echo that hasn't
+    been drawn yet - then it queues like any other output.
+     */
+    pub fn send_echo(&self, output: ProcOutput) -> anyhow::Result<()> {
+        let waiting = self.waiting.lock().map_err(|_| anyhow!("Output queue is poisoned"))?;
+        if waiting.contains_key(&output.name) || !self.echo_tx.is_empty() {
+            drop(waiting);
+            return self.send(output);
+        }
+        self.echo_tx.send(output).map_err(|_| anyhow!("Output queue is closed"))
+    }
+
+    fn enqueue(&self, output: ProcOutput) -> anyhow::Result<()> {
+        match self.policy {
+            BackpressurePolicy::Block => {
+                if self.tx.is_full() {
+                    self.stats.blocked.fetch_add(1, Ordering::Relaxed);
+                }
+                self.tx.send(output).map_err(|_| anyhow!("Output queue is closed"))
+            }
+            BackpressurePolicy::DropOldest => {
+                let mut output = output;
+                loop {
+                    match self.tx.try_send(output) {
+                        Ok(_) => { return Ok(()) }
+                        Err(TrySendError::Full(rejected)) => {
+                            if let Ok(oldest) = self.rx.try_recv() {
+                                self.stats.record_drop(oldest.output.len());
+                                remove_waiting(&self.waiting, &oldest.name, oldest.output.len());
+                            }
+                            output = rejected;
+                        }
+                        Err(TrySendError::Disconnected(_)) => { return Err(anyhow!("Output queue is closed")) }
+                    }
+                }
+            }
+            BackpressurePolicy::Coalesce => {
+                let mut pending = self.pending.lock().map_err(|_| anyhow!("Output queue is poisoned"))?;
+
+                // Once a pane has output held aside, everything after it waits too, to keep it in order
+                if let Some(held) = pending.get_mut(&output.name) {
+                    held.extend_from_slice(&output.output);
+                    self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                    remove_waiting(&self.waiting, &output.name, self.stats.trim(held));
+                    return Ok(());
+                }
+
+                match self.tx.try_send(output) {
+                    Ok(_) => { Ok(()) }
+                    Err(TrySendError::Full(rejected)) => {
+                        let mut held = rejected.output;
+                        self.stats.coalesced.fetch_add(1, Ordering::Relaxed);
+                        remove_waiting(&self.waiting, &rejected.name, self.stats.trim(&mut held));
+                        pending.insert(rejected.name, held);
+                        Ok(())
+                    }
+                    Err(TrySendError::Disconnected(_)) => { Err(anyhow!("Output queue is closed")) }
+                }
+            }
+        }
+    }
+}
+
+fn add_waiting(waiting: &Mutex<HashMap<TaskId, usize>>, name: &str, bytes: usize) {
+    if let Ok(mut waiting) = waiting.lock() {
+        *waiting.entry(name.to_string()).or_insert(0) += bytes;
+    }
+}
+
+fn remove_waiting(waiting: &Mutex<HashMap<TaskId, usize>>, name: &str, bytes: usize) {
+    if let Ok(mut waiting) = waiting.lock() {
+        let left = waiting.get(name).map(|w| w.saturating_sub(bytes)).unwrap_or(0);
+        match left {
+            0 => { waiting.remove(name); }
+            left => { waiting.insert(name.to_string(), left); }
+        }
+    }
+}
+
+impl OutputReceiver {
+    /***
+    Wait up to `timeout` for the next chunk of output. Echoes of typing come first, then queued
+    output, then anything held aside.
+     */
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<ProcOutput, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(echo) = self.echo_rx.try_recv() {
+                return Ok(self.deliver(echo, true));
+            }
+            if let Some(output) = self.deferred.take() {
+                return Ok(self.deliver(output, false));
+            }
+
+            match self.rx.try_recv() {
+                Ok(output) => { return Ok(self.queued(output)) }
+                Err(TryRecvError::Disconnected) => { return self.take_pending().ok_or(RecvTimeoutError::Disconnected) }
+                Err(TryRecvError::Empty) => {}
+            }
+
+            if let Some(output) = self.take_pending() {
+                return Ok(output);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            // Poll, since output can be held aside without anything arriving on the queue
+            select! {
+                recv(self.echo_rx) -> echo => {
+                    if let Ok(echo) = echo {
+                        return Ok(self.deliver(echo, true));
+                    }
+                }
+                recv(self.rx) -> output => {
+                    return match output {
+                        Ok(output) => { Ok(self.queued(output)) }
+                        Err(_) => { self.take_pending().ok_or(RecvTimeoutError::Disconnected) }
+                    }
+                }
+                default((deadline - now).min(Duration::from_millis(100))) => {}
+            }
+        }
+    }
+
+    /***
+    Whether the output received last was an echo of typing, sent ahead of the queue
+     */
+    pub fn echoed(&self) -> bool {
+        self.echoed.get()
+    }
+
+    // Output just taken off the queue, unless an echo sent before it has arrived in the meantime
+    fn queued(&self, output: ProcOutput) -> ProcOutput {
+        match self.echo_rx.try_recv() {
+            Ok(echo) => {
+                self.deferred.set(Some(output));
+                self.deliver(echo, true)
+            }
+            Err(_) => { self.deliver(output, false) }
+        }
+    }
+
+    fn deliver(&self, output: ProcOutput, echo: bool) -> ProcOutput {
+        if !echo {
+            remove_waiting(&self.waiting, &output.name, output.output.len());
+        }
+        self.echoed.set(echo);
+        output
+    }
+
+    /***
+    How many chunks are waiting, and how many fit
+     */
+    pub fn depth(&self) -> (usize, usize) {
+        (self.rx.len(), self.rx.capacity().unwrap_or(0))
+    }
+
+    pub fn stats(&self) -> Arc<OutputStats> {
+        self.stats.clone()
+    }
+
+    fn take_pending(&self) -> Option<ProcOutput> {
+        let mut pending = self.pending.lock().ok()?;
+        let name = pending.keys().next()?.clone();
+        let output = pending.remove(&name).map(|output| ProcOutput { name, output })?;
+        drop(pending);
+        Some(self.deliver(output, false))
+    }
+}
+
+impl OutputStats {
+    pub fn snapshot(&self) -> OutputStatsSnapshot {
+        OutputStatsSnapshot {
+            blocked: self.blocked.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            dropped_bytes: self.dropped_bytes.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_drop(&self, bytes: usize) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.dropped_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Keep held-aside output under MAX_PENDING_BYTES by shedding its oldest bytes. How many were shed.
+    fn trim(&self, held: &mut Vec<u8>) -> usize {
+        if held.len() <= MAX_PENDING_BYTES {
+            return 0;
+        }
+        let excess = held.len() - MAX_PENDING_BYTES;
+        held.drain(..excess);
+        self.record_drop(excess);
+        excess
+    }
+}
+
+impl Display for OutputStatsSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "blocked {} | dropped {} ({}KB) | coalesced {}",
+               self.blocked, self.dropped, self.dropped_bytes / 1024, self.coalesced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recv(rx: &OutputReceiver) -> ProcOutput {
+        rx.recv_timeout(Duration::from_secs(1)).unwrap()
+    }
+
+    fn out(name: &str, text: &str) -> ProcOutput {
+        ProcOutput { name: name.to_string(), output: text.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn drop_oldest_sheds_the_oldest_output() {
+        let (tx, rx) = output_queue(2, BackpressurePolicy::DropOldest);
+        tx.send(out("a", "1")).unwrap();
+        tx.send(out("a", "2")).unwrap();
+        tx.send(out("a", "3")).unwrap();
+
+        assert_eq!(recv(&rx).output, b"2");
+        assert_eq!(recv(&rx).output, b"3");
+        assert_eq!(rx.stats().snapshot().dropped, 1);
+        assert_eq!(rx.stats().snapshot().dropped_bytes, 1);
+    }
+
+    #[test]
+    fn coalesce_merges_a_panes_backlog_in_order() {
+        let (tx, rx) = output_queue(1, BackpressurePolicy::Coalesce);
+        tx.send(out("a", "1")).unwrap();
+        tx.send(out("a", "2")).unwrap();
+        tx.send(out("a", "3")).unwrap();
+
+        assert_eq!(recv(&rx).output, b"1");
+        // The queue has room again, but "a" has held output - this must queue up behind it
+        tx.send(out("a", "4")).unwrap();
+        assert_eq!(recv(&rx).output, b"234");
+        assert_eq!(rx.stats().snapshot().coalesced, 3);
+    }
+
+    #[test]
+    fn echoes_skip_the_queue_but_not_their_own_output() {
+        let (tx, rx) = output_queue(4, BackpressurePolicy::Block);
+        tx.send(out("clock", "12:00")).unwrap();
+        tx.send_echo(out("main", "l")).unwrap();
+        assert_eq!(recv(&rx).output, b"l");
+        assert!(rx.echoed());
+        assert_eq!(recv(&rx).output, b"12:00");
+        assert!(!rx.echoed());
+
+        // main still has output queued, so its echo waits behind it
+        tx.send(out("main", "s\r\n")).unwrap();
+        tx.send_echo(out("main", "x")).unwrap();
+        assert_eq!(recv(&rx).output, b"s\r\n");
+        assert_eq!(recv(&rx).output, b"x");
+        assert!(!rx.echoed());
+
+        // Output sent after an echo never overtakes it, even when it's taken off the queue first
+        tx.send_echo(out("main", "y")).unwrap();
+        tx.send(out("main", "z")).unwrap();
+        let raced = rx.rx.try_recv().unwrap();
+        assert_eq!(rx.queued(raced).output, b"y");
+        assert_eq!(recv(&rx).output, b"z");
+        assert!(rx.waiting.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn block_counts_waits() {
+        let (tx, rx) = output_queue(1, BackpressurePolicy::Block);
+        tx.send(out("a", "1")).unwrap();
+        std::thread::spawn(move || tx.send(out("a", "2")).unwrap());
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert_eq!(recv(&rx).output, b"1");
+        assert_eq!(recv(&rx).output, b"2");
+        assert_eq!(rx.stats().snapshot().blocked, 1);
+    }
+}
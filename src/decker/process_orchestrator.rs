@@ -1,24 +1,46 @@
-use crate::decker::{ProcessOrchestrator, ProcOutput, TaskId, Task};
-use crate::decker::child::ChildProcess;
-use std::collections::HashMap;
+use crate::decker::{CaptureDone, DisabledSet, HealthMap, MainOutput, ProcessOrchestrator, ProcInput, ProcOutput, Task, TaskHealth, TaskId, TaskKind, TaskStatus};
+use crate::decker::sysstats::SysStats;
+use crate::decker::output::OutputSender;
+use crate::decker::output::{Decoder, Encoding};
+use crate::decker::supervisor::Supervisor;
+use crate::decker::trigger::{FileWatch, OutputTrigger};
+use crate::decker::logging::TaskLogs;
+use crate::decker::display::{AnsiStripper, ChangeHighlighter};
+use crate::decker::child::{exit_status, signal_group, terminate, wait_for_exit, ChildProcess, OutputThrottle};
+use libc::c_int;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::thread;
 use log::{debug, info, error};
 use crate::decker::master_control::{RegisterTask, ResizeTask};
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::os::unix::process::ExitStatusExt;
+use portable_pty::Child;
 use crossbeam_channel::{Sender, Receiver};
 use portable_pty::PtySize;
 use std::io::{Read, Write};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
 use anyhow::anyhow;
-use std::sync::{Arc, RwLock, LockResult};
+use std::sync::{Arc, Mutex, RwLock, LockResult};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(not(feature = "crossterm"))]
 use termion::raw::IntoRawMode;
 
+// Most non-interactive runs (and so capture threads) going at once, unless configured otherwise
+const DEFAULT_MAX_RUNNING: usize = 32;
+// How often to check whether the active child has exited
+const EXIT_POLL: Duration = Duration::from_millis(250);
+// Most of a line of main output held back for the rest of it, before it's filtered anyway
+const MAX_HELD_LINE: usize = 64 * 1024;
+// Main output this soon after typing is treated as its echo, and drawn ahead of other output
+const ECHO_WINDOW: Duration = Duration::from_millis(200);
+
 impl ProcessOrchestrator {
     /***
     Create a new ProcessOrchestrator.
     @arg output_tx: A sender to transmit aggregated output
      */
-    pub fn new(output_tx: Sender<ProcOutput>, cmd_tx: Sender<String>, cmd_rx: Receiver<String>, resp_tx: Sender<String>, input_rx: Receiver<String>, pane_size: (u16, u16)) -> ProcessOrchestrator {
+    pub fn new(output_tx: OutputSender, cmd_tx: Sender<String>, cmd_rx: Receiver<String>, resp_tx: Sender<String>, input_rx: Receiver<ProcInput>, pane_size: (u16, u16)) -> ProcessOrchestrator {
         let pty = portable_pty::native_pty_system().openpty(PtySize {
             rows: pane_size.1,
             cols: pane_size.0,
@@ -26,25 +48,92 @@ impl ProcessOrchestrator {
             pixel_height: 0,
         }).unwrap();
 
+        // crossterm can only put the real terminal into raw mode
+        #[cfg(not(feature = "crossterm"))]
         pty.master.try_clone_writer().unwrap().into_raw_mode().unwrap();
 
         ProcessOrchestrator {
             tasks: HashMap::new(),
             sizes: HashMap::new(),
+            routes: HashMap::new(),
             periodic_tasks: Arc::new(RwLock::new(HashMap::new())),
+            watched_tasks: Arc::new(RwLock::new(HashMap::new())),
+            output_triggers: Arc::new(Mutex::new(Vec::new())),
+            task_logs: None,
+            teardowns: HashMap::new(),
+            last_runs: Arc::new(RwLock::new(HashMap::new())),
+            health: Arc::new(RwLock::new(HashMap::new())),
+            paused: HashSet::new(),
+            disabled: Arc::new(RwLock::new(HashSet::new())),
+            running_children: Arc::new(Mutex::new(HashMap::new())),
+            changes: Default::default(),
+            captures: HashMap::new(),
+            pending: VecDeque::new(),
+            follow_ups: HashSet::new(),
+            skip_next: HashSet::new(),
+            awaiting_dependencies: HashSet::new(),
+            finished: HashSet::new(),
+            max_running: DEFAULT_MAX_RUNNING,
+            group_limits: HashMap::new(),
             command_tx: cmd_tx,
             command_rx: cmd_rx,
             resp_tx: resp_tx,
             output_tx,
             input_rx,
             main_pty: pty,
+            main_output: Arc::new(MainOutput {
+                rate: AtomicU64::new(OutputThrottle::for_rate_kb(None).max_bytes_per_sec()),
+                encoding: Default::default(),
+                strip_ansi: AtomicBool::new(false),
+                filters: Default::default(),
+                typed_at: Mutex::new(None),
+            }),
             active_proc: None,
-            active_child: None,
+            main_task: Arc::new(RwLock::new(None)),
+            sys_stats: None,
+            active_child: Arc::new(Mutex::new(None)),
             has_active_task: false,
             shutdown: false,
         }
     }
 
+    /***
+    The health of each task's last run, kept up to date as they finish
+     */
+    pub fn health(&self) -> HealthMap {
+        self.health.clone()
+    }
+
+    /***
+    The tasks that are disabled, kept up to date as they're toggled
+     */
+    pub fn disabled(&self) -> DisabledSet {
+        self.disabled.clone()
+    }
+
+    /***
+    Cap how many non-interactive tasks run at once, overall and within groups. Runs past the cap
+    wait their turn.
+     */
+    pub fn set_concurrency(&mut self, max_running: usize, group_limits: HashMap<String, usize>) {
+        self.max_running = max_running.max(1);
+        self.group_limits = group_limits;
+    }
+
+    /***
+    Highlight changes between runs like this, for tasks that ask. Must be called before `run`.
+     */
+    pub fn set_change_highlighter(&mut self, changes: ChangeHighlighter) {
+        self.changes = Arc::new(changes);
+    }
+
+    /***
+    Record every task's output in these logs. Must be called before `run`.
+     */
+    pub fn set_task_logs(&mut self, logs: TaskLogs) {
+        self.task_logs = Some(Arc::new(Mutex::new(logs)));
+    }
+
     /***
     Run the processing loop
      */
@@ -52,10 +141,16 @@ impl ProcessOrchestrator {
         info!("main: Starting ProcessOrchestrator");
         info!("main: Total tasks: {}", self.tasks.len());
 
-        Self::start_forward_output_loop(self.main_pty.master.try_clone_reader()?, self.output_tx.clone())?;
-        Self::start_forward_input_loop(self.input_rx.clone(), self.main_pty.master.try_clone_writer()?, "main".to_string());
-        Self::start_period_task_loop(self.periodic_tasks.clone(), self.command_tx.clone());
-        self.process_commands()?;
+        Self::start_forward_output_loop(self.main_pty.master.try_clone_reader()?, self.output_tx.clone(), self.main_output.clone(),
+                                        self.output_triggers.clone(), self.task_logs.clone(), self.main_task.clone(), self.command_tx.clone())?;
+        Self::start_forward_input_loop(self.input_rx.clone(), self.main_pty.master.try_clone_writer()?, "main".to_string(), self.main_output.clone());
+        Self::start_period_task_loop(self.periodic_tasks.clone(), self.last_runs.clone(), self.command_tx.clone());
+        Self::start_file_watch_loop(self.watched_tasks.clone(), self.command_tx.clone());
+
+        // A command that blows up shouldn't take every later command down with it
+        if !Supervisor::new("orchestrator").run(|| self.process_commands()) {
+            return Err(anyhow!("too many failures processing commands"));
+        }
         Ok(())
     }
 
@@ -70,7 +165,11 @@ impl ProcessOrchestrator {
 
                     self.handle_command(&cmd, &data)?;
                 }
-                Err(e) => { return Err(e.into()); }
+                Err(_) => {
+                    // Nobody's left to send us commands
+                    info!("main: Command channel closed");
+                    break;
+                }
             }
         }
 
@@ -82,39 +181,109 @@ impl ProcessOrchestrator {
     Execute a task by name
      */
     fn execute(&mut self, task_id: &str) -> anyhow::Result<()> {
+        if self.is_disabled(task_id) {
+            info!("{}: Disabled - not running", task_id);
+            return Ok(());
+        }
+        if self.tasks.get(task_id).map(|t| t.kind) == Some(TaskKind::SysStats) {
+            return self.show_sys_stats(task_id);
+        }
+
         match self.tasks.get(task_id) {
             None => {
                 info!("Could not find task {} to execute in {:?}", task_id, self.tasks.keys());
             }
             Some(task) => {
-                let size = self.sizes.get(task_id);
+                let pane_id = self.routes.get(task_id).map(|p| p.as_str()).unwrap_or(task_id);
+                // Routed and ad-hoc tasks may have no size of their own - they get the main pane's
+                let size = self.sizes.get(pane_id).or_else(|| self.sizes.get("main"));
 
-                match size.unwrap() {
+                match size.cloned().flatten() {
                     None => {
                         info!("Cannot run {} - no terminal size was assigned! Does this have a pane?", task_id);
                     }
                     Some((width, height)) => {
-                        let new_kid = ChildProcess::new(task.command.as_str(),
-                                                        task.path.as_str(),
-                                                        (*height, *width));
-
                         let run_interactively = match self.active_proc.clone() {
                             None => { false }
                             Some(active_task) => { task_id == active_task }
                         };
 
-                        let pane_id = if run_interactively { "main" } else { task_id }.to_string();
+                        if !run_interactively {
+                            if self.captures.contains_key(task_id) {
+                                if self.follow_ups.insert(task_id.to_string()) {
+                                    info!("{}: Still running from last time - running again once it's done", task_id);
+                                }
+                                return Ok(());
+                            }
+                            if self.waiting_on_dependencies(task) {
+                                if self.awaiting_dependencies.insert(task.id.clone()) {
+                                    info!("{}: Waiting for {:?} to finish their first runs", task_id, task.depends_on.clone().unwrap_or_default());
+                                }
+                                return Ok(());
+                            }
+                            if !self.has_room_for(task) {
+                                if !self.pending.contains(&task.id) {
+                                    info!("{}: Too many tasks running - queueing this run", task_id);
+                                    self.pending.push_back(task.id.clone());
+                                }
+                                return Ok(());
+                            }
+                        }
+
+                        let new_kid = ChildProcess::for_task(task, (height, width), run_interactively)?;
+                        if let Some(teardown) = new_kid.teardown.clone() {
+                            self.teardowns.insert(task_id.to_string(), teardown);
+                        }
+
+                        let pane_id = if run_interactively { "main" } else { pane_id }.to_string();
 
                         info!("{}: Running interactively: {}", pane_id, run_interactively);
+                        if let Ok(mut last_runs) = self.last_runs.write() {
+                            last_runs.insert(task_id.to_string(), SystemTime::now());
+                        }
+
+                        let throttle = OutputThrottle::for_rate_kb(task.output_rate_kb);
 
                         if run_interactively {
+                            self.main_output.rate.store(throttle.max_bytes_per_sec(), Ordering::Relaxed);
+                            if let Ok(mut encoding) = self.main_output.encoding.write() {
+                                *encoding = task.encoding.unwrap_or_default();
+                            }
+                            self.main_output.strip_ansi.store(task.strip_ansi, Ordering::Relaxed);
+                            if let Ok(mut filters) = self.main_output.filters.write() {
+                                *filters = task.output_filters().clone();
+                            }
                             let child = self.main_pty.slave.spawn_command(new_kid.command_for_pty())?;
-                            self.active_child = Some(child);
+                            let pid = child.process_id();
+                            if let Ok(mut active_child) = self.active_child.lock() {
+                                *active_child = Some(child);
+                            }
+                            Self::start_exit_watch(self.active_child.clone(), pid, self.output_tx.clone());
                         } else {
                             let output_tx = self.output_tx.clone();
-                            thread::spawn(move || {
-                                Self::capture_output(output_tx, new_kid, pane_id).unwrap();
+                            let health = self.health.clone();
+                            let running_children = self.running_children.clone();
+                            let changes = self.changes.clone();
+                            let triggers = self.output_triggers.clone();
+                            let task_logs = self.task_logs.clone();
+                            let commander = self.command_tx.clone();
+                            let task = task.clone();
+                            let done = CaptureDone { commander: commander.clone(), task_id: task.id.clone() };
+                            let capture = thread::spawn(move || {
+                                let _done = done;
+                                match Self::capture_output(output_tx, new_kid, pane_id, throttle, &task, health, running_children, changes) {
+                                    Err(e) => { error!("{}: Run failed: {}", task.id, e) }
+                                    Ok(output) => {
+                                        let output = [output.stdout.as_slice(), output.stderr.as_slice()].concat();
+                                        Self::log_output(&task_logs, &task.id, &output);
+                                        if let Err(e) = Self::fire_triggers(&triggers, &task.id, &output, true, &commander) {
+                                            // The command channel is gone once we're shutting down
+                                            error!("{}: Could not fire triggers: {}", task.id, e);
+                                        }
+                                    }
+                                }
                             });
+                            self.captures.insert(task_id.to_string(), capture);
                         }
                     }
                 }
@@ -124,17 +293,106 @@ impl ProcessOrchestrator {
         Ok(())
     }
 
-    fn start_forward_output_loop(mut reader: Box<dyn Read + Send>, sender: Sender<ProcOutput>) -> anyhow::Result<()> {
-        thread::spawn(move || {
-            let pane = "main".to_string(); // Always the same name
-            let mut output = [0u8; 1024];
+    /***
+    Draw the latest system stats in a sysstats task's pane
+     */
+    fn show_sys_stats(&mut self, task_id: &str) -> anyhow::Result<()> {
+        if let Ok(mut last_runs) = self.last_runs.write() {
+            last_runs.insert(task_id.to_string(), SystemTime::now());
+        }
+
+        let stats = self.sys_stats.get_or_insert_with(SysStats::new).snapshot()?;
+        self.output_tx.send(ProcOutput { name: task_id.to_string(), output: stats.render().into_bytes() })?;
+        Ok(())
+    }
+
+    fn log_output(task_logs: &Option<Arc<Mutex<TaskLogs>>>, task_id: &str, output: &[u8]) {
+        if let Some(Ok(mut logs)) = task_logs.as_ref().map(|logs| logs.lock()) {
+            logs.write(task_id, output);
+        }
+    }
+
+    /***
+    Run any task waiting on a pattern in this output. `complete` output is a whole run's worth.
+     */
+    fn fire_triggers(triggers: &Mutex<Vec<OutputTrigger>>, source: &str, output: &[u8], complete: bool, commander: &Sender<String>) -> anyhow::Result<()> {
+        let fired = match triggers.lock() {
+            Err(_) => { return Ok(()) }
+            Ok(mut triggers) => {
+                triggers.iter_mut().filter_map(|trigger| {
+                    let matched = if complete { trigger.scan_complete(source, output) } else { trigger.scan(source, output) };
+                    if matched { Some(trigger.task.clone()) } else { None }
+                }).collect::<Vec<_>>()
+            }
+        };
+
+        for task_id in fired {
+            info!("{}: Triggered by output from {} - sending local_execute", task_id, source);
+            commander.send(format!("local_execute: {}", task_id))?;
+        }
+        Ok(())
+    }
+
+    fn start_forward_output_loop(mut reader: Box<dyn Read + Send>, sender: OutputSender, main_output: Arc<MainOutput>,
+                                  triggers: Arc<Mutex<Vec<OutputTrigger>>>, task_logs: Option<Arc<Mutex<TaskLogs>>>,
+                                  main_task: Arc<RwLock<Option<TaskId>>>, commander: Sender<String>) -> anyhow::Result<()> {
+        let pane = "main".to_string(); // Always the same name
+        let mut output = [0u8; 1024];
+        let mut throttle = OutputThrottle::new(main_output.rate.load(Ordering::Relaxed));
+        let mut decoder = Decoder::new(Encoding::Utf8);
+        let mut stripper = AnsiStripper::default();
+        let mut held = Vec::new();
+        Supervisor::new("main output").spawn(move || {
             loop {
                 info!("main: Reading from output reader");
                 let size = reader.read(&mut output).unwrap_or(0);
                 info!("main: Read {} bytes", size);
                 if size > 0 {
-                    let output = String::from_utf8(output[..size].to_owned()).unwrap();
-                    sender.send(ProcOutput {    name: pane.clone(), output }).unwrap();
+                    let source = main_task.read().ok().and_then(|t| t.clone());
+                    if let Some(source) = source {
+                        Self::log_output(&task_logs, &source, &output[..size]);
+                        Self::fire_triggers(&triggers, &source, &output[..size], false, &commander)?;
+                    }
+
+                    // Keep reading even when we're dropping output, so the child never stalls
+                    throttle.set_max_bytes_per_sec(main_output.rate.load(Ordering::Relaxed));
+                    let encoding = main_output.encoding.read().map(|e| *e).unwrap_or_default();
+                    if encoding != decoder.encoding() {
+                        decoder = Decoder::new(encoding);
+                    }
+                    let decoded = decoder.feed(&output[..size]);
+                    let decoded = match main_output.strip_ansi.load(Ordering::Relaxed) {
+                        true => { Cow::Owned(stripper.feed(&decoded)) }
+                        false => {
+                            stripper = AnsiStripper::default();
+                            decoded
+                        }
+                    };
+                    // Filtered a line at a time, so a match can't be split between two reads. The
+                    // end of a line waits for the rest of it - unless the read didn't fill the
+                    // buffer, when it's likely all there is for now (e.g. a prompt).
+                    held.extend_from_slice(&decoded);
+                    let filters = main_output.filters.read().ok();
+                    let filters = filters.as_deref().filter(|f| !f.is_empty());
+                    let ready = match filters {
+                        Some(_) if size == output.len() && held.len() < MAX_HELD_LINE => {
+                            held.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1)
+                        }
+                        _ => { held.len() }
+                    };
+                    if ready == 0 {
+                        continue;
+                    }
+                    let lines = held.drain(..ready).collect::<Vec<u8>>();
+                    let filtered = filters.map(|f| f.apply(&lines));
+                    if let Some(admitted) = throttle.admit(filtered.as_deref().unwrap_or(&lines)) {
+                        let output = ProcOutput { name: pane.clone(), output: admitted };
+                        let typed_at = main_output.typed_at.lock().ok().and_then(|t| *t);
+                        match typed_at {
+                            Some(typed_at) if typed_at.elapsed() < ECHO_WINDOW => { sender.send_echo(output)? }
+                            _ => { sender.send(output)? }
+                        }
+                    }
                 }
             }
         });
@@ -142,55 +400,493 @@ impl ProcessOrchestrator {
         Ok(())
     }
 
-    fn capture_output(sender: Sender<ProcOutput>, child: ChildProcess, pane: String) -> anyhow::Result<()> {
-        info!("{}: Running {} non-interactively", pane, child.command);
+    /***
+    Wait for the active child to exit, then mark the end of its run in the main pane
+     */
+    fn start_exit_watch(active_child: Arc<Mutex<Option<Box<dyn Child + Send>>>>, pid: Option<u32>, sender: OutputSender) {
+        let started = Instant::now();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(EXIT_POLL);
+                let mut active = match active_child.lock() {
+                    Ok(active) => { active }
+                    Err(_) => { return }
+                };
+                // Stop watching once some other child has taken its place
+                let child = match active.as_mut().filter(|c| c.process_id() == pid) {
+                    None => { return }
+                    Some(child) => { child }
+                };
+                // portable_pty only says whether the child succeeded, so see how it ended before
+                // try_wait reaps it
+                let ended = match pid.map(exit_status) {
+                    Some(Ok(None)) => { continue }
+                    Some(Ok(Some(ended))) => { Some(ended) }
+                    _ => { None }
+                };
+                let status = child.try_wait();
 
-        let mut cmd_and_args = child.command.split_ascii_whitespace();
-        let command = cmd_and_args.next().unwrap();
-        let args = cmd_and_args.collect::<Vec<_>>();
+                match status {
+                    Ok(None) => {}
+                    Ok(Some(status)) => {
+                        info!("main: Active child exited, successfully: {}", status.success());
+                        *active = None;
+                        let code = ended.unwrap_or_else(|| String::from(if status.success() { "code 0" } else { "failed" }));
+                        let banner = ChildProcess::exit_banner(&code, status.success(), started.elapsed());
+                        let _ = sender.send(ProcOutput { name: String::from("main"), output: banner });
+                        return;
+                    }
+                    Err(e) => {
+                        error!("main: Could not check on the active child: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
 
-        let mut cmd = Command::new(command);
-        cmd.current_dir(child.path.clone());
-        if args.len() > 0 { cmd.args(args); }
+    /***
+    Join the capture threads that have finished. One that panicked leaves its task unhealthy,
+    with the panic shown in its pane.
+     */
+    fn reap_captures(&mut self) {
+        let finished = self.captures.iter().
+            filter(|(_, capture)| capture.is_finished()).
+            map(|(task_id, _)| task_id.clone()).
+            collect::<Vec<_>>();
 
-        let stdout = String::from_utf8(cmd.output()?.stdout)?;
-        let stderr = String::from_utf8(cmd.output()?.stderr)?;
+        for task_id in finished {
+            self.reap_capture(&task_id);
+        }
+    }
+
+    fn reap_capture(&mut self, task_id: &str) {
+        let panic = match self.captures.remove(task_id).map(|capture| capture.join()) {
+            Some(Err(panic)) => { panic }
+            _ => { return }
+        };
+        let message = panic.downcast_ref::<&str>().map(|s| s.to_string()).
+            or_else(|| panic.downcast_ref::<String>().cloned()).
+            unwrap_or_else(|| String::from("unknown error"));
+        error!("{}: Capturing output panicked: {}", task_id, message);
+
+        if let Ok(mut health) = self.health.write() {
+            health.insert(task_id.to_string(), TaskHealth::Unhealthy);
+        }
+        if let Ok(mut running) = self.running_children.lock() {
+            running.remove(task_id);
+        }
+        let output = format!("\r\n\x1b[0;31mdecker: capturing this task's output failed: {}\x1b[0m\r\n", message);
+        if let Err(e) = self.output_tx.send(ProcOutput { name: task_id.to_string(), output: output.into_bytes() }) {
+            error!("{}: Could not report the failure: {}", task_id, e);
+        }
+    }
+
+    /***
+    Whether another non-interactive run fits under the overall limit and the task's group's
+     */
+    fn has_room_for(&self, task: &Task) -> bool {
+        let group_limit = task.group.as_ref().and_then(|g| self.group_limits.get(g).map(|limit| (g, *limit)));
+        let group_full = match group_limit {
+            None => { false }
+            Some((group, limit)) => {
+                let running = self.captures.keys().
+                    filter(|id| self.tasks.get(*id).and_then(|t| t.group.as_ref()) == Some(group)).
+                    count();
+                running >= limit
+            }
+        };
+        self.captures.len() < self.max_running && !group_full
+    }
+
+    /***
+    Whether a task's first run should wait for one of its dependencies' first run to finish. Only
+    dependencies on their way to running count, so one that never runs can't hold things up.
+     */
+    fn waiting_on_dependencies(&self, task: &Task) -> bool {
+        let first_run = self.last_runs.read().map(|runs| !runs.contains_key(&task.id)).unwrap_or(false);
+        first_run && task.depends_on.iter().flatten().any(|dep| {
+            !self.finished.contains(dep) &&
+                (self.captures.contains_key(dep) || self.pending.contains(dep) || self.awaiting_dependencies.contains(dep))
+        })
+    }
 
-        if !stdout.is_empty() {
-            info!("{}: Sending {}", pane, stdout);
-            sender.send(ProcOutput { name: pane.clone(), output: format!("\x1B[2J{}", stdout) })?;
+    /***
+    Queue the first runs of tasks that are no longer waiting on their dependencies
+     */
+    fn release_dependents(&mut self) {
+        let ready = self.awaiting_dependencies.iter().
+            filter(|id| self.tasks.get(*id).map(|task| !self.waiting_on_dependencies(task)).unwrap_or(true)).
+            cloned().
+            collect::<Vec<_>>();
+        for task_id in ready {
+            self.awaiting_dependencies.remove(&task_id);
+            self.pending.push_back(task_id);
         }
+    }
 
-        if !stderr.is_empty() {
-            info!("{}: Sending (Err) {}", pane, stderr);
-            sender.send(ProcOutput { name: pane, output: stderr })?;
+    /***
+    Start queued runs, oldest first, for as long as there's room. Runs that still don't fit go
+    back in the queue in the same order.
+     */
+    fn start_pending(&mut self) -> anyhow::Result<()> {
+        self.release_dependents();
+        for task_id in std::mem::take(&mut self.pending) {
+            if let Err(e) = self.execute(&task_id) {
+                error!("{}: Could not start its queued run: {}", task_id, e);
+            }
         }
         Ok(())
     }
 
-    fn start_forward_input_loop(input_rx: Receiver<String>, mut input_tx: Box<dyn Write + Send>, pane: String) {
+    #[allow(clippy::too_many_arguments)]
+    fn capture_output(sender: OutputSender, child: ChildProcess, pane: String, mut throttle: OutputThrottle, task: &Task, health: HealthMap,
+                      running_children: Arc<Mutex<HashMap<TaskId, std::process::Child>>>, changes: Arc<ChangeHighlighter>) -> anyhow::Result<Output> {
+        info!("{}: Running {} non-interactively", pane, child.command);
+
+        let input = task.stdin_bytes()?;
+        let stdin = if input.is_some() { Stdio::piped() } else { Stdio::null() };
+
+        let started = Instant::now();
+        let mut running = child.command().stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        let pid = running.id();
+        fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+            thread::spawn(move || {
+                let mut read = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut read);
+                }
+                read
+            })
+        }
+        let stdout = read_all(running.stdout.take());
+        let stderr = read_all(running.stderr.take());
+
+        // Write from another thread, so a child that fills its stdout before reading all of its
+        // input can't deadlock us. Dropping the pipe afterwards closes it.
+        if let (Some(input), Some(mut child_stdin)) = (input, running.stdin.take()) {
+            let pane = pane.clone();
+            thread::spawn(move || {
+                if let Err(e) = child_stdin.write_all(&input) {
+                    info!("{}: Child stopped reading stdin: {}", pane, e);
+                }
+            });
+        }
+
+        // Hold on to the child while it runs, so the task's group can be killed through it
+        running_children.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(task.id.clone(), running);
+        let exited = wait_for_exit(pid);
+        // Let go of it and wait on it with the lock held, so nothing signals its pid afterwards
+        let status = {
+            let mut running = running_children.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            match (exited, running.remove(&task.id)) {
+                (_, None) => { return Err(anyhow!("pid {} was let go of before it finished", pid)) }
+                (Ok(()), Some(mut child)) => { child.wait()? }
+                (Err(e), Some(mut child)) => {
+                    // Don't hold everyone else up while it finishes
+                    drop(running);
+                    error!("{}: Could not wait for pid {} to exit: {}", pane, pid, e);
+                    child.wait()?
+                }
+            }
+        };
+        let output = Output {
+            status,
+            stdout: stdout.join().map_err(|_| anyhow!("reading stdout panicked"))?,
+            stderr: stderr.join().map_err(|_| anyhow!("reading stderr panicked"))?,
+        };
+
+        let task_health = task.health_of(output.status.code(), &output.stdout);
+        info!("{}: Finished with {:?}, {:?}", pane, output.status, task_health);
+        if let Ok(mut health) = health.write() {
+            health.insert(task.id.clone(), task_health);
+        }
+
+        // The run as it's drawn, minus any highlights, to draw again once they fade
+        let mut plain = Vec::new();
+        let mut highlighted = None;
+        if !output.stdout.is_empty() {
+            info!("{}: Sending {} bytes", pane, output.stdout.len());
+            let mut stdout = b"\x1B[2J".to_vec();
+            let decoded = task.encoding.unwrap_or_default().decode(&output.stdout);
+            let decoded = if task.strip_ansi { Cow::Owned(AnsiStripper::default().feed(&decoded)) } else { decoded };
+            let rewritten = task.filters.as_ref().map(|_| task.output_filters().apply(&decoded));
+            let rewritten = rewritten.as_deref().unwrap_or(&decoded);
+            let filtered = task.display.map(|filter| filter.apply(rewritten));
+            let shown = filtered.as_deref().unwrap_or(rewritten);
+            if task.highlight_changes {
+                highlighted = changes.highlight(&task.id, shown);
+            }
+            // Highlights don't count against the rate. They're only drawn if the whole run fits in it.
+            let admitted = throttle.admit(shown).unwrap_or_default();
+            if admitted.len() != shown.len() {
+                highlighted = None;
+            }
+            stdout.extend(highlighted.clone().unwrap_or(admitted));
+            plain.extend_from_slice(b"\x1B[2J");
+            plain.extend_from_slice(shown);
+            sender.send(ProcOutput { name: pane.clone(), output: stdout })?;
+        }
+
+        if !output.stderr.is_empty() {
+            info!("{}: Sending (Err) {} bytes", pane, output.stderr.len());
+            let decoded = task.encoding.unwrap_or_default().decode(&output.stderr);
+            let decoded = if task.strip_ansi { Cow::Owned(AnsiStripper::default().feed(&decoded)) } else { decoded };
+            let rewritten = task.filters.as_ref().map(|_| task.output_filters().apply(&decoded));
+            if let Some(stderr) = throttle.admit(rewritten.as_deref().unwrap_or(&decoded)) {
+                plain.extend_from_slice(&stderr);
+                sender.send(ProcOutput { name: pane.clone(), output: stderr })?;
+            }
+        }
+
+        // Successful runs just redraw the pane, but a failure should stand out
+        if !output.status.success() {
+            let status = match (output.status.code(), output.status.signal()) {
+                (Some(code), _) => { format!("code {}", code) }
+                (None, Some(signal)) => { format!("signal {}", signal) }
+                (None, None) => { String::from("failed") }
+            };
+            let banner = ChildProcess::exit_banner(&status, false, started.elapsed());
+            plain.extend_from_slice(&banner);
+            sender.send(ProcOutput { name: pane.clone(), output: banner })?;
+        }
+
+        if highlighted.is_some() {
+            Self::fade_highlights(sender, pane, task.id.clone(), changes, throttle, plain);
+        }
+        Ok(output)
+    }
+
+    /***
+    Draw a run again without its highlights once they've been up long enough - unless another
+    run has drawn over it by then. Drawing it again counts against the task's rate like any output.
+     */
+    fn fade_highlights(sender: OutputSender, pane: String, task_id: TaskId, changes: Arc<ChangeHighlighter>, mut throttle: OutputThrottle, plain: Vec<u8>) {
         thread::spawn(move || {
-            while let Ok(input) = input_rx.recv() {
-                write!(input_tx, "{}", input).unwrap();
-                input_tx.flush().unwrap();
+            thread::sleep(changes.fade_after());
+            let shown = plain.strip_prefix(b"\x1B[2J".as_slice()).unwrap_or(&plain);
+            if !changes.is_latest(&task_id, shown) {
+                return;
+            }
+            if let Some(output) = throttle.admit(&plain) {
+                let _ = sender.send(ProcOutput { name: pane, output });
+            }
+        });
+    }
+
+    /***
+    Write typing to the PTY as soon as it arrives - it never waits on output or drawing - and
+    note when it was typed, so the output loop can hurry its echo back
+     */
+    fn start_forward_input_loop(input_rx: Receiver<ProcInput>, mut input_tx: Box<dyn Write + Send>, pane: String, main_output: Arc<MainOutput>) {
+        Supervisor::new(&format!("{} input", pane)).spawn(move || {
+            while let Ok(ProcInput { input, typed_at }) = input_rx.recv() {
+                input_tx.write_all(&input)?;
+                input_tx.flush()?;
+                if let (Some(at), Ok(mut last)) = (typed_at, main_output.typed_at.lock()) {
+                    *last = Some(at);
+                }
             }
 
             info!("{}: Exited input loop!", pane);
             // Send EOF/^D to kill the PTY
-            input_tx.write(&[26, 4]).unwrap();
-            input_tx.flush().unwrap();
+            input_tx.write_all(&[26, 4])?;
+            input_tx.flush()?;
+            Ok(())
         });
     }
 
     /***
-    Activate a child process
+    Activate a child process. There's only the one main PTY, so whatever else was running in it
+    (even the main shell) is stopped to make way - attaching swaps tasks, it doesn't juggle them.
      */
     fn activate_proc(&mut self, name: &str) -> anyhow::Result<()> {
-        // FIXME: Verify this name is in 'tasks'
+        if !self.tasks.contains_key(name) {
+            return Err(anyhow!("no task '{}'", name));
+        }
+        if self.active_proc.as_deref() != Some(name) {
+            self.stop_active();
+        }
+        // The main pane may have been re-tiled since the config said how big it is
+        if let Some(size) = self.sizes.get("main").cloned() {
+            self.sizes.insert(name.to_string(), size);
+        }
         self.active_proc = Some(name.to_string());
+        if let Ok(mut main_task) = self.main_task.write() {
+            *main_task = Some(name.to_string());
+        }
         Ok(())
     }
 
+    /***
+    Stop the active child to make way for another in the main pane. It gets its task's grace
+    period, in the background.
+     */
+    fn stop_active(&mut self) {
+        let mut child = match self.active_child.lock().ok().and_then(|mut active| active.take()) {
+            None => { return }
+            Some(child) => { child }
+        };
+        let pid = match child.process_id() {
+            None => { return }
+            Some(pid) => { pid }
+        };
+        let grace = self.active_proc.as_ref().and_then(|id| self.tasks.get(id)).map(|t| t.stop_grace()).unwrap_or_default();
+        info!("main: Stopping pid {} to make way in the main pane", pid);
+        thread::spawn(move || {
+            let left = terminate(&[(pid, grace)], |pid, signal| {
+                let held = matches!(child.try_wait(), Ok(None));
+                if held { signal_group(pid, signal); }
+                held
+            });
+            if !left.is_empty() {
+                error!("main: pid {} was killed, but never seen to exit", pid);
+            }
+        });
+    }
+
+    fn is_disabled(&self, task_id: &str) -> bool {
+        self.disabled.read().map(|d| d.contains(task_id)).unwrap_or(false)
+    }
+
+    /***
+    Turn a task on or off. Turning it off doesn't stop a run that's already going.
+     */
+    fn set_enabled(&mut self, task_id: &str, enabled: bool) -> anyhow::Result<()> {
+        if !self.tasks.contains_key(task_id) {
+            return Err(anyhow!("no task '{}'", task_id));
+        }
+
+        info!("{}: {}", task_id, if enabled { "Enabled" } else { "Disabled" });
+        if let Ok(mut disabled) = self.disabled.write() {
+            if enabled {
+                disabled.remove(task_id);
+            } else {
+                disabled.insert(task_id.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /***
+    Drop a task's waiting runs - queued, follow-up and its next periodic one - without disabling it.
+    A run already in progress carries on.
+     */
+    fn cancel(&mut self, task_id: &str) -> anyhow::Result<()> {
+        let task = match self.tasks.get(task_id) {
+            None => { return Err(anyhow!("no task '{}'", task_id)) }
+            Some(task) => { task }
+        };
+
+        info!("{}: Cancelling pending runs", task_id);
+        self.pending.retain(|id| id != task_id);
+        self.follow_ups.remove(task_id);
+        self.awaiting_dependencies.remove(task_id);
+        if task.period.is_some() {
+            self.skip_next.insert(task_id.to_string());
+        }
+        // Anything waiting on this task to start up can go ahead
+        self.start_pending()
+    }
+
+    /***
+    The ids of every task in `group`. Naming a group with no tasks is an error.
+     */
+    fn group_members(&self, group: &str) -> anyhow::Result<Vec<TaskId>> {
+        let mut members = self.tasks.values().
+            filter(|task| task.group.as_deref() == Some(group)).
+            map(|task| task.id.clone()).
+            collect::<Vec<_>>();
+        if members.is_empty() {
+            return Err(anyhow!("no tasks in group '{}'", group));
+        }
+        members.sort();
+        Ok(members)
+    }
+
+    /***
+    Run every task in a group right now, paused or not
+     */
+    fn run_group(&mut self, group: &str) -> anyhow::Result<()> {
+        for task_id in self.group_members(group)? {
+            self.execute(&task_id)?;
+        }
+        Ok(())
+    }
+
+    /***
+    Stop (or restart) a group's periodic runs
+     */
+    fn pause_group(&mut self, group: &str, paused: bool) -> anyhow::Result<()> {
+        for task_id in self.group_members(group)? {
+            info!("{}: {}", task_id, if paused { "Paused" } else { "Resumed" });
+            if paused {
+                self.paused.insert(task_id);
+            } else {
+                self.paused.remove(&task_id);
+            }
+        }
+        Ok(())
+    }
+
+    /***
+    Kill whatever a group's tasks are running at the moment
+     */
+    fn kill_group(&mut self, group: &str) -> anyhow::Result<()> {
+        let targets = self.stop_targets(&self.group_members(group)?);
+        let held = self.held_signaller();
+        thread::spawn(move || {
+            let left = terminate(&targets, held);
+            if !left.is_empty() {
+                error!("main: pids {:?} were killed, but never seen to exit", left);
+            }
+        });
+        Ok(())
+    }
+
+    /***
+    The processes running for these tasks right now, and how long each gets to stop
+     */
+    fn stop_targets(&self, task_ids: &[TaskId]) -> Vec<(u32, Duration)> {
+        let running_pids = self.running_children.lock().
+            map(|running| running.iter().map(|(task_id, child)| (task_id.clone(), child.id())).collect::<HashMap<_, _>>()).
+            unwrap_or_default();
+        let active_pid = self.active_child.lock().ok().and_then(|c| c.as_ref().and_then(|c| c.process_id()));
+
+        task_ids.iter().flat_map(|task_id| {
+            let active = if self.active_proc.as_ref() == Some(task_id) { active_pid } else { None };
+            let grace = self.tasks.get(task_id).map(|t| t.stop_grace()).unwrap_or_default();
+            active.into_iter().chain(running_pids.get(task_id).copied()).
+                inspect(move |pid| info!("{}: Stopping pid {}", task_id, pid)).
+                map(move |pid| (pid, grace))
+        }).collect()
+    }
+
+    /***
+    Signal a pid's group through the child we hold for it, for `terminate`. Says whether we still
+    hold it: background tasks are let go of by the thread capturing their output once they exit.
+     */
+    fn held_signaller(&self) -> impl FnMut(u32, c_int) -> bool + Send {
+        let running_children = self.running_children.clone();
+        let active_child = self.active_child.clone();
+        move |pid, signal| {
+            if let Ok(mut active) = active_child.lock() {
+                if let Some(child) = active.as_mut().filter(|c| c.process_id() == Some(pid)) {
+                    let held = matches!(child.try_wait(), Ok(None));
+                    if held { signal_group(pid, signal); }
+                    return held;
+                }
+            }
+            match running_children.lock() {
+                Ok(running) if running.values().any(|child| child.id() == pid) => {
+                    signal_group(pid, signal);
+                    true
+                }
+                _ => { false }
+            }
+        }
+    }
+
     /***
     Handle a requested execution
      */
@@ -198,31 +894,175 @@ impl ProcessOrchestrator {
         info!("Commanded to {}: {}", command, data);
 
         let cmd_result = match command {
-            "execute" | "local_execute" => { self.execute(data) }
+            // Queries respond with data instead of a bare "Success"
+            "status" => { self.status() }
+            "tasks" => {
+                let mut tasks = self.tasks.values().collect::<Vec<_>>();
+                tasks.sort_by(|a, b| a.id.cmp(&b.id));
+                Ok(serde_json::to_string(&tasks)?)
+            }
+            _ => { self.run_command(command, data).map(|_| String::from("Success")) }
+        };
+
+        if !command.starts_with("local") {
+            match cmd_result {
+                Err(e) => { self.resp_tx.send(format!("{}: Error - {}", command, e))? }
+                Ok(resp) => { self.resp_tx.send(format!("{}: {}", command, resp))? }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run_command(&mut self, command: &str, data: &str) -> anyhow::Result<()> {
+        match command {
+            "execute" => { self.execute(data) }
+            "local_execute" => {
+                if self.paused.contains(data) {
+                    debug!("{}: Paused - skipping periodic run", data);
+                    Ok(())
+                } else if self.skip_next.remove(data) {
+                    info!("{}: Skipping cancelled periodic run", data);
+                    Ok(())
+                } else {
+                    self.execute(data)
+                }
+            }
+            "run_group" => { self.run_group(data) }
+            "pause_group" => { self.pause_group(data, true) }
+            "resume_group" => { self.pause_group(data, false) }
+            "kill_group" => { self.kill_group(data) }
+            "cancel" => { self.cancel(data) }
+            "restore" => { self.restore(data) }
+            "enable" => { self.set_enabled(data, true) }
+            "disable" => { self.set_enabled(data, false) }
             "activate" => { self.activate_proc(data) }
             "register" => { self.register_task(data) }
+            "unregister" => { self.unregister_task(data) }
             "resize" => { self.resize_task(data) }
+            "local_resize" => {
+                // Nobody's waiting to hear how it went
+                if let Err(e) = self.resize_task(data) {
+                    error!("Could not resize for {}: {}", data, e);
+                }
+                Ok(())
+            }
             "running" => { if self.running() { Ok(()) } else { Err(anyhow!("not running")) } }
+            "shutdown" => { self.shutdown() }
+            "ping" => { Ok(()) }
+            "local_capture_done" => {
+                // Sent as the capture thread finishes, so joining it hardly waits. Only this
+                // reaps a capture, so a newer run's can't be joined by mistake.
+                self.reap_capture(data);
+                self.finished.insert(data.to_string());
+                if self.follow_ups.remove(data) {
+                    self.pending.push_back(data.to_string());
+                }
+                self.start_pending()
+            }
             _ => {
                 info!("Unsupported command: {}", command);
                 Ok(())
             }
-        };
+        }
+    }
 
-        if !command.starts_with("local") {
-            match cmd_result {
-                Err(e) => { self.resp_tx.send(format!("{}: Error - {}", command, e))? }
-                Ok(()) => { self.resp_tx.send(format!("{}: Success", command))? }
+    /***
+    Set tasks' last runs, and pause or disable them, as a saved session had them
+     */
+    fn restore(&mut self, schedules_str: &str) -> anyhow::Result<()> {
+        let schedules: Vec<TaskStatus> = serde_json::from_str(schedules_str)?;
+        for schedule in schedules {
+            if !self.tasks.contains_key(&schedule.id) {
+                continue;
+            }
+            if let (Some(secs), Ok(mut last_runs)) = (schedule.last_run, self.last_runs.write()) {
+                last_runs.insert(schedule.id.clone(), UNIX_EPOCH + Duration::from_secs(secs));
+            }
+            if schedule.paused {
+                self.paused.insert(schedule.id.clone());
+            }
+            if schedule.disabled {
+                if let Ok(mut disabled) = self.disabled.write() {
+                    disabled.insert(schedule.id.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /***
+    Summarize every registered task as JSON
+     */
+    fn status(&mut self) -> anyhow::Result<String> {
+        let active_running = self.running();
+
+        let mut statuses = self.tasks.values().map(|task| {
+            let active = self.active_proc.as_ref() == Some(&task.id);
+            TaskStatus {
+                id: task.id.clone(),
+                name: task.name.clone(),
+                command: task.command.clone(),
+                period: task.period.clone(),
+                group: task.group.clone(),
+                paused: self.paused.contains(&task.id),
+                disabled: self.is_disabled(&task.id),
+                size: self.sizes.get(&task.id).cloned().flatten(),
+                active,
+                running: (active && active_running) || self.captures.contains_key(&task.id),
+                queued: self.pending.contains(&task.id) || self.awaiting_dependencies.contains(&task.id),
+                follow_up: self.follow_ups.contains(&task.id),
+                skip_next: self.skip_next.contains(&task.id),
+                last_run: self.last_runs.read().ok().and_then(|runs| runs.get(&task.id).copied()).
+                    and_then(|t| t.duration_since(UNIX_EPOCH).ok()).
+                    map(|d| d.as_secs()),
+                health: self.health.read().ok().and_then(|h| h.get(&task.id).copied()),
             }
+        }).collect::<Vec<_>>();
+        statuses.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(serde_json::to_string(&statuses)?)
+    }
+
+    /***
+    Stop the active child, clean up after any others and stop processing commands
+     */
+    fn shutdown(&mut self) -> anyhow::Result<()> {
+        info!("main: Shutting down ProcessOrchestrator");
+
+        // Give everything still running a chance to clean up after itself before it's killed
+        let task_ids = self.tasks.keys().cloned().collect::<Vec<_>>();
+        let left = terminate(&self.stop_targets(&task_ids), self.held_signaller());
+        if !left.is_empty() {
+            error!("main: pids {:?} were killed, but never seen to exit", left);
         }
 
+        // Anything the children left behind may still be holding their output open
+        self.reap_captures();
+        if !self.captures.is_empty() {
+            info!("main: Leaving {} output captures unfinished", self.captures.len());
+        }
+
+        for (task_id, teardown) in self.teardowns.drain() {
+            info!("{}: Tearing down with {:?}", task_id, teardown);
+            match Command::new(&teardown[0]).args(&teardown[1..]).output() {
+                Ok(_) => {}
+                Err(e) => { error!("{}: Teardown failed: {}", task_id, e) }
+            }
+        }
+
+        self.shutdown = true;
         Ok(())
     }
 
     fn running(&mut self) -> bool {
         let child_was_running = self.has_active_task;
 
-        self.has_active_task = match self.active_child.as_mut() {
+        let mut active_child = match self.active_child.lock() {
+            Ok(active_child) => { active_child }
+            Err(_) => { return self.has_active_task }
+        };
+        self.has_active_task = match active_child.as_mut() {
             None => { false }
             Some(child) => { child.try_wait().unwrap().is_none() }
         };
@@ -231,17 +1071,49 @@ impl ProcessOrchestrator {
             // Child is not running. But if it was at the last check, log that it switched off
             if child_was_running {
                 info!("main: Active process has stopped");
-                self.active_child = None;
-                self.active_child = None;
             }
         }
 
         self.has_active_task
     }
 
+    /***
+    Forget everything about a task that isn't running or waiting to run
+     */
+    fn unregister_task(&mut self, task_id: &str) -> anyhow::Result<()> {
+        if !self.tasks.contains_key(task_id) {
+            return Err(anyhow!("no task '{}'", task_id));
+        }
+        if self.captures.contains_key(task_id) || self.active_proc.as_deref() == Some(task_id) || self.pending.iter().any(|id| id == task_id) {
+            return Err(anyhow!("{} is still running", task_id));
+        }
+
+        info!("{}: Unregistering", task_id);
+        self.tasks.remove(task_id);
+        self.sizes.remove(task_id);
+        self.routes.remove(task_id);
+        self.teardowns.remove(task_id);
+        self.paused.remove(task_id);
+        self.follow_ups.remove(task_id);
+        self.skip_next.remove(task_id);
+        self.awaiting_dependencies.remove(task_id);
+        self.finished.remove(task_id);
+        if let Ok(mut last_runs) = self.last_runs.write() { last_runs.remove(task_id); }
+        if let Ok(mut health) = self.health.write() { health.remove(task_id); }
+        if let Ok(mut disabled) = self.disabled.write() { disabled.remove(task_id); }
+        if let Ok(mut periodic) = self.periodic_tasks.write() { periodic.remove(task_id); }
+        if let Ok(mut watched) = self.watched_tasks.write() { watched.remove(task_id); }
+        if let Ok(mut triggers) = self.output_triggers.lock() { triggers.retain(|t| t.task != task_id); }
+        Ok(())
+    }
+
     fn register_task(&mut self, register_str: &str) -> anyhow::Result<()> {
-        let register: RegisterTask = serde_json::from_str(register_str)?;
+        let mut register: RegisterTask = serde_json::from_str(register_str)?;
+        register.task.compile_filters();
         self.sizes.insert(register.task.id.clone(), register.size);
+        if let Some(pane) = register.pane {
+            self.routes.insert(register.task.id.clone(), pane);
+        }
 
         if register.task.period_secs.is_some() {
             match self.periodic_tasks.write() {
@@ -252,6 +1124,25 @@ impl ProcessOrchestrator {
             }
         }
 
+        if register.task.disabled {
+            if let Ok(mut disabled) = self.disabled.write() {
+                disabled.insert(register.task.id.clone());
+            }
+        }
+
+        if let Some(trigger_on) = &register.task.trigger_on {
+            let trigger = OutputTrigger::new(&register.task.id, trigger_on)?;
+            if let Ok(mut triggers) = self.output_triggers.lock() {
+                triggers.push(trigger);
+            }
+        }
+
+        if let Some(patterns) = &register.task.watch {
+            if let Ok(mut watched) = self.watched_tasks.write() {
+                watched.insert(register.task.id.clone(), patterns.clone());
+            }
+        }
+
         self.tasks.insert(register.task.id.clone(), register.task);
 
         Ok(())
@@ -261,22 +1152,64 @@ impl ProcessOrchestrator {
         let resize: ResizeTask = serde_json::from_str(resize_str)?;
         self.sizes.insert(resize.task_id.clone(), resize.size);
 
+        // The main pane's terminal belongs to whichever task is active in it
+        if let ("main", Some((cols, rows))) = (resize.task_id.as_str(), resize.size) {
+            if let Some(active) = self.active_proc.clone() {
+                self.sizes.insert(active, resize.size);
+            }
+            self.main_pty.master.resize(PtySize { rows, cols, pixel_width: 0, pixel_height: 0 })?;
+        }
+
         Ok(())
     }
 
-    fn start_period_task_loop(task_periods: Arc<RwLock<HashMap<TaskId, u64>>>, commander: Sender<String>) {
+    /***
+    Run tasks when the files they watch change
+     */
+    fn start_file_watch_loop(watched_tasks: Arc<RwLock<HashMap<TaskId, Vec<String>>>>, commander: Sender<String>) {
+        let mut watches: HashMap<TaskId, FileWatch> = HashMap::new();
+
+        Supervisor::new("file watch").spawn(move || {
+            loop {
+                // Pick up tasks registered since the last pass
+                if let Ok(watched) = watched_tasks.read() {
+                    for (task_id, patterns) in watched.iter() {
+                        if !watches.contains_key(task_id) {
+                            info!("{}: Watching {:?}", task_id, patterns);
+                            watches.insert(task_id.clone(), FileWatch::new(patterns));
+                        }
+                    }
+                }
+
+                for (task_id, watch) in watches.iter_mut() {
+                    if watch.changed() {
+                        info!("{}: Watched files changed - sending local_execute", task_id);
+                        commander.send(format!("local_execute: {}", task_id))?;
+                    }
+                }
 
+                thread::sleep(Duration::from_millis(500));
+            }
+        });
+    }
+
+    fn start_period_task_loop(task_periods: Arc<RwLock<HashMap<TaskId, u64>>>, last_runs: Arc<RwLock<HashMap<TaskId, SystemTime>>>, commander: Sender<String>) {
+
+        // When we last asked for each task to run. It may have run since, e.g. by hand.
         let mut last_run_times: HashMap<String, SystemTime> = HashMap::new();
 
-        thread::spawn(move || {
+        Supervisor::new("periodic tasks").spawn(move || {
             loop {
                 let now = SystemTime::now();
                 debug!("PTL: Awake - checking for tasks");
 
                 let ready_task_ids = task_periods.read().unwrap().iter().
                     filter(|(t_id, period)| {
-                        let most_recent_run = *last_run_times.get(*t_id).unwrap_or(&UNIX_EPOCH);
-                        let time_since = now.duration_since(most_recent_run).unwrap();
+                        let requested = *last_run_times.get(*t_id).unwrap_or(&UNIX_EPOCH);
+                        let ran = last_runs.read().ok().and_then(|runs| runs.get(*t_id).copied()).unwrap_or(UNIX_EPOCH);
+                        let most_recent_run = requested.max(ran);
+                        // A run that started after we woke up hasn't been running for any time yet
+                        let time_since = now.duration_since(most_recent_run).unwrap_or_default();
                         time_since.as_secs() > **period
                     }).
                     map(|(t_id, _)| t_id.clone()).collect::<Vec<_>>();
@@ -291,7 +1224,7 @@ impl ProcessOrchestrator {
 
                 for task_id in ready_task_ids {
                     info!("PTL: Sending local_execute command for: {}", task_id);
-                    commander.send(format!("local_execute: {}", task_id.to_owned())).unwrap();
+                    commander.send(format!("local_execute: {}", task_id.to_owned()))?;
                     last_run_times.insert(task_id, SystemTime::now());
                 }
             }
@@ -299,13 +1232,21 @@ impl ProcessOrchestrator {
     }
 }
 
+impl Drop for CaptureDone {
+    fn drop(&mut self) {
+        // Lets the orchestrator reap the capture and start a queued run
+        let _ = self.commander.send(format!("local_capture_done: {}", self.task_id));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crossbeam_channel::unbounded;
+    use crate::decker::output::{output_queue, BackpressurePolicy};
 
     fn instance() -> ProcessOrchestrator {
-        let (output_tx, _) = unbounded();
+        let (output_tx, _) = output_queue(50, BackpressurePolicy::Block);
         let (cmd_tx, cmd_rx) = unbounded();
         let (resp_tx, _) = unbounded();
         let (_, input_rx) = unbounded();
@@ -322,7 +1263,10 @@ mod tests {
     #[test]
     fn setting_active_proc_works() {
         let mut po = instance();
+        let task = toml::from_str("id = 'a handle'\nname = 'Shell'\npath = '.'\ncommand = 'sh'").unwrap();
+        po.tasks.insert(String::from("a handle"), task);
         po.activate_proc(&"a handle".to_owned()).unwrap();
         assert_eq!(po.active_proc, Some(String::from("a handle")));
+        assert!(po.activate_proc("missing").is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,29 @@
+/***
+* Sessions
+* A snapshot of a running dashboard - its tasks and panes, what each pane was
+* showing and where each task's schedule had got to - that a new dashboard can
+* pick up from, on this machine or another.
+***/
+mod session_file;
+
+use crate::decker::{Task, TaskId, TaskStatus};
+use crate::decker::config::PaneDefinition;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    // Every registered task, as it was defined, and every configured pane
+    pub tasks: Vec<Task>,
+    pub panes: Vec<PaneDefinition>,
+    // The task running in the main pane
+    pub active: Option<TaskId>,
+    // When each task last ran, and whether it was paused or disabled
+    pub schedules: Vec<TaskStatus>,
+    // The text each task's pane was showing, after whatever had scrolled off the top
+    pub screens: HashMap<TaskId, String>,
+    // How many lines back through that each pane was scrolled. Panes following their task's
+    // output are left out.
+    #[serde(default)]
+    pub scroll_offsets: HashMap<TaskId, usize>,
+}
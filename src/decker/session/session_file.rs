@@ -0,0 +1,117 @@
+use crate::decker::session::Session;
+use crate::decker::config::{DeckerConfig, PaneDefinition};
+use crate::decker::terminal::PaneRequest;
+use crate::decker::{period_to_secs, MasterControl, Task};
+use crossbeam_channel::{bounded, Sender};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{anyhow, Context};
+
+// The render thread looks for pane requests at least this often
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(2);
+
+impl Session {
+    /***
+    Everything needed to pick the running dashboard up again later. `panes` are the configured
+    ones; `pane_requests` reaches the render thread, for what they're showing.
+     */
+    pub fn capture(mcp: &MasterControl, panes: &[PaneDefinition], pane_requests: &Sender<PaneRequest>) -> anyhow::Result<Session> {
+        let (reply_tx, reply_rx) = bounded(1);
+        pane_requests.send(PaneRequest::Snapshot(reply_tx))?;
+        let snapshot = reply_rx.recv_timeout(SNAPSHOT_TIMEOUT).map_err(|_| anyhow!("The panes didn't answer"))?;
+        let scroll_offsets = snapshot.iter().
+            filter(|(_, (_, back))| *back > 0).
+            map(|(task_id, (_, back))| (task_id.clone(), *back)).
+            collect();
+        let screens = snapshot.into_iter().map(|(task_id, (screen, _))| (task_id, screen)).collect();
+
+        let schedules = mcp.status()?;
+        Ok(Session {
+            tasks: mcp.tasks()?,
+            panes: panes.to_vec(),
+            active: schedules.iter().find(|s| s.active).map(|s| s.id.clone()),
+            schedules,
+            screens,
+            scroll_offsets,
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Session> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Could not read session {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("{} isn't a decker session", path.display()))
+    }
+
+    /***
+    Write the session out. It goes to a temporary file first, so a crash part way through can't
+    leave a half-written session in place of the last good one.
+     */
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /***
+    Swap the config's tasks and panes for the session's. Everything else - logging, keys and
+    so on - stays as configured.
+     */
+    pub fn apply(&self, config: &mut DeckerConfig) {
+        config.tasks = self.tasks.clone();
+        config.panes = self.panes.clone();
+    }
+
+    /***
+    Whether a task should run as soon as the session is restored. Periodic tasks wait out
+    whatever's left of their period.
+     */
+    pub fn is_due(&self, task: &Task) -> bool {
+        let period = match task.period.as_deref().map(period_to_secs) {
+            Some(Ok(secs)) => { secs }
+            _ => { return true }
+        };
+        let last_run = self.schedules.iter().find(|s| s.id == task.id).and_then(|s| s.last_run);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        last_run.map(|ran| now.saturating_sub(ran) > period).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn task(id: &str, period: &str) -> Task {
+        toml::from_str(&format!("id = '{}'\nname = '{}'\npath = '.'\ncommand = 'true'\nperiod = '{}'", id, id, period)).unwrap()
+    }
+
+    #[test]
+    fn it_round_trips_through_a_file() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let status = serde_json::from_value(serde_json::json!({
+            "id": "weather", "name": "weather", "command": "true", "period": "1h", "size": [10, 5],
+            "active": false, "running": false, "last_run": now - 60
+        })).unwrap();
+        let session = Session {
+            tasks: vec![task("weather", "1h"), task("time", "1s")],
+            panes: Vec::new(),
+            active: None,
+            schedules: vec![status],
+            screens: HashMap::from([(String::from("weather"), String::from("Sunny"))]),
+            scroll_offsets: HashMap::from([(String::from("weather"), 3)]),
+        };
+
+        let path = std::env::temp_dir().join(format!("decker-session-{}.json", uuid::Uuid::new_v4()));
+        session.save(&path).unwrap();
+        let loaded = Session::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.screens["weather"], "Sunny");
+        assert_eq!(loaded.scroll_offsets["weather"], 3);
+        assert!(!loaded.is_due(&loaded.tasks[0]));
+        assert!(loaded.is_due(&loaded.tasks[1]));
+    }
+}
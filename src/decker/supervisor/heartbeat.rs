@@ -0,0 +1,62 @@
+use crate::decker::supervisor::Heartbeat;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+impl Heartbeat {
+    pub fn new() -> Heartbeat {
+        Heartbeat {
+            started: Instant::now(),
+            last_beat: AtomicU64::new(0),
+            recovery_requested: AtomicBool::new(false),
+        }
+    }
+
+    pub fn beat(&self) {
+        self.last_beat.store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    pub fn since_last_beat(&self) -> Duration {
+        let last_beat = Duration::from_millis(self.last_beat.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last_beat)
+    }
+
+    pub fn request_recovery(&self) {
+        self.recovery_requested.store(true, Ordering::Relaxed);
+    }
+
+    /***
+    Whether a recovery was requested since the last call
+     */
+    pub fn take_recovery_request(&self) -> bool {
+        self.recovery_requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tracks_time_since_the_last_beat() {
+        let heartbeat = Heartbeat::new();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(heartbeat.since_last_beat() >= Duration::from_millis(20));
+
+        heartbeat.beat();
+        assert!(heartbeat.since_last_beat() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn recovery_requests_are_taken_once() {
+        let heartbeat = Heartbeat::new();
+        heartbeat.request_recovery();
+        assert!(heartbeat.take_recovery_request());
+        assert!(!heartbeat.take_recovery_request());
+    }
+}
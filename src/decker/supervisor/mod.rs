@@ -0,0 +1,30 @@
+/***
+* Thread supervision
+* Runs a long-lived component, restarting it if it panics or fails, so one dead thread
+* doesn't silently freeze part of the dashboard. Components that keep failing are given up on.
+***/
+mod supervised;
+mod heartbeat;
+
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::{Duration, Instant};
+
+pub struct Supervisor {
+    name: String,
+    // Give up after this many restarts within `window`
+    max_restarts: usize,
+    window: Duration,
+    // Pause before each restart, so a component failing instantly doesn't spin
+    delay: Duration,
+}
+
+/***
+Lets a busy loop show it's still making progress, so another thread can notice when it stalls
+ */
+pub struct Heartbeat {
+    started: Instant,
+    // Milliseconds after `started` of the most recent beat
+    last_beat: AtomicU64,
+    // Set by whoever noticed a stall, for the loop to act on once it's moving again
+    recovery_requested: AtomicBool,
+}
@@ -0,0 +1,109 @@
+use crate::decker::supervisor::Supervisor;
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use log::{info, warn, error};
+
+impl Supervisor {
+    pub fn new(name: &str) -> Supervisor {
+        Supervisor {
+            name: name.to_string(),
+            max_restarts: 5,
+            window: Duration::from_secs(60),
+            delay: Duration::from_millis(250),
+        }
+    }
+
+    /***
+    Run `body` in the current thread until it returns Ok, restarting it whenever it panics or
+    returns an error. Returns false if it had to give up.
+    `body` should keep whatever it needs between runs (channels, readers) in its captures.
+     */
+    pub fn run<F>(&self, mut body: F) -> bool where F: FnMut() -> anyhow::Result<()> {
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+
+        loop {
+            let failure = match panic::catch_unwind(AssertUnwindSafe(&mut body)) {
+                Ok(Ok(_)) => {
+                    info!("{}: Stopped", self.name);
+                    return true;
+                }
+                Ok(Err(e)) => { format!("failed: {}", e) }
+                Err(payload) => { format!("panicked: {}", Self::panic_message(&payload)) }
+            };
+            error!("{}: {}", self.name, failure);
+
+            let now = Instant::now();
+            while restarts.front().map(|t| now.duration_since(*t) > self.window).unwrap_or(false) {
+                restarts.pop_front();
+            }
+            if restarts.len() >= self.max_restarts {
+                error!("{}: Failed {} times in {:?} - giving up", self.name, restarts.len() + 1, self.window);
+                return false;
+            }
+            restarts.push_back(now);
+
+            thread::sleep(self.delay);
+            warn!("{}: Restarting", self.name);
+        }
+    }
+
+    /***
+    Run `body` under supervision on a new thread
+     */
+    pub fn spawn<F>(self, body: F) -> JoinHandle<bool> where F: FnMut() -> anyhow::Result<()> + Send + 'static {
+        thread::Builder::new().
+            name(self.name.clone()).
+            spawn(move || self.run(body)).
+            expect("Could not start thread")
+    }
+
+    fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+        if let Some(msg) = payload.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            String::from("<unknown panic>")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quick(name: &str) -> Supervisor {
+        Supervisor { delay: Duration::from_millis(0), ..Supervisor::new(name) }
+    }
+
+    #[test]
+    fn it_restarts_after_panics_and_errors() {
+        let mut runs = 0;
+        let finished = quick("flaky").run(|| {
+            runs += 1;
+            match runs {
+                1 => { panic!("boom") }
+                2 => { anyhow::bail!("oops") }
+                _ => { Ok(()) }
+            }
+        });
+
+        assert!(finished);
+        assert_eq!(runs, 3);
+    }
+
+    #[test]
+    fn it_gives_up_on_components_that_keep_failing() {
+        let mut runs = 0;
+        let finished = quick("broken").run(|| {
+            runs += 1;
+            anyhow::bail!("still broken")
+        });
+
+        assert!(!finished);
+        assert_eq!(runs, 6);
+    }
+}
@@ -0,0 +1,26 @@
+/***
+* Built-in system stats
+* CPU, memory, load and disk usage for the local machine, read directly rather than by running
+* a `top`-style command. Needs the `sysinfo` feature.
+***/
+mod sys_stats;
+
+pub struct SysStats {
+    // Kept between runs, since CPU usage is measured from one refresh to the next
+    #[cfg(feature = "sysinfo")]
+    system: sysinfo::System,
+    #[cfg(feature = "sysinfo")]
+    disks: sysinfo::Disks,
+}
+
+/***
+One reading of the machine's stats. Sizes are in bytes.
+ */
+pub struct StatsSnapshot {
+    pub cpu_percent: f32,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub load: [f64; 3],
+    // Mount point, bytes used, bytes total
+    pub disks: Vec<(String, u64, u64)>,
+}
@@ -0,0 +1,88 @@
+use crate::decker::sysstats::{StatsSnapshot, SysStats};
+
+const GIB: f64 = (1024 * 1024 * 1024) as f64;
+
+#[cfg(feature = "sysinfo")]
+impl SysStats {
+    pub fn new() -> SysStats {
+        SysStats { system: sysinfo::System::new(), disks: sysinfo::Disks::new_with_refreshed_list() }
+    }
+
+    pub fn snapshot(&mut self) -> anyhow::Result<StatsSnapshot> {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        self.disks.refresh();
+
+        let load = sysinfo::System::load_average();
+        let disks = self.disks.list().iter().
+            filter(|disk| disk.total_space() > 0).
+            map(|disk| (disk.mount_point().display().to_string(), disk.total_space() - disk.available_space(), disk.total_space())).
+            collect();
+
+        Ok(StatsSnapshot {
+            cpu_percent: self.system.global_cpu_info().cpu_usage(),
+            memory_used: self.system.used_memory(),
+            memory_total: self.system.total_memory(),
+            load: [load.one, load.five, load.fifteen],
+            disks,
+        })
+    }
+}
+
+#[cfg(not(feature = "sysinfo"))]
+impl SysStats {
+    pub fn new() -> SysStats {
+        SysStats {}
+    }
+
+    pub fn snapshot(&mut self) -> anyhow::Result<StatsSnapshot> {
+        Err(anyhow::anyhow!("decker was built without system stats - rebuild with `--features sysinfo`"))
+    }
+}
+
+impl Default for SysStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn percent(used: u64, total: u64) -> u64 {
+    (used * 100).checked_div(total).unwrap_or(0)
+}
+
+impl StatsSnapshot {
+    /***
+    Draw these stats as a screenful of text, one stat per line
+     */
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            format!("CPU   {:.0}%", self.cpu_percent),
+            format!("Mem   {:.1}/{:.1}G {}%", self.memory_used as f64 / GIB, self.memory_total as f64 / GIB,
+                    percent(self.memory_used, self.memory_total)),
+            format!("Load  {:.2} {:.2} {:.2}", self.load[0], self.load[1], self.load[2]),
+        ];
+        for (mount, used, total) in &self.disks {
+            lines.push(format!("Disk  {} {:.1}/{:.1}G {}%", mount, *used as f64 / GIB, *total as f64 / GIB, percent(*used, *total)));
+        }
+
+        // Start from a clean pane each time
+        format!("\x1b[H\x1b[2J{}", lines.join("\r\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renders_one_stat_per_line() {
+        let stats = StatsSnapshot {
+            cpu_percent: 12.4,
+            memory_used: GIB as u64,
+            memory_total: 4 * GIB as u64,
+            load: [0.5, 0.25, 0.75],
+            disks: vec![(String::from("/"), 3 * GIB as u64, 12 * GIB as u64)],
+        };
+        assert_eq!(stats.render(), "\x1b[H\x1b[2JCPU   12%\r\nMem   1.0/4.0G 25%\r\nLoad  0.50 0.25 0.75\r\nDisk  / 3.0/12.0G 25%");
+    }
+}
@@ -0,0 +1,59 @@
+/***
+* systemd integration
+* Implements just enough of the sd_notify protocol to tell systemd when we're ready,
+* when we're stopping, and that we're still alive (for WatchdogSec= units).
+* Everything here is a no-op when we weren't started by systemd.
+***/
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+use log::debug;
+
+/***
+Send a state update (e.g. "READY=1") to systemd.
+Returns false if there's no notification socket to send it to.
+ */
+pub fn notify(state: &str) -> anyhow::Result<bool> {
+    let socket_path = match std::env::var("NOTIFY_SOCKET") {
+        Ok(path) if !path.is_empty() => { path }
+        _ => { return Ok(false) }
+    };
+
+    debug!("systemd: Notifying {}", state);
+    let socket = UnixDatagram::unbound()?;
+
+    match socket_path.strip_prefix('@') {
+        None => { socket.send_to(state.as_bytes(), &socket_path)?; }
+        Some(abstract_name) => { send_to_abstract(&socket, abstract_name, state)?; }
+    }
+
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn send_to_abstract(socket: &UnixDatagram, name: &str, state: &str) -> anyhow::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_to_abstract(_socket: &UnixDatagram, name: &str, _state: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Abstract notification socket @{} is only supported on Linux", name)
+}
+
+/***
+How often systemd expects to hear from us, if it's watching at all
+ */
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None; // The watchdog is meant for someone else
+        }
+    }
+
+    std::env::var("WATCHDOG_USEC").ok().
+        and_then(|usec| usec.parse::<u64>().ok()).
+        filter(|usec| *usec > 0).
+        map(Duration::from_micros)
+}
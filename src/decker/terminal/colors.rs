@@ -0,0 +1,183 @@
+use crate::decker::terminal::{Color, ColorDepth};
+use std::process::Command;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// What the real terminal can show. Every pane draws to the same one, so it's set once at startup.
+static COLOR_DEPTH: AtomicU8 = AtomicU8::new(ColorDepth::TrueColor as u8);
+
+pub fn set_color_depth(depth: ColorDepth) {
+    COLOR_DEPTH.store(depth as u8, Ordering::Relaxed);
+}
+
+pub fn color_depth() -> ColorDepth {
+    match COLOR_DEPTH.load(Ordering::Relaxed) {
+        0 => { ColorDepth::Monochrome }
+        1 => { ColorDepth::Eight }
+        2 => { ColorDepth::TwoFiftySix }
+        _ => { ColorDepth::TrueColor }
+    }
+}
+
+/***
+The SGR for decker's own warnings: white on red, or just bold and reversed without colors
+ */
+pub fn alert_sgr() -> &'static str {
+    match color_depth() {
+        ColorDepth::Monochrome => { "\x1b[1;7m" }
+        _ => { "\x1b[1;37;41m" }
+    }
+}
+
+impl ColorDepth {
+    /***
+    Work out what the terminal we're running in can show from $COLORTERM, $TERM, and failing
+    those, terminfo. A non-empty $NO_COLOR means no colors at all (see https://no-color.org).
+     */
+    pub fn detect() -> ColorDepth {
+        if std::env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false) {
+            return ColorDepth::Monochrome;
+        }
+        let term = std::env::var("TERM").ok();
+        let colorterm = std::env::var("COLORTERM").ok();
+        let terminfo_colors = || {
+            Command::new("tput").arg("colors").output().ok().
+                filter(|output| output.status.success()).
+                and_then(|output| String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+        };
+        Self::from_env(term.as_deref(), colorterm.as_deref(), terminfo_colors)
+    }
+
+    fn from_env(term: Option<&str>, colorterm: Option<&str>, terminfo_colors: impl FnOnce() -> Option<u32>) -> ColorDepth {
+        if matches!(colorterm, Some("truecolor") | Some("24bit")) {
+            return ColorDepth::TrueColor;
+        }
+        let term = term.unwrap_or_default();
+        if term.ends_with("-direct") {
+            return ColorDepth::TrueColor;
+        }
+        if term.contains("256color") {
+            return ColorDepth::TwoFiftySix;
+        }
+        match terminfo_colors() {
+            Some(colors) if colors >= 1 << 24 => { ColorDepth::TrueColor }
+            Some(colors) if colors >= 256 => { ColorDepth::TwoFiftySix }
+            _ => { ColorDepth::Eight }
+        }
+    }
+}
+
+impl Color {
+    /***
+    The nearest color a terminal of the given depth can show. None if it shouldn't be shown at all.
+     */
+    pub fn degrade(self, depth: ColorDepth) -> Option<Color> {
+        let color = match (self, depth) {
+            (_, ColorDepth::Monochrome) => { return None }
+            (_, ColorDepth::TrueColor) => { self }
+            (Color::RGB(r, g, b), ColorDepth::TwoFiftySix) => { Color::TWOFIFTYSIX(rgb_to_256(r, g, b)) }
+            (Color::RGB(r, g, b), ColorDepth::Eight) => { rgb_to_8(r, g, b) }
+            (Color::TWOFIFTYSIX(n), ColorDepth::Eight) => {
+                match n {
+                    0..=7 => { Color::eight_color(n) }
+                    8..=15 => { Color::eight_color(n - 8) }
+                    _ => {
+                        let (r, g, b) = palette_256(n);
+                        rgb_to_8(r, g, b)
+                    }
+                }
+            }
+            _ => { self }
+        };
+        Some(color)
+    }
+}
+
+// xterm's sixteen basic colors, normal then bright
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0), (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0), (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+impl Color {
+    /***
+    What this color looks like in a typical terminal. The eight basic colors have bright
+    versions, which bold text is drawn in.
+     */
+    pub fn to_rgb(self, bright: bool) -> (u8, u8, u8) {
+        match self {
+            Color::RGB(r, g, b) => { (r, g, b) }
+            Color::TWOFIFTYSIX(n) if n < 16 => { BASIC_COLORS[n as usize] }
+            Color::TWOFIFTYSIX(n) => { palette_256(n) }
+            basic => { BASIC_COLORS[(basic.to_offset() + if bright { 8 } else { 0 }) as usize] }
+        }
+    }
+}
+
+// The levels of each channel in the 256 color palette's 6x6x6 cube
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |v: u8| CUBE_LEVELS.iter().enumerate().min_by_key(|(_, l)| (**l as i16 - v as i16).abs()).unwrap().0 as u8;
+    let (lr, lg, lb) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * lr + 6 * lg + lb;
+
+    // Greys are usually closer to one of the 24 step grey ramp than to the cube
+    let grey = (r as u16 + g as u16 + b as u16) / 3;
+    let ramp = ((grey.saturating_sub(3)) / 10).min(23) as u8;
+    let ramp_level = 8 + 10 * ramp as i32;
+
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        [(cr, r), (cg, g), (cb, b)].iter().map(|(a, b)| (*a as i32 - *b as i32).pow(2)).sum::<i32>()
+    };
+    let cube_rgb = (CUBE_LEVELS[lr as usize], CUBE_LEVELS[lg as usize], CUBE_LEVELS[lb as usize]);
+    let ramp_rgb = (ramp_level as u8, ramp_level as u8, ramp_level as u8);
+    if distance(ramp_rgb) < distance(cube_rgb) { 232 + ramp } else { cube }
+}
+
+// Each channel that's at least half as bright as the brightest counts, in ANSI's r=1, g=2, b=4 order
+fn rgb_to_8(r: u8, g: u8, b: u8) -> Color {
+    let brightest = r.max(g).max(b);
+    if brightest < 64 {
+        return Color::Black;
+    }
+    let on = |v: u8| (v as u16 * 2 > brightest as u16) as u8;
+    Color::eight_color(on(r) | on(g) << 1 | on(b) << 2)
+}
+
+// What a color from the 256 color palette's cube or grey ramp looks like
+fn palette_256(n: u8) -> (u8, u8, u8) {
+    if n >= 232 {
+        let v = 8 + 10 * (n - 232);
+        (v, v, v)
+    } else {
+        let n = n - 16;
+        (CUBE_LEVELS[(n / 36) as usize], CUBE_LEVELS[(n / 6 % 6) as usize], CUBE_LEVELS[(n % 6) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_detects_color_depth() {
+        let none = || None;
+        assert_eq!(ColorDepth::from_env(Some("xterm"), Some("truecolor"), none), ColorDepth::TrueColor);
+        assert_eq!(ColorDepth::from_env(Some("screen-256color"), None, none), ColorDepth::TwoFiftySix);
+        assert_eq!(ColorDepth::from_env(Some("linux"), None, || Some(8)), ColorDepth::Eight);
+        assert_eq!(ColorDepth::from_env(Some("rxvt"), None, || Some(256)), ColorDepth::TwoFiftySix);
+        assert_eq!(ColorDepth::from_env(None, None, none), ColorDepth::Eight);
+    }
+
+    #[test]
+    fn it_degrades_colors() {
+        assert_eq!(Color::RGB(255, 0, 0).degrade(ColorDepth::TwoFiftySix), Some(Color::TWOFIFTYSIX(196)));
+        assert_eq!(Color::RGB(128, 128, 128).degrade(ColorDepth::TwoFiftySix), Some(Color::TWOFIFTYSIX(244)));
+        assert_eq!(Color::RGB(255, 128, 0).degrade(ColorDepth::Eight), Some(Color::Yellow));
+        assert_eq!(Color::RGB(20, 20, 30).degrade(ColorDepth::Eight), Some(Color::Black));
+        assert_eq!(Color::TWOFIFTYSIX(21).degrade(ColorDepth::Eight), Some(Color::Blue));
+        assert_eq!(Color::TWOFIFTYSIX(9).degrade(ColorDepth::Eight), Some(Color::Red));
+        assert_eq!(Color::RGB(1, 2, 3).degrade(ColorDepth::TrueColor), Some(Color::RGB(1, 2, 3)));
+        assert_eq!(Color::Red.degrade(ColorDepth::Monochrome), None);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::decker::backend::Goto;
+use crate::decker::terminal::Pane;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use log::info;
+
+// How long typing stays on screen without the task echoing it, e.g. at a password prompt
+const ECHO_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Pane {
+    /***
+    Draw keystrokes at the cursor as they're typed, rather than waiting for the task to echo them.
+    Handy over a slow ssh connection.
+     */
+    pub fn set_local_echo(&mut self, on: bool) {
+        self.local_echo = on;
+        self.drop_predictions();
+    }
+
+    pub fn local_echo(&self) -> bool {
+        self.local_echo
+    }
+
+    /***
+    Guess what the task will echo for keystrokes typed at `typed_at`. Printable characters go at
+    the cursor and backspace takes one back. Anything else - Enter, arrows, control keys - could do
+    anything, so we stop guessing until the task has answered. If it already has, there's nothing
+    to guess.
+     */
+    pub fn predict(&mut self, typed: &[u8], typed_at: Instant) {
+        if !self.local_echo || self.output_at.map(|at| at > typed_at).unwrap_or(false) { return; }
+
+        for c in String::from_utf8_lossy(typed).chars() {
+            match c {
+                _ if self.echo_paused => {}
+                '\x7f' | '\x08' => {
+                    if self.predictions.pop_back().is_some() {
+                        self.invalidate();
+                    }
+                }
+                c if c.is_control() => {
+                    self.drop_predictions();
+                    self.echo_paused = true;
+                }
+                c => { self.predictions.push_back(c); }
+            }
+        }
+        self.predicted_at = Some(Instant::now());
+    }
+
+    pub fn has_predictions(&self) -> bool {
+        !self.predictions.is_empty()
+    }
+
+    /***
+    The task printed `c`. If it's the next character we guessed, the guess was right and the real
+    thing replaces it. Otherwise our guesses are no good.
+     */
+    pub(super) fn confirm_echo(&mut self, c: char) {
+        match self.predictions.front() {
+            None => {}
+            Some(&predicted) if predicted == c => { self.predictions.pop_front(); }
+            Some(_) => {
+                info!("{}: The task echoed {:?}, not what we guessed", self.id, c);
+                self.drop_predictions();
+            }
+        }
+    }
+
+    /***
+    Stop drawing guesses, painting over any already drawn
+     */
+    fn drop_predictions(&mut self) {
+        if !self.predictions.is_empty() {
+            self.predictions.clear();
+            self.invalidate();
+        }
+    }
+
+    /***
+    Draw the guesses, underlined, from the cursor. Guesses the task has sat on too long go.
+     */
+    pub(super) fn write_predictions(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        if self.predicted_at.map(|at| at.elapsed() > ECHO_TIMEOUT).unwrap_or(false) {
+            self.predicted_at = None;
+            self.drop_predictions();
+        }
+        if self.predictions.is_empty() { return Ok(()); }
+
+        let cursor = self.view_port.cursor();
+        let (x, y) = (cursor.x().min(self.width().saturating_sub(1)), cursor.y().min(self.height().saturating_sub(1)));
+        let text = self.predictions.iter().take(self.width().saturating_sub(x) as usize).collect::<String>();
+        write!(frame, "{}\x1b[0m\x1b[4m{}\x1b[0m", Goto(self.x + x, self.content_y() + y), text)?;
+        Ok(())
+    }
+
+    /***
+    How far right of the task's own cursor the guesses leave it
+     */
+    pub(super) fn predicted_columns(&self) -> u16 {
+        self.predictions.len().min(u16::MAX as usize) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::terminal::Pane;
+    use std::time::Instant;
+
+    #[test]
+    fn it_echoes_typing_until_the_task_does() {
+        let mut pane = Pane::new("main", 1, 1, 2, 10);
+        pane.set_local_echo(true);
+        pane.push("$ ").unwrap();
+        pane.predict(b"lx\x7fs", Instant::now());
+
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("\x1b[4mls\x1b[0m"));
+
+        // The real echo replaces the guesses as it arrives
+        pane.push("l").unwrap();
+        assert_eq!(pane.predictions.iter().collect::<String>(), "s");
+        pane.push("S").unwrap();
+        assert!(!pane.has_predictions());
+
+        // Nothing's guessed after Enter until the task answers
+        pane.predict(b"\rab", Instant::now());
+        assert!(!pane.has_predictions());
+        pane.push("\r\n$ ").unwrap();
+        let typed_at = Instant::now();
+        pane.predict(b"c", typed_at);
+        assert!(pane.has_predictions());
+
+        // Nor once the task has echoed it already
+        pane.push("c").unwrap();
+        pane.predict(b"d", typed_at);
+        assert!(!pane.has_predictions());
+    }
+}
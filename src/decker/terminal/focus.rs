@@ -0,0 +1,111 @@
+use crate::decker::TaskId;
+use crate::decker::terminal::Focus;
+
+impl Focus {
+    pub fn new() -> Focus {
+        Focus { panes: Vec::new(), current: 0, previous: None }
+    }
+
+    pub fn add(&mut self, task_id: &str) {
+        self.panes.push(task_id.to_string());
+        if task_id == "main" {
+            self.current = self.panes.len() - 1;
+        }
+    }
+
+    /***
+    Forget a pane that's gone. If it had focus, the next pane gets it.
+     */
+    pub fn remove(&mut self, task_id: &str) {
+        if let Some(index) = self.panes.iter().position(|p| p == task_id) {
+            self.panes.remove(index);
+            if index < self.current {
+                self.current -= 1;
+            }
+            if self.current >= self.panes.len() {
+                self.current = 0;
+            }
+            self.previous = None;
+        }
+    }
+
+    pub fn focused(&self) -> Option<&TaskId> {
+        self.panes.get(self.current)
+    }
+
+    /***
+    Move focus to the next pane, wrapping around after the last
+     */
+    pub fn cycle(&mut self) -> Option<&TaskId> {
+        if !self.panes.is_empty() {
+            self.previous = Some(self.current);
+            self.current = (self.current + 1) % self.panes.len();
+        }
+        self.focused()
+    }
+
+    /***
+    Flip focus back to the pane that had it last, e.g. to hop between an editor and a shell
+     */
+    pub fn last(&mut self) -> Option<&TaskId> {
+        if let Some(previous) = self.previous {
+            self.previous = Some(self.current);
+            self.current = previous;
+        }
+        self.focused()
+    }
+}
+
+impl Default for Focus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_starts_on_main_and_cycles() {
+        let mut focus = Focus::new();
+        assert_eq!(focus.cycle(), None);
+
+        focus.add("time");
+        focus.add("main");
+        focus.add("weather");
+        assert_eq!(focus.focused().unwrap(), "main");
+        assert_eq!(focus.cycle().unwrap(), "weather");
+        assert_eq!(focus.cycle().unwrap(), "time");
+    }
+
+    #[test]
+    fn it_flips_back_to_the_last_pane() {
+        let mut focus = Focus::new();
+        focus.add("main");
+        focus.add("time");
+        focus.add("weather");
+        assert_eq!(focus.last().unwrap(), "main");
+
+        focus.cycle();
+        focus.cycle();
+        assert_eq!(focus.last().unwrap(), "time");
+        assert_eq!(focus.last().unwrap(), "weather");
+        assert_eq!(focus.last().unwrap(), "time");
+    }
+
+    #[test]
+    fn it_moves_on_from_removed_panes() {
+        let mut focus = Focus::new();
+        focus.add("time");
+        focus.add("main");
+        focus.add("run-1");
+        focus.remove("time");
+        assert_eq!(focus.focused().unwrap(), "main");
+
+        focus.cycle();
+        focus.remove("run-1");
+        assert_eq!(focus.focused().unwrap(), "main");
+        assert_eq!(focus.last().unwrap(), "main");
+    }
+}
@@ -0,0 +1,110 @@
+use crate::decker::terminal::{Color, Pane, PrintStyle};
+
+impl Pane {
+    /***
+    Everything this pane still has - its scrollback, spilled history included, then what's on
+    screen - as a standalone HTML page, with inline styles for its colors and attributes
+     */
+    pub fn export_html(&mut self) -> String {
+        self.snapshot(); // parses anything still held back
+        let history = self.view_port.history(0, self.view_port.history_len());
+        let mut lines = history.iter().map(|l| l.cells().collect::<Vec<_>>()).collect::<Vec<_>>();
+        lines.extend(self.view_port.take_visible_lines().iter().map(|l| l.cells().collect::<Vec<_>>()));
+        // Blank lines the task hasn't reached yet aren't worth sharing
+        while lines.last().map(|l| l.iter().all(|(c, _)| c.is_whitespace())).unwrap_or(false) {
+            lines.pop();
+        }
+
+        let mut body = String::new();
+        for line in lines {
+            let mut styled: Option<PrintStyle> = None;
+            for (c, style) in line {
+                if styled != Some(style) {
+                    if styled.is_some() {
+                        body.push_str("</span>");
+                    }
+                    body.push_str(&format!("<span style=\"{}\">", css(&style)));
+                    styled = Some(style);
+                }
+                push_escaped(&mut body, c);
+            }
+            if styled.is_some() {
+                body.push_str("</span>");
+            }
+            body.push('\n');
+        }
+
+        let title = match &self.title {
+            None => { escape(&self.id) }
+            Some(title) => { format!("{} - {}", escape(&self.id), escape(title)) }
+        };
+        format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n\
+                 <body style=\"margin: 0; background: #000000; color: #e5e5e5\">\n\
+                 <pre style=\"margin: 0; padding: 1em; font-family: monospace\">{}</pre>\n</body>\n</html>\n",
+                title, body)
+    }
+}
+
+/***
+Inline CSS for a style, as the pane would draw it: bold text is in the bright versions of the
+eight basic colors
+ */
+fn css(style: &PrintStyle) -> String {
+    let (mut fg, mut bg) = (hex(style.foreground, style.bold), hex(style.background, false));
+    if style.invert {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    let mut css = format!("color: {}; background: {}", fg, bg);
+    if style.bold { css.push_str("; font-weight: bold"); }
+    if style.italicized { css.push_str("; font-style: italic"); }
+    if style.hidden { css.push_str("; visibility: hidden"); }
+    let lines = [(style.underline, "underline"), (style.strikethrough, "line-through"), (style.overline, "overline")].iter().
+        filter(|(on, _)| *on).
+        map(|(_, line)| *line).
+        collect::<Vec<_>>();
+    if !lines.is_empty() {
+        css.push_str(&format!("; text-decoration: {}", lines.join(" ")));
+    }
+    css
+}
+
+fn hex(color: Color, bright: bool) -> String {
+    let (r, g, b) = color.to_rgb(bright);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    text.chars().for_each(|c| push_escaped(&mut escaped, c));
+    escaped
+}
+
+fn push_escaped(out: &mut String, c: char) {
+    match c {
+        '&' => { out.push_str("&amp;") }
+        '<' => { out.push_str("&lt;") }
+        '>' => { out.push_str("&gt;") }
+        '"' => { out.push_str("&quot;") }
+        c => { out.push(c) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::terminal::{Pane, ScrollMode};
+
+    #[test]
+    fn it_exports_scrollback_and_screen_as_html() {
+        let mut pane = Pane::new("build", 1, 1, 2, 20);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane.push("old <line>\r\n\x1b[1;31mFAIL\x1b[0m & \x1b[4mmore\x1b[0m\r\nlast").unwrap();
+
+        let html = pane.export_html();
+        assert!(html.contains("<title>build</title>"), "{}", html);
+        let body = &html[html.find("<pre").unwrap()..];
+        let text = regex::Regex::new("<[^>]*>").unwrap().replace_all(body, "");
+        assert_eq!(text.trim_end(), "old &lt;line&gt;\nFAIL &amp; more\nlast");
+        assert!(html.contains("<span style=\"color: #ff0000; background: #000000; font-weight: bold\">FAIL</span>"), "{}", html);
+        assert!(html.contains("text-decoration: underline\">more</span>"), "{}", html);
+    }
+}
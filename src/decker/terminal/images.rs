@@ -0,0 +1,65 @@
+/***
+Inline image sequences, resized to fit a pane
+ */
+
+/***
+Size an iTerm2 image (OSC 1337 ; File=args : data) to width x height cells
+ */
+pub fn fit_iterm2(seq: &str, width: u16, height: u16) -> String {
+    let (args, data) = match seq.strip_prefix("\x1b]1337;File=") {
+        Some(rest) if rest.contains(':') => { rest.split_at(rest.find(':').unwrap()) }
+        _ => { return seq.to_string() }
+    };
+
+    let mut args = args.split(';').
+        filter(|arg| !arg.is_empty() && !arg.starts_with("width=") && !arg.starts_with("height=")).
+        map(String::from).
+        collect::<Vec<_>>();
+    args.push(format!("width={}", width));
+    args.push(format!("height={}", height));
+
+    format!("\x1b]1337;File={}{}", args.join(";"), data)
+}
+
+/***
+Size a kitty graphics command (APC G keys ; data) to width x height cells. Only commands that
+display something are touched - continuation chunks can't carry sizes.
+ */
+pub fn fit_kitty(seq: &str, width: u16, height: u16) -> String {
+    let rest = match seq.strip_prefix("\x1b_G") {
+        None => { return seq.to_string() }
+        Some(rest) => { rest }
+    };
+    let end = rest.find([';', '\x1b']).unwrap_or(rest.len());
+    let (keys, data) = rest.split_at(end);
+
+    let mut keys = keys.split(',').filter(|key| !key.is_empty()).map(String::from).collect::<Vec<_>>();
+    if !keys.iter().any(|key| key == "a=T" || key == "a=p") {
+        return seq.to_string();
+    }
+    keys.retain(|key| !key.starts_with("c=") && !key.starts_with("r="));
+    keys.push(format!("c={}", width));
+    keys.push(format!("r={}", height));
+
+    format!("\x1b_G{}{}", keys.join(","), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_fits_iterm2_images_to_the_pane() {
+        let image = fit_iterm2("\x1b]1337;File=name=eC5wbmc=;width=100%;inline=1:AAAA\x07", 20, 5);
+        assert_eq!(image, "\x1b]1337;File=name=eC5wbmc=;inline=1;width=20;height=5:AAAA\x07");
+    }
+
+    #[test]
+    fn it_fits_kitty_images_to_the_pane() {
+        let image = fit_kitty("\x1b_Ga=T,f=100,c=200,m=1;AAAA\x1b\\", 20, 5);
+        assert_eq!(image, "\x1b_Ga=T,f=100,m=1,c=20,r=5;AAAA\x1b\\");
+
+        let chunk = "\x1b_Gm=0;AAAA\x1b\\";
+        assert_eq!(fit_kitty(chunk, 20, 5), chunk);
+    }
+}
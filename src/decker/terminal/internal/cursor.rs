@@ -27,18 +27,17 @@ impl Cursor {
         self.y = min(max(0, n), self.y_max)
     }
 
+    // Offsets come straight from child programs' escape sequences, so they can be anything
     pub fn incr_x(&mut self, offset: VirtualCoord) {
-        self.set_x(self.x + offset)
+        self.set_x(self.x.saturating_add(offset))
     }
 
     pub fn incr_y(&mut self, offset: VirtualCoord) {
-        self.set_y(self.y + offset)
+        self.set_y(self.y.saturating_add(offset))
     }
 
     pub fn decr_x(&mut self, offset: VirtualCoord) {
-        if self.x > 0 {
-            self.set_x(self.x - offset)
-        }
+        self.set_x(self.x.saturating_sub(offset))
     }
 
     pub fn decr_y(&mut self, offset: VirtualCoord) {
@@ -1,33 +1,29 @@
 use std::cmp::{max, min};
+use std::ops::Range;
 use std::io::Write;
-use log::{debug, info};
+use log::info;
 use std::fmt::{Debug, Formatter};
 use crate::decker::terminal::{PrintStyle, VirtualCoord};
 
 #[derive(Clone)]
 pub struct GlyphString {
-    pub glyphs: Vec<Glyph>,
+    glyphs: Vec<Glyph>,
+    // The distinct styles used on this line. Glyphs refer to them by index, which keeps
+    // each cell small and makes "did the style change?" a cheap integer comparison.
+    styles: Vec<PrintStyle>,
     string_rep: String,
     dirty: bool
 }
 
+type StyleId = u16;
+
+// Once a line's style table grows this large, drop the styles no glyph uses any more
+const MAX_STYLES: usize = 1024;
+
 #[derive(Copy, Clone, Debug)]
 pub struct Glyph {
     pub c: char,
-    pub style: PrintStyle,
-    pub dirty: bool,
-}
-
-impl Glyph {
-    pub fn new(c: char, state: PrintStyle) -> Self {
-        Glyph { c, style: state, dirty: true }
-    }
-}
-
-impl Default for Glyph {
-    fn default() -> Self {
-        Glyph::new(' ', PrintStyle::default())
-    }
+    style: StyleId,
 }
 
 impl Debug for GlyphString {
@@ -40,42 +36,126 @@ impl GlyphString {
     pub fn new() -> GlyphString {
         GlyphString {
             glyphs: Vec::new(),
+            styles: Vec::new(),
             string_rep: String::new(),
             dirty: true
         }
     }
 
-    pub fn last_style(&self) -> PrintStyle {
-        match self.glyphs.last() {
+    /***
+    Find (or add) a style in this line's style table
+     */
+    fn intern(&mut self, style: &PrintStyle) -> StyleId {
+        match self.styles.iter().position(|s| s == style) {
+            Some(id) => { id as StyleId }
+            None => {
+                if self.styles.len() >= MAX_STYLES {
+                    self.compact_styles();
+                }
+                self.styles.push(*style);
+                (self.styles.len() - 1) as StyleId
+            }
+        }
+    }
+
+    fn compact_styles(&mut self) {
+        let mut styles: Vec<PrintStyle> = Vec::new();
+        let mut remapped: Vec<Option<StyleId>> = vec![None; self.styles.len()];
+
+        for g in self.glyphs.iter_mut() {
+            let old = g.style as usize;
+            g.style = match remapped[old] {
+                Some(id) => { id }
+                None => {
+                    styles.push(self.styles[old]);
+                    let id = (styles.len() - 1) as StyleId;
+                    remapped[old] = Some(id);
+                    id
+                }
+            };
+        }
+
+        self.styles = styles;
+    }
+
+    fn style_of(&self, glyph: Option<&Glyph>) -> PrintStyle {
+        match glyph {
             None => { PrintStyle::default() }
-            Some(g) => { g.style }
+            Some(g) => { self.styles[g.style as usize] }
         }
     }
 
+    pub fn last_style(&self) -> PrintStyle {
+        self.style_of(self.glyphs.last())
+    }
+
     pub fn dirty(&self) -> bool {
-        self.dirty || self.glyphs.iter().any(|g| g.dirty)
+        self.dirty
     }
 
     pub fn make_dirty(&mut self) {
         self.dirty = true
     }
 
+    pub fn make_clean(&mut self) {
+        self.dirty = false
+    }
+
     pub fn set(&mut self, index: VirtualCoord, c: char, style: &PrintStyle) {
         let extra_chars_reqd = max(0, index as i32 - (self.glyphs.len() as i32 - 1));
-        let default_style = self.glyphs.last().unwrap_or(&Glyph::default()).style;
+        let default_style = self.last_style();
+        let default_style = self.intern(&default_style);
         for _ in 0..extra_chars_reqd {
-            self.glyphs.push(Glyph::new(' ', default_style.clone()));
+            self.glyphs.push(Glyph { c: ' ', style: default_style });
         }
 
-        self.glyphs[index as usize] = Glyph::new(c, style.clone());
+        let style = self.intern(style);
+        self.glyphs[index as usize] = Glyph { c, style };
         self.make_dirty()
     }
 
+    /***
+    Put a character at index, shifting everything from there on right by one
+     */
+    pub fn insert(&mut self, index: VirtualCoord, c: char, style: &PrintStyle) {
+        if index as usize >= self.glyphs.len() {
+            return self.set(index, c, style);
+        }
+
+        let style = self.intern(style);
+        self.glyphs.insert(index as usize, Glyph { c, style });
+        self.make_dirty()
+    }
+
+    /***
+    Drop everything past the first len glyphs
+     */
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.glyphs.len() {
+            self.glyphs.truncate(len);
+            self.make_dirty()
+        }
+    }
+
+    /***
+    Cut the last word off the end of this line, for word wrapping to carry on to the next one.
+    A line without a space to break at keeps everything.
+     */
+    pub fn take_last_word(&mut self) -> Vec<(char, PrintStyle)> {
+        match self.glyphs.iter().rposition(|g| g.c.is_whitespace()) {
+            Some(space) if space + 1 < self.glyphs.len() => {
+                let word = self.cells().skip(space + 1).collect();
+                self.truncate(space + 1);
+                word
+            }
+            _ => { Vec::new() }
+        }
+    }
+
     pub fn push(&mut self, s: &str, style: &PrintStyle) {
-        let mut i = self.glyphs.len();
-        for c in s.chars() {
-            self.set(i as VirtualCoord, c, style);
-            i += 1;
+        let start = self.glyphs.len();
+        for (i, c) in s.chars().enumerate() {
+            self.set((start + i) as VirtualCoord, c, style);
         }
     }
 
@@ -106,88 +186,111 @@ impl GlyphString {
     pub fn clear(&mut self) {
         info!("Clearing string: '{}'", self.plaintext());
         self.glyphs.clear();
+        self.styles.clear();
         self.make_dirty()
     }
 
-    pub fn write(&mut self, x_offset: u16, y_offset: u16, width: u16, style: &PrintStyle, target: &mut dyn Write) -> anyhow::Result<()> {
+    /***
+    Append the columns in `window` of this line to `target`: positioned, styled, and padded or
+    clipped to fit. With `ellipsis`, a clipped line shows "…" at whichever ends there's more.
+     */
+    pub fn write(&mut self, x_offset: u16, y_offset: u16, window: Range<u16>, style: &PrintStyle, ellipsis: bool, target: &mut Vec<u8>) {
         // write our line at the appropriate offset, style and size!
-        let line_style = style.diff_str(&self.glyphs.first().unwrap_or(&Glyph::default()).style);
-        let reset_style = self.glyphs.last().unwrap_or(&Glyph::default()).style.diff_str(style);
-
-        let set_cursor = format!("\x1b[{};{}H", y_offset, x_offset);
-        let output = format!("{}{}{}{}",
-                                 set_cursor,
-                                 line_style,
-                                 self.str_with_width(width as usize),
-                                 reset_style);
-
-        let pad_width = if self.len() < width as usize {
-            // Have to pad using the formatted output string length, 'cause the writer doesn't handle
-            // VT100 sequences.
-            let extra_padding_reqd = width - self.len() as u16;
-            output.len() + extra_padding_reqd as usize
-        } else {
-            width as usize
-        };
-
-        write!(target, "{0: <1$}", output, pad_width)?;
-        self.dirty = false;
-
-        Ok(())
-    }
-
-    fn str_with_width(&mut self, width: usize) -> String {
-        info!("Printing string with width {}", width);
-
-        let mut output = String::new();
-        let mut cur_style = self.glyphs.first().unwrap_or(&Glyph::default()).style.clone(); // No mutating args!
+        let _ = write!(target, "\x1b[{};{}H", y_offset, x_offset);
+        let first = self.glyphs.get(window.start as usize);
+        style.write_diff(&self.style_of(first), target);
+
+        let mut utf8 = [0u8; 4];
+        let mut shown = 0;
+        let mut last_style = self.style_of(first);
+        if let Some(first) = first {
+            let mut cur_style = first.style;
+            for (n, g) in self.glyphs.iter().enumerate().skip(window.start as usize).take(window.len()) {
+                // Make sure to keep the correct style for each glyph
+                if g.style != cur_style {
+                    self.styles[cur_style as usize].write_diff(&self.styles[g.style as usize], target);
+                    cur_style = g.style;
+                }
+
+                let c = if self.cut_at(n, &window, ellipsis) { '…' } else { g.c };
+                target.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+                shown += 1;
+            }
+            last_style = self.styles[cur_style as usize];
+        }
 
-        self.glyphs.iter_mut().take(width).for_each(|g| {
-            g.dirty = false; // We've printed you now!
+        last_style.write_diff(style, target);
 
-            // Make sure to keep the correct style for each glyph
-            let diff = cur_style.diff_str(&g.style);
+        for _ in shown..window.len() {
+            target.push(b' ');
+        }
 
-            if diff.len() > 0 {
-                debug!("Updating style. FG/BG: {}/{} Str: {}", g.style.foreground, g.style.background, g.c);
-                cur_style = g.style;
-                output.push_str(&diff);
-            }
+        self.dirty = false;
+    }
 
-            output.push(g.c);
-        });
+    /***
+    Like write, but faint and without any of the line's own styling
+     */
+    pub fn write_dimmed(&mut self, x_offset: u16, y_offset: u16, window: Range<u16>, ellipsis: bool, target: &mut Vec<u8>) {
+        let _ = write!(target, "\x1b[{};{}H\x1b[0;2m", y_offset, x_offset);
+        let text = self.glyphs.iter().enumerate().skip(window.start as usize).take(window.len()).
+            map(|(n, g)| if self.cut_at(n, &window, ellipsis) { '…' } else { g.c }).
+            collect::<String>();
+        target.extend_from_slice(text.as_bytes());
+        for _ in text.chars().count()..window.len() {
+            target.push(b' ');
+        }
+        target.extend_from_slice(b"\x1b[0m");
 
-        info!("output: {}, glyph len: {}", output, self.glyphs.len());
+        self.dirty = false;
+    }
 
-        output
+    /***
+    Whether the glyph at `index` gives way to an ellipsis: the first or last one shown when
+    there's more of the line beyond that edge of the `window`
+     */
+    fn cut_at(&self, index: usize, window: &Range<u16>, ellipsis: bool) -> bool {
+        let (start, end) = (window.start as usize, window.end as usize);
+        ellipsis && ((index + 1 == end && self.glyphs.len() > end) || (index == start && start > 0))
     }
 
     pub fn len(&self) -> usize {
         self.glyphs.len()
     }
 
+    /***
+    Each character on this line along with the style it's drawn in
+     */
+    pub fn cells(&self) -> impl Iterator<Item=(char, PrintStyle)> + '_ {
+        self.glyphs.iter().map(move |g| (g.c, self.styles[g.style as usize]))
+    }
+
     pub fn plaintext(&self) -> String {
-        self.glyphs.iter().map(|g| g.c.to_string()).collect::<Vec<String>>().join("")
+        self.glyphs.iter().map(|g| g.c).collect()
     }
 
     pub fn to_str(&self, current_state: &PrintStyle) -> String {
         let mut current_state = *current_state;
-        let mut s = String::new();
+        let mut s = Vec::new();
+        let mut utf8 = [0u8; 4];
         for g in &self.glyphs {
-            if g.style != current_state {
-                s += &g.style.to_str();
-                current_state = g.style.clone();
+            let style = self.styles[g.style as usize];
+            if style != current_state {
+                style.write_to(&mut s);
+                current_state = style;
             }
-            s.push(g.c);
+            s.extend_from_slice(g.c.encode_utf8(&mut utf8).as_bytes());
         }
 
-        s
+        String::from_utf8(s).unwrap()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::decker::terminal::Color;
+    use crate::decker::terminal::internal::EscSeq;
 
     #[test]
     fn it_writes_lines_at_offset() {
@@ -197,7 +300,7 @@ mod tests {
         g.push("a line of text", &ps);
 
         let mut output = Vec::new();
-        g.write(1, 3, 14, &ps, &mut output).unwrap();
+        g.write(1, 3, 0..14, &ps, false, &mut output);
 
         assert_eq!(output, b"\x1b[3;1Ha line of text");
     }
@@ -210,29 +313,58 @@ mod tests {
         g.push("a line of text", &ps);
 
         let mut output = Vec::new();
-        g.write(1, 3, 15, &ps, &mut output).unwrap();
+        g.write(1, 3, 0..15, &ps, false, &mut output);
 
         assert_eq!(output, b"\x1b[3;1Ha line of text ");
     }
 
+    #[test]
+    fn it_ends_clipped_lines_in_an_ellipsis() {
+        let mut g = GlyphString::new();
+        let ps = PrintStyle::default();
+        g.push("a line of text", &ps);
+
+        let mut output = Vec::new();
+        g.write(1, 3, 0..6, &ps, true, &mut output);
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "\x1b[3;1Ha lin…");
+
+        output.clear();
+        g.write_dimmed(1, 3, 0..14, true, &mut output);
+        assert_eq!(std::str::from_utf8(&output).unwrap(), "\x1b[3;1H\x1b[0;2ma line of text\x1b[0m");
+    }
+
     #[test]
     fn it_respects_glyph_styles() {
         let mut g = GlyphString::new();
         let mut ps = PrintStyle::default();
-        ps.apply_vt100("\x1b[32m").unwrap();
+        ps.apply_sgr(&EscSeq::parse("\x1b[32m")).unwrap();
 
         g.push("a line", &ps);
 
-        ps.apply_vt100("\x1b[37m").unwrap();
+        ps.apply_sgr(&EscSeq::parse("\x1b[37m")).unwrap();
 
         g.push(" of text", &ps);
 
         let mut output = Vec::new();
-        g.write(1, 3, 14, &ps, &mut output).unwrap();
+        g.write(1, 3, 0..14, &ps, false, &mut output);
 
         assert_eq!(std::str::from_utf8(&output).unwrap(), "\x1b[3;1H\x1b[32ma line\x1b[37m of text");
     }
 
+    #[test]
+    fn it_forgets_unused_styles() {
+        let mut g = GlyphString::new();
+        let mut ps = PrintStyle::default();
+
+        for i in 0..(MAX_STYLES + 10) {
+            ps.foreground = Color::RGB((i % 256) as u8, (i / 256) as u8, 0);
+            g.set(0, 'x', &ps);
+        }
+
+        assert!(g.styles.len() <= MAX_STYLES);
+        assert_eq!(g.last_style(), ps);
+    }
+
     #[test]
     fn it_clears_leading_chars() {
         let mut g = GlyphString::new();
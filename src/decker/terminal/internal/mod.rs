@@ -1,167 +1,107 @@
-use crate::decker::terminal::internal::TerminalOutput::{CSI, Plaintext};
-use std::str::FromStr;
-use crate::decker::terminal::internal::VT100::{SGR, PassThrough, MoveCursor, EraseScreen, ClearLine, Unknown, ScrollDown, ScrollUp, MoveCursorApp, HideCursor, ShowCursor, GetCursorPos, EnterApplicationKeyMode, ExitAltKeypadMode, EraseLineAfterCursor, EraseLineBeforeCursor, EnterAltKeypadMode};
-use anyhow::Error;
 use std::fmt::Debug;
+use std::collections::{BTreeSet, VecDeque};
+use std::fs::File;
+use std::path::PathBuf;
 use crate::decker::terminal::internal::glyph_string::GlyphString;
-use crate::decker::terminal::{Cursor, ScrollMode, PrintStyle};
+use crate::decker::terminal::{Cursor, Overflow, ScrollMode, PrintStyle, VirtualCoord};
+use crate::decker::terminal::{Align, VAlign};
+
+pub use crate::decker::terminal::parser::{EscSeq, StreamState, TerminalOutput, VT100};
 
 pub mod glyph_string;
 
-mod stream_state;
 mod view_port;
 mod cursor;
-
-enum VT100State {
-    PlainText,
-    FoundEsc,
-}
+mod spill;
 
 pub(crate) struct ViewPort {
     pane_id: String,
-    visible_lines: Vec<GlyphString>,
+    visible_lines: VecDeque<GlyphString>,
+    // Lines that have scrolled off the top, oldest first
+    scrollback: VecDeque<GlyphString>,
+    scrollback_limit: usize,
+    // Where scrollback goes once it's past the limit, rather than being forgotten
+    spill: Option<Spill>,
+    // How many lines have ever scrolled off the top, kept or not
+    scrolled_off: u64,
     cur_style: PrintStyle,
     scroll_mode: ScrollMode,
     width: u16,
     height: u16,
     cursor: Cursor,
+    // IRM: printing shifts the rest of the line right instead of overwriting it
+    insert_mode: bool,
+    // LNM: a line feed also returns the cursor to the start of the line. On by default, since
+    // non-interactive tasks' output doesn't go through a tty to turn \n into \r\n.
+    newline_mode: bool,
+    // Which glyphs printable characters stand for: G0 (ESC ( 0 / ESC ( B) and G1 (ESC ) ...),
+    // and whether SO has shifted us to G1
+    charsets: [CharSet; 2],
+    shifted_out: bool,
+    // Set by DECSC, for DECRC to go back to
+    saved_cursor: Option<SavedCursor>,
+    // Columns a tab moves the cursor to
+    tab_stops: BTreeSet<VirtualCoord>,
+    // DECAWM: printing past the last column continues on the next line. With reverse wrap
+    // (xterm's mode 45) too, backspacing from the first column goes back up to the line before.
+    auto_wrap: bool,
+    // Whether long lines wrap, or are cut off with an ellipsis, unless the task says otherwise
+    overflow: Overflow,
+    // Characters printed past the right edge since the cursor got there. Lines that don't wrap
+    // keep them, for panning across.
+    overhang: u16,
+    reverse_wrap: bool,
+    // Where content smaller than the view port is drawn in it
+    align: Align,
+    valign: VAlign,
 }
 
-/***
-Output is either plaintext or a VT100 command sequence instruction
- */
-#[derive(Clone, Debug)]
-pub enum TerminalOutput {
-    Plaintext(String),
-    CSI(VT100),
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CharSet {
+    Ascii,
+    // DEC Special Graphics: box-drawing lines and a few symbols in place of lowercase letters
+    LineDrawing,
 }
 
-/*
-Classifications of VT100 codes.
-Each contains its own string, but this makes it easy to detect and switch
-on different types. No need to inspect the last character at use time.
+/***
+Everything DECSC saves and DECRC puts back. Origin mode belongs here too, once panes support
+scroll regions. (A pending wrap is the cursor sitting just past the last column, so x covers it.)
  */
-#[derive(Clone, Debug)]
-pub enum VT100 {
-    ScrollDown(String),
-    ScrollUp(String),
-    SGR(String),
-    MoveCursor(String),
-    MoveCursorApp(String),
-    ClearLine(String),
-    EraseLineAfterCursor(String),
-    EraseLineBeforeCursor(String),
-    EraseScreen(String),
-    PassThrough(String),
-    HideCursor(String),
-    ShowCursor(String),
-    GetCursorPos(String),
-    EnterApplicationKeyMode(String),
-    EnterAltKeypadMode(String),
-    ExitAltKeypadMode(String),
-    Unknown(String),
-}
-
-impl VT100 {
-    pub fn to_string(&self) -> String {
-        match self {
-            ScrollDown(s) => { s.clone() }
-            ScrollUp(s) => { s.clone() }
-            SGR(s) => { s.clone() }
-            MoveCursor(s) => { s.clone() }
-            MoveCursorApp(s) => { s.clone() }
-            ClearLine(s) => { s.clone() }
-            EraseLineBeforeCursor(s) => { s.clone() }
-            EraseLineAfterCursor(s) => { s.clone() }
-            EraseScreen(s) => { s.clone() }
-            HideCursor(s) => { s.clone() }
-            ShowCursor(s) => { s.clone() }
-            PassThrough(s) => { s.clone() }
-            GetCursorPos(s) => { s.clone() }
-            Unknown(s) => { s.clone() }
-            EnterApplicationKeyMode(s) => { s.clone() }
-            EnterAltKeypadMode(s) => { s.clone() }
-            ExitAltKeypadMode(s) => { s.clone() }
-        }
-    }
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SavedCursor {
+    x: VirtualCoord,
+    y: VirtualCoord,
+    style: PrintStyle,
+    charsets: [CharSet; 2],
+    shifted_out: bool,
 }
 
-impl FromStr for VT100 {
-    type Err = Error;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.is_empty() {
-            return Err(anyhow::anyhow!("Cannot parse an empty string"));
-        }
-
-        let vt100 = match s.chars().last().unwrap() {
-            'M' => ScrollDown(s.to_string()),
-            'D' => {
-                // D can be either ESC D which means Scroll Up
-                // OR it can be ESC [#D which means Move left.
-                if s == "\x1BD" {
-                    ScrollUp(s.to_string())
-                } else {
-                    MoveCursor(s.to_string())
-                }
-            }
-            'm' => SGR(s.to_string()),
-            'H' | 'f' | 'A' | 'B' | 'C' => {
-                /* cursor movement */
-                if s.get(1..2).unwrap() == "O" {
-                    // When alternate mode is set, arrow keys send ESC O[A-D] instead of ESC[[A-D]
-                    // This can trip up e.g. vim.
-                    MoveCursorApp(s.to_string())
-                } else {
-                    MoveCursor(s.to_string())
-                }
-            }
-            'J' => EraseScreen(s.to_string()),
-            'K' => match s {
-                "\x1B[1K" => EraseLineBeforeCursor(s.to_string()),
-                "\x1B[2K" => ClearLine(s.to_string()),
-                _ => EraseLineAfterCursor(s.to_string())
-            }
-            'L' => ClearLine(s.to_string()),
-            'h' | 'l' | 'n' | 'r' => /* Various control / query options */
-                match s {
-                    "\x1b[?1h" => EnterApplicationKeyMode(s.to_string()),
-                    "\x1b[?25l" => HideCursor(s.to_string()),
-                    "\x1b[?25h" => ShowCursor(s.to_string()),
-                    "\x1b[6n" => GetCursorPos(s.to_string()),
-                    _ => PassThrough(s.to_string())
-                }
-            _ => {
-                if s[0..2] == *"\x1Bk" {
-                    ClearLine(s.to_string())
-                } else {
-                    Unknown(s.to_string())
-                }
-            }
-        };
-
-        Ok(vt100)
-    }
-}
-
-impl TerminalOutput {
-    pub fn to_string(&self) -> String {
-        match self {
-            Plaintext(s) => { s.clone() }
-            CSI(s) => { s.to_string() }
-        }
-    }
-
-    pub fn is_empty(&self) -> bool {
-        match self {
-            Plaintext(s) => { s.len() == 0 }
-            CSI(s) => { s.to_string().len() == 0 }
-        }
-    }
+/***
+Scrollback too old to keep in memory, compressed into a file a chunk of lines at a time and read
+back a chunk at a time as the pane scrolls back over it. Once the file reaches half its allowance
+it becomes the `.1` file, replacing the one before, so the two together stay within it.
+ */
+pub(crate) struct Spill {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    written: u64,
+    // Every chunk on disk, oldest first, in either file
+    chunks: VecDeque<SpillChunk>,
+    // Lines waiting to make up a whole chunk
+    pending: Vec<GlyphString>,
+    // The chunks read back most recently, by their first line
+    cache: VecDeque<(u64, Vec<GlyphString>)>,
 }
 
-pub(crate) struct StreamState {
-    buffer: String,
-    vetted_output: Vec<TerminalOutput>,
-    build_state: VT100State,
+#[derive(Copy, Clone, Debug)]
+struct SpillChunk {
+    // Whether it's in the `.1` file
+    rotated: bool,
+    offset: u64,
+    len: u32,
+    // How many lines had been spilled before this chunk, and how many it holds
+    first: u64,
+    lines: u32,
 }
@@ -0,0 +1,383 @@
+use crate::decker::terminal::internal::{Spill, SpillChunk};
+use crate::decker::terminal::internal::glyph_string::GlyphString;
+use crate::decker::terminal::{Color, PrintStyle};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use anyhow::bail;
+
+// Lines compressed together. Bigger chunks compress better, but scrolling back into one reads
+// and unpacks all of it.
+const CHUNK_LINES: usize = 256;
+// Chunks kept unpacked, so a screenful spanning two of them doesn't unpack both on every frame
+const CACHED_CHUNKS: usize = 2;
+// Each chunk on disk starts with how many lines it holds and how many bytes follow
+const HEADER_LEN: u64 = 8;
+
+impl Spill {
+    /***
+    Spill into the file at `path`, keeping it and its `.1` file under `max_bytes` between them.
+    Anything spilled there by an earlier run is picked up again, so history survives a restart.
+     */
+    pub fn open(path: &Path, max_bytes: u64) -> anyhow::Result<Spill> {
+        if max_bytes == 0 { bail!("Scrollback files need room for at least one chunk") }
+
+        let (mut chunks, _) = Self::scan(&Self::rotated_path(path), true, 0)?;
+        let first = chunks.last().map(|c| c.first + c.lines as u64).unwrap_or(0);
+        let (current, written) = Self::scan(path, false, first)?;
+        chunks.extend(current);
+
+        // Drop anything after the last whole chunk, e.g. from losing power mid-write
+        let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+        file.set_len(written)?;
+        file.seek(SeekFrom::Start(written))?;
+
+        let spill = Spill {
+            path: path.to_path_buf(),
+            max_bytes,
+            file,
+            written,
+            chunks: chunks.into(),
+            pending: Vec::new(),
+            cache: VecDeque::new(),
+        };
+        info!("Spilling scrollback to {} ({} lines there already)", path.display(), spill.len());
+        Ok(spill)
+    }
+
+    fn rotated_path(path: &Path) -> PathBuf {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        PathBuf::from(rotated)
+    }
+
+    /***
+    Find the whole chunks in a spill file, and where the last of them ends
+     */
+    fn scan(path: &Path, rotated: bool, mut first: u64) -> anyhow::Result<(Vec<SpillChunk>, u64)> {
+        let mut chunks = Vec::new();
+        let mut file = match File::open(path) {
+            Ok(file) => { file }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => { return Ok((chunks, 0)) }
+            Err(e) => { return Err(e.into()) }
+        };
+
+        let size = file.metadata()?.len();
+        let mut offset = 0;
+        let mut header = [0u8; HEADER_LEN as usize];
+        while offset + HEADER_LEN <= size {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut header)?;
+            let lines = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if offset + HEADER_LEN + len as u64 > size { break; }
+
+            chunks.push(SpillChunk { rotated, offset, len, first, lines });
+            first += lines as u64;
+            offset += HEADER_LEN + len as u64;
+        }
+        Ok((chunks, offset))
+    }
+
+    /***
+    How many lines have been spilled and are still around to read back
+     */
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|c| c.lines as usize).sum::<usize>() + self.pending.len()
+    }
+
+    pub fn push(&mut self, line: GlyphString) {
+        self.pending.push(line);
+        if self.pending.len() >= CHUNK_LINES {
+            if let Err(e) = self.write_chunk() {
+                // Better to lose some history than to stop drawing the pane
+                warn!("Could not spill scrollback to {}: {}", self.path.display(), e);
+                self.pending.clear();
+            }
+        }
+    }
+
+    fn write_chunk(&mut self) -> anyhow::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        for line in &self.pending {
+            encoder.write_all(&encode_line(line))?;
+        }
+        let packed = encoder.finish()?;
+
+        let lines = self.pending.len() as u32;
+        let len = packed.len() as u32;
+        self.file.write_all(&lines.to_le_bytes())?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(&packed)?;
+
+        let first = self.chunks.back().map(|c| c.first + c.lines as u64).unwrap_or(0);
+        self.chunks.push_back(SpillChunk { rotated: false, offset: self.written, len, first, lines });
+        self.written += HEADER_LEN + len as u64;
+        self.pending.clear();
+
+        if self.written >= self.max_bytes / 2 {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /***
+    Start a new file, the current one replacing the `.1` file and its chunks
+     */
+    fn rotate(&mut self) -> anyhow::Result<()> {
+        std::fs::rename(&self.path, Self::rotated_path(&self.path))?;
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+
+        self.chunks.retain(|c| !c.rotated);
+        for chunk in self.chunks.iter_mut() {
+            chunk.rotated = true;
+        }
+        info!("Rotated the scrollback spilled to {}", self.path.display());
+        Ok(())
+    }
+
+    /***
+    Up to `count` spilled lines, starting `start` lines after the oldest
+     */
+    pub fn lines(&mut self, start: usize, count: usize) -> Vec<GlyphString> {
+        let end = (start + count).min(self.len());
+        let on_disk = self.len() - self.pending.len();
+        let mut lines = Vec::with_capacity(end.saturating_sub(start));
+
+        let mut n = start;
+        while n < end.min(on_disk) {
+            let index = self.chunk_index(n);
+            let chunk = self.chunks[index];
+            let skipped = n - self.chunk_start(index);
+            let wanted = (end - n).min(chunk.lines as usize - skipped);
+            match self.read_chunk(chunk) {
+                Ok(chunk_lines) => { lines.extend(chunk_lines.iter().skip(skipped).take(wanted).cloned()) }
+                Err(e) => {
+                    warn!("Could not read scrollback back from {}: {}", self.path.display(), e);
+                    lines.extend((0..wanted).map(|_| GlyphString::new()));
+                }
+            }
+            n += wanted;
+        }
+
+        lines.extend(self.pending.iter().skip(n.saturating_sub(on_disk)).take(end.saturating_sub(n)).cloned());
+        lines
+    }
+
+    // Where a chunk starts, counting from the oldest line still around
+    fn chunk_start(&self, index: usize) -> usize {
+        self.chunks.iter().take(index).map(|c| c.lines as usize).sum()
+    }
+
+    fn chunk_index(&self, line: usize) -> usize {
+        let mut start = 0;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            start += chunk.lines as usize;
+            if line < start { return index; }
+        }
+        self.chunks.len() - 1
+    }
+
+    fn read_chunk(&mut self, chunk: SpillChunk) -> anyhow::Result<&Vec<GlyphString>> {
+        if let Some(cached) = self.cache.iter().position(|(first, _)| *first == chunk.first) {
+            return Ok(&self.cache[cached].1);
+        }
+
+        let path = if chunk.rotated { Self::rotated_path(&self.path) } else { self.path.clone() };
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(chunk.offset + HEADER_LEN))?;
+        let mut packed = vec![0; chunk.len as usize];
+        file.read_exact(&mut packed)?;
+
+        let mut data = Vec::new();
+        DeflateDecoder::new(packed.as_slice()).read_to_end(&mut data)?;
+        let mut lines = Vec::with_capacity(chunk.lines as usize);
+        let mut rest = data.as_slice();
+        while lines.len() < chunk.lines as usize {
+            lines.push(decode_line(&mut rest)?);
+        }
+
+        if self.cache.len() >= CACHED_CHUNKS {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((chunk.first, lines));
+        Ok(&self.cache.back().unwrap().1)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        // Write out the lines short of a whole chunk, so the next run has them too
+        if !self.pending.is_empty() {
+            if let Err(e) = self.write_chunk() {
+                warn!("Could not spill scrollback to {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/***
+A line as runs of text sharing a style: how many runs, then each run's style, length and text
+ */
+fn encode_line(line: &GlyphString) -> Vec<u8> {
+    let mut runs: Vec<(PrintStyle, String)> = Vec::new();
+    for (c, style) in line.cells() {
+        match runs.last_mut() {
+            Some((run_style, text)) if *run_style == style => { text.push(c) }
+            _ => { runs.push((style, c.to_string())) }
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (style, text) in runs {
+        out.extend_from_slice(&encode_color(style.foreground));
+        out.extend_from_slice(&encode_color(style.background));
+        let flags = [style.italicized, style.underline, style.blink, style.bold, style.invert,
+            style.hidden, style.strikethrough, style.overline].iter().
+            enumerate().fold(0u8, |flags, (bit, set)| if *set { flags | 1 << bit } else { flags });
+        out.push(flags);
+        out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+        out.extend_from_slice(text.as_bytes());
+    }
+    out
+}
+
+fn decode_line(data: &mut &[u8]) -> anyhow::Result<GlyphString> {
+    let mut line = GlyphString::new();
+    let runs = read_u32(data)?;
+    for _ in 0..runs {
+        let foreground = decode_color(take(data, 4)?)?;
+        let background = decode_color(take(data, 4)?)?;
+        let flags = take(data, 1)?[0];
+        let set = |bit: u8| flags & (1 << bit) != 0;
+        let style = PrintStyle {
+            foreground,
+            background,
+            italicized: set(0),
+            underline: set(1),
+            blink: set(2),
+            bold: set(3),
+            invert: set(4),
+            hidden: set(5),
+            strikethrough: set(6),
+            overline: set(7),
+        };
+        let len = read_u32(data)? as usize;
+        line.push(std::str::from_utf8(take(data, len)?)?, &style);
+    }
+    Ok(line)
+}
+
+fn encode_color(color: Color) -> [u8; 4] {
+    match color {
+        Color::Black => { [0, 0, 0, 0] }
+        Color::Red => { [1, 0, 0, 0] }
+        Color::Green => { [2, 0, 0, 0] }
+        Color::Yellow => { [3, 0, 0, 0] }
+        Color::Blue => { [4, 0, 0, 0] }
+        Color::Magenta => { [5, 0, 0, 0] }
+        Color::Cyan => { [6, 0, 0, 0] }
+        Color::White => { [7, 0, 0, 0] }
+        Color::TWOFIFTYSIX(n) => { [8, n, 0, 0] }
+        Color::RGB(r, g, b) => { [9, r, g, b] }
+    }
+}
+
+fn decode_color(bytes: &[u8]) -> anyhow::Result<Color> {
+    let color = match bytes[0] {
+        0 => { Color::Black }
+        1 => { Color::Red }
+        2 => { Color::Green }
+        3 => { Color::Yellow }
+        4 => { Color::Blue }
+        5 => { Color::Magenta }
+        6 => { Color::Cyan }
+        7 => { Color::White }
+        8 => { Color::TWOFIFTYSIX(bytes[1]) }
+        9 => { Color::RGB(bytes[1], bytes[2], bytes[3]) }
+        other => { bail!("Unknown color {} in spilled scrollback", other) }
+    };
+    Ok(color)
+}
+
+fn read_u32(data: &mut &[u8]) -> anyhow::Result<u32> {
+    let bytes = take(data, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    if data.len() < len { bail!("Spilled scrollback is cut short") }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spill_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("decker-spill-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(Spill::rotated_path(&path));
+        path
+    }
+
+    fn line(text: &str) -> GlyphString {
+        let mut line = GlyphString::new();
+        line.push(text, &PrintStyle::default());
+        line
+    }
+
+    #[test]
+    fn it_reads_back_what_it_spilled() {
+        let path = spill_path("read");
+        let mut spill = Spill::open(&path, 1024 * 1024).unwrap();
+        let red = PrintStyle { foreground: Color::Red, bold: true, ..PrintStyle::default() };
+
+        for n in 0..600 {
+            let mut l = line(&format!("line {} ", n));
+            l.push("é", &red);
+            spill.push(l);
+        }
+        assert_eq!(spill.len(), 600);
+
+        // Across a chunk boundary, and into the lines not written out yet
+        let lines = spill.lines(254, 4);
+        let text = lines.iter().map(|l| l.plaintext()).collect::<Vec<_>>();
+        assert_eq!(text, vec!["line 254 é", "line 255 é", "line 256 é", "line 257 é"]);
+        assert_eq!(lines[0].cells().last(), Some(('é', red)));
+        assert_eq!(spill.lines(598, 10).iter().map(|l| l.plaintext()).collect::<Vec<_>>(), vec!["line 598 é", "line 599 é"]);
+
+        // Another run picks it all up, the lines short of a whole chunk included
+        drop(spill);
+        let mut spill = Spill::open(&path, 1024 * 1024).unwrap();
+        assert_eq!(spill.len(), 600);
+        assert_eq!(spill.lines(0, 1)[0].plaintext(), "line 0 é");
+        assert_eq!(spill.lines(599, 1)[0].plaintext(), "line 599 é");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn it_forgets_the_oldest_chunks_once_the_files_are_full() {
+        let path = spill_path("rotate");
+        let mut spill = Spill::open(&path, 1).unwrap();
+        for n in 0..CHUNK_LINES * 3 {
+            spill.push(line(&format!("line {}", n)));
+        }
+
+        // Every chunk fills half the allowance, so each one pushes the one before it out
+        assert_eq!(spill.len(), CHUNK_LINES);
+        assert_eq!(spill.lines(0, 1)[0].plaintext(), format!("line {}", CHUNK_LINES * 2));
+        assert!(Spill::rotated_path(&path).exists());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(Spill::rotated_path(&path));
+    }
+}
@@ -1,21 +1,124 @@
-use crate::decker::terminal::internal::ViewPort;
+use crate::decker::terminal::internal::{CharSet, EscSeq, SavedCursor, Spill, ViewPort};
 use crate::decker::terminal::internal::glyph_string::GlyphString;
 use crate::decker::terminal::{Cursor, ScrollMode, PrintStyle, DeletionType, ScreenCoord, VirtualCoord};
+use crate::decker::terminal::Overflow;
+use crate::decker::terminal::{Align, VAlign};
 use log::{info, warn};
+use std::collections::{BTreeSet, VecDeque};
+
+// The most columns a line that doesn't wrap keeps, however much is printed past the edge
+const MAX_LINE_WIDTH: u16 = 1024;
+
+// Lines of history kept for each pane, unless configured otherwise
+const DEFAULT_SCROLLBACK: usize = 2000;
+// Columns between tab stops, unless configured otherwise
+const DEFAULT_TAB_WIDTH: u16 = 8;
+
+// DEC Special Graphics, for the characters '`' through '~'
+const LINE_DRAWING: [char; 31] = [
+    '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼', '⎺',
+    '⎻', '─', '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+];
+
+fn line_drawing(c: char) -> char {
+    match c {
+        '`'..='~' => { LINE_DRAWING[c as usize - '`' as usize] }
+        _ => { c }
+    }
+}
 
 impl ViewPort {
     pub fn new(pane_id: String, width: u16, height: u16, scroll_mode: ScrollMode) -> Self {
         ViewPort {
             pane_id,
-            visible_lines: Vec::with_capacity(height as usize),
+            visible_lines: VecDeque::with_capacity(height as usize),
+            scrollback: VecDeque::new(),
+            scrollback_limit: DEFAULT_SCROLLBACK,
+            spill: None,
+            scrolled_off: 0,
             cur_style: PrintStyle::default(),
             cursor: Cursor::new(width.into(), height.into()),
             scroll_mode,
             width,
             height,
+            insert_mode: false,
+            newline_mode: true,
+            charsets: [CharSet::Ascii; 2],
+            shifted_out: false,
+            saved_cursor: None,
+            tab_stops: Self::tab_stops_every(DEFAULT_TAB_WIDTH, width),
+            auto_wrap: false,
+            overflow: Overflow::Truncate,
+            overhang: 0,
+            reverse_wrap: false,
+            align: Align::Left,
+            valign: VAlign::Top,
+        }
+    }
+
+    fn tab_stops_every(columns: u16, width: u16) -> BTreeSet<VirtualCoord> {
+        (columns..width).step_by(columns.max(1) as usize).collect()
+    }
+
+    /***
+    Put a tab stop every `columns` columns, replacing any others
+     */
+    pub fn set_tab_width(&mut self, columns: u16) {
+        self.tab_stops = Self::tab_stops_every(columns, self.width);
+    }
+
+    /***
+    Change size, keeping what's on screen. A scrolling viewport that gets shorter scrolls enough
+    lines into scrollback to keep the cursor's line visible; lines that get narrower are cut short.
+     */
+    pub fn resize(&mut self, width: u16, height: u16) {
+        if let ScrollMode::Scroll = self.scroll_mode {
+            let overflow = (self.cursor.y() + 1).saturating_sub(height);
+            for _ in 0..overflow.min(self.visible_lines.len() as u16) {
+                self.scroll_off_top();
+            }
+        }
+        self.visible_lines.iter_mut().for_each(|line| line.truncate(width as usize));
+
+        // Keep the tab stops that still fit, with default ones across any new columns
+        self.tab_stops.retain(|&stop| stop < width);
+        self.tab_stops.extend((self.width..width).filter(|x| x % DEFAULT_TAB_WIDTH == 0 && *x > 0));
+
+        let (x, y) = (self.cursor.x(), self.cursor.y());
+        self.cursor = Cursor::new(width, height);
+        self.cursor.set_x(x);
+        self.cursor.set_y(y.min(height.saturating_sub(1)));
+        self.width = width;
+        self.height = height;
+    }
+
+    /***
+    HTS: add a tab stop at the cursor
+     */
+    pub fn set_tab_stop(&mut self) {
+        self.tab_stops.insert(self.cursor.x());
+    }
+
+    /***
+    TBC: remove the tab stop at the cursor, or all of them
+     */
+    pub fn clear_tab_stop(&mut self, all: bool) {
+        if all {
+            self.tab_stops.clear();
+        } else {
+            self.tab_stops.remove(&self.cursor.x());
         }
     }
 
+    /***
+    Move to the next tab stop, or the last column if there isn't one
+     */
+    pub fn tab(&mut self) {
+        let x = self.cursor.x();
+        let next = self.tab_stops.range(x + 1..).next().copied();
+        self.cursor.set_x(next.unwrap_or_else(|| self.width.saturating_sub(1)));
+    }
+
     pub fn width(&self) -> u16 {
         self.width as u16
     }
@@ -28,12 +131,189 @@ impl ViewPort {
         self.scroll_mode = mode
     }
 
+    pub fn set_insert_mode(&mut self, insert: bool) {
+        self.insert_mode = insert
+    }
+
+    pub fn set_newline_mode(&mut self, newline: bool) {
+        self.newline_mode = newline
+    }
+
+    pub fn set_auto_wrap(&mut self, wrap: bool) {
+        self.auto_wrap = wrap
+    }
+
+    /***
+    Wrap long lines, mid-word or between words, or cut them off with an ellipsis. Tasks can still
+    turn wrapping on and off for themselves (DECAWM).
+     */
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.overflow = overflow;
+        self.auto_wrap = overflow != Overflow::Truncate;
+    }
+
+    /***
+    Whether lines cut off at the edge should end in an ellipsis
+     */
+    pub fn ellipsis(&self) -> bool {
+        self.overflow == Overflow::Truncate
+    }
+
+    pub fn set_align(&mut self, align: Align, valign: VAlign) {
+        self.align = align;
+        self.valign = valign;
+    }
+
+    /***
+    Whether content is drawn anywhere but the top-left
+     */
+    pub fn aligned(&self) -> bool {
+        (self.align, self.valign) != (Align::Left, VAlign::Top)
+    }
+
+    /***
+    Where to draw each visible line, as (row, column) offsets into the view port. Trailing
+    blank lines and blanks at the ends of lines don't count as content.
+     */
+    pub fn placements(&mut self) -> Vec<(u16, u16)> {
+        let (width, height) = (self.width, self.height);
+        let (align, valign) = (self.align, self.valign);
+        let lines = self.take_visible_lines();
+        let used = |line: &GlyphString| line.plaintext().trim_end().chars().count().min(width as usize) as u16;
+        let rows = lines.iter().rposition(|line| used(line) > 0).map(|last| last + 1).unwrap_or(0) as u16;
+
+        let top = match valign {
+            VAlign::Top => { 0 }
+            VAlign::Middle => { height.saturating_sub(rows) / 2 }
+            VAlign::Bottom => { height.saturating_sub(rows) }
+        };
+        lines.iter().enumerate().map(|(n, line)| {
+            let spare = width - used(line);
+            let left = match align {
+                Align::Left => { 0 }
+                Align::Center => { spare / 2 }
+                Align::Right => { spare }
+            };
+            (top + n as u16, left)
+        }).collect()
+    }
+
+    pub fn set_reverse_wrap(&mut self, wrap: bool) {
+        self.reverse_wrap = wrap
+    }
+
+    /***
+    Move the cursor back one column. From the first column, reverse wrap takes it to the end of
+    the line above instead.
+     */
+    pub fn backspace(&mut self) {
+        if self.cursor.x() == 0 && self.auto_wrap && self.reverse_wrap && self.cursor.y() > 0 {
+            self.cursor.decr_y(1);
+            self.cursor.set_x(self.width.saturating_sub(1));
+        } else {
+            self.cursor_left(1);
+        }
+    }
+
+    /***
+    Set what G0 (0) or G1 (1) stands for
+     */
+    pub fn designate(&mut self, g: usize, charset: CharSet) {
+        self.charsets[g.min(1)] = charset
+    }
+
+    /***
+    SO (true) prints from G1, SI (false) goes back to G0
+     */
+    pub fn shift_out(&mut self, shifted: bool) {
+        self.shifted_out = shifted
+    }
+
+    /***
+    Remember the cursor's position, style and charsets (DECSC)
+     */
+    pub fn save_cursor(&mut self) {
+        self.saved_cursor = Some(SavedCursor {
+            x: self.cursor.x(),
+            y: self.cursor.y(),
+            style: self.cur_style,
+            charsets: self.charsets,
+            shifted_out: self.shifted_out,
+        });
+    }
+
+    /***
+    Go back to the last saved cursor (DECRC). With nothing saved, that's the home position with
+    default attributes.
+     */
+    pub fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor.unwrap_or(SavedCursor {
+            x: 0,
+            y: 0,
+            style: PrintStyle::default(),
+            charsets: [CharSet::Ascii; 2],
+            shifted_out: false,
+        });
+        self.cursor.set_x(saved.x);
+        self.cursor.set_y(saved.y);
+        self.cur_style = saved.style;
+        self.charsets = saved.charsets;
+        self.shifted_out = saved.shifted_out;
+    }
+
+    /***
+    Print a character at the cursor and move past it. In insert mode the rest of the line shifts
+    right, and whatever is pushed past the right edge is lost. Without wrapping, what's printed
+    past the edge is kept beyond the line's last column, out of sight until the pane is panned.
+     */
+    pub fn print(&mut self, c: char) {
+        let c = match self.charsets[self.shifted_out as usize] {
+            CharSet::Ascii => { c }
+            CharSet::LineDrawing => { line_drawing(c) }
+        };
+        if self.auto_wrap && self.cursor.x() >= self.width {
+            // The last character filled the line - this one starts the next. Word wrapping takes
+            // the word it's part of along with it.
+            let word = match self.overflow == Overflow::WordWrap && !c.is_whitespace() {
+                true => { self.cur_line().take_last_word() }
+                false => { Vec::new() }
+            };
+            self.newline();
+            self.cursor.set_x(0);
+            for (c, style) in word {
+                let index = self.cursor.x();
+                self.cur_line().set(index, c, &style);
+                self.cursor_right(1);
+            }
+        }
+
+        let index = if !self.auto_wrap && self.cursor.x() >= self.width {
+            let index = self.width.saturating_add(self.overhang);
+            if index >= MAX_LINE_WIDTH.max(self.width) { return; }
+            self.overhang += 1;
+            index
+        } else {
+            self.overhang = 0;
+            self.cursor.x()
+        };
+        let style = self.cur_style;
+        let (insert, width) = (self.insert_mode, self.width as usize);
+        let line = self.cur_line();
+        if insert {
+            line.insert(index, c, &style);
+            line.truncate(width);
+        } else {
+            line.set(index, c, &style);
+        }
+        self.cursor_right(1);
+    }
+
     pub fn style(&self) -> PrintStyle {
         self.cur_style
     }
 
-    pub fn apply_style(&mut self, vt100: &str) -> anyhow::Result<()> {
-        self.cur_style.apply_vt100(vt100)?;
+    pub fn apply_style(&mut self, sgr: &EscSeq) -> anyhow::Result<()> {
+        self.cur_style.apply_sgr(sgr)?;
         Ok(())
     }
 
@@ -53,15 +333,13 @@ impl ViewPort {
             }
             DeletionType::ClearScreenToCursor => {
                 // Clear all the lines before us
-                self.visible_lines[..y_idx].iter_mut().for_each(|l| l.clear());
+                self.visible_lines.iter_mut().take(y_idx).for_each(|l| l.clear());
                 // and our line
                 self.cur_line().clear_to(x_idx);
             }
             DeletionType::ClearScreenAfterCursor => {
                 // Clear all the lines after us
-                if y_idx + 1 < self.visible_lines.len() {
-                    self.visible_lines[y_idx + 1..].iter_mut().for_each(|l| l.clear());
-                }
+                self.visible_lines.iter_mut().skip(y_idx + 1).for_each(|l| l.clear());
                 // and our line
                 self.cur_line().clear_after(x_idx);
             }
@@ -71,13 +349,13 @@ impl ViewPort {
         }
     }
 
-    pub fn take_visible_lines(&mut self) -> &mut Vec<GlyphString> {
+    pub fn take_visible_lines(&mut self) -> &mut VecDeque<GlyphString> {
         info!("Lines before truncation: {:?}", self.visible_lines);
         match self.scroll_mode {
             ScrollMode::Scroll => {
                 while self.visible_lines.len() > self.height as usize {
-                    info!("Popping line 0: {:?}", self.visible_lines.get(0));
-                    self.visible_lines.remove(0);
+                    info!("Popping line 0: {:?}", self.visible_lines.front());
+                    self.scroll_off_top();
                 }
             }
             ScrollMode::Fixed => {
@@ -97,8 +375,8 @@ impl ViewPort {
         if self.cursor().y() == self.height {
             match self.scroll_mode {
                 ScrollMode::Scroll => {
-                    self.remove(0);
-                    self.visible_lines.push(GlyphString::new());
+                    self.scroll_off_top();
+                    self.visible_lines.push_back(GlyphString::new());
                 }
                 ScrollMode::Fixed => {
                     // This output will be dropped
@@ -106,15 +384,21 @@ impl ViewPort {
             }
         }
 
-        self.cursor.set_x(0);
+        if self.newline_mode {
+            self.cursor.set_x(0);
+        }
         self.cursor.incr_y(1); // this is bounded to the window size, so we don't have to check here.
     }
 
     pub fn cur_line(&mut self) -> &mut GlyphString {
         if self.cursor.y() >= self.height {
-            let lines_to_pop = self.cursor.y() - self.height;
-            for _ in (0..lines_to_pop) {
-                self.visible_lines.remove(0);
+            // The cursor sits one row past the bottom after a newline. Printing there scrolls.
+            let lines_to_pop = match self.scroll_mode {
+                ScrollMode::Scroll => { self.cursor.y() - self.height + 1 }
+                ScrollMode::Fixed => { self.cursor.y() - self.height }
+            };
+            for _ in 0..lines_to_pop {
+                self.scroll_off_top();
             }
 
             self.cursor.set_y(self.height - 1);
@@ -125,14 +409,82 @@ impl ViewPort {
 
     pub fn mut_line(&mut self, index: VirtualCoord) -> &mut GlyphString {
         while self.visible_lines.len() <= index as usize {
-            self.visible_lines.push(GlyphString::new());
+            self.visible_lines.push_back(GlyphString::new());
         }
 
         self.visible_lines.get_mut(index as usize).unwrap()
     }
 
-    pub fn remove(&mut self, index: usize) -> GlyphString {
-        self.visible_lines.remove(index)
+    /***
+    Move the top visible line into scrollback, spilling (or forgetting) the oldest scrollback line
+    if we're full
+     */
+    fn scroll_off_top(&mut self) {
+        if let Some(line) = self.visible_lines.pop_front() {
+            self.scrolled_off += 1;
+            if self.scrollback_limit == 0 {
+                self.spill_line(line);
+                return;
+            }
+
+            if self.scrollback.len() >= self.scrollback_limit {
+                if let Some(oldest) = self.scrollback.pop_front() {
+                    self.spill_line(oldest);
+                }
+            }
+            self.scrollback.push_back(line);
+        }
+    }
+
+    fn spill_line(&mut self, line: GlyphString) {
+        if let Some(spill) = self.spill.as_mut() {
+            spill.push(line);
+        }
+    }
+
+    /***
+    How many lines have scrolled off the top so far, including any scrollback has since forgotten
+     */
+    pub fn scrolled_off(&self) -> u64 {
+        self.scrolled_off
+    }
+
+    pub fn set_scrollback_limit(&mut self, limit: usize) {
+        self.scrollback_limit = limit;
+        while self.scrollback.len() > limit {
+            if let Some(oldest) = self.scrollback.pop_front() {
+                self.spill_line(oldest);
+            }
+        }
+    }
+
+    /***
+    Keep scrollback past the limit on disk, rather than forgetting it
+     */
+    pub fn set_spill(&mut self, spill: Spill) {
+        self.spill = Some(spill);
+    }
+
+    /***
+    How many lines of history there are to scroll back through, spilled ones included
+     */
+    pub fn history_len(&self) -> usize {
+        self.spill.as_ref().map(|s| s.len()).unwrap_or(0) + self.scrollback.len()
+    }
+
+    /***
+    Up to `count` lines of history, starting `start` lines after the oldest. Spilled lines are
+    read back from disk.
+     */
+    pub fn history(&mut self, start: usize, count: usize) -> Vec<GlyphString> {
+        let spilled = self.spill.as_ref().map(|s| s.len()).unwrap_or(0);
+        let mut lines = match self.spill.as_mut() {
+            Some(spill) if start < spilled => { spill.lines(start, count) }
+            _ => { Vec::new() }
+        };
+        let from = start.saturating_sub(spilled);
+        lines.extend(self.scrollback.iter().skip(from).take(count - lines.len()).cloned());
+        lines
     }
 
     pub fn cursor_goto(&mut self, row: ScreenCoord, col: ScreenCoord) {
@@ -145,14 +497,14 @@ impl ViewPort {
     }
 
     pub fn cursor_down(&mut self, amount: u16) {
-        let final_row = self.cursor.x() + amount;
+        let final_row = self.cursor.y().saturating_add(amount);
         self.cursor.incr_y(amount);
 
         // If we are scrolling past the bottom row, scroll the base up.
         // TODO: This is for SCROLL, but not for FIXED panes
         if final_row >= self.height() {
             (self.height..final_row).for_each(|_| {
-                self.visible_lines.remove(0);
+                self.scroll_off_top();
             });
         }
     }
@@ -173,3 +525,154 @@ impl ViewPort {
         (self.cursor.col(), self.cursor.row())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrolling_view_port(height: u16) -> ViewPort {
+        ViewPort::new(String::from("test"), 10, height, ScrollMode::Scroll)
+    }
+
+    fn print_lines(vp: &mut ViewPort, count: usize) {
+        for i in 0..count {
+            let style = vp.style();
+            vp.cur_line().push(&i.to_string(), &style);
+            vp.newline();
+        }
+    }
+
+    #[test]
+    fn it_shifts_text_right_in_insert_mode() {
+        let mut vp = scrolling_view_port(3);
+        "abcdefghij".chars().for_each(|c| vp.print(c));
+        vp.cursor_goto(1, 3);
+        vp.set_insert_mode(true);
+        vp.print('X');
+        assert_eq!(vp.cur_line().plaintext(), "abXcdefghi");
+
+        vp.set_insert_mode(false);
+        vp.print('Y');
+        assert_eq!(vp.cur_line().plaintext(), "abXYdefghi");
+    }
+
+    #[test]
+    fn it_only_returns_to_the_first_column_in_newline_mode() {
+        let mut vp = scrolling_view_port(3);
+        vp.print('a');
+        vp.newline();
+        assert_eq!(vp.cursor_loc(), (1, 2));
+
+        vp.set_newline_mode(false);
+        vp.print('b');
+        vp.newline();
+        assert_eq!(vp.cursor_loc(), (2, 3));
+    }
+
+    #[test]
+    fn it_restores_the_saved_cursor_state() {
+        let mut vp = scrolling_view_port(3);
+        vp.cursor_goto(2, 3);
+        vp.apply_style(&EscSeq::parse("\x1b[31m")).unwrap();
+        vp.designate(0, CharSet::LineDrawing);
+        vp.save_cursor();
+
+        vp.cursor_goto(1, 1);
+        vp.apply_style(&EscSeq::parse("\x1b[0m")).unwrap();
+        vp.designate(0, CharSet::Ascii);
+        vp.restore_cursor();
+
+        assert_eq!(vp.cursor_loc(), (3, 2));
+        assert_ne!(vp.style(), PrintStyle::default());
+        vp.print('q');
+        assert_eq!(vp.cur_line().plaintext(), "  ─");
+    }
+
+    #[test]
+    fn it_moves_to_tab_stops() {
+        let mut vp = ViewPort::new(String::from("test"), 20, 3, ScrollMode::Scroll);
+        vp.tab();
+        assert_eq!(vp.cursor_loc(), (9, 1));
+
+        vp.cursor_right(2);
+        vp.set_tab_stop();
+        vp.cursor_home();
+        vp.tab();
+        vp.tab();
+        assert_eq!(vp.cursor_loc(), (11, 1));
+
+        vp.clear_tab_stop(true);
+        vp.tab();
+        assert_eq!(vp.cursor_loc(), (20, 1));
+
+        vp.set_tab_width(4);
+        vp.cursor_home();
+        vp.tab();
+        assert_eq!(vp.cursor_loc(), (5, 1));
+    }
+
+    #[test]
+    fn it_wraps_forwards_and_backwards() {
+        let mut vp = scrolling_view_port(3);
+        vp.set_auto_wrap(true);
+        "abcdefghijkl".chars().for_each(|c| vp.print(c));
+        assert_eq!(vp.cursor_loc(), (3, 2));
+        assert_eq!(vp.cur_line().plaintext(), "kl");
+
+        (0..3).for_each(|_| vp.backspace());
+        assert_eq!(vp.cursor_loc(), (1, 2));
+
+        vp.set_reverse_wrap(true);
+        vp.backspace();
+        assert_eq!(vp.cursor_loc(), (10, 1));
+    }
+
+    #[test]
+    fn it_wraps_between_words() {
+        let mut vp = scrolling_view_port(3);
+        vp.set_overflow(Overflow::WordWrap);
+        "the quick brown fox jumps".chars().for_each(|c| vp.print(c));
+        let lines = vp.take_visible_lines().iter().map(|l| l.plaintext()).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["the quick ", "brown fox ", "jumps"]);
+
+        // Words too long for a line still break somewhere
+        vp.newline();
+        vp.cursor_home();
+        "abcdefghijkl".chars().for_each(|c| vp.print(c));
+        assert_eq!(vp.cur_line().plaintext(), "kl");
+    }
+
+    #[test]
+    fn it_keeps_lines_scrolled_off_the_top() {
+        let mut vp = scrolling_view_port(3);
+        print_lines(&mut vp, 6);
+        vp.take_visible_lines();
+
+        let history = vp.scrollback.iter().map(|l| l.plaintext()).collect::<Vec<_>>();
+        assert_eq!(history, vec!["0", "1", "2"]);
+    }
+
+    #[test]
+    fn it_forgets_the_oldest_scrollback_when_full() {
+        let mut vp = scrolling_view_port(3);
+        vp.set_scrollback_limit(2);
+        print_lines(&mut vp, 8);
+        vp.take_visible_lines();
+
+        let history = vp.scrollback.iter().map(|l| l.plaintext()).collect::<Vec<_>>();
+        assert_eq!(history, vec!["3", "4"]);
+    }
+
+    #[test]
+    fn it_keeps_the_cursor_line_when_resized() {
+        let mut vp = scrolling_view_port(4);
+        print_lines(&mut vp, 3);
+        vp.resize(20, 2);
+
+        let history = vp.scrollback.iter().map(|l| l.plaintext()).collect::<Vec<_>>();
+        assert_eq!(history, vec!["0", "1"]);
+        assert_eq!(vp.visible_lines.front().unwrap().plaintext(), "2");
+        assert_eq!(vp.cursor_loc(), (1, 2));
+        assert_eq!(vp.tab_stops.iter().copied().collect::<Vec<_>>(), vec![8, 16]);
+    }
+}
@@ -0,0 +1,77 @@
+use crate::decker::terminal::Layout;
+
+// (x, y, width, height), 1-based like the cursor's Goto
+pub type Rect = (u16, u16, u16, u16);
+
+const CYCLE: [Layout; 4] = [Layout::Configured, Layout::EvenHorizontal, Layout::EvenVertical, Layout::MainVertical];
+
+impl Layout {
+    /***
+    The layout after this one, wrapping around to the configured one at the end
+     */
+    pub fn next(&self) -> Layout {
+        let index = CYCLE.iter().position(|l| l == self).unwrap_or(0);
+        CYCLE[(index + 1) % CYCLE.len()]
+    }
+
+    /***
+    Where `count` panes go in a `width` x `height` area, main first. None for the configured
+    layout, which leaves every pane where the config put it.
+     */
+    pub fn tile(&self, count: usize, width: u16, height: u16) -> Option<Vec<Rect>> {
+        match self {
+            Layout::Configured => { None }
+            Layout::EvenHorizontal => {
+                Some(split(width, count).into_iter().map(|(x, w)| (x, 1, w, height)).collect())
+            }
+            Layout::EvenVertical => {
+                Some(split(height, count).into_iter().map(|(y, h)| (1, y, width, h)).collect())
+            }
+            Layout::MainVertical if count < 2 => { Layout::EvenHorizontal.tile(count, width, height) }
+            Layout::MainVertical => {
+                let main_width = (width / 2).max(1);
+                let rest = split(height, count - 1).into_iter().
+                    map(|(y, h)| (main_width + 1, y, width.saturating_sub(main_width).max(1), h));
+                Some(std::iter::once((1, 1, main_width, height)).chain(rest).collect())
+            }
+        }
+    }
+}
+
+/***
+Cut `total` cells into `count` runs as evenly as possible, as (1-based start, length). The first
+runs take any leftover cells. Every run gets at least one cell, even if that means overlapping.
+ */
+fn split(total: u16, count: usize) -> Vec<(u16, u16)> {
+    let count = count.min(u16::MAX as usize) as u16;
+    if count == 0 { return Vec::new(); }
+
+    let (size, extra) = (total / count, total % count);
+    let mut start = 1;
+    (0..count).map(|n| {
+        let len = (size + (n < extra) as u16).max(1);
+        let run = (start.min(total.max(1)), len);
+        start += len;
+        run
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_cycles_through_every_layout() {
+        assert_eq!(Layout::Configured.next(), Layout::EvenHorizontal);
+        assert_eq!(Layout::MainVertical.next(), Layout::Configured);
+    }
+
+    #[test]
+    fn it_tiles_panes_evenly() {
+        assert_eq!(Layout::Configured.tile(2, 80, 24), None);
+        assert_eq!(Layout::EvenHorizontal.tile(3, 80, 24).unwrap(), vec![(1, 1, 27, 24), (28, 1, 27, 24), (55, 1, 26, 24)]);
+        assert_eq!(Layout::EvenVertical.tile(2, 80, 24).unwrap(), vec![(1, 1, 80, 12), (1, 13, 80, 12)]);
+        assert_eq!(Layout::MainVertical.tile(3, 80, 25).unwrap(), vec![(1, 1, 40, 25), (41, 1, 40, 13), (41, 14, 40, 12)]);
+        assert_eq!(Layout::MainVertical.tile(1, 80, 24).unwrap(), vec![(1, 1, 80, 24)]);
+    }
+}
@@ -0,0 +1,105 @@
+use crate::decker::terminal::PaneManager;
+use std::io::Write;
+
+impl PaneManager {
+    /***
+    Instead of painting panes where they sit on screen, print what changes in each as plain lines
+    labelled with its task, e.g. "[weather] 12°C, cloudy" - something a screen reader can follow
+     */
+    pub fn set_linear(&mut self, linear: bool) {
+        self.linear = linear;
+    }
+
+    /***
+    Print the lines that have changed in every pane since the last write
+     */
+    pub(super) fn write_linear(&mut self, target: &mut dyn Write) -> anyhow::Result<()> {
+        // Nothing's cursor-addressed, so there's nothing to clear or redraw for opened panes
+        let mut discarded = Vec::new();
+        self.handle_pane_requests(&mut discarded);
+
+        let mut task_ids = self.panes.keys().cloned().collect::<Vec<_>>();
+        task_ids.sort();
+        for task_id in task_ids {
+            let pane = match self.panes.get_mut(&task_id).and_then(|panes| panes.first_mut()) {
+                None => { continue }
+                Some(pane) => { pane }
+            };
+            // There's no screen of ours for images and the like to go to
+            pane.take_graphics();
+            pane.take_passthrough();
+            let screen = pane.snapshot();
+            let mut lines = screen.lines().map(|l| l.trim_end().to_string()).collect::<Vec<_>>();
+            while lines.last().map(|l| l.is_empty()).unwrap_or(false) {
+                lines.pop();
+            }
+
+            let spoken = self.spoken.entry(task_id.clone()).or_default();
+            for line in changed_lines(spoken, &lines) {
+                write!(target, "[{}] {}\r\n", task_id, line)?;
+            }
+            *spoken = lines;
+        }
+        let panes = &self.panes;
+        self.spoken.retain(|task_id, _| panes.contains_key(task_id));
+        Ok(())
+    }
+}
+
+/***
+The lines in `new` that weren't in `old`, whichever's fewer of: those that changed in place, or
+those added at the bottom when `old` scrolled up. Blank lines aren't worth reading out.
+ */
+fn changed_lines<'a>(old: &[String], new: &'a [String]) -> Vec<&'a str> {
+    let in_place = new.iter().enumerate().
+        filter(|(n, line)| old.get(*n) != Some(line)).
+        map(|(_, line)| line.as_str());
+
+    // How far up the old lines moved, if they all still show
+    let scrolled = (0..=old.len()).find(|shift| {
+        let kept = &old[*shift..];
+        new.len() >= kept.len() && new[..kept.len()] == *kept
+    });
+    let added = scrolled.map(|shift| new[old.len() - shift..].iter().map(|l| l.as_str()).collect::<Vec<_>>());
+
+    let in_place = in_place.collect::<Vec<_>>();
+    let lines = match added {
+        Some(added) if added.len() < in_place.len() => { added }
+        _ => { in_place }
+    };
+    lines.into_iter().filter(|l| !l.trim().is_empty()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::terminal::Pane;
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn it_finds_changed_lines() {
+        assert_eq!(changed_lines(&lines(&[]), &lines(&["a", "", "b"])), vec!["a", "b"]);
+        assert_eq!(changed_lines(&lines(&["12°C", "cloudy"]), &lines(&["13°C", "cloudy"])), vec!["13°C"]);
+        assert_eq!(changed_lines(&lines(&["a", "b", "c"]), &lines(&["b", "c", "d"])), vec!["d"]);
+        assert!(changed_lines(&lines(&["a", "b"]), &lines(&["a", "b"])).is_empty());
+    }
+
+    #[test]
+    fn it_prints_labelled_updates() {
+        let mut manager = PaneManager::new();
+        manager.set_linear(true);
+        manager.register(String::from("weather"), Pane::new("weather", 1, 1, 2, 20));
+        manager.push(String::from("weather"), b"12\xc2\xb0C, cloudy");
+
+        let mut out = Vec::new();
+        manager.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "[weather] 12°C, cloudy\r\n");
+
+        let mut out = Vec::new();
+        manager.write(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
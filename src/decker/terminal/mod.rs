@@ -1,14 +1,154 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crossbeam_channel::{Receiver, Sender};
 
-use crate::decker::TaskId;
+use crate::decker::{DisabledSet, HealthMap, MasterControl, ProcInput, TaskHealth, TaskId};
+use crate::decker::keys::KeypadModes;
+use crate::decker::theme::Theme;
 use crate::decker::terminal::internal::{StreamState, ViewPort};
 
 mod pane_manager;
 mod pane;
+mod pane_pipes;
+mod replay;
+mod focus;
+mod images;
+mod layout;
+mod colors;
+mod linear;
+mod screen;
+mod echo;
+mod html;
 mod internal;
 
+// The parser is built once, in the library, and shared with the dashboard from there
+pub use crate::parser;
+pub use colors::{alert_sgr, set_color_depth};
+
 pub struct PaneManager {
-    panes: HashMap<TaskId, Pane>,
+    // Each task's panes. Tasks may be mirrored into more than one.
+    panes: HashMap<TaskId, Vec<Pane>>,
+    // Shared with the control server, which opens and closes them at runtime
+    pipes: Arc<Mutex<PanePipes>>,
+    // Reusable buffer each frame is rendered into before it's written out
+    frame: Vec<u8>,
+    // Optional line of info drawn across the bottom of the screen
+    status_line: Option<String>,
+    // Task health from the orchestrator, and what was last drawn for each pane
+    health: Option<HealthMap>,
+    drawn_health: HashMap<TaskId, TaskHealth>,
+    // Panes of disabled tasks are drawn dimmed
+    disabled: Option<DisabledSet>,
+    // Shared with the input loop, which moves focus around. The last pane marked as focused.
+    focus: Arc<Mutex<Focus>>,
+    drawn_focus: Option<TaskId>,
+    // Tasks that have printed something since their pane last had focus
+    unread: HashSet<TaskId>,
+    // The main pane's input, where its answers to terminal queries go
+    main_input: Option<Sender<ProcInput>>,
+    // Shared with the input loop. The real terminal's keypad mode follows the focused pane.
+    keypad: Arc<KeypadModes>,
+    // Pass the focused pane's sixel / iTerm2 and kitty images through to the real terminal?
+    sixel: bool,
+    inline_images: bool,
+    // Shared with the input loop, which cycles through layouts. The last one the panes were tiled into.
+    layout: Arc<Mutex<Layout>>,
+    drawn_layout: Layout,
+    // Tells tasks their new size when their panes are re-tiled, and whether the orchestrator
+    // is answering. Whether we last drew it as unresponsive.
+    mcp: Option<MasterControl>,
+    drawn_unresponsive: bool,
+    // The (needed, actual) screen size last time the panes didn't fit on screen
+    drawn_cramped: Option<((u16, u16), (u16, u16))>,
+    // Panes opened and closed while we're running, e.g. by `decker run`. The ones opened over
+    // the rest of the panes, which are drawn last.
+    pane_requests_tx: Sender<PaneRequest>,
+    pane_requests: Receiver<PaneRequest>,
+    overlays: HashSet<TaskId>,
+    // Print changes as labelled lines rather than painting the screen, for screen readers. The
+    // lines last printed for each task.
+    linear: bool,
+    spoken: HashMap<TaskId, Vec<String>>,
+    // Decker's own prompt, while it's open. Drawn over the bottom row, and has the cursor.
+    prompt: Option<Pane>,
+    // The real terminal's default colors, once a theme has swapped them
+    theme: Option<Theme>,
+}
+
+/***
+A pane to open or close while decker is running
+ */
+pub enum PaneRequest {
+    // Draw this pane over the others, showing the task's output. Answered once the pane is
+    // taking the task's output.
+    Open(TaskId, Box<Pane>, Sender<()>),
+    Close(TaskId),
+    // Send back the text every task's pane has, scrollback included, and how many lines back
+    // through it the pane is scrolled
+    Snapshot(Sender<HashMap<TaskId, (String, usize)>>),
+    // Scroll a task's panes back through their scrollback by this many half-pages, or forward
+    // towards the live output if negative
+    Scroll(TaskId, i32),
+    // Pan a task's panes right across long lines by this many half-widths, or left if negative
+    Pan(TaskId, i32),
+    // Keys just typed at the main pane, and when, for it to echo ahead of its task
+    Typed(Vec<u8>, Instant),
+    // Turn local echo on or off for a task's panes
+    ToggleEcho(TaskId),
+    // Show decker's prompt across the bottom row: what to send a one-row pane to draw it. None
+    // closes the prompt.
+    Prompt(Option<Vec<u8>>),
+    // Ask the real terminal what's on its clipboard. It answers through the keyboard.
+    QueryClipboard,
+    // Clear the real terminal and draw everything again, e.g. after a stray escape sequence
+    // scribbled on it
+    Repaint,
+    // Swap the real terminal's default colors, and redraw in them
+    Theme(Theme),
+    // Send back a task's pane as an HTML page, scrollback included. None if it has no pane.
+    ExportHtml(TaskId, Sender<Option<String>>),
+}
+
+/***
+Which pane has focus. Focus starts on the main pane.
+ */
+pub struct Focus {
+    // Every pane, in the order they were registered
+    panes: Vec<TaskId>,
+    current: usize,
+    // The pane focused before this one, to flip back to
+    previous: Option<usize>,
+}
+
+/***
+Copies of panes' raw output streams, a la tmux's pipe-pane
+ */
+pub struct PanePipes {
+    pipes: HashMap<TaskId, PanePipe>,
+}
+
+pub enum PanePipe {
+    File(File),
+    Command(Child),
+}
+
+/***
+A recorded PTY byte stream, split into chunks along with how long to wait before each one.
+Feeding one back through a Pane reproduces exactly what the task drew.
+ */
+pub struct Replay {
+    chunks: Vec<(Duration, Vec<u8>)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PipeTarget {
+    File(String),
+    Command(String),
 }
 
 #[derive(Eq, PartialEq, Debug, Copy, Clone)]
@@ -25,6 +165,23 @@ pub enum Color {
     RGB(u8, u8, u8),
 }
 
+/***
+How many colors the real terminal can show. Richer colors than it can manage are drawn as the
+nearest one it can. With none, no colors are drawn at all, though bold, reverse video and the
+like still are.
+ */
+#[derive(Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ColorDepth {
+    #[serde(rename = "none")]
+    Monochrome,
+    #[serde(rename = "8")]
+    Eight,
+    #[serde(rename = "256")]
+    TwoFiftySix,
+    #[serde(rename = "truecolor")]
+    TrueColor,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct PrintStyle {
     pub foreground: Color,
@@ -34,8 +191,85 @@ pub struct PrintStyle {
     pub blink: bool,
     pub bold: bool,
     pub invert: bool,
+    pub hidden: bool,
+    pub strikethrough: bool,
+    pub overline: bool,
+}
+
+
+/***
+What a pane is showing, cell by cell, with where its cursor is. Lets tests check what was drawn
+without picking apart the escape codes a frame is made of.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Screen {
+    pub width: u16,
+    pub height: u16,
+    // One row per line of the pane, each exactly `width` cells wide
+    pub rows: Vec<Vec<Cell>>,
+    // (column, row), 0-based from the pane's top left
+    pub cursor: (u16, u16),
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    pub style: PrintStyle,
+}
+
+/***
+Arrangements the panes can be re-tiled into, a la tmux's layouts
+ */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Layout {
+    // Where the config put each pane
+    Configured,
+    // Side by side, in equal columns
+    EvenHorizontal,
+    // Stacked, in equal rows
+    EvenVertical,
+    // Main down the left half, the rest stacked on the right
+    MainVertical,
+}
+
+/***
+What a pane does with lines too long for it
+ */
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Overflow {
+    // Carry on on the next row, as a terminal would - right for logs
+    Wrap,
+    // Carry on on the next row, breaking between words where possible - right for prose
+    #[serde(rename = "word-wrap")]
+    WordWrap,
+    // Cut off at the pane's edge, ending in an ellipsis - right for tables
+    Truncate,
+}
+
+/***
+Where each line of a pane's content sits across the pane, when it's shorter than the pane is wide
+ */
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/***
+Where a pane's content sits up and down the pane, when it doesn't fill it
+ */
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
 
 #[derive(Eq, PartialEq)]
 pub enum ScrollMode {
@@ -69,10 +303,82 @@ pub struct Pane {
     // Location and Dimensions
     pub x: u16,
     pub y: u16,
+    // Where the config put it (x, y, width, height), to go back to after trying other layouts
+    home: (u16, u16, u16, u16),
 
     // Viewable area
     view_port: ViewPort,
 
     // Input buffer
     stream_state: StreamState,
+
+    // Most output parsed per frame. Past half of it, only the newest output is kept
+    // (in `tail`) and the middle is thrown away.
+    frame_cap: usize,
+    frame_bytes: usize,
+    tail: VecDeque<u8>,
+    tail_dropped: usize,
+
+    // The least time between drawing this pane, if its frame rate is limited, and when it last
+    // drew anything. Whether changes since then are waiting for it to be due again.
+    frame_gap: Option<Duration>,
+    drawn_at: Option<Instant>,
+    held_back: bool,
+    // Where the frame rate gets the time from - swapped out by tests
+    clock: fn() -> Instant,
+
+    // Drawn faintly, e.g. because its task is disabled
+    dimmed: bool,
+
+    // Answers to the child's queries (e.g. cursor position), waiting to go back to it
+    replies: Vec<u8>,
+
+    // Numeric keypad in application mode (DECKPAM)?
+    keypad_app: bool,
+
+    // Does the child want pastes bracketed (mode 2004)?
+    bracketed_paste: bool,
+
+    // How much the child may put on the clipboard with OSC 52, if it may at all. Its last
+    // clipboard write, waiting for the pane manager to pass it on.
+    copy_limit: Option<usize>,
+    copied: Option<String>,
+
+    // Images drawn since the last frame, waiting for the pane manager to pass them on (or not)
+    graphics: Vec<String>,
+
+    // Sequences for the real terminal (e.g. hiding the cursor), waiting for the pane manager to
+    // pass them on (or not)
+    passthrough: Vec<u8>,
+
+    // The child rang the bell
+    bell: bool,
+
+    // How many lines back through scrollback the pane is showing; 0 follows the live output.
+    // Lines keep scrolling off underneath a pane that's scrolled back, so remember how many had
+    // when we last looked, and keep the view on the same lines.
+    scrolled_back: usize,
+    scroll_mark: u64,
+    scroll_dirty: bool,
+
+    // How many columns the pane is panned right, to see lines wider than it
+    panned: u16,
+
+    // Whether keystrokes are drawn ahead of the task echoing them
+    local_echo: bool,
+    // The keystrokes drawn that way which the task hasn't echoed yet, and when the last were typed
+    predictions: VecDeque<char>,
+    predicted_at: Option<Instant>,
+    // Once a keystroke we can't guess the effect of is typed, nothing more is drawn ahead until
+    // the task answers
+    echo_paused: bool,
+    // When the task last printed anything, since its echo can beat the keystrokes here
+    output_at: Option<Instant>,
+
+    // An optional header across the top row: a label, the title the child set (OSC 0/2), and
+    // status glyphs from the pane manager
+    title_row: Option<String>,
+    title: Option<String>,
+    status: String,
+    title_row_dirty: bool,
 }
@@ -1,13 +1,27 @@
-use regex::Regex;
-use crate::decker::terminal::internal::{StreamState, VT100, ViewPort};
+use crate::decker::backend::{terminal_size, Goto};
+use crate::decker::terminal::internal::{CharSet, EscSeq, Spill, StreamState, VT100, ViewPort};
+use crate::decker::terminal::internal::glyph_string::GlyphString;
 use crate::decker::terminal::internal::TerminalOutput::{Plaintext, CSI};
 use std::io::Write;
-use log::{info};
-use anyhow::bail;
+use log::{debug, info};
 use std::fmt::{Display, Formatter};
-use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use std::path::Path;
+use crate::decker::terminal::images;
+use crate::decker::terminal::colors::color_depth;
+use crate::decker::terminal::{Cell, Screen};
+use crate::decker::terminal::Overflow;
+use crate::decker::terminal::{Align, VAlign};
+use crate::decker::clipboard::clipboard_write;
 use crate::decker::terminal::{ScrollMode, Pane, Color, PrintStyle, DeletionType, ScreenCoord, VirtualCoord};
 
+// How much output a pane draws per frame unless told otherwise
+const DEFAULT_FRAME_CAP: usize = 256 * 1024;
+
+// Drawn where output was thrown away
+const TRUNCATED_MARKER: &[u8] = "\r\n\x1b[7m… output truncated …\x1b[27m\r\n".as_bytes();
+
 impl Display for Color {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(format!("{:?}", self).as_str())
@@ -43,11 +57,16 @@ impl Color {
     }
 
 
-    pub fn extended_color(args: &mut Vec<u8>) -> anyhow::Result<Color> {
-        match args.remove(0) {
-            2 => { Ok(Color::RGB(args.remove(0), args.remove(0), args.remove(0))) }
-            5 => { Ok(Color::TWOFIFTYSIX(args.remove(0))) }
-            c => { bail!("{} is not a valid SGR extended color argument!", c) }
+    /***
+    Read a 256-color ("5;n") or RGB ("2;r;g;b") color from SGR arguments.
+    None if the arguments are missing or make no sense.
+     */
+    pub fn extended_color<'a, I: Iterator<Item = &'a u16>>(args: &mut I) -> Option<Color> {
+        let mut next = || args.next().map(|v| (*v).min(255) as u8);
+        match next()? {
+            2 => { Some(Color::RGB(next()?, next()?, next()?)) }
+            5 => { Some(Color::TWOFIFTYSIX(next()?)) }
+            _ => { None }
         }
     }
 }
@@ -62,100 +81,103 @@ impl Default for PrintStyle {
             invert: false,
             blink: false,
             bold: false,
+            hidden: false,
+            strikethrough: false,
+            overline: false,
         }
     }
 }
 
-lazy_static! {
-    static ref PARAM_REGEX: Regex = Regex::new("\x1b\\[([0-9;]*)%?m").unwrap();
-    static ref HOME_REGEX: Regex = Regex::new("\x1b\\[(\\d*);?(\\d*).").unwrap();
-    static ref CUR_MOVE_REGEX: Regex = Regex::new("\x1b\\[(\\d*).").unwrap();
-}
-
 impl PrintStyle {
     /****
-    Returns the VT100 codes required to transform self -> other, but does not mutate
+    Appends the VT100 codes required to transform self -> other to `out`
      */
-    pub fn diff_str(&self, other: &PrintStyle) -> String {
-        let mut out = String::new();
-
+    pub fn write_diff(&self, other: &PrintStyle, out: &mut Vec<u8>) {
         if self.foreground != other.foreground {
-            out += &other.foreground_string();
+            other.write_foreground(out);
         }
 
         if self.background != other.background {
-            out += &other.background_string();
+            other.write_background(out);
         }
 
         if self.underline != other.underline {
-            if other.underline { out += "\x1b[4m" } else { out += "\x1b[24m" }
+            out.extend_from_slice(if other.underline { b"\x1b[4m" } else { b"\x1b[24m" });
         }
 
         if self.blink != other.blink {
-            if other.blink { out += "\x1b[5m" } else { out += "\x1b[25m" }
+            out.extend_from_slice(if other.blink { b"\x1b[5m" } else { b"\x1b[25m" });
         }
 
         if self.italicized != other.italicized {
-            if other.italicized { out += "\x1b[3m" } else { out += "\x1b[23m" }
+            out.extend_from_slice(if other.italicized { b"\x1b[3m" } else { b"\x1b[23m" });
         }
 
         if self.invert != other.invert {
-            if other.invert { out += "\x1b[7m" } else { out += "\x1b[27m" }
+            out.extend_from_slice(if other.invert { b"\x1b[7m" } else { b"\x1b[27m" });
         }
 
-        out
-    }
-
-    pub fn to_str(&self) -> String {
-        // Check colors first
-        let fg_str = self.foreground_string();
-        let bg_str = self.background_string();
-
-        let blink = if self.blink {
-            "\x1b[5m"
-        } else {
-            ""
-        };
+        if self.hidden != other.hidden {
+            out.extend_from_slice(if other.hidden { b"\x1b[8m" } else { b"\x1b[28m" });
+        }
 
-        let underlined = if self.underline {
-            "\x1b[4m"
-        } else {
-            ""
-        };
+        if self.strikethrough != other.strikethrough {
+            out.extend_from_slice(if other.strikethrough { b"\x1b[9m" } else { b"\x1b[29m" });
+        }
 
-        let italicized = if self.italicized {
-            "\x1b[3m"
-        } else {
-            ""
-        };
+        if self.overline != other.overline {
+            out.extend_from_slice(if other.overline { b"\x1b[53m" } else { b"\x1b[55m" });
+        }
+    }
 
-        let mut out = String::from(fg_str);
-        out.push_str(&bg_str);
-        out.push_str(&blink);
-        out.push_str(&underlined);
-        out.push_str(&italicized);
+    pub fn to_str(&self) -> String {
+        let mut out = Vec::new();
+        self.write_to(&mut out);
+        String::from_utf8(out).unwrap()
+    }
 
-        out
+    /****
+    Appends the VT100 codes that fully describe this style to `out`
+     */
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        // Check colors first
+        self.write_foreground(out);
+        self.write_background(out);
+
+        if self.blink { out.extend_from_slice(b"\x1b[5m"); }
+        if self.underline { out.extend_from_slice(b"\x1b[4m"); }
+        if self.italicized { out.extend_from_slice(b"\x1b[3m"); }
+        if self.invert { out.extend_from_slice(b"\x1b[7m"); }
+        if self.hidden { out.extend_from_slice(b"\x1b[8m"); }
+        if self.strikethrough { out.extend_from_slice(b"\x1b[9m"); }
+        if self.overline { out.extend_from_slice(b"\x1b[53m"); }
     }
 
-    fn background_string(&self) -> String {
+    fn write_background(&self, out: &mut Vec<u8>) {
         let bg_base = if self.bold { 100 } else { 40 };
-        let bg_str = match self.background {
-            Color::TWOFIFTYSIX(num) => { format!("\x1b[38;5;{}m", num) }
-            Color::RGB(r, g, b) => { format!("\x1b[38;2;{};{};{}m", r, g, b) }
-            color => { format!("\x1b[{}m", bg_base + color.to_offset()) }
+        // Writing to a Vec can't fail
+        let color = match self.background.degrade(color_depth()) {
+            None => { return }
+            Some(color) => { color }
+        };
+        let _ = match color {
+            Color::TWOFIFTYSIX(num) => { write!(out, "\x1b[48;5;{}m", num) }
+            Color::RGB(r, g, b) => { write!(out, "\x1b[48;2;{};{};{}m", r, g, b) }
+            color => { write!(out, "\x1b[{}m", bg_base + color.to_offset()) }
         };
-        bg_str
     }
 
-    fn foreground_string(&self) -> String {
+    fn write_foreground(&self, out: &mut Vec<u8>) {
         let fg_base = if self.bold { 90 } else { 30 };
-        let fg_str = match self.foreground {
-            Color::TWOFIFTYSIX(num) => { format!("\x1b[38;5;{}m", num) }
-            Color::RGB(r, g, b) => { format!("\x1b[38;2;{};{};{}m", r, g, b) }
-            color => { format!("\x1b[{}m", fg_base + color.to_offset()) }
+        let color = match self.foreground.degrade(color_depth()) {
+            None => { return }
+            Some(color) => { color }
+        };
+        let _ = match color {
+            Color::TWOFIFTYSIX(num) => { write!(out, "\x1b[38;5;{}m", num) }
+            Color::RGB(r, g, b) => { write!(out, "\x1b[38;2;{};{};{}m", r, g, b) }
+            color => { write!(out, "\x1b[{}m", fg_base + color.to_offset()) }
         };
-        fg_str
     }
 
     pub fn reset(&mut self) -> anyhow::Result<()> {
@@ -167,70 +189,76 @@ impl PrintStyle {
         self.invert = false;
         self.blink = false;
         self.bold = false;
+        self.hidden = false;
+        self.strikethrough = false;
+        self.overline = false;
         Ok(())
     }
 
-    pub fn apply_vt100(&mut self, s: &str) -> anyhow::Result<()> {
-        info!("Attempting to apply SGR command '{:?}'", s);
+    /***
+    Apply an already-parsed SGR sequence
+     */
+    pub fn apply_sgr(&mut self, seq: &EscSeq) -> anyhow::Result<()> {
+        info!("Attempting to apply SGR command '{:?}'", seq.as_str());
 
-        match PARAM_REGEX.captures(s) {
-            None => { bail!("'{:?}' does not look like an SGR sequence!", s) }
-            Some(captures) => {
-                let mut int_parts: Vec<u8> = captures.get(1).unwrap().as_str().
-                    split(";").
-                    map(|a| a.to_string().parse::<u8>()).
-                    filter_map(|p| p.ok()).
-                    collect();
+        if seq.params.is_empty() {
+            // Special case - this is shorthand for reset
+            self.reset()?;
+        }
 
-                if int_parts.is_empty() {
-                    // Special case - this is shorthand for reset
-                    self.reset()?;
+        // Child programs send all sorts of things - anything we can't draw is skipped, never fatal
+        let mut params = seq.params.iter();
+        while let Some(&sgr_code) = params.next() {
+            match sgr_code {
+                0 => { self.reset()?; }
+                1 => { self.bold = true; }
+                2 => { self.bold = false; } // faint - "not bold" is as close as we get
+                3 => { self.italicized = true; }
+                4 | 21 => { self.underline = true; } // 21 is doubly underlined
+                5 | 6 => { self.blink = true; }
+                7 => { self.invert = true; }
+                8 => { self.hidden = true; }
+                9 => { self.strikethrough = true; }
+                10..=20 => {} // alternate fonts
+                22 => { self.bold = false; }
+                23 => { self.italicized = false; }
+                24 => { self.underline = false; }
+                25 => { self.blink = false; }
+                27 => { self.invert = false; }
+                28 => { self.hidden = false; }
+                29 => { self.strikethrough = false; }
+                30..=37 => { self.foreground = Color::eight_color(sgr_code as u8); }
+                38 => {
+                    match Color::extended_color(&mut params) {
+                        Some(color) => { self.foreground = color }
+                        None => { debug!("Malformed extended color in {:?}", seq.as_str()); break; }
+                    }
                 }
-
-                // until int_parts is empty, consume and apply the settings
-                while !int_parts.is_empty() {
-                    let sgr_code = int_parts.remove(0);
-
-                    match sgr_code {
-                        0 => {
-                            /* reset */
-                            self.foreground = Color::White;
-                            self.background = Color::Black;
-                            self.blink = false;
-                            self.underline = false;
-                            self.bold = false;
-                        }
-                        1 => { self.bold = true; }
-                        2 => { self.bold = false; }
-                        3 => { self.italicized = true; }
-                        4 => { self.underline = true; }
-                        5 => { self.blink = true; }
-                        7 => { self.invert = true; }
-                        22 => { self.bold = false; }
-                        23 => { self.italicized = false; }
-                        24 => { self.underline = false; }
-                        25 => { self.blink = false; }
-                        27 => { self.invert = false; }
-                        30..=37 => { self.foreground = Color::eight_color(sgr_code); }
-                        38 => { self.foreground = Color::extended_color(&mut int_parts)? }
-                        39 => { self.foreground = Color::White }
-                        40..=47 => { self.background = Color::eight_color(sgr_code); }
-                        48 => { self.background = Color::extended_color(&mut int_parts)? }
-                        49 => { self.foreground = Color::Black }
-                        90..=97 => {
-                            self.foreground = Color::eight_color(sgr_code);
-                            self.bold = true;
-                        }
-                        100..=107 => {
-                            self.background = Color::eight_color(sgr_code);
-                            self.bold = true;
-                        }
-
-                        _ => { panic!("Invalid or unknown SGR code {}", sgr_code) }
+                39 => { self.foreground = Color::White }
+                40..=47 => { self.background = Color::eight_color(sgr_code as u8); }
+                48 => {
+                    match Color::extended_color(&mut params) {
+                        Some(color) => { self.background = color }
+                        None => { debug!("Malformed extended color in {:?}", seq.as_str()); break; }
                     }
-
-                    PARAM_REGEX.captures(s).unwrap();
                 }
+                49 => { self.background = Color::Black }
+                53 => { self.overline = true; }
+                55 => { self.overline = false; }
+                58 => {
+                    // Underline color - we can't draw it, but its arguments still need skipping
+                    if Color::extended_color(&mut params).is_none() { break; }
+                }
+                90..=97 => {
+                    self.foreground = Color::eight_color(sgr_code as u8);
+                    self.bold = true;
+                }
+                100..=107 => {
+                    self.background = Color::eight_color(sgr_code as u8);
+                    self.bold = true;
+                }
+
+                _ => { debug!("Ignoring unsupported SGR code {} in {:?}", sgr_code, seq.as_str()) }
             }
         }
 
@@ -246,9 +274,102 @@ impl Pane {
             id: String::from(id),
             x,
             y,
+            home: (x, y, width, height),
             view_port,
             stream_state: StreamState::new(),
+            frame_cap: DEFAULT_FRAME_CAP,
+            frame_bytes: 0,
+            tail: VecDeque::new(),
+            tail_dropped: 0,
+            frame_gap: None,
+            drawn_at: None,
+            held_back: false,
+            clock: Instant::now,
+            dimmed: false,
+            replies: Vec::new(),
+            keypad_app: false,
+            bracketed_paste: false,
+            copy_limit: None,
+            copied: None,
+            graphics: Vec::new(),
+            passthrough: Vec::new(),
+            bell: false,
+            scrolled_back: 0,
+            scroll_mark: 0,
+            scroll_dirty: false,
+            panned: 0,
+            local_echo: false,
+            predictions: VecDeque::new(),
+            predicted_at: None,
+            echo_paused: false,
+            output_at: None,
+            title_row: None,
+            title: None,
+            status: String::new(),
+            title_row_dirty: false,
+        }
+    }
+
+    /***
+    A pane across the bottom half of the screen, for output that's just passing through. Its top
+    row is labelled with the task's id, and the screen's bottom row is left for the status line.
+     */
+    pub fn scratch(task_id: &str) -> Pane {
+        let (width, height) = terminal_size().unwrap_or((80, 24));
+        let top = height / 2 + 1;
+        let mut pane = Pane::new(task_id, 1, top, height.saturating_sub(top).max(2), width);
+        pane.set_title_row(task_id);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane
+    }
+
+    /***
+    Give this pane a header row with the given label. The pane's own output gets one row less.
+    Call this before anything else: the pane starts over with the smaller size.
+     */
+    pub fn set_title_row(&mut self, label: &str) {
+        let (width, height) = (self.width(), self.height());
+        if self.title_row.is_none() && height > 1 {
+            self.view_port = ViewPort::new(self.id.clone(), width, height - 1, ScrollMode::Fixed);
         }
+        self.title_row = Some(label.to_string());
+        self.title_row_dirty = true;
+    }
+
+    /***
+    Move and resize the pane, keeping what it shows. The height includes any title row.
+     */
+    pub fn set_geometry(&mut self, x: u16, y: u16, width: u16, height: u16) {
+        self.x = x;
+        self.y = y;
+        let content_height = height.saturating_sub(self.title_row.is_some() as u16);
+        self.view_port.resize(width.max(1), content_height.max(1));
+        self.invalidate();
+    }
+
+    pub fn home(&self) -> (u16, u16, u16, u16) {
+        self.home
+    }
+
+    pub fn has_title_row(&self) -> bool {
+        self.title_row.is_some()
+    }
+
+    /***
+    Glyphs shown at the right of the title row, e.g. for focus and health
+     */
+    pub fn set_status(&mut self, status: &str) {
+        if self.status != status {
+            self.status = status.to_string();
+            self.title_row_dirty = true;
+        }
+    }
+
+    /***
+    The screen row the pane's output starts on
+     */
+    pub fn content_y(&self) -> u16 {
+        self.y + self.title_row.is_some() as u16
     }
 
     pub fn width(&self) -> u16 {
@@ -263,10 +384,196 @@ impl Pane {
         self.view_port.set_scroll_mode(mode);
     }
 
-    pub fn push(&mut self, s: &str) -> anyhow::Result<()> {
-        self.stream_state.push(s);
+    /***
+    Continue output that reaches the right edge on the next line, breaking mid-word or between
+    words, or cut it off there with an ellipsis. Children can still turn wrapping on and off for
+    themselves.
+     */
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.view_port.set_overflow(overflow);
+    }
+
+    /***
+    Where content smaller than the pane sits in it, e.g. a clock centered in a big pane. Redraws
+    the whole pane, since everything in it moves.
+     */
+    pub fn set_align(&mut self, align: Align, valign: VAlign) {
+        self.view_port.set_align(align, valign);
+        self.invalidate();
+    }
+
+    pub fn set_tab_width(&mut self, columns: u16) {
+        self.view_port.set_tab_width(columns);
+    }
+
+    pub fn set_scrollback_limit(&mut self, lines: usize) {
+        self.view_port.set_scrollback_limit(lines);
+    }
 
-        for out in self.stream_state.consume() {
+    /***
+    Spill scrollback older than the limit into a compressed file at `path`, up to `max_bytes` of
+    it, and read it back when scrolled back that far
+     */
+    pub fn spill_scrollback(&mut self, path: &Path, max_bytes: u64) -> anyhow::Result<()> {
+        self.view_port.set_spill(Spill::open(path, max_bytes)?);
+        Ok(())
+    }
+
+    /***
+    Draw this pane faintly, or go back to drawing it normally
+     */
+    pub fn set_dimmed(&mut self, dimmed: bool) {
+        if dimmed != self.dimmed {
+            self.dimmed = dimmed;
+            self.invalidate();
+        }
+    }
+
+    /***
+    Take the answers to the child's queries, to be written back to its input
+     */
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.replies)
+    }
+
+    pub fn dimmed(&self) -> bool {
+        self.dimmed
+    }
+
+    /***
+    Whether the child has put the numeric keypad in application mode
+     */
+    pub fn keypad_app(&self) -> bool {
+        self.keypad_app
+    }
+
+    /***
+    Whether the child has asked for pastes to be bracketed
+     */
+    pub fn bracketed_paste(&self) -> bool {
+        self.bracketed_paste
+    }
+
+    /***
+    Did the child ring the bell since last asked?
+     */
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell)
+    }
+
+    /***
+    Take the images this pane drew since last asked
+     */
+    pub fn take_graphics(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.graphics)
+    }
+
+    /***
+    Take the sequences the child meant for the real terminal since last asked
+     */
+    pub fn take_passthrough(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.passthrough)
+    }
+
+    /***
+    Let the child set the clipboard, up to `limit` bytes at a time. None stops it.
+     */
+    pub fn set_copy_limit(&mut self, limit: Option<usize>) {
+        self.copy_limit = limit;
+    }
+
+    /***
+    The child's last clipboard write since the last frame, ready for the real terminal
+     */
+    pub fn take_copied(&mut self) -> Option<String> {
+        self.copied.take()
+    }
+
+    /***
+    Limit how much output (in bytes) is drawn per frame
+     */
+    pub fn set_frame_cap(&mut self, bytes: usize) {
+        self.frame_cap = bytes.max(2);
+    }
+
+    /***
+    Draw this pane at most `fps` times a second, e.g. for a task that spams progress bars. Its
+    output is still read every frame; what changes in between is drawn together once it's due.
+     */
+    pub fn set_max_fps(&mut self, fps: f64) {
+        self.frame_gap = Some(Duration::from_secs_f64(1.0 / fps));
+    }
+
+    /***
+    How long until this pane is due drawing what its frame rate held back, if anything was
+     */
+    pub fn held_back_for(&self) -> Option<Duration> {
+        if !self.held_back { return None; }
+        match (self.frame_gap, self.drawn_at) {
+            (Some(gap), Some(drawn_at)) => { Some(gap.saturating_sub((self.clock)().duration_since(drawn_at))) }
+            _ => { Some(Duration::ZERO) }
+        }
+    }
+
+    /***
+    Whether it's too soon, at `now`, to draw this pane again
+     */
+    fn too_soon(&mut self, now: Instant) -> bool {
+        let gap = match self.frame_gap {
+            None => { return false }
+            Some(gap) => { gap }
+        };
+        self.held_back = matches!(self.drawn_at, Some(drawn_at) if now.duration_since(drawn_at) < gap);
+        self.held_back
+    }
+
+    /***
+    Process raw output from the pane's task. Bytes needn't split on UTF-8 character boundaries.
+    Once a frame's worth has been parsed, only the newest output is held until the next frame.
+     */
+    pub fn push<B: AsRef<[u8]>>(&mut self, data: B) -> anyhow::Result<()> {
+        let data = data.as_ref();
+        let head_room = (self.frame_cap / 2).saturating_sub(self.frame_bytes).min(data.len());
+        let (head, rest) = data.split_at(head_room);
+
+        self.frame_bytes += head.len();
+        self.parse(head)?;
+
+        if !rest.is_empty() {
+            self.tail.extend(rest);
+            let excess = self.tail.len().saturating_sub(self.frame_cap / 2);
+            self.tail.drain(..excess);
+            self.tail_dropped += excess;
+        }
+
+        Ok(())
+    }
+
+    /***
+    Parse the newest output held back this frame, marking where anything was thrown away
+     */
+    fn flush_tail(&mut self) -> anyhow::Result<()> {
+        if self.tail_dropped > 0 {
+            info!("{}: Dropped {} bytes of output this frame", self.id, self.tail_dropped);
+            // Whatever was mid-sequence when we started dropping will never be finished
+            self.stream_state.reset();
+            self.parse(TRUNCATED_MARKER)?;
+        }
+
+        let tail = std::mem::take(&mut self.tail);
+        self.parse(tail.as_slices().0)?;
+        self.parse(tail.as_slices().1)?;
+
+        self.frame_bytes = 0;
+        self.tail_dropped = 0;
+        Ok(())
+    }
+
+    fn parse(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        if data.is_empty() { return Ok(()); }
+        self.echo_paused = false;
+        self.output_at = Some(Instant::now());
+        for out in self.stream_state.feed(data) {
             match out {
                 Plaintext(plain) => {
                     info!("{}: Processing TXT {:?} {:?}", self.id, self.view_port.cursor_loc(), plain);
@@ -276,41 +583,35 @@ impl Pane {
 
                     for c in plain.chars() {
                         match c {
-                            '\u{8}' => {
-                                /* Backspace */
-                                self.view_port.cursor_left(1);
-                            }
-                            '\n' => {
-                                info!("main: New line for \\n");
+                            '\u{8}' => { self.view_port.backspace() }
+                            '\n' | '\x0b' | '\x0c' => {
+                                // VT and FF are line feeds too
+                                info!("main: New line for {:?}", c);
                                 self.view_port.newline();
                             }
-                            '\t' => {
-                                // Replace tabs with 4 spaces
-                                let line = self.view_port.cur_line();
-                                line.push("    ", &line.last_style());
-                                self.view_port.cursor_right(4);
-                            }
+                            '\t' => { self.view_port.tab() }
                             '\r' => {
                                 self.view_port.cursor_home();
                             }
                             '\x7F' => { /* Delete */ }
+                            '\x07' => {
+                                // Bell - the pane manager has the real terminal ring it
+                                self.bell = true;
+                            }
+                            '\x0e' => { self.view_port.shift_out(true) }
+                            '\x0f' => { self.view_port.shift_out(false) }
+                            '\x00' | '\x05' => {
+                                // NUL is padding. ENQ asks for an answerback message, and ours is empty.
+                            }
+                            c if c.is_control() => {
+                                // Binary noise, or controls we don't handle. Passing these through
+                                // would scramble the real terminal, so drop them.
+                                debug!("{}: Dropping control char {:?}", self.id, c);
+                            }
                             _ => {
-                                // check to see if this is a printable character or not
-                                match c as u8 {
-                                    0x20..=0xFF => {
-                                        // Visible characters
-                                        let index = self.view_port.cursor().x();
-                                        let style = self.view_port.style();
-                                        let line = self.view_port.cur_line();
-                                        line.set(index, c, &style);
-                                        self.view_port.cursor_right(1);
-                                    }
-                                    _ => {
-                                        // Special chars that don't have fill
-                                        info!("main: Unhandled char: {:?}({})", c, c as u8);
-                                        print!("{}", c);
-                                    }
-                                }
+                                // Visible characters
+                                self.confirm_echo(c);
+                                self.view_port.print(c);
                             }
                         }
                     }
@@ -333,11 +634,92 @@ impl Pane {
                             /* text deletion */
                             self.delete_text(&code)?
                         }
-                        VT100::HideCursor(code) => { print!("{}", code) }
-                        VT100::ShowCursor(code) => { print!("{}", code) }
-                        VT100::GetCursorPos(code) => { print!("{}", code) }
-                        VT100::EnterApplicationKeyMode(code) => { print!("{}", code) }
-                        VT100::ExitAltKeypadMode(code) => { print!("{}", code) }
+                        VT100::HideCursor(code) |
+                        VT100::ShowCursor(code) => { self.passthrough.extend_from_slice(code.as_str().as_bytes()) }
+                        VT100::GetCursorPos(_) => {
+                            let cursor = self.view_port.cursor();
+                            let _ = write!(self.replies, "\x1b[{};{}R", cursor.row(), cursor.col());
+                        }
+                        VT100::DeviceAttributes(_) => {
+                            // A VT220 with ANSI color
+                            self.replies.extend_from_slice(b"\x1b[?62;22c");
+                        }
+                        VT100::SecondaryDeviceAttributes(_) => {
+                            // VT220, firmware 10, no ROM cartridge
+                            self.replies.extend_from_slice(b"\x1b[>1;10;0c");
+                        }
+                        VT100::DeviceStatus(_) => {
+                            // We're always OK
+                            self.replies.extend_from_slice(b"\x1b[0n");
+                        }
+                        VT100::EnterApplicationKeyMode(code) => { self.passthrough.extend_from_slice(code.as_str().as_bytes()) }
+                        VT100::EnterAltKeypadMode(_) => {
+                            // The pane manager passes this on once the pane has focus
+                            self.keypad_app = true;
+                        }
+                        VT100::ExitAltKeypadMode(_) => { self.keypad_app = false; }
+                        VT100::EnterInsertMode(_) => { self.view_port.set_insert_mode(true) }
+                        VT100::ExitInsertMode(_) => { self.view_port.set_insert_mode(false) }
+                        VT100::EnterAutoWrapMode(_) => { self.view_port.set_auto_wrap(true) }
+                        VT100::ExitAutoWrapMode(_) => { self.view_port.set_auto_wrap(false) }
+                        VT100::EnterReverseWrapMode(_) => { self.view_port.set_reverse_wrap(true) }
+                        VT100::ExitReverseWrapMode(_) => { self.view_port.set_reverse_wrap(false) }
+                        VT100::EnterNewlineMode(_) => { self.view_port.set_newline_mode(true) }
+                        VT100::ExitNewlineMode(_) => { self.view_port.set_newline_mode(false) }
+                        VT100::DeviceControl(code) => {
+                            let body = code.as_str().trim_start_matches("\x1bP").trim_end_matches("\x1b\\");
+                            let sixel = body.trim_start_matches(|c: char| c.is_ascii_digit() || c == ';').starts_with('q');
+                            if sixel {
+                                // Whether it's shown is up to the pane manager
+                                self.graphics.push(code.as_str().to_string());
+                            } else if body.starts_with("+q") || body.starts_with("$q") {
+                                // Terminfo (XTGETTCAP) and setting (DECRQSS) queries. Say we
+                                // don't know rather than leave the child waiting.
+                                let _ = write!(self.replies, "\x1bP0{}r\x1b\\", &body[..1]);
+                            } else {
+                                // tmux passthrough, etc - never drawn as text
+                                info!("{}: Discarding {} byte DCS", self.id, code.as_str().len());
+                            }
+                        }
+                        VT100::OperatingSystem(code) if code.as_str().starts_with("\x1b]1337;File=") => {
+                            let (width, height) = (self.width(), self.height());
+                            self.graphics.push(images::fit_iterm2(code.as_str(), width, height));
+                        }
+                        VT100::ApplicationProgram(code) if code.as_str().starts_with("\x1b_G") => {
+                            let (width, height) = (self.width(), self.height());
+                            self.graphics.push(images::fit_kitty(code.as_str(), width, height));
+                        }
+                        VT100::OperatingSystem(code) if code.as_str().starts_with("\x1b]0;") || code.as_str().starts_with("\x1b]2;") => {
+                            // Window title
+                            let title = code.as_str()[4..].trim_end_matches('\x07').trim_end_matches("\x1b\\");
+                            self.title = Some(title.to_string());
+                            self.title_row_dirty = true;
+                        }
+                        VT100::OperatingSystem(code) if code.as_str().starts_with("\x1b]52;") => {
+                            // Clipboard writes go on to the real terminal, if this pane's allowed them
+                            match self.copy_limit.map(|limit| clipboard_write(code.as_str(), limit)) {
+                                None => { info!("{}: Not allowed to set the clipboard", self.id) }
+                                Some(Err(e)) => { info!("{}: Refused a clipboard write: {}", self.id, e) }
+                                Some(Ok(write)) => { self.copied = Some(write) }
+                            }
+                        }
+                        VT100::OperatingSystem(code) | VT100::ApplicationProgram(code) => {
+                            info!("{}: Discarding control string {:?}", self.id, code.as_str());
+                        }
+                        VT100::SetTabStop(_) => { self.view_port.set_tab_stop() }
+                        VT100::ClearTabStop(code) => { self.view_port.clear_tab_stop(code.param(0, 0) == 3) }
+                        VT100::NextLine(_) => {
+                            self.view_port.newline();
+                            self.view_port.cursor_home();
+                        }
+                        VT100::SaveCursor(_) => { self.view_port.save_cursor() }
+                        VT100::RestoreCursor(_) => { self.view_port.restore_cursor() }
+                        VT100::SelectCharSet(code) => {
+                            // ESC ( sets G0 and ESC ) G1. 0 is line drawing, anything else we treat as ASCII.
+                            let g = if code.as_str().starts_with("\x1b(") { 0 } else { 1 };
+                            let charset = if code.as_str().ends_with('0') { CharSet::LineDrawing } else { CharSet::Ascii };
+                            self.view_port.designate(g, charset);
+                        }
                         VT100::PassThrough(code) => {
                             /* Loads of control options */
                             match code.as_str() {
@@ -350,30 +732,30 @@ impl Pane {
                                 => {
                                     // All of these can be managed by the
                                     // top level terminal emulator...
-                                    // if vt100_code != "\x1b[?25l" {  /* hide cursor */
-                                    print!("{}", code);
-                                    // }
+                                    self.passthrough.extend_from_slice(code.as_str().as_bytes());
+                                    match code.as_str() {
+                                        "\x1b[?2004h" => { self.bracketed_paste = true }
+                                        "\x1b[?2004l" => { self.bracketed_paste = false }
+                                        _ => {}
+                                    }
                                 }
                                 // Alternate screen
                                 "\x1b[?1049h" => {
                                     /* Alternate screen ON */
-                                    self.delete_text("\x1b[2J").unwrap(); // clear screen
+                                    self.view_port.clear(DeletionType::ClearScreen);
                                 }
                                 "\x1b[?1049l" => {
                                     /* Alternate screen OFF */
-                                    self.delete_text("\x1b[2J").unwrap(); // clear screen
+                                    self.view_port.clear(DeletionType::ClearScreen);
                                 }
                                 _ => {}
                             }
                         }
                         VT100::Unknown(code) => {
-                            /* Just print these directly... I guess */
+                            /* Just pass these on... I guess */
                             info!("{}: Unknown CSI {:?}", self.id, code);
-                            print!("{}", code);
+                            self.passthrough.extend_from_slice(code.as_str().as_bytes());
                         }
-
-                        // FIXME: Not yet handled
-                        VT100::EnterAltKeypadMode(_) => {}
                     }
                 }
             }
@@ -384,40 +766,275 @@ impl Pane {
         Ok(())
     }
 
-    pub fn write(&mut self, target: &mut dyn Write) -> anyhow::Result<()> {
+    /***
+    Append the escape sequences to draw this pane's changed lines to `frame`
+     */
+    pub fn write(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        self.flush_tail()?;
+        self.follow_scrolled_lines();
+        let now = (self.clock)();
+        if self.too_soon(now) {
+            return Ok(());
+        }
+        // Only a frame that drew something counts against the frame rate
+        let start_len = frame.len();
+        self.draw(frame)?;
+        if frame.len() > start_len {
+            self.drawn_at = Some(now);
+        }
+        Ok(())
+    }
+
+    /***
+    Append whatever of the pane changed to `frame`, however it's being shown
+     */
+    fn draw(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        if self.scrolled_back > 0 {
+            return self.write_scrolled_back(frame);
+        }
+        if self.view_port.aligned() {
+            return self.write_aligned(frame);
+        }
+
         let mut line_idx = 0;
 
-        let ps = self.view_port.style().clone();
+        let ps = self.view_port.style();
         // Values cloned to avoid having immutable references inside a mutable reference to self
         let x_off = self.x;
-        let y_off = self.y;
+        let y_off = self.content_y();
         let width = self.width();
         let pane_id = self.id.as_str();
-        let mut chunks: Vec<u8> = Vec::with_capacity(1024);
+        let dimmed = self.dimmed;
+        let ellipsis = self.view_port.ellipsis();
+        let window = self.panned..self.panned.saturating_add(width);
+        let start_len = frame.len();
 
         self.view_port.take_visible_lines().iter_mut().for_each(|line| {
             if line.dirty() {
                 info!("{}: Printing plaintext@({}): {:?}", pane_id, line_idx, line.plaintext());
-                info!("{}: glyphs: {}", pane_id, line.glyphs.len());
-                line.write(x_off, y_off + line_idx, width, &ps, &mut chunks).unwrap();
+                info!("{}: glyphs: {}", pane_id, line.len());
+                if dimmed {
+                    line.write_dimmed(x_off, y_off + line_idx, window.clone(), ellipsis, frame);
+                } else {
+                    line.write(x_off, y_off + line_idx, window.clone(), &ps, ellipsis, frame);
+                }
             }
             line_idx += 1;
         });
 
-        if chunks.len() > 0 {
-            info!("Writing {} bytes", chunks.len());
-            write!(target, "{}", String::from_utf8(chunks)?)?;
+        self.write_predictions(frame)?;
+        if self.title_row_dirty {
+            self.write_title_row(frame)?;
+        }
+
+        if frame.len() > start_len {
+            info!("Writing {} bytes", frame.len() - start_len);
+        }
+
+        Ok(())
+    }
+
+    /***
+    Draw just the line the cursor's on, for an echo of typing that shouldn't wait for a whole
+    frame. Draws nothing, and says so, if anything else about the pane needs drawing too.
+     */
+    pub fn write_cursor_line(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<bool> {
+        // Bells, images and the like are passed on by the pane manager's frames
+        let side_effects = self.bell || self.copied.is_some() || !self.graphics.is_empty() || !self.passthrough.is_empty();
+        if side_effects || !self.tail.is_empty() || self.scrolled_back > 0 || self.view_port.aligned() ||
+            self.title_row_dirty || self.has_predictions() {
+            return Ok(false);
+        }
+
+        let scrolled_off = self.view_port.scrolled_off();
+        let row = self.view_port.cursor().row() as usize - 1;
+        let lines = self.view_port.take_visible_lines();
+        let others_changed = lines.iter().enumerate().any(|(n, line)| n != row && line.dirty());
+        if others_changed || self.view_port.scrolled_off() != scrolled_off {
+            return Ok(false);
+        }
+
+        let (x, y, width) = (self.x, self.content_y() + row as u16, self.width());
+        let ps = self.view_port.style();
+        let ellipsis = self.view_port.ellipsis();
+        let window = self.panned..self.panned.saturating_add(width);
+        let dimmed = self.dimmed;
+        if let Some(line) = self.view_port.take_visible_lines().get_mut(row).filter(|line| line.dirty()) {
+            match dimmed {
+                true => { line.write_dimmed(x, y, window, ellipsis, frame) }
+                false => { line.write(x, y, window, &ps, ellipsis, frame) }
+            }
+        }
+        Ok(true)
+    }
+
+    /***
+    Draw content smaller than the pane where it's been aligned to. Any change can move every
+    line, so the whole pane is redrawn.
+     */
+    fn write_aligned(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        let changed = self.view_port.take_visible_lines().iter().any(|line| line.dirty());
+        if self.scroll_dirty || changed {
+            self.scroll_dirty = false;
+            let (x, y, width, height) = (self.x, self.content_y(), self.width(), self.height());
+            let ps = self.view_port.style();
+            let ellipsis = self.view_port.ellipsis();
+            let placements = self.view_port.placements();
+
+            let mut rows = vec![None; height as usize];
+            for (line, (row, column)) in self.view_port.take_visible_lines().iter_mut().zip(placements) {
+                line.make_clean();
+                if let Some(slot) = rows.get_mut(row as usize) {
+                    *slot = Some((line.clone(), column));
+                }
+            }
+            for (n, row) in rows.into_iter().enumerate() {
+                let (mut line, column) = row.unwrap_or((GlyphString::new(), 0));
+                let mut margin = GlyphString::new();
+                let (row_y, line_x) = (y + n as u16, x + column);
+                let window = self.panned..self.panned.saturating_add(width - column);
+                match (self.dimmed, column > 0) {
+                    (true, true) => { margin.write_dimmed(x, row_y, 0..column, false, frame) }
+                    (false, true) => { margin.write(x, row_y, 0..column, &ps, false, frame) }
+                    (_, false) => {}
+                }
+                if self.dimmed {
+                    line.write_dimmed(line_x, row_y, window, ellipsis, frame);
+                } else {
+                    line.write(line_x, row_y, window, &ps, ellipsis, frame);
+                }
+            }
+        }
+
+        if self.title_row_dirty {
+            self.write_title_row(frame)?;
+        }
+        Ok(())
+    }
+
+    /***
+    Draw the pane as it was `scrolled_back` lines ago, with a badge in the corner saying so. New
+    output redraws it, since it moves every line the pane is showing.
+     */
+    fn write_scrolled_back(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        let changed = self.view_port.take_visible_lines().iter().any(|line| line.dirty());
+        if self.scroll_dirty || changed {
+            self.scroll_dirty = false;
+            let (x, y, width, height) = (self.x, self.content_y(), self.width(), self.height());
+            let ps = self.view_port.style();
+            let ellipsis = self.view_port.ellipsis();
+            let window = self.panned..self.panned.saturating_add(width);
+
+            let history = self.view_port.history_len();
+            let mut lines = self.view_port.history(history.saturating_sub(self.scrolled_back), self.scrolled_back.min(height as usize));
+            lines.extend(self.view_port.take_visible_lines().iter_mut().map(|line| {
+                line.make_clean();
+                line.clone()
+            }));
+            lines.resize(height as usize, GlyphString::new());
+            for (n, line) in lines.iter_mut().enumerate() {
+                if self.dimmed {
+                    line.write_dimmed(x, y + n as u16, window.clone(), ellipsis, frame);
+                } else {
+                    line.write(x, y + n as u16, window.clone(), &ps, ellipsis, frame);
+                }
+            }
+
+            let plural = if self.scrolled_back == 1 { "" } else { "s" };
+            let badge = format!("[{} line{} back]", self.scrolled_back, plural).chars().take(width as usize).collect::<String>();
+            let badge_x = x + width - badge.chars().count() as u16;
+            write!(frame, "{}\x1b[0m\x1b[7m{}\x1b[0m", Goto(badge_x, y), badge)?;
+        }
+
+        if self.title_row_dirty {
+            self.write_title_row(frame)?;
         }
+        Ok(())
+    }
+
+    /***
+    Show older output: go `lines` further back through scrollback, or towards the live output if
+    negative. Back at the bottom, the pane follows its task's output again.
+     */
+    pub fn scroll_back(&mut self, lines: isize) {
+        self.follow_scrolled_lines();
+        let limit = self.view_port.history_len() as isize;
+        let back = (self.scrolled_back as isize).saturating_add(lines).clamp(0, limit) as usize;
+        if back != self.scrolled_back {
+            info!("{}: Scrolled back {} lines", self.id, back);
+            self.scrolled_back = back;
+            self.invalidate();
+        }
+    }
+
+    /***
+    How many lines back through scrollback the pane is showing. 0 when it follows its task.
+     */
+    pub fn scrolled_back(&mut self) -> usize {
+        self.follow_scrolled_lines();
+        self.scrolled_back
+    }
+
+    /***
+    Pan `columns` further right across lines too wide for the pane, or back left if negative.
+    Stops once the widest line on screen ends at the pane's right edge.
+     */
+    pub fn pan(&mut self, columns: isize) {
+        let widest = self.view_port.take_visible_lines().iter().map(|line| line.len()).max().unwrap_or(0);
+        let limit = widest.saturating_sub(self.width() as usize) as isize;
+        let panned = (self.panned as isize).saturating_add(columns).clamp(0, limit) as u16;
+        if panned != self.panned {
+            info!("{}: Panned {} columns right", self.id, panned);
+            self.panned = panned;
+            self.invalidate();
+        }
+    }
+
+    /***
+    Keep a scrolled back pane on the lines it was showing as new output pushes them further up
+     */
+    fn follow_scrolled_lines(&mut self) {
+        self.view_port.take_visible_lines(); // lets any lines due to scroll off go
+        let scrolled_off = self.view_port.scrolled_off();
+        let added = scrolled_off.saturating_sub(self.scroll_mark) as usize;
+        self.scroll_mark = scrolled_off;
+        if self.scrolled_back > 0 && added > 0 {
+            self.scrolled_back = (self.scrolled_back + added).min(self.view_port.history_len());
+            self.scroll_dirty = true;
+        }
+    }
+
+    fn write_title_row(&mut self, frame: &mut Vec<u8>) -> anyhow::Result<()> {
+        self.title_row_dirty = false;
+        let label = match &self.title_row {
+            None => { return Ok(()) }
+            Some(label) => { label }
+        };
 
+        let width = self.width() as usize;
+        let status = self.status.chars().take(width).collect::<String>();
+        let room = width - status.chars().count();
+        let text = match &self.title {
+            None => { format!(" {}", label) }
+            Some(title) => { format!(" {} - {}", label, title) }
+        };
+        let text = text.chars().take(room).collect::<String>();
+        let padding = room - text.chars().count();
+
+        write!(frame, "{}\x1b[0m\x1b[7m{}{:padding$}{}\x1b[0m", Goto(self.x, self.y), text, "", status, padding = padding)?;
         Ok(())
     }
 
     pub fn take_cursor(&self, target: &mut dyn Write) -> anyhow::Result<()> {
         // put cursor where it belongs (Note that screen coordinates are 1-based instead of zero based.
         let row = self.view_port.cursor().row();
-        let col = self.view_port.cursor().col();
+        let mut col = self.view_port.cursor().col();
+        if self.has_predictions() {
+            // Past what's been typed but not echoed yet
+            col = (col + self.predicted_columns() as i32).min(self.width() as i32);
+        }
 
-        let global_y = row + self.y as i32 - 1;
+        let global_y = row + self.content_y() as i32 - 1;
         let global_x = col + self.x as i32 - 1;
 
         info!("{}: Putting cursor at {}x{}y (global: {},{})", self.id, col, row, global_x, global_y);
@@ -425,31 +1042,30 @@ impl Pane {
         Ok(())
     }
 
-    fn delete_text(&mut self, vt100_code: &str) -> anyhow::Result<()> {
-        let last_char = vt100_code.chars().last().unwrap();
-
-        let deletion_type = match last_char {
+    fn delete_text(&mut self, code: &EscSeq) -> anyhow::Result<()> {
+        let deletion_type = match code.final_byte {
             'L' => DeletionType::ClearLineToCursor,
             'K' => {
-                match Pane::deletion_type(vt100_code) {
-                    None => DeletionType::ClearLineAfterCursor,
-                    Some(1) => DeletionType::ClearLineToCursor,
-                    Some(2) => DeletionType::ClearLine,
-                    _ => DeletionType::Unknown(vt100_code.to_string())
+                match code.param(0, 0) {
+                    0 => DeletionType::ClearLineAfterCursor,
+                    1 => DeletionType::ClearLineToCursor,
+                    2 => DeletionType::ClearLine,
+                    _ => DeletionType::Unknown(code.to_string())
                 }},
             'J' => {
-                match Pane::deletion_type(vt100_code) {
-                    None => DeletionType::ClearScreenAfterCursor,
-                    Some(1) => DeletionType::ClearScreenToCursor,
-                    Some(2) => DeletionType::ClearScreen,
-                    _ => DeletionType::Unknown(vt100_code.to_string())
+                match code.param(0, 0) {
+                    0 => DeletionType::ClearScreenAfterCursor,
+                    1 => DeletionType::ClearScreenToCursor,
+                    2 => DeletionType::ClearScreen,
+                    _ => DeletionType::Unknown(code.to_string())
                 }
             }
             _ => {
                 /* Should be a 'k' string */
-                match &vt100_code[0..2] {
-                    "\x1Bk" => DeletionType::ClearLineAfterCursor,
-                    _ => DeletionType::Unknown(vt100_code.to_string())
+                if code.as_str().starts_with("\x1Bk") {
+                    DeletionType::ClearLineAfterCursor
+                } else {
+                    DeletionType::Unknown(code.to_string())
                 }
             }
         };
@@ -459,68 +1075,89 @@ impl Pane {
         Ok(())
     }
 
-    fn move_cursor(&mut self, vt100_code: &str) -> anyhow::Result<()> {
-        let last_char = vt100_code.chars().last().unwrap();
-        match last_char {
+    fn move_cursor(&mut self, code: &EscSeq) -> anyhow::Result<()> {
+        match code.final_byte {
             'H' | 'f' => {
-                let captures = HOME_REGEX.captures(vt100_code).unwrap();
-
-                let row = match captures.get(1) {
-                    None => { 1 }
-                    Some(m) => { m.as_str().to_owned().parse::<ScreenCoord>().unwrap_or(1) }
-                };
-                let col = match captures.get(2) {
-                    None => { 1 }
-                    Some(m) => { m.as_str().to_owned().parse::<ScreenCoord>().unwrap_or(1) }
-                };
+                let row = code.param(0, 1) as ScreenCoord;
+                let col = code.param(1, 1) as ScreenCoord;
 
                 // Subtract one to move into zero-based indices
                 self.view_port.cursor_goto(row, col);
             }
 
-            'A' => {
-                let up = Pane::cursor_move_amount(vt100_code)?;
-                self.view_port.cursor_up(up)
-            }
-            'B' => {
-                let down = Pane::cursor_move_amount(vt100_code)?;
-                self.view_port.cursor_down(down)
-            }
-            'C' => {
-                let right = Pane::cursor_move_amount(vt100_code)?;
-                self.view_port.cursor_right(right)
-            }
-            'D' => {
-                let left = Pane::cursor_move_amount(vt100_code)?;
-                self.view_port.cursor_left(left)
-            }
+            'A' => { self.view_port.cursor_up(code.param(0, 1)) }
+            'B' => { self.view_port.cursor_down(code.param(0, 1)) }
+            'C' => { self.view_port.cursor_right(code.param(0, 1)) }
+            'D' => { self.view_port.cursor_left(code.param(0, 1)) }
             /*****
             TODO: Save/Restore cursor states
              */
             // ^[s/^[u => save/restore cursor position
             // ^7/^8 => save/restore cursor pos + print state
-            _ => {} // No movement to do!
+            _ => { debug!("{}: Ignoring cursor movement {:?}", self.id, code.as_str()) } // No movement to do!
         }
 
         Ok(())
     }
 
-    fn cursor_move_amount(vt100_code: &str) -> anyhow::Result<u16> {
-        let captures = CUR_MOVE_REGEX.captures(vt100_code).unwrap();
-        let out = match captures.get(1) {
-            None => { 1 }
-            Some(m) => { m.as_str().to_owned().parse::<u16>().unwrap_or(1) }
-        };
+    /***
+    Mark every line as changed, so the next write redraws the whole pane
+     */
+    pub fn invalidate(&mut self) {
+        // Whatever's behind it (e.g. a cleared screen) can't wait for its next frame
+        self.drawn_at = None;
+        self.title_row_dirty = true;
+        self.scroll_dirty = true;
+        self.view_port.take_visible_lines().iter_mut().for_each(|line| line.make_dirty());
+    }
 
-        Ok(out)
+    /***
+    The text currently visible in this pane, one line per row, without any styling
+     */
+    pub fn snapshot(&mut self) -> String {
+        if let Err(e) = self.flush_tail() {
+            info!("{}: Could not parse held output: {}", self.id, e);
+        }
+        self.view_port.take_visible_lines().iter().
+            map(|l| l.plaintext()).
+            collect::<Vec<String>>().join("\n")
     }
 
-    fn deletion_type(vt100_code: &str) -> Option<u16> {
-        let captures = CUR_MOVE_REGEX.captures(vt100_code).unwrap();
-        match captures.get(1) {
-            None => { None }
-            Some(m) => { if m.as_str().is_empty() { None } else { Some(m.as_str().to_owned().parse::<u16>().unwrap()) } }
+    /***
+    The visible cells of this pane, styles and all, and where its cursor is
+     */
+    pub fn screen(&mut self) -> Screen {
+        if let Err(e) = self.flush_tail() {
+            info!("{}: Could not parse held output: {}", self.id, e);
         }
+        let (width, height) = (self.view_port.width(), self.view_port.height());
+        let cursor = self.view_port.cursor();
+        let cursor = (cursor.x().min(width.saturating_sub(1)), cursor.y().min(height.saturating_sub(1)));
+        let blank = Cell { c: ' ', style: PrintStyle::default() };
+        let panned = self.panned as usize;
+        let mut rows = self.view_port.take_visible_lines().iter().
+            take(height as usize).
+            map(|line| {
+                let mut row = line.cells().skip(panned).take(width as usize).map(|(c, style)| Cell { c, style }).collect::<Vec<_>>();
+                row.resize(width as usize, blank);
+                row
+            }).
+            collect::<Vec<_>>();
+        // Lines the task hasn't reached yet are blank
+        rows.resize(height as usize, vec![blank; width as usize]);
+        Screen { width, height, rows, cursor }
+    }
+
+    /***
+    Everything this pane still has - its scrollback, spilled history included, then the visible
+    text - without styling
+     */
+    pub fn transcript(&mut self) -> String {
+        let screen = self.snapshot();
+        self.view_port.history(0, self.view_port.history_len()).iter().
+            map(|l| l.plaintext()).
+            chain(std::iter::once(screen)).
+            collect::<Vec<String>>().join("\n")
     }
 
     // A Handle for testing
@@ -549,6 +1186,312 @@ mod tests {
         assert_eq!("a line of text\n\n\n\n\n\n\n\n\n", pane.plaintext());
     }
 
+    #[test]
+    fn it_keeps_scrollback_in_its_transcript() {
+        let mut pane = Pane::new("p1", 1, 1, 2, 10);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane.push("one\r\ntwo\r\nthree").unwrap();
+        assert_eq!(pane.transcript().lines().map(str::trim_end).collect::<Vec<_>>(), vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn it_answers_status_and_cursor_queries() {
+        let mut pane = Pane::new("p1", 1, 1, 5, 10);
+        pane.push("\x1b[5n").unwrap();
+        pane.push("ab\r\nc\x1b[6n").unwrap();
+        assert_eq!(pane.take_replies(), b"\x1b[0n\x1b[2;2R".to_vec());
+        assert!(pane.take_replies().is_empty());
+
+        pane.push("\x1b[c\x1b[>0c").unwrap();
+        assert_eq!(pane.take_replies(), b"\x1b[?62;22c\x1b[>1;10;0c".to_vec());
+    }
+
+    #[test]
+    fn it_wraps_or_truncates_long_lines() {
+        let mut pane = Pane::new("p1", 1, 1, 3, 5);
+        pane.push("abcdefgh\r\nxy").unwrap();
+        assert_eq!(pane.screen().text(), "abcde\nxy\n");
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("abcd…"));
+
+        let mut pane = Pane::new("p1", 1, 1, 3, 5);
+        pane.set_overflow(Overflow::Wrap);
+        pane.push("abcdefgh\r\nxy").unwrap();
+        assert_eq!(pane.screen().text(), "abcde\nfgh\nxy");
+    }
+
+    #[test]
+    fn it_pans_across_lines_wider_than_it() {
+        let mut pane = Pane::new("p1", 1, 1, 2, 5);
+        pane.push("abcdefghij\r\nxyz").unwrap();
+        pane.pan(3);
+        assert_eq!(pane.screen().text(), "defgh\n");
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("…efg…"));
+
+        // No further than the end of the widest line
+        pane.pan(100);
+        assert_eq!(pane.screen().text(), "fghij\n");
+        pane.pan(-100);
+        assert_eq!(pane.screen().text(), "abcde\nxyz");
+    }
+
+    #[test]
+    fn it_stays_put_while_scrolled_back() {
+        let mut pane = Pane::new("p1", 1, 1, 2, 20);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane.push("one\r\ntwo\r\nthree\r\nfour").unwrap();
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+
+        pane.scroll_back(1);
+        frame.clear();
+        pane.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("two") && drawn.contains("three") && !drawn.contains("four"));
+        assert!(drawn.contains("[1 line back]"));
+
+        // New output pushes the lines being shown further back, rather than dragging the view along
+        pane.push("\r\nfive").unwrap();
+        frame.clear();
+        pane.write(&mut frame).unwrap();
+        assert_eq!(pane.scrolled_back, 2);
+        assert!(String::from_utf8_lossy(&frame).contains("[2 lines back]"));
+
+        pane.scroll_back(-10);
+        frame.clear();
+        pane.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("four") && drawn.contains("five") && !drawn.contains("back]"));
+        pane.scroll_back(100);
+        assert_eq!(pane.scrolled_back, 3);
+    }
+
+    thread_local! {
+        static FAKE_NOW: std::cell::Cell<Instant> = std::cell::Cell::new(Instant::now());
+    }
+
+    // A clock for this test's thread that only moves when `advance_clock` says so
+    fn fake_clock() -> Instant {
+        FAKE_NOW.with(|now| now.get())
+    }
+
+    fn advance_clock(by: Duration) {
+        FAKE_NOW.with(|now| now.set(now.get() + by));
+    }
+
+    #[test]
+    fn it_holds_back_frames_past_its_frame_rate() {
+        let mut pane = Pane::new("p1", 1, 1, 2, 20);
+        pane.clock = fake_clock;
+        pane.set_max_fps(20.0);
+        let mut frame = Vec::new();
+        pane.push("one").unwrap();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("one"));
+        assert_eq!(pane.held_back_for(), None);
+
+        // Too soon: the change waits for the pane's next frame
+        frame.clear();
+        pane.push("\r\ntwo").unwrap();
+        pane.write(&mut frame).unwrap();
+        assert!(frame.is_empty());
+        assert!(pane.held_back_for().is_some());
+
+        advance_clock(Duration::from_millis(20));
+        assert_eq!(pane.held_back_for(), Some(Duration::from_millis(30)));
+        advance_clock(Duration::from_millis(30));
+        assert_eq!(pane.held_back_for(), Some(Duration::ZERO));
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("two"));
+        assert_eq!(pane.held_back_for(), None);
+
+        // A frame with nothing to draw doesn't hold back the next change
+        advance_clock(Duration::from_millis(50));
+        frame.clear();
+        pane.write(&mut frame).unwrap();
+        assert!(frame.is_empty());
+        pane.push("\r\nthree").unwrap();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("three"));
+
+        // Anything that needs the whole pane redrawn can't wait
+        frame.clear();
+        pane.invalidate();
+        pane.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains("one"));
+    }
+
+    #[test]
+    fn it_draws_just_the_cursor_line_for_an_echo() {
+        let mut pane = Pane::new("p1", 1, 1, 3, 20);
+        pane.push("top\r\n$ ").unwrap();
+        pane.write(&mut Vec::new()).unwrap();
+
+        let mut frame = Vec::new();
+        pane.push("l").unwrap();
+        assert!(pane.write_cursor_line(&mut frame).unwrap());
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("$ l") && !drawn.contains("top"), "{:?}", drawn);
+
+        // More than the cursor's line changed, so it needs a whole frame
+        frame.clear();
+        pane.push("s\r\nfoo").unwrap();
+        assert!(!pane.write_cursor_line(&mut frame).unwrap());
+        assert!(frame.is_empty());
+    }
+
+    #[test]
+    fn it_scrolls_back_into_spilled_history() {
+        let path = std::env::temp_dir().join(format!("decker-pane-spill-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut pane = Pane::new("p1", 1, 1, 2, 20);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane.set_scrollback_limit(10);
+        pane.spill_scrollback(&path, 1024 * 1024).unwrap();
+        let output = (0..400).map(|n| format!("line {}", n)).collect::<Vec<_>>().join("\r\n");
+        pane.push(output).unwrap();
+
+        pane.scroll_back(1000);
+        assert_eq!(pane.scrolled_back, 398);
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("line 0") && drawn.contains("line 1") && !drawn.contains("line 2"));
+
+        // Transcripts and exports have all of it too
+        assert!(pane.transcript().starts_with("line 0\nline 1\n"));
+        assert!(pane.export_html().contains("line 0</span>\n"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn it_answers_dcs_queries_and_hides_other_payloads() {
+        let mut pane = Pane::new("p1", 1, 1, 5, 10);
+        pane.push("a\x1bP+q544e\x1b\\b\x1bPq#0;2;0;0;0~~\x1b\\c").unwrap();
+        assert_eq!("abc", pane.plaintext().trim_end());
+        assert_eq!(pane.take_replies(), b"\x1bP0+r\x1b\\".to_vec());
+        assert_eq!(pane.take_graphics(), vec!["\x1bPq#0;2;0;0;0~~\x1b\\"]);
+        assert!(pane.take_graphics().is_empty());
+    }
+
+    #[test]
+    fn it_handles_the_remaining_c0_controls() {
+        let mut pane = Pane::new("p1", 1, 1, 5, 10);
+        pane.push("\x1b)0a\x0eq\x0fq\x00\x05\x07\x0bb\x0cc").unwrap();
+        assert_eq!(pane.plaintext().lines().map(str::trim_end).collect::<Vec<_>>(), vec!["a─q", "b", "c"]);
+        assert!(pane.take_bell());
+        assert!(!pane.take_bell());
+        assert!(pane.take_replies().is_empty());
+    }
+
+    #[test]
+    fn it_draws_a_title_row_the_child_cannot_clear() {
+        let mut pane = Pane::new("p1", 1, 1, 3, 14);
+        pane.set_title_row("weather");
+        pane.set_status("!");
+        pane.push("\x1b]2;rain\x07sunny\x1b[2J").unwrap();
+        assert_eq!(pane.title.as_deref(), Some("rain"));
+        assert_eq!(pane.height(), 2);
+
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        let frame = String::from_utf8(frame).unwrap();
+        assert!(frame.contains("\x1b[7m weather - ra!\x1b[0m"), "{:?}", frame);
+
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        assert!(!String::from_utf8(frame).unwrap().contains("weather"));
+    }
+
+    #[test]
+    fn it_tracks_the_keypad_mode() {
+        let mut pane = Pane::new("p1", 1, 1, 5, 10);
+        pane.push("\x1b=1").unwrap();
+        assert!(pane.keypad_app());
+        pane.push("\x1b>").unwrap();
+        assert!(!pane.keypad_app());
+        assert_eq!("1", pane.plaintext().trim_end());
+    }
+
+    #[test]
+    fn it_aligns_content_smaller_than_it() {
+        let mut pane = Pane::new("clock", 1, 1, 3, 10);
+        pane.set_align(Align::Center, VAlign::Middle);
+        pane.push("12:00\n").unwrap();
+
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        let frame = String::from_utf8(frame).unwrap();
+        assert!(frame.contains("\x1b[2;1H  \x1b[2;3H12:00   "), "{:?}", frame);
+        assert!(frame.contains("\x1b[1;1H          "));
+    }
+
+    #[test]
+    fn it_passes_on_clipboard_writes_only_when_allowed() {
+        let mut pane = Pane::new("p1", 1, 1, 3, 20);
+        pane.push("\x1b]52;c;aGk=\x07").unwrap();
+        assert_eq!(pane.take_copied(), None);
+
+        pane.set_copy_limit(Some(4));
+        pane.push("\x1b]52;c;aGk=\x1b\\").unwrap();
+        assert_eq!(pane.take_copied().as_deref(), Some("\x1b]52;c;aGk=\x07"));
+        pane.push("\x1b]52;c;aGVsbG8=\x07").unwrap();
+        assert_eq!(pane.take_copied(), None);
+    }
+
+    #[test]
+    fn it_survives_hostile_csi_sequences() {
+        let mut pane = Pane::new("p1", 1, 1, 5, 10);
+        for seq in ["\x1b[65535C", "\x1b[99999D", "\x1b[65535A", "\x1b[65535B", "\x1b[;;;H", "\x1b[0;0f",
+                    "\x1bOH", "\x1b[0K", "\x1b[9K", "\x1b[7J", "\x1b[?Z", "\x1bé", "\x1b[65535;65535H"] {
+            pane.push(seq).unwrap();
+            pane.push("x").unwrap();
+        }
+        pane.write(&mut Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn it_degrades_binary_output_gracefully() {
+        let mut pane = Pane::new("p1", 1, 1, 2, 20);
+        pane.push(b"ok\x00\x0e\xff\xc2\x85\x1b\x01done").unwrap();
+        assert_eq!(pane.snapshot(), "ok\u{FFFD}done");
+    }
+
+    #[test]
+    fn it_drops_the_middle_of_oversized_output() {
+        let mut pane = Pane::new("p1", 1, 1, 4, 30);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        pane.set_frame_cap(40);
+
+        let output = (0..100).map(|n| format!("line {}\r\n", n)).collect::<String>();
+        pane.push(&output).unwrap();
+        pane.write(&mut Vec::new()).unwrap();
+
+        let screen = pane.snapshot();
+        assert!(screen.contains("… output truncated …"), "{}", screen);
+        assert!(screen.contains("line 99"), "{}", screen);
+
+        // The next frame starts with a fresh allowance
+        pane.push("ok").unwrap();
+        assert!(pane.snapshot().ends_with("ok"));
+    }
+
+    #[test]
+    fn it_draws_dimmed_panes_without_their_colors() {
+        let mut pane = Pane::new("p1", 1, 1, 1, 10);
+        pane.push("\x1b[31mred").unwrap();
+        pane.write(&mut Vec::new()).unwrap();
+
+        pane.set_dimmed(true);
+        let mut frame = Vec::new();
+        pane.write(&mut frame).unwrap();
+        let frame = String::from_utf8(frame).unwrap();
+        assert_eq!(frame, "\x1b[1;1H\x1b[0;2mred       \x1b[0m");
+    }
+
     #[test]
     fn it_displays_line_at_bottom_of_screen() {
         let mut pane = Pane::new("p1", 1, 1, 5, 10);
@@ -561,11 +1504,18 @@ mod tests {
     /***
     PrintStyle Tests
      */
+    // The codes that turn `from` into `to`
+    fn diff(from: &PrintStyle, to: &PrintStyle) -> String {
+        let mut out = Vec::new();
+        from.write_diff(to, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
     #[test]
     fn it_converts_simple_vt100_sgr_to_print_state() {
         let code = "\x1b[33m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
         assert_eq!(ps.foreground, Color::Yellow);
     }
 
@@ -573,7 +1523,7 @@ mod tests {
     fn it_converts_bold_vt100_sgr_to_print_state() {
         let code = "\x1b[93m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
         assert_eq!(ps.foreground, Color::Yellow);
         assert_eq!(ps.bold, true);
     }
@@ -582,7 +1532,7 @@ mod tests {
     fn it_converts_background_vt100_sgr_to_print_state() {
         let code = "\x1b[43m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
         assert_eq!(ps.background, Color::Yellow);
     }
 
@@ -590,7 +1540,7 @@ mod tests {
     fn it_converts_256_color_vt100_sgr_to_print_state() {
         let code = "\x1b[38;5;128m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
         assert_eq!(ps.foreground, Color::TWOFIFTYSIX(128));
     }
 
@@ -598,7 +1548,7 @@ mod tests {
     fn it_converts_rgb_color_vt100_sgr_to_print_state() {
         let code = "\x1b[38;2;128;42;255m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
         assert_eq!(ps.foreground, Color::RGB(128, 42, 255));
     }
 
@@ -607,8 +1557,8 @@ mod tests {
         let fg_code = "\x1b[38;2;128;42;255m";
         let bg_code = "\x1b[47m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(fg_code).unwrap();
-        ps.apply_vt100(bg_code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(fg_code)).unwrap();
+        ps.apply_sgr(&EscSeq::parse(bg_code)).unwrap();
 
         assert_eq!(ps.to_str(), fg_code.to_owned() + bg_code);
     }
@@ -617,7 +1567,7 @@ mod tests {
     fn it_applies_multiple_codes_at_once() {
         let code = "\x1b[;1;33;42m";
         let mut ps = PrintStyle::default();
-        ps.apply_vt100(code).unwrap();
+        ps.apply_sgr(&EscSeq::parse(code)).unwrap();
 
         assert_eq!(ps.foreground, Color::Yellow);
         assert_eq!(ps.background, Color::Green);
@@ -626,40 +1576,84 @@ mod tests {
     #[test]
     fn it_finds_diff_between_states() {
         let mut red_on_black = PrintStyle::default();
-        red_on_black.apply_vt100("\x1b[33m").unwrap();
+        red_on_black.apply_sgr(&EscSeq::parse("\x1b[33m")).unwrap();
 
         let mut red_on_cyan = PrintStyle::default();
-        red_on_cyan.apply_vt100("\x1b[33m").unwrap();
-        red_on_cyan.apply_vt100("\x1b[46m").unwrap();
+        red_on_cyan.apply_sgr(&EscSeq::parse("\x1b[33m")).unwrap();
+        red_on_cyan.apply_sgr(&EscSeq::parse("\x1b[46m")).unwrap();
 
-        assert_eq!(red_on_black.diff_str(&red_on_cyan), "\x1b[46m");
+        assert_eq!(diff(&red_on_black, &red_on_cyan), "\x1b[46m");
     }
 
     #[test]
     fn it_turns_off_underline() {
         let default = PrintStyle::default();
         let mut underlined = PrintStyle::default();
-        underlined.apply_vt100("\x1b[4m").unwrap();
+        underlined.apply_sgr(&EscSeq::parse("\x1b[4m")).unwrap();
 
-        assert_eq!(underlined.diff_str(&default), "\x1b[24m".to_owned());
+        assert_eq!(diff(&underlined, &default), "\x1b[24m".to_owned());
     }
 
     #[test]
     fn it_turns_off_blink() {
         let default = PrintStyle::default();
         let mut blinking = PrintStyle::default();
-        blinking.apply_vt100("\x1b[5m").unwrap();
+        blinking.apply_sgr(&EscSeq::parse("\x1b[5m")).unwrap();
 
-        assert_eq!(blinking.diff_str(&default), "\x1b[25m".to_owned());
+        assert_eq!(diff(&blinking, &default), "\x1b[25m".to_owned());
     }
 
     #[test]
     fn it_turns_off_italics() {
         let default = PrintStyle::default();
         let mut blinking = PrintStyle::default();
-        blinking.apply_vt100("\x1b[3m").unwrap();
+        blinking.apply_sgr(&EscSeq::parse("\x1b[3m")).unwrap();
+
+        assert_eq!(diff(&blinking, &default), "\x1b[23m".to_owned());
+    }
+
+    #[test]
+    fn it_ignores_unknown_sgr_codes() {
+        let mut ps = PrintStyle::default();
+        ps.apply_sgr(&EscSeq::parse("\x1b[31;73;1000;4m")).unwrap();
+
+        assert_eq!(ps.foreground, Color::Red);
+        assert!(ps.underline);
+    }
+
+    #[test]
+    fn it_survives_malformed_extended_colors() {
+        let mut ps = PrintStyle::default();
+        ps.apply_sgr(&EscSeq::parse("\x1b[38;5m")).unwrap();
+        ps.apply_sgr(&EscSeq::parse("\x1b[48;2;1m")).unwrap();
+        ps.apply_sgr(&EscSeq::parse("\x1b[38;7;1m")).unwrap();
+        assert_eq!(ps, PrintStyle::default());
+
+        ps.apply_sgr(&EscSeq::parse("\x1b[38;2;999;42;65535m")).unwrap();
+        assert_eq!(ps.foreground, Color::RGB(255, 42, 255));
+    }
 
-        assert_eq!(blinking.diff_str(&default), "\x1b[23m".to_owned());
+    #[test]
+    fn it_applies_the_less_common_attributes() {
+        let mut ps = PrintStyle::default();
+        ps.apply_sgr(&EscSeq::parse("\x1b[8;9;53;21m")).unwrap();
+        assert!(ps.hidden && ps.strikethrough && ps.overline && ps.underline);
+        assert_eq!(diff(&ps, &PrintStyle::default()), "\x1b[24m\x1b[28m\x1b[29m\x1b[55m");
+
+        // Underline colors are skipped along with their arguments
+        ps.apply_sgr(&EscSeq::parse("\x1b[0;58;2;1;2;3;32m")).unwrap();
+        assert_eq!(ps.foreground, Color::Green);
+        assert!(!ps.hidden);
+    }
+
+    #[test]
+    fn default_background_resets_the_background() {
+        let mut ps = PrintStyle::default();
+        ps.apply_sgr(&EscSeq::parse("\x1b[33;44m")).unwrap();
+        ps.apply_sgr(&EscSeq::parse("\x1b[49m")).unwrap();
+
+        assert_eq!(ps.foreground, Color::Yellow);
+        assert_eq!(ps.background, Color::Black);
     }
 }
 
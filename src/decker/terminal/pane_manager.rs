@@ -1,44 +1,772 @@
-use crate::decker::TaskId;
-use crate::decker::terminal::{PaneManager, Pane};
+use crate::decker::{DisabledSet, HealthMap, MasterControl, ProcInput, TaskHealth, TaskId};
+use crate::decker::keys::KeypadModes;
+use crate::decker::clipboard::OSC52_QUERY;
+use crate::decker::backend::{terminal_size, Goto};
+use crate::decker::terminal::{alert_sgr, Focus, Layout, PaneManager, Pane, PanePipes, PaneRequest};
+use crate::decker::terminal::Screen;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use log::{info, error};
+use crossbeam_channel::{unbounded, Sender};
 
 impl PaneManager {
     pub fn new() -> PaneManager {
+        let (pane_requests_tx, pane_requests) = unbounded();
         PaneManager {
-            panes: Default::default()
+            panes: Default::default(),
+            pipes: Arc::new(Mutex::new(PanePipes::new())),
+            frame: Vec::with_capacity(16 * 1024),
+            status_line: None,
+            health: None,
+            drawn_health: Default::default(),
+            disabled: None,
+            focus: Arc::new(Mutex::new(Focus::new())),
+            drawn_focus: None,
+            unread: Default::default(),
+            main_input: None,
+            keypad: Arc::new(KeypadModes::new()),
+            sixel: false,
+            inline_images: false,
+            layout: Arc::new(Mutex::new(Layout::Configured)),
+            drawn_layout: Layout::Configured,
+            mcp: None,
+            drawn_unresponsive: false,
+            drawn_cramped: None,
+            pane_requests_tx,
+            pane_requests,
+            overlays: Default::default(),
+            linear: false,
+            spoken: Default::default(),
+            prompt: None,
+            theme: None,
         }
     }
 
+    /***
+    Where to ask for panes to be opened or closed. They are, on the next write.
+     */
+    pub fn pane_requests(&self) -> Sender<PaneRequest> {
+        self.pane_requests_tx.clone()
+    }
+
+    /***
+    A handle to the pipes that copy panes' raw output elsewhere
+     */
+    pub fn pipes(&self) -> Arc<Mutex<PanePipes>> {
+        self.pipes.clone()
+    }
+
+    /***
+    A handle to the focus, for moving it between panes
+     */
+    pub fn focus(&self) -> Arc<Mutex<Focus>> {
+        self.focus.clone()
+    }
+
+    /***
+    A handle to the keypad modes, for translating keypad input to the main pane
+     */
+    pub fn keypad(&self) -> Arc<KeypadModes> {
+        self.keypad.clone()
+    }
+
+    /***
+    A handle to the current layout, for cycling through them
+     */
+    pub fn layout(&self) -> Arc<Mutex<Layout>> {
+        self.layout.clone()
+    }
+
+    /***
+    Tell tasks about their new sizes through this when the panes are re-tiled, and warn when
+    it says the orchestrator has stopped answering
+     */
+    pub fn set_master_control(&mut self, mcp: MasterControl) {
+        self.mcp = Some(mcp);
+    }
+
+    /***
+    Whether focus has moved, or a task been enabled or disabled, since the last write. Panes
+    echoing typing need drawing until the task echoes it too, or the echo times out, and panes
+    whose frame rate held changes back need drawing once they're due.
+     */
+    pub fn needs_redraw(&self) -> bool {
+        let disabled = match &self.disabled {
+            None => { Default::default() }
+            Some(disabled) => { disabled.read().map(|d| d.clone()).unwrap_or_default() }
+        };
+        !self.pane_requests.is_empty() ||
+            self.focused() != self.drawn_focus || self.current_layout() != self.drawn_layout ||
+            self.cramped() != self.drawn_cramped || self.unresponsive() != self.drawn_unresponsive ||
+            self.panes.iter().any(|(task_id, panes)| panes.iter().any(|p| p.dimmed() != disabled.contains(task_id))) ||
+            self.panes.values().flatten().any(|p| p.has_predictions()) ||
+            self.redraw_due_in() == Some(Duration::ZERO)
+    }
+
+    /***
+    How long until the soonest pane held back by its frame rate is due drawing, if any are
+     */
+    pub fn redraw_due_in(&self) -> Option<Duration> {
+        self.panes.values().flatten().filter_map(|p| p.held_back_for()).min()
+    }
+
+    fn focused(&self) -> Option<TaskId> {
+        self.focus.lock().ok().and_then(|f| f.focused().cloned())
+    }
+
+    /***
+    The screen size the panes need and the size the terminal actually is, if it's too small
+     */
+    fn cramped(&self) -> Option<((u16, u16), (u16, u16))> {
+        let have = terminal_size().ok()?;
+        let status_row = self.status_line.is_some() as u16;
+        let need = self.panes.values().flatten().fold((0, 0), |(width, height), pane| {
+            let bottom = pane.content_y() + pane.height() - 1 + status_row;
+            (width.max(pane.x + pane.width() - 1), height.max(bottom))
+        });
+        if need.0 > have.0 || need.1 > have.1 { Some((need, have)) } else { None }
+    }
+
+    /***
+    Explain, in the middle of an otherwise blank screen, that the panes don't fit
+     */
+    fn write_cramped(frame: &mut Vec<u8>, need: (u16, u16), have: (u16, u16)) -> anyhow::Result<()> {
+        let lines = [format!("decker needs {}x{}, have {}x{}", need.0, need.1, have.0, have.1),
+                     String::from("Make the terminal bigger to see your panes")];
+        frame.extend_from_slice(b"\x1b[0m\x1b[2J");
+        for (n, line) in lines.iter().enumerate() {
+            let line = line.chars().take(have.0 as usize).collect::<String>();
+            let x = (have.0.saturating_sub(line.len() as u16) / 2).max(1);
+            let y = (have.1 / 2).max(1) + n as u16;
+            write!(frame, "{}{}", Goto(x, y), line)?;
+        }
+        Ok(())
+    }
+
+    /***
+    Open and close the panes asked for since the last write. Closing one uncovers whatever was
+    underneath, so everything is redrawn.
+     */
+    pub(super) fn handle_pane_requests(&mut self, frame: &mut Vec<u8>) {
+        while let Ok(request) = self.pane_requests.try_recv() {
+            match request {
+                PaneRequest::Open(task_id, pane, opened) => {
+                    info!("{}: Opening a pane", task_id);
+                    self.overlays.insert(task_id.clone());
+                    self.register(task_id, *pane);
+                    let _ = opened.send(());
+                }
+                PaneRequest::Close(task_id) => {
+                    info!("{}: Closing its pane", task_id);
+                    self.overlays.remove(&task_id);
+                    self.panes.remove(&task_id);
+                    if let Ok(mut focus) = self.focus.lock() {
+                        focus.remove(&task_id);
+                    }
+                    frame.extend_from_slice(b"\x1b[0m\x1b[2J");
+                    self.panes.values_mut().flatten().for_each(|pane| pane.invalidate());
+                }
+                PaneRequest::Snapshot(reply) => {
+                    let overlays = &self.overlays;
+                    let screens = self.panes.iter_mut().
+                        filter(|(task_id, _)| !overlays.contains(*task_id)).
+                        filter_map(|(task_id, panes)| panes.first_mut().map(|p| (task_id.clone(), (p.transcript(), p.scrolled_back())))).
+                        collect();
+                    let _ = reply.send(screens);
+                }
+                PaneRequest::ExportHtml(task_id, reply) => {
+                    let html = self.panes.get_mut(&task_id).and_then(|panes| panes.first_mut()).map(|p| p.export_html());
+                    let _ = reply.send(html);
+                }
+                PaneRequest::Scroll(task_id, half_pages) => {
+                    for pane in self.panes.get_mut(&task_id).into_iter().flatten() {
+                        let half_page = (pane.height() as isize / 2).max(1);
+                        pane.scroll_back(half_pages as isize * half_page);
+                    }
+                }
+                PaneRequest::Typed(keys, typed_at) if !self.linear => {
+                    // Only the main pane's task hears the keyboard
+                    for pane in self.panes.get_mut("main").into_iter().flatten() {
+                        pane.predict(&keys, typed_at);
+                    }
+                }
+                PaneRequest::Typed(..) => {}
+                PaneRequest::Prompt(Some(line)) => {
+                    let prompt = self.prompt.get_or_insert_with(|| {
+                        let (width, height) = terminal_size().unwrap_or((80, 24));
+                        Pane::new("prompt", 1, height, 1, width)
+                    });
+                    if let Err(e) = prompt.push(&line) {
+                        error!("main: Could not draw the prompt: {}", e);
+                    }
+                }
+                PaneRequest::Prompt(None) => {
+                    // Uncover whatever the prompt was drawn over
+                    if let Some(prompt) = self.prompt.take() {
+                        frame.extend_from_slice(b"\x1b[0m");
+                        let _ = write!(frame, "{}\x1b[2K", Goto(prompt.x, prompt.y));
+                        self.panes.values_mut().flatten().for_each(|pane| pane.invalidate());
+                    }
+                }
+                PaneRequest::ToggleEcho(task_id) => {
+                    for pane in self.panes.get_mut(&task_id).into_iter().flatten() {
+                        pane.set_local_echo(!pane.local_echo());
+                        info!("{}: Local echo {}", task_id, if pane.local_echo() { "on" } else { "off" });
+                    }
+                }
+                PaneRequest::QueryClipboard => { frame.extend_from_slice(OSC52_QUERY) }
+                PaneRequest::Repaint => {
+                    info!("main: Repainting the screen");
+                    self.forget_screen(frame);
+                }
+                PaneRequest::Theme(theme) => {
+                    info!("main: Switching to theme {:?}", theme);
+                    self.theme = Some(theme);
+                    self.forget_screen(frame);
+                }
+                PaneRequest::Pan(task_id, half_widths) => {
+                    for pane in self.panes.get_mut(&task_id).into_iter().flatten() {
+                        let half_width = (pane.width() as isize / 2).max(1);
+                        pane.pan(half_widths as isize * half_width);
+                    }
+                }
+            }
+        }
+    }
+
+    fn unresponsive(&self) -> bool {
+        self.mcp.as_ref().map(|mcp| !mcp.responsive()).unwrap_or(false)
+    }
+
+    fn current_layout(&self) -> Layout {
+        self.layout.lock().map(|l| *l).unwrap_or(self.drawn_layout)
+    }
+
+    /***
+    Move every pane to its place in the layout, main first and the rest in reading order, and
+    resize their tasks to match. Tasks see their new size from their next run.
+     */
+    fn tile(&mut self, layout: Layout) {
+        let (width, height) = terminal_size().unwrap_or((80, 24));
+        let height = height.saturating_sub(self.status_line.is_some() as u16);
+
+        // Panes opened over the rest stay where they are
+        let overlays = &self.overlays;
+        let mut order = self.panes.iter().
+            filter(|(task_id, _)| !overlays.contains(*task_id)).
+            flat_map(|(task_id, panes)| panes.iter().enumerate().map(move |(n, pane)| (task_id.clone(), n, pane.home()))).
+            collect::<Vec<_>>();
+        order.sort_by_key(|(task_id, n, (x, y, _, _))| (task_id != "main", *y, *x, task_id.clone(), *n));
+
+        let spots = layout.tile(order.len(), width, height);
+        for (i, (task_id, n, home)) in order.iter().enumerate() {
+            let (x, y, w, h) = spots.as_ref().map(|s| s[i]).unwrap_or(*home);
+            if let Some(pane) = self.panes.get_mut(task_id).and_then(|panes| panes.get_mut(*n)) {
+                pane.set_geometry(x, y, w, h);
+            }
+        }
+
+        if let Some(mcp) = &self.mcp {
+            for (task_id, panes) in self.panes.iter() {
+                let size = panes.first().map(|p| (p.width(), p.height()));
+                if let Err(e) = mcp.resize(task_id, size) {
+                    error!("{}: Could not resize for the new layout: {}", task_id, e);
+                }
+            }
+        }
+        info!("main: Tiled panes into {:?}", layout);
+    }
+
+    pub fn set_status_line(&mut self, status: Option<String>) {
+        self.status_line = status;
+    }
+
+    /***
+    Mark panes whose task's last run was unhealthy
+     */
+    pub fn set_health(&mut self, health: HealthMap) {
+        self.health = Some(health);
+    }
+
+    /***
+    Let the focused pane's sixel images through to the real terminal
+     */
+    pub fn set_sixel(&mut self, sixel: bool) {
+        self.sixel = sixel;
+    }
+
+    /***
+    Let the focused pane's iTerm2 and kitty images through to the real terminal
+     */
+    pub fn set_inline_images(&mut self, inline_images: bool) {
+        self.inline_images = inline_images;
+    }
+
+    /***
+    Send the main pane's replies to terminal queries (e.g. "where's the cursor?") here
+     */
+    pub fn set_main_input(&mut self, input: Sender<ProcInput>) {
+        self.main_input = Some(input);
+    }
+
+    /***
+    Dim the panes of disabled tasks
+     */
+    pub fn set_disabled(&mut self, disabled: DisabledSet) {
+        self.disabled = Some(disabled);
+    }
+
+    /***
+    Add a pane for a task. A task may have several panes, which all show the same output.
+     */
     pub fn register(&mut self, task_id: TaskId, pane: Pane) {
-        self.panes.insert(task_id, pane);
+        let panes = self.panes.entry(task_id.clone()).or_default();
+        if panes.is_empty() {
+            if let Ok(mut focus) = self.focus.lock() {
+                focus.add(&task_id);
+            }
+        }
+        panes.push(pane);
     }
 
-    pub fn find_by_id(&mut self, id: &str) -> Option<&Pane> {
-        match self.panes.iter().find(|(task_id, _) | **task_id == id) {
-            None => { None }
-            Some((_, pane)) => { Some(pane) }
+    /***
+    What each of a task's panes is showing, cell by cell. Empty for tasks without panes.
+     */
+    pub fn screens(&mut self, task_id: &str) -> Vec<Screen> {
+        self.panes.get_mut(task_id).map(|panes| panes.iter_mut().map(|p| p.screen()).collect()).unwrap_or_default()
+    }
+
+    /***
+    Scroll each of a task's panes `lines` back through their scrollback.
+     */
+    pub fn scroll_back(&mut self, task_id: &str, lines: usize) {
+        for pane in self.panes.get_mut(task_id).into_iter().flatten() {
+            pane.scroll_back(lines as isize);
         }
     }
 
+    /***
+    The first pane registered for a task. Its size is the one the task's terminal gets.
+     */
+    pub fn find_by_id(&mut self, id: &str) -> Option<&Pane> {
+        self.panes.get(id).and_then(|panes| panes.first())
+    }
+
     pub fn write(&mut self, target: &mut dyn Write) -> anyhow::Result<()>{
-        for (_, pane) in self.panes.iter_mut() {
-            pane.write(target).unwrap();
+        if self.linear {
+            return self.write_linear(target);
         }
-        // send the cursor to the main pane's location
-        let main_pane = self.find_by_id("main").unwrap();
-        main_pane.take_cursor(target)?;
+
+        // Every pane renders into the same buffer, which is reused frame to frame
+        let mut frame = std::mem::take(&mut self.frame);
+        frame.clear();
+
+        let health = match &self.health {
+            None => { Default::default() }
+            Some(health) => { health.read().map(|h| h.clone()).unwrap_or_default() }
+        };
+
+        let disabled = match &self.disabled {
+            None => { Default::default() }
+            Some(disabled) => { disabled.read().map(|d| d.clone()).unwrap_or_default() }
+        };
+        let focused = self.focused();
+        let mut bell = false;
+        let mut copied = None;
+
+        self.handle_pane_requests(&mut frame);
+        let layout = self.current_layout();
+        if layout != self.drawn_layout {
+            self.tile(layout);
+            self.drawn_layout = layout;
+            frame.extend_from_slice(b"\x1b[2J");
+        }
+
+        // Drawing panes that don't fit would scribble them over each other. Wait for a resize.
+        let cramped = self.cramped();
+        if cramped != self.drawn_cramped {
+            self.drawn_cramped = cramped;
+            match cramped {
+                Some((need, have)) => {
+                    info!("main: The terminal is {:?}, but the panes need {:?}", have, need);
+                    Self::write_cramped(&mut frame, need, have)?;
+                    target.write_all(&frame)?;
+                }
+                None => {
+                    frame.extend_from_slice(b"\x1b[2J");
+                    self.panes.values_mut().flatten().for_each(|pane| pane.invalidate());
+                }
+            }
+        }
+        if cramped.is_some() {
+            self.frame = frame;
+            return Ok(());
+        }
+
+        // Once the orchestrator's answering again, paint over the warning about it
+        let unresponsive = self.unresponsive();
+        let (width, height) = terminal_size().unwrap_or((80, 24));
+        if self.drawn_unresponsive && !unresponsive {
+            write!(frame, "{}\x1b[2K", Goto(1, height))?;
+            self.panes.values_mut().flatten().for_each(|pane| pane.invalidate());
+        }
+
+        // Focusing a pane catches up on what it's printed
+        if let Some(task_id) = &focused {
+            self.unread.remove(task_id);
+        }
+
+        // Panes opened over the rest go last, and are drawn in full so nothing shows through
+        let overlays = &self.overlays;
+        let mut panes = self.panes.iter_mut().flat_map(|(id, panes)| panes.iter_mut().map(move |p| (id, p))).collect::<Vec<_>>();
+        panes.sort_by_key(|(id, _)| overlays.contains(*id));
+        for (task_id, pane) in panes {
+            if overlays.contains(task_id) {
+                pane.invalidate();
+            }
+
+            // Redraw a pane when its markers change, so stale ones get painted over
+            let was_focused = self.drawn_focus.as_ref() == Some(task_id);
+            let is_focused = focused.as_ref() == Some(task_id);
+            if health.get(task_id) != self.drawn_health.get(task_id) || was_focused != is_focused {
+                pane.invalidate();
+            }
+            pane.set_dimmed(disabled.contains(task_id));
+
+            // Panes with a title row show their markers there
+            let unhealthy = health.get(task_id) == Some(&TaskHealth::Unhealthy);
+            let unread = self.unread.contains(task_id);
+            let status = [(is_focused, "*"), (unread, "+"), (disabled.contains(task_id), "-"), (unhealthy, "!")].iter().
+                filter(|(on, _)| *on).
+                map(|(_, glyph)| *glyph).
+                collect::<String>();
+            pane.set_status(&status);
+            pane.write(&mut frame).unwrap();
+
+            // Mirrors of a task all hear its bell, but it should only ring once. Likewise clipboard writes.
+            bell |= pane.take_bell();
+            copied = pane.take_copied().or(copied);
+
+            // Only the main pane's task is interactive, so only it gets to change the real terminal
+            let passthrough = pane.take_passthrough();
+            if task_id == "main" {
+                frame.extend_from_slice(&passthrough);
+            }
+
+            // Images go over the text, from the pane's top-left corner
+            for image in pane.take_graphics() {
+                let enabled = if image.starts_with("\x1bP") { self.sixel } else { self.inline_images };
+                if is_focused && enabled {
+                    write!(frame, "{}{}", Goto(pane.x, pane.content_y()), image)?;
+                } else {
+                    info!("{}: Dropping an image - images are off or the pane isn't focused", task_id);
+                }
+            }
+
+            if pane.has_title_row() {
+                continue;
+            }
+
+            // The main pane has the cursor to show it's focused. Label any other pane.
+            if is_focused && task_id != "main" {
+                let label = task_id.chars().take(pane.width() as usize).collect::<String>();
+                write!(frame, "{}\x1b[0m\x1b[7m{}\x1b[0m", Goto(pane.x, pane.y), label)?;
+            }
+
+            if unhealthy && pane.width() > 0 {
+                let corner = Goto(pane.x + pane.width() - 1, pane.y);
+                write!(frame, "{}\x1b[0m{}!\x1b[0m", corner, alert_sgr())?;
+            }
+        }
+        self.drawn_health = health;
+
+        if bell {
+            frame.push(b'\x07');
+        }
+        if let Some(copied) = copied {
+            frame.extend_from_slice(copied.as_bytes());
+        }
+
+        // Only the focused pane gets to set the real terminal's keypad mode
+        let keypad_app = |id: &str| self.panes.get(id).and_then(|p| p.first()).map(|p| p.keypad_app()).unwrap_or(false);
+        let focused_app = keypad_app(focused.as_deref().unwrap_or("main"));
+        if focused_app != self.keypad.host_app() {
+            frame.extend_from_slice(if focused_app { b"\x1b=" } else { b"\x1b>" });
+            self.keypad.set_host_app(focused_app);
+        }
+        self.keypad.set_main_app(keypad_app("main"));
+        let main_paste = self.panes.get("main").and_then(|p| p.first()).map(|p| p.bracketed_paste()).unwrap_or(false);
+        self.keypad.set_main_paste(main_paste);
+        self.drawn_focus = focused;
+
+        // Warn across the bottom while the orchestrator isn't answering
+        if unresponsive {
+            let warning = "decker's task runner has stopped responding - tasks may not be running".
+                chars().take(width as usize).collect::<String>();
+            write!(frame, "{}\x1b[2K{}{}\x1b[0m", Goto(1, height), alert_sgr(), warning)?;
+        } else if let Some(status) = &self.status_line {
+            let status = status.chars().take(width as usize).collect::<String>();
+            write!(frame, "{}\x1b[2K\x1b[7m{}\x1b[0m", Goto(1, height), status)?;
+        }
+        self.drawn_unresponsive = unresponsive;
+
+        // The prompt goes over everything, and has the cursor while it's open. Otherwise, send the
+        // cursor to the main pane's location.
+        match self.prompt.as_mut() {
+            Some(prompt) => {
+                prompt.invalidate();
+                prompt.write(&mut frame)?;
+                prompt.take_cursor(&mut frame)?;
+            }
+            None => {
+                let main_pane = self.find_by_id("main").unwrap();
+                main_pane.take_cursor(&mut frame)?;
+            }
+        }
+
+        target.write_all(&frame)?;
+        self.frame = frame;
         Ok(())
     }
 
-    pub fn push(&mut self, task_id: TaskId, data: &String) {
+    /***
+    Draw the echo of typing just pushed to the main pane straight away: only its cursor's line,
+    then the cursor. Anything more than that to draw gets a whole frame instead.
+     */
+    pub fn write_echo(&mut self, target: &mut dyn Write) -> anyhow::Result<()> {
+        let main_focused = self.focused().is_none_or(|f| f == "main");
+        if self.linear || self.prompt.is_some() || !self.overlays.is_empty() || !main_focused || self.needs_redraw() {
+            return self.write(target);
+        }
+
+        let mut frame = std::mem::take(&mut self.frame);
+        frame.clear();
+        let mut drawn = true;
+        for pane in self.panes.get_mut("main").into_iter().flatten() {
+            drawn &= pane.write_cursor_line(&mut frame)?;
+        }
+        if !drawn {
+            self.frame = frame;
+            return self.write(target);
+        }
+
+        if let Some(main_pane) = self.find_by_id("main") {
+            main_pane.take_cursor(&mut frame)?;
+        }
+        target.write_all(&frame)?;
+        self.frame = frame;
+        Ok(())
+    }
+
+    /***
+    Clear the screen and redraw every pane from scratch
+     */
+    pub fn repaint(&mut self, target: &mut dyn Write) -> anyhow::Result<()> {
+        if self.linear {
+            return self.write(target);
+        }
+        let mut frame = Vec::new();
+        self.forget_screen(&mut frame);
+        target.write_all(&frame)?;
+        self.write(target)
+    }
+
+    /***
+    Reset the real terminal and clear it, so the next write draws every pane, title row and
+    marker from scratch, and puts back the modes the focused and main panes asked for
+     */
+    fn forget_screen(&mut self, frame: &mut Vec<u8>) {
+        if self.linear {
+            return;
+        }
+        // Plain text, a visible cursor, a numeric keypad and the theme's colors, then a blank screen
+        frame.extend_from_slice(b"\x1b[0m\x1b[?25h\x1b>");
+        if let Some(theme) = &self.theme {
+            theme.write_to(frame);
+        }
+        frame.extend_from_slice(b"\x1b[2J");
+        self.keypad.set_host_app(false);
+        if self.panes.get("main").and_then(|p| p.first()).map(|p| p.bracketed_paste()).unwrap_or(false) {
+            frame.extend_from_slice(b"\x1b[?2004h");
+        }
+
+        self.panes.values_mut().flatten().for_each(|pane| pane.invalidate());
+        self.drawn_cramped = None;
+        self.drawn_health.clear();
+    }
+
+    pub fn push(&mut self, task_id: TaskId, data: &[u8]) {
+        if let Ok(mut pipes) = self.pipes.lock() {
+            pipes.tee(&task_id, data);
+        }
+
         match self.panes.get_mut(&task_id) {
             None => {  info!("Received output for unregistered task {}", &task_id); } // Drop data for unknown tasks
-            Some(pane) => {
-                match pane.push(data) {
-                    Ok(_) => {}
-                    Err(e) => { error!("Error: {}", e.to_string()) }
-                } }
+            Some(panes) => {
+                if self.focus.lock().map(|f| f.focused() != Some(&task_id)).unwrap_or(false) {
+                    self.unread.insert(task_id.clone());
+                }
+                for pane in panes.iter_mut() {
+                    match pane.push(data) {
+                        Ok(_) => {}
+                        Err(e) => { error!("Error: {}", e.to_string()) }
+                    }
+                }
+
+                // Every mirror answers the task's queries, but the task should only hear from one of them.
+                // Only the main pane's child is listening to its input.
+                let replies = panes.first_mut().map(|p| p.take_replies()).unwrap_or_default();
+                for mirror in panes.iter_mut().skip(1) {
+                    mirror.take_replies();
+                }
+                match (&self.main_input, replies.is_empty()) {
+                    (_, true) => {}
+                    (Some(input), false) if task_id == "main" => {
+                        if let Err(e) = input.send(ProcInput { input: replies, typed_at: None }) {
+                            error!("main: Could not reply to terminal query: {}", e);
+                        }
+                    }
+                    _ => { info!("{}: Dropping {} bytes of replies - nothing is listening", task_id, replies.len()) }
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decker::terminal::ScrollMode;
+    use crossbeam_channel::unbounded;
+
+    #[test]
+    fn it_mirrors_a_task_into_each_of_its_panes() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 5, 4, 8));
+        let (input_tx, input_rx) = unbounded();
+        manager.set_main_input(input_tx);
+
+        manager.push(TaskId::from("main"), b"hello\x1b[5n");
+        let panes = manager.panes.get_mut("main").unwrap();
+        assert_eq!(panes[0].snapshot().trim_end(), "hello");
+        assert_eq!(panes[1].snapshot().trim_end(), "hello");
+        assert_eq!(manager.find_by_id("main").unwrap().width(), 10);
+
+        // Both mirrors answer, but the task only hears back once
+        assert_eq!(input_rx.try_recv().unwrap().input, b"\x1b[0n".to_vec());
+        assert!(input_rx.try_recv().is_err());
+        assert_eq!(manager.focus.lock().unwrap().cycle().unwrap(), "main");
+    }
+
+    #[test]
+    fn it_snapshots_each_of_a_tasks_panes() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 5, 4, 8));
+        manager.push(TaskId::from("main"), b"hello");
+        let screens = manager.screens("main");
+        assert_eq!(screens.iter().map(|s| s.row_text(0)).collect::<Vec<_>>(), vec!["hello     ", "hello   "]);
+        assert!(manager.screens("missing").is_empty());
+    }
+
+    #[test]
+    fn it_snapshots_how_far_back_panes_are_scrolled() {
+        let mut manager = PaneManager::new();
+        let mut pane = Pane::new("main", 1, 1, 2, 10);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+        manager.register(TaskId::from("main"), pane);
+        manager.push(TaskId::from("main"), b"one\r\ntwo\r\nthree\r\nfour");
+        manager.scroll_back("main", 2);
+
+        let (reply_tx, reply_rx) = unbounded();
+        manager.pane_requests().send(PaneRequest::Snapshot(reply_tx)).unwrap();
+        manager.write(&mut Vec::new()).unwrap();
+        let snapshot = reply_rx.recv().unwrap();
+        assert_eq!(snapshot["main"].1, 2);
+    }
+
+    #[test]
+    fn it_draws_the_prompt_over_everything() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        let requests = manager.pane_requests();
+
+        requests.send(PaneRequest::Prompt(Some(b"\r\x1b[Krun: tim\r\x1b[8C".to_vec()))).unwrap();
+        let mut frame = Vec::new();
+        manager.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("run: tim"));
+        // The prompt sits on the bottom row of whatever terminal the tests run in
+        let (_, height) = terminal_size().unwrap_or((80, 24));
+        assert!(drawn.ends_with(&format!("\x1b[{};9H", height)), "{:?}", drawn);
+
+        requests.send(PaneRequest::Prompt(None)).unwrap();
+        frame.clear();
+        manager.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).ends_with("\x1b[1;1H"));
+    }
+
+    #[test]
+    fn it_marks_panes_with_unread_output_until_focused() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        let mut time = Pane::new("time", 1, 4, 3, 12);
+        time.set_title_row("time");
+        manager.register(TaskId::from("time"), time);
+
+        manager.push(TaskId::from("time"), b"12:00");
+        let mut frame = Vec::new();
+        manager.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains(" time      +"));
+
+        manager.focus.lock().unwrap().cycle();
+        frame.clear();
+        manager.write(&mut frame).unwrap();
+        assert!(String::from_utf8_lossy(&frame).contains(" time      *"));
+        assert!(!manager.unread.contains("time"));
+    }
+
+    #[test]
+    fn it_repaints_everything_on_request() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        manager.push(TaskId::from("main"), b"hi\x1b[?2004h");
+        let mut frame = Vec::new();
+        manager.write(&mut frame).unwrap();
+
+        frame.clear();
+        manager.write(&mut frame).unwrap();
+        assert!(!String::from_utf8_lossy(&frame).contains("hi"));
+
+        manager.pane_requests().send(PaneRequest::Repaint).unwrap();
+        frame.clear();
+        manager.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.starts_with("\x1b[0m\x1b[?25h\x1b>\x1b[2J\x1b[?2004h"), "{:?}", drawn);
+        assert!(drawn.contains("hi"));
+    }
+
+    #[test]
+    fn it_draws_an_echo_ahead_of_other_panes() {
+        let mut manager = PaneManager::new();
+        manager.register(TaskId::from("main"), Pane::new("main", 1, 1, 2, 10));
+        manager.register(TaskId::from("time"), Pane::new("time", 1, 4, 1, 10));
+        manager.push(TaskId::from("main"), b"$ ");
+        manager.write(&mut Vec::new()).unwrap();
+
+        manager.push(TaskId::from("time"), b"12:00");
+        manager.push(TaskId::from("main"), b"l");
+        let mut frame = Vec::new();
+        manager.write_echo(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("$ l") && !drawn.contains("12:00"), "{:?}", drawn);
+        assert!(drawn.ends_with("\x1b[1;4H"), "{:?}", drawn);
+
+        // The rest is drawn with the next frame
+        frame.clear();
+        manager.write(&mut frame).unwrap();
+        let drawn = String::from_utf8_lossy(&frame).to_string();
+        assert!(drawn.contains("12:00") && !drawn.contains("$ l"), "{:?}", drawn);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::decker::terminal::{PanePipes, PanePipe, PipeTarget};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use log::{info, warn};
+
+impl PanePipes {
+    pub fn new() -> PanePipes {
+        PanePipes {
+            pipes: HashMap::new()
+        }
+    }
+
+    /***
+    Start copying a pane's output to `target`, replacing any existing pipe for that pane.
+    Files are appended to. Commands are run with `sh -c` and receive the output on stdin.
+     */
+    pub fn open(&mut self, pane_id: &str, target: &PipeTarget) -> anyhow::Result<()> {
+        self.close(pane_id);
+
+        let pipe = match target {
+            PipeTarget::File(path) => {
+                PanePipe::File(OpenOptions::new().create(true).append(true).open(path)?)
+            }
+            PipeTarget::Command(command) => {
+                PanePipe::Command(Command::new("sh").arg("-c").arg(command).
+                    stdin(Stdio::piped()).
+                    stdout(Stdio::null()).
+                    stderr(Stdio::null()).
+                    spawn()?)
+            }
+        };
+
+        info!("{}: Piping output to {:?}", pane_id, target);
+        self.pipes.insert(pane_id.to_string(), pipe);
+        Ok(())
+    }
+
+    /***
+    Stop copying a pane's output. Returns whether there was a pipe to close.
+     */
+    pub fn close(&mut self, pane_id: &str) -> bool {
+        match self.pipes.remove(pane_id) {
+            None => { false }
+            Some(PanePipe::File(_)) => { true }
+            Some(PanePipe::Command(mut child)) => {
+                // Closing stdin lets well-behaved commands finish up on their own
+                drop(child.stdin.take());
+                let _ = child.wait();
+                true
+            }
+        }
+    }
+
+    /***
+    Copy raw output to the pane's pipe, if it has one. A pipe that can't be written to is closed.
+     */
+    pub fn tee(&mut self, pane_id: &str, data: &[u8]) {
+        let result = match self.pipes.get_mut(pane_id) {
+            None => { return }
+            Some(PanePipe::File(file)) => { file.write_all(data) }
+            Some(PanePipe::Command(child)) => {
+                match child.stdin.as_mut() {
+                    None => { return }
+                    Some(stdin) => { stdin.write_all(data).and_then(|_| stdin.flush()) }
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("{}: Closing broken output pipe: {}", pane_id, e);
+            self.close(pane_id);
+        }
+    }
+}
+
+impl Default for PanePipes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_tees_output_to_a_file_until_closed() {
+        let path = std::env::temp_dir().join(format!("decker-test-{}.log", uuid::Uuid::new_v4()));
+        let mut pipes = PanePipes::new();
+
+        pipes.open("time", &PipeTarget::File(path.to_str().unwrap().to_string())).unwrap();
+        pipes.tee("time", b"12:00\n");
+        pipes.tee("weather", b"sunny\n");
+        assert!(pipes.close("time"));
+        pipes.tee("time", b"12:01\n");
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "12:00\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
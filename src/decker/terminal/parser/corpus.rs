@@ -0,0 +1,136 @@
+use super::Torture;
+use std::io::Write;
+
+// A little xorshift generator, so the same seed always makes the same corpus
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+const WORDS: &[&str] = &["cpu", "load", "0.42", "OK", "eth0", "1024", "FAIL", "build", "--", "|", "42%", "12:00:01"];
+const UNICODE: &[&str] = &["héllo", "☃", "日本語", "→", "▁▂▃▅▇", "🦀", "e\u{301}"];
+
+impl Torture {
+    pub const ALL: [Torture; 4] = [Torture::SgrChurn, Torture::Scrolling, Torture::CursorJumps, Torture::Mixed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Torture::SgrChurn => { "sgr-churn" }
+            Torture::Scrolling => { "scrolling" }
+            Torture::CursorJumps => { "cursor-jumps" }
+            Torture::Mixed => { "mixed" }
+        }
+    }
+
+    /***
+    At least `bytes` of this kind of output, for a screen `size` (width, height) cells big. The
+    same seed makes the same output every time.
+     */
+    pub fn generate(&self, bytes: usize, seed: u64, size: (u16, u16)) -> Vec<u8> {
+        let mut rng = Rng(seed.max(1));
+        let (width, height) = (size.0.max(10) as u64, size.1.max(5) as u64);
+        let mut out = Vec::with_capacity(bytes + 256);
+        while out.len() < bytes {
+            let kind = match self {
+                Torture::Mixed => { Torture::ALL[rng.below(3) as usize] }
+                kind => { *kind }
+            };
+            match kind {
+                Torture::SgrChurn => { Self::sgr_churn(&mut rng, &mut out, width) }
+                Torture::Scrolling => { Self::scrolling(&mut rng, &mut out) }
+                Torture::CursorJumps => { Self::cursor_jumps(&mut rng, &mut out, width, height) }
+                Torture::Mixed => {}
+            }
+            if *self == Torture::Mixed {
+                Self::oddities(&mut rng, &mut out);
+            }
+        }
+        out
+    }
+
+    // A line with a style change every word or so: basic, 256 and RGB colors, attributes, resets
+    fn sgr_churn(rng: &mut Rng, out: &mut Vec<u8>, width: u64) {
+        let mut line = 0;
+        while line < width - 8 {
+            let _ = match rng.below(8) {
+                0 => { write!(out, "\x1b[0m") }
+                1 => { write!(out, "\x1b[{}m", [1, 3, 4, 5, 7, 9][rng.below(6) as usize]) }
+                2 => { write!(out, "\x1b[3{}m", rng.below(8)) }
+                3 => { write!(out, "\x1b[4{}m", rng.below(8)) }
+                4 => { write!(out, "\x1b[38;5;{}m", rng.below(256)) }
+                5 => { write!(out, "\x1b[48;2;{};{};{}m", rng.below(256), rng.below(256), rng.below(256)) }
+                6 => { write!(out, "\x1b[1;3{};4{}m", rng.below(8), rng.below(8)) }
+                _ => { write!(out, "\x1b[22;23;24;39;49m") }
+            };
+            let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+            out.extend_from_slice(word.as_bytes());
+            out.push(b' ');
+            line += word.len() as u64 + 1;
+        }
+        out.extend_from_slice(b"\x1b[0m\r\n");
+    }
+
+    // Short lines, as from `tail -f`, so the pane scrolls on nearly every one
+    fn scrolling(rng: &mut Rng, out: &mut Vec<u8>) {
+        for _ in 0..1 + rng.below(4) {
+            let words = rng.below(12);
+            let line = (0..words).map(|_| WORDS[rng.below(WORDS.len() as u64) as usize]).collect::<Vec<_>>().join(" ");
+            let _ = write!(out, "{}\r\n", line);
+        }
+    }
+
+    // Text dotted about the screen, as full-screen programs like top draw it
+    fn cursor_jumps(rng: &mut Rng, out: &mut Vec<u8>, width: u64, height: u64) {
+        let _ = match rng.below(10) {
+            0 => { write!(out, "\x1b[K") }
+            1 => { write!(out, "\x1b[{}A\x1b[{}C", rng.below(5), rng.below(10)) }
+            2 => { write!(out, "\x1b7\x1b[{};1H\x1b[2K\x1b8", 1 + rng.below(height)) }
+            3 => { write!(out, "\x1b[{};{}r\x1b[{}S\x1b[r", 1 + rng.below(4), height - rng.below(4), 1 + rng.below(3)) }
+            4 => { write!(out, "\x1b[{}L", 1 + rng.below(3)) }
+            5 if rng.below(20) == 0 => { write!(out, "\x1b[H\x1b[2J") }
+            _ => { write!(out, "\x1b[{};{}H", 1 + rng.below(height), 1 + rng.below(width)) }
+        };
+        out.extend_from_slice(WORDS[rng.below(WORDS.len() as u64) as usize].as_bytes());
+    }
+
+    // The odd things real programs send between the rest: wide and combining characters, titles,
+    // hyperlinks and queries
+    fn oddities(rng: &mut Rng, out: &mut Vec<u8>) {
+        let _ = match rng.below(6) {
+            0 => { write!(out, "{}", UNICODE[rng.below(UNICODE.len() as u64) as usize]) }
+            1 => { write!(out, "\x1b]0;job {}\x07", rng.below(1000)) }
+            2 => { write!(out, "\x1b]8;;https://example.com/{}\x1b\\link\x1b]8;;\x1b\\", rng.below(1000)) }
+            3 => { write!(out, "\x1b[6n") }
+            _ => { Ok(()) }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::StreamState;
+
+    #[test]
+    fn it_makes_the_same_corpus_from_the_same_seed() {
+        for torture in Torture::ALL {
+            let corpus = torture.generate(4096, 7, (80, 24));
+            assert!(corpus.len() >= 4096, "{}", torture.name());
+            assert_eq!(corpus, torture.generate(4096, 7, (80, 24)));
+            assert_ne!(corpus, torture.generate(4096, 8, (80, 24)));
+
+            let mut stream = StreamState::new();
+            assert!(!stream.feed(&corpus).is_empty(), "{}", torture.name());
+        }
+    }
+}
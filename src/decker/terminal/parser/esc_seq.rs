@@ -0,0 +1,100 @@
+use super::EscSeq;
+use std::fmt::{Display, Formatter};
+
+impl EscSeq {
+    /***
+    Break a complete escape sequence into its parts. Never fails: anything that doesn't look
+    like a parameter is skipped, and oversized parameters are clamped.
+     */
+    pub fn parse(s: &str) -> EscSeq {
+        let final_byte = s.chars().last().unwrap_or('\0');
+        let mut private = None;
+        let mut params = Vec::new();
+
+        // Only CSIs (ESC [) carry parameters
+        if let Some(body) = s.strip_prefix("\x1b[") {
+            let body = body.strip_suffix(final_byte).unwrap_or(body);
+            let mut chars = body.chars().peekable();
+
+            if let Some(c) = chars.peek() {
+                if "?><=".contains(*c) {
+                    private = Some(*c);
+                    chars.next();
+                }
+            }
+
+            let param_str = chars.take_while(|c| c.is_ascii_digit() || *c == ';' || *c == ':').collect::<String>();
+            if !param_str.is_empty() {
+                params = param_str.split([';', ':']).map(Self::parse_param).collect();
+            }
+        }
+
+        EscSeq { raw: s.to_string(), private, params, final_byte }
+    }
+
+    fn parse_param(digits: &str) -> u16 {
+        digits.chars().
+            filter_map(|c| c.to_digit(10)).
+            fold(0u32, |n, d| (n * 10 + d).min(u16::MAX as u32)) as u16
+    }
+
+    /***
+    The parameter at `index`, or `default` if it's missing or zero
+     */
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params.get(index) {
+            None | Some(0) => { default }
+            Some(n) => { *n }
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+}
+
+impl Display for EscSeq {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_params_and_final_byte() {
+        let seq = EscSeq::parse("\x1b[12;40H");
+        assert_eq!(seq.params, vec![12, 40]);
+        assert_eq!(seq.final_byte, 'H');
+        assert_eq!(seq.private, None);
+    }
+
+    #[test]
+    fn it_treats_missing_params_as_zero() {
+        let seq = EscSeq::parse("\x1b[;5H");
+        assert_eq!(seq.params, vec![0, 5]);
+        assert_eq!(seq.param(0, 1), 1);
+        assert!(EscSeq::parse("\x1b[m").params.is_empty());
+    }
+
+    #[test]
+    fn it_recognizes_private_markers() {
+        let seq = EscSeq::parse("\x1b[?1049h");
+        assert_eq!(seq.private, Some('?'));
+        assert_eq!(seq.params, vec![1049]);
+    }
+
+    #[test]
+    fn it_clamps_huge_params() {
+        assert_eq!(EscSeq::parse("\x1b[99999999999A").params, vec![u16::MAX]);
+    }
+
+    #[test]
+    fn it_has_no_params_outside_of_csis() {
+        let seq = EscSeq::parse("\x1bM");
+        assert!(seq.params.is_empty());
+        assert_eq!(seq.final_byte, 'M');
+    }
+}
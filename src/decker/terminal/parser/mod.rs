@@ -0,0 +1,278 @@
+/***
+* VT parser
+* Turns a task's raw output into plain text and the escape sequences between it, without acting
+* on any of them - that's up to whoever reads the tokens. Nothing is drawn or printed while
+* parsing, so it can be fuzzed, and used on its own as `decker::parser`.
+***/
+use self::TerminalOutput::{CSI, Plaintext};
+use self::VT100::{SGR, PassThrough, MoveCursor, EraseScreen, ClearLine, Unknown, ScrollDown, ScrollUp, MoveCursorApp, HideCursor, ShowCursor, GetCursorPos, DeviceStatus, EnterApplicationKeyMode, ExitAltKeypadMode, EraseLineAfterCursor, EraseLineBeforeCursor, EnterAltKeypadMode, EnterInsertMode, ExitInsertMode, EnterNewlineMode, ExitNewlineMode, SaveCursor, RestoreCursor, SelectCharSet, NextLine, DeviceControl, OperatingSystem, ApplicationProgram, DeviceAttributes, SecondaryDeviceAttributes, SetTabStop, ClearTabStop, EnterAutoWrapMode, ExitAutoWrapMode, EnterReverseWrapMode, ExitReverseWrapMode};
+use anyhow::Error;
+use std::str::FromStr;
+
+mod stream_state;
+mod esc_seq;
+#[cfg(feature = "bench")]
+mod corpus;
+
+enum VT100State {
+    PlainText,
+    FoundEsc,
+    // Inside a DCS, OSC or APC (ESC P/]/_ ... ESC \\). esc: was the last character an unpaired ESC?
+    InString { esc: bool },
+    // A control string too long to keep. Everything up to its ST is thrown away.
+    SkippingString { esc: bool },
+}
+
+/***
+Made-up output that's hard on a terminal, for benchmarking and torture testing the parser and
+whatever draws its tokens. See `Torture::generate`.
+ */
+#[cfg(feature = "bench")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Torture {
+    // A style change every word: colors of every kind, attributes and resets
+    SgrChurn,
+    // Short lines, fast, so the screen scrolls on nearly every one
+    Scrolling,
+    // Text dotted about the screen with cursor moves, erases and scroll regions
+    CursorJumps,
+    // All of the above, interleaved, with wide characters, titles, hyperlinks and queries
+    Mixed,
+}
+
+/***
+Output is either plaintext or a VT100 command sequence instruction
+ */
+#[derive(Clone, Debug)]
+pub enum TerminalOutput {
+    Plaintext(String),
+    CSI(VT100),
+}
+
+/*
+Classifications of VT100 codes.
+Each contains its own string, but this makes it easy to detect and switch
+on different types. No need to inspect the last character at use time.
+ */
+#[derive(Clone, Debug)]
+pub enum VT100 {
+    ScrollDown(EscSeq),
+    ScrollUp(EscSeq),
+    SGR(EscSeq),
+    MoveCursor(EscSeq),
+    MoveCursorApp(EscSeq),
+    ClearLine(EscSeq),
+    EraseLineAfterCursor(EscSeq),
+    EraseLineBeforeCursor(EscSeq),
+    EraseScreen(EscSeq),
+    PassThrough(EscSeq),
+    HideCursor(EscSeq),
+    ShowCursor(EscSeq),
+    GetCursorPos(EscSeq),
+    DeviceStatus(EscSeq),
+    // DA1 and DA2: "what kind of terminal are you?"
+    DeviceAttributes(EscSeq),
+    SecondaryDeviceAttributes(EscSeq),
+    EnterApplicationKeyMode(EscSeq),
+    EnterAltKeypadMode(EscSeq),
+    ExitAltKeypadMode(EscSeq),
+    EnterInsertMode(EscSeq),
+    ExitInsertMode(EscSeq),
+    EnterNewlineMode(EscSeq),
+    ExitNewlineMode(EscSeq),
+    EnterAutoWrapMode(EscSeq),
+    ExitAutoWrapMode(EscSeq),
+    EnterReverseWrapMode(EscSeq),
+    ExitReverseWrapMode(EscSeq),
+    SaveCursor(EscSeq),
+    RestoreCursor(EscSeq),
+    SelectCharSet(EscSeq),
+    NextLine(EscSeq),
+    // HTS and TBC
+    SetTabStop(EscSeq),
+    ClearTabStop(EscSeq),
+    // A whole DCS string, payload and all
+    DeviceControl(EscSeq),
+    // A whole OSC (e.g. window titles, iTerm2 images)
+    OperatingSystem(EscSeq),
+    // A whole APC (e.g. kitty graphics)
+    ApplicationProgram(EscSeq),
+    Unknown(EscSeq),
+}
+
+/***
+An escape sequence, parsed once when it's recognized so handlers never need to re-parse it.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EscSeq {
+    raw: String,
+    // '?', '>', '=' or '<' immediately following a CSI, if there was one
+    pub private: Option<char>,
+    // Numeric parameters. Missing ones are 0, which every command treats as "use the default".
+    pub params: Vec<u16>,
+    pub final_byte: char,
+}
+
+/***
+Splits a stream of output into tokens. Sequences split between reads are held until the rest
+of them arrives.
+ */
+pub struct StreamState {
+    // The start of a UTF-8 character whose remaining bytes haven't arrived yet
+    partial_utf8: Vec<u8>,
+    buffer: String,
+    vetted_output: Vec<TerminalOutput>,
+    build_state: VT100State,
+}
+
+impl VT100 {
+    pub fn to_string(&self) -> String {
+        self.seq().as_str().to_string()
+    }
+
+    pub fn seq(&self) -> &EscSeq {
+        match self {
+            ScrollDown(s) => { s }
+            ScrollUp(s) => { s }
+            SGR(s) => { s }
+            MoveCursor(s) => { s }
+            MoveCursorApp(s) => { s }
+            ClearLine(s) => { s }
+            EraseLineBeforeCursor(s) => { s }
+            EraseLineAfterCursor(s) => { s }
+            EraseScreen(s) => { s }
+            HideCursor(s) => { s }
+            ShowCursor(s) => { s }
+            PassThrough(s) => { s }
+            GetCursorPos(s) => { s }
+            DeviceStatus(s) => { s }
+            DeviceAttributes(s) => { s }
+            SecondaryDeviceAttributes(s) => { s }
+            Unknown(s) => { s }
+            EnterApplicationKeyMode(s) => { s }
+            EnterAltKeypadMode(s) => { s }
+            ExitAltKeypadMode(s) => { s }
+            EnterInsertMode(s) => { s }
+            ExitInsertMode(s) => { s }
+            EnterNewlineMode(s) => { s }
+            ExitNewlineMode(s) => { s }
+            EnterAutoWrapMode(s) => { s }
+            ExitAutoWrapMode(s) => { s }
+            EnterReverseWrapMode(s) => { s }
+            ExitReverseWrapMode(s) => { s }
+            SaveCursor(s) => { s }
+            RestoreCursor(s) => { s }
+            SelectCharSet(s) => { s }
+            NextLine(s) => { s }
+            SetTabStop(s) => { s }
+            ClearTabStop(s) => { s }
+            DeviceControl(s) => { s }
+            OperatingSystem(s) => { s }
+            ApplicationProgram(s) => { s }
+        }
+    }
+}
+
+impl FromStr for VT100 {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(anyhow::anyhow!("Cannot parse an empty string"));
+        }
+
+        let seq = EscSeq::parse(s);
+        let vt100 = match s.chars().last().unwrap() {
+            _ if s.starts_with("\x1bP") => DeviceControl(seq),
+            _ if s.starts_with("\x1b]") => OperatingSystem(seq),
+            _ if s.starts_with("\x1b_") => ApplicationProgram(seq),
+            _ if s.starts_with("\x1b(") || s.starts_with("\x1b)") => SelectCharSet(seq),
+            '7' if s == "\x1b7" => SaveCursor(seq),
+            '8' if s == "\x1b8" => RestoreCursor(seq),
+            'E' if s == "\x1bE" => NextLine(seq),
+            'H' if s == "\x1bH" => SetTabStop(seq),
+            'g' if s.starts_with("\x1b[") => ClearTabStop(seq),
+            's' if s == "\x1b[s" => SaveCursor(seq),
+            'u' if s == "\x1b[u" => RestoreCursor(seq),
+            'M' => ScrollDown(seq),
+            'D' => {
+                // D can be either ESC D which means Scroll Up
+                // OR it can be ESC [#D which means Move left.
+                if s == "\x1BD" {
+                    ScrollUp(seq)
+                } else {
+                    MoveCursor(seq)
+                }
+            }
+            'm' => SGR(seq),
+            'c' if s.starts_with("\x1b[") && seq.param(0, 0) == 0 => match seq.private {
+                None => DeviceAttributes(seq),
+                Some('>') => SecondaryDeviceAttributes(seq),
+                _ => Unknown(seq),
+            }
+            'H' | 'f' | 'A' | 'B' | 'C' => {
+                /* cursor movement */
+                if s.get(1..2) == Some("O") {
+                    // When alternate mode is set, arrow keys send ESC O[A-D] instead of ESC[[A-D]
+                    // This can trip up e.g. vim.
+                    MoveCursorApp(seq)
+                } else {
+                    MoveCursor(seq)
+                }
+            }
+            'J' => EraseScreen(seq),
+            'K' => match seq.param(0, 0) {
+                1 => EraseLineBeforeCursor(seq),
+                2 => ClearLine(seq),
+                _ => EraseLineAfterCursor(seq)
+            }
+            'L' => ClearLine(seq),
+            'h' | 'l' | 'n' | 'r' => /* Various control / query options */
+                match s {
+                    "\x1b[?1h" => EnterApplicationKeyMode(seq),
+                    "\x1b[?25l" => HideCursor(seq),
+                    "\x1b[?25h" => ShowCursor(seq),
+                    "\x1b[6n" => GetCursorPos(seq),
+                    "\x1b[5n" => DeviceStatus(seq),
+                    "\x1b[4h" => EnterInsertMode(seq),
+                    "\x1b[4l" => ExitInsertMode(seq),
+                    "\x1b[20h" => EnterNewlineMode(seq),
+                    "\x1b[20l" => ExitNewlineMode(seq),
+                    "\x1b[?7h" => EnterAutoWrapMode(seq),
+                    "\x1b[?7l" => ExitAutoWrapMode(seq),
+                    "\x1b[?45h" => EnterReverseWrapMode(seq),
+                    "\x1b[?45l" => ExitReverseWrapMode(seq),
+                    _ => PassThrough(seq)
+                }
+            '=' | '>' if s.len() == 2 => {
+                // DECKPAM / DECKPNM
+                if s == "\x1b=" { EnterAltKeypadMode(seq) } else { ExitAltKeypadMode(seq) }
+            }
+            _ => {
+                if s.starts_with("\x1Bk") {
+                    ClearLine(seq)
+                } else {
+                    Unknown(seq)
+                }
+            }
+        };
+
+        Ok(vt100)
+    }
+}
+
+impl TerminalOutput {
+    pub fn to_string(&self) -> String {
+        match self {
+            Plaintext(s) => { s.clone() }
+            CSI(s) => { s.to_string() }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Plaintext(s) => { s.len() == 0 }
+            CSI(s) => { s.to_string().len() == 0 }
+        }
+    }
+}
@@ -0,0 +1,465 @@
+use super::{StreamState, TerminalOutput, VT100};
+use super::VT100State::{PlainText, FoundEsc, InString, SkippingString};
+use super::TerminalOutput::{Plaintext, CSI};
+use regex::Regex;
+use lazy_static::lazy_static;
+use std::str::FromStr;
+use log::info;
+
+// Longer than any real sequence. Binary noise can start one that never ends, and we'd rather
+// show the noise than swallow everything after it.
+const MAX_ESC_SEQ_LEN: usize = 256;
+// DCS, OSC and APC payloads (e.g. images) are legitimately much bigger
+const MAX_STRING_LEN: usize = 1024 * 1024;
+
+lazy_static! {
+    static ref CSI_BEGINNING: Regex = Regex::new(r"\x1b[\[\x9b>=MDEHk\\78()]").unwrap();
+    static ref VT100_REGEX:  Regex = Regex::new(r"\x1b[\[\x9b>=MD]([0-?]*[ -/]*[@-~])").unwrap();
+    static ref VT100_SCROLL_REGEX: Regex = Regex::new(r"\x1b[MD]").unwrap();
+    static ref VT100_CLEAR_REGEX: Regex = Regex::new(r"\x1bk\S+\\").unwrap();
+    // DECKPAM, DECKPNM, DECSC, DECRC, NEL, HTS and a bare ST are complete as soon as they start.
+    // Charset selection takes one more character.
+    static ref VT100_SHORT_REGEX: Regex = Regex::new(r"^\x1b([=>\\78EH]|[()].)$").unwrap();
+}
+
+/***
+The 7-bit (ESC-prefixed) form of an 8-bit C1 control byte, for the ones we understand
+ */
+fn c1_as_esc(byte: u8) -> Option<&'static str> {
+    match byte {
+        0x84 => { Some("\x1bD") }  // IND
+        0x85 => { Some("\x1bE") }  // NEL
+        0x8D => { Some("\x1bM") }  // RI
+        0x9B => { Some("\x1b[") }  // CSI
+        0x9C => { Some("\x1b\\") } // ST
+        0x9D => { Some("\x1b]") }  // OSC
+        _ => { None }
+    }
+}
+
+impl StreamState {
+    pub fn new() -> StreamState {
+        StreamState {
+            partial_utf8: Vec::new(),
+            buffer: String::new(),
+            vetted_output: Vec::new(),
+            build_state: PlainText,
+        }
+    }
+
+    /***
+    Forget any partial character or escape sequence, e.g. because the rest of it was thrown away
+     */
+    pub fn reset(&mut self) {
+        self.partial_utf8.clear();
+        self.buffer.clear();
+        self.build_state = PlainText;
+    }
+
+    /***
+    Decode raw output and push it. Characters split across calls are held until they're complete,
+    and invalid UTF-8 is replaced with U+FFFD rather than rejected - except for single-byte C1
+    controls, which become their ESC-prefixed equivalents.
+     */
+    pub fn push_bytes(&mut self, bytes: &[u8]) {
+        let mut data = std::mem::take(&mut self.partial_utf8);
+        data.extend_from_slice(bytes);
+
+        let mut rest = &data[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.push(text);
+                    break;
+                }
+                Err(e) => {
+                    let (valid, invalid) = rest.split_at(e.valid_up_to());
+                    self.push(std::str::from_utf8(valid).unwrap());
+
+                    match e.error_len() {
+                        None => {
+                            // Ran out of bytes mid-character - wait for the rest
+                            self.partial_utf8 = invalid.to_vec();
+                            break;
+                        }
+                        Some(len) => {
+                            match c1_as_esc(invalid[0]) {
+                                Some(esc) if len == 1 => { self.push(esc) }
+                                _ => { self.push("\u{FFFD}") }
+                            }
+                            rest = &invalid[len..];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /***
+    Push raw output and take every token it completes
+     */
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<TerminalOutput> {
+        self.push_bytes(bytes);
+        self.consume()
+    }
+
+    pub fn push(&mut self, stdin: &str) {
+        for c in stdin.chars() {
+            match self.build_state {
+                PlainText => {
+                    if c == '\x1b' { // start looking for an esc seq
+                        self.consume_buffer();
+                        self.buffer.push(c);
+                        self.build_state = FoundEsc
+                    } else {
+                        let last_output = self.vetted_output.pop().unwrap_or(Plaintext(String::new()));
+                        match last_output {
+                            Plaintext(mut plaintext_str) => {
+                                plaintext_str.push(c);
+                                self.vetted_output.push(Plaintext(plaintext_str));
+                            }
+                            CSI(csi_str) => {
+                                // Whoops - we can't append directly to this one!
+                                // Put it back and start a new string
+                                self.vetted_output.push(CSI(csi_str));
+                                self.vetted_output.push(Plaintext(String::from(c)));
+                            }
+                        }
+                    }
+                }
+
+                FoundEsc => {
+                    if c == '\x1b' {
+                        // An ESC cuts short whatever sequence came before it
+                        let cut_short = std::mem::replace(&mut self.buffer, String::from(c));
+                        match cut_short.as_str() {
+                            "\x1b" => { self.vetted_output.push(Plaintext(cut_short)) }
+                            _ => {
+                                info!("Incomplete escape sequence {:?}", cut_short);
+                                if let Ok(vt100) = VT100::from_str(&cut_short) {
+                                    self.vetted_output.push(CSI(vt100))
+                                }
+                            }
+                        }
+                        continue;
+                    }
+
+                    self.buffer.push(c);
+                    if self.buffer == "\x1bP" || self.buffer == "\x1b]" || self.buffer == "\x1b_" {
+                        // The start of a DCS, OSC or APC - the payload runs until ST
+                        self.build_state = InString { esc: false };
+                        continue;
+                    }
+
+                    let not_an_esc_seq = self.buffer.len() == 1 + c.len_utf8() && !self.is_esc_seq();
+                    let runaway = self.buffer.len() > MAX_ESC_SEQ_LEN;
+
+                    if not_an_esc_seq || runaway || self.is_esc_seq_complete() {
+                        self.consume_buffer();
+                        self.build_state = PlainText;
+                    }
+                }
+
+                InString { esc } => {
+                    self.buffer.push(c);
+                    match (esc, c) {
+                        (true, '\\') => { self.consume_string() }
+                        // OSCs may end with BEL instead
+                        (false, '\x07') if self.buffer.starts_with("\x1b]") => { self.consume_string() }
+                        // tmux-style payloads double their ESCs
+                        (true, _) => { self.build_state = InString { esc: false } }
+                        (false, '\x1b') => { self.build_state = InString { esc: true } }
+                        (false, _) if self.buffer.len() > MAX_STRING_LEN => {
+                            info!("Dropping a control string longer than {} bytes", MAX_STRING_LEN);
+                            // Keep the introducer, to know how it ends
+                            self.buffer.truncate(2);
+                            self.build_state = SkippingString { esc: false };
+                        }
+                        (false, _) => {}
+                    }
+                }
+
+                SkippingString { esc } => {
+                    self.build_state = match (esc, c) {
+                        (true, '\\') => { PlainText }
+                        (false, '\x07') if self.buffer.starts_with("\x1b]") => { PlainText }
+                        (false, '\x1b') => { SkippingString { esc: true } }
+                        _ => { SkippingString { esc: false } }
+                    };
+                    if let PlainText = self.build_state {
+                        self.buffer.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    fn consume_string(&mut self) {
+        let string = std::mem::take(&mut self.buffer);
+        match VT100::from_str(&string) {
+            Ok(vt100) => { self.vetted_output.push(CSI(vt100)) }
+            Err(e) => { info!("Dropping control string: {}", e) }
+        }
+        self.build_state = PlainText;
+    }
+
+    fn consume_buffer(&mut self) {
+        let buf_str = self.buffer.clone();
+
+        if self.is_esc_seq_complete() {
+            match VT100::from_str(buf_str.as_str()) {
+                Ok(vt100) => { self.vetted_output.push(CSI(vt100)) }
+                Err(e) => { info!("Dropping escape sequence {:?}: {}", buf_str, e) }
+            }
+        } else {
+            self.vetted_output.push(Plaintext(buf_str));
+        }
+
+        self.buffer.clear();
+    }
+
+    pub fn is_esc_seq(&self) -> bool {
+        CSI_BEGINNING.is_match(&self.buffer)
+    }
+
+    fn is_esc_seq_complete(&self) -> bool {
+        self.is_esc_seq() && (
+            VT100_REGEX.is_match(&self.buffer) ||
+            VT100_CLEAR_REGEX.is_match(&self.buffer) ||
+            VT100_SHORT_REGEX.is_match(&self.buffer) ||
+            VT100_SCROLL_REGEX.is_match(&self.buffer))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        // If we have anything vetted, go consume it!
+        let have_vetted_output = self.vetted_output.iter().any(
+            |v| match v {
+                Plaintext(s) => { !s.is_empty() }
+                CSI(_) => { true } // CSIs always have contents
+            }
+        );
+        self.pending_esc() || have_vetted_output
+    }
+
+    /***
+    Is the buffer just the start of an escape sequence? (As opposed to e.g. a half-read DCS.)
+     */
+    fn pending_esc(&self) -> bool {
+        matches!(self.build_state, FoundEsc) && self.buffer.ends_with('\x1b')
+    }
+
+    pub fn consume(&mut self) -> Vec<TerminalOutput> {
+        if self.pending_esc() {
+            self.consume_buffer();
+            self.build_state = PlainText;
+        }
+        // reject any empty strings.
+        let out = self.vetted_output.iter().filter(|o| !o.is_empty()).cloned().collect();
+
+        self.vetted_output = Vec::new();
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn given_a_blank_stream() -> StreamState {
+        StreamState::new()
+    }
+
+    fn given_a_stream_with_chars(chars: &str) -> StreamState {
+        let mut s = StreamState::new();
+        s.push(chars);
+        s
+    }
+
+    fn as_raw_string(output_vec: &Vec<TerminalOutput>) -> String {
+        output_vec.iter().
+            map(|c| c.to_string()).
+            collect::<Vec<String>>().
+            join("")
+    }
+
+    #[test]
+    fn it_detects_an_esc_seq() {
+        let mut s = given_a_blank_stream();
+        s.push("\x1b[");
+
+        assert!(s.is_esc_seq());
+    }
+
+    #[test]
+    fn it_detects_plain_text() {
+        let mut s = given_a_blank_stream();
+        s.push("hi!");
+
+        assert!(!s.is_esc_seq());
+    }
+
+    #[test]
+    fn it_does_not_detect_an_esc_seq_when_just_an_esc() {
+        let s = given_a_stream_with_chars("\x1b");
+        assert!(!s.is_esc_seq());
+    }
+
+    #[test]
+    fn it_detects_when_esc_seq_is_complete() {
+        let mut s = given_a_blank_stream();
+        s.push("\x1b[");
+
+        assert!(s.is_esc_seq() && !s.is_complete());
+
+        s.push("33m");
+        assert!(s.is_complete());
+    }
+
+    #[test]
+    fn it_says_normal_text_is_complete() {
+        let mut s = given_a_blank_stream();
+        s.push("normal");
+
+        assert!(!s.is_esc_seq() && s.is_complete());
+
+        s.push(" text");
+        assert!(!s.is_esc_seq() && s.is_complete());
+    }
+
+    #[test]
+    fn it_clears_data_when_consumed() {
+        let mut s = given_a_stream_with_chars("some chars");
+        assert!(s.is_complete());
+        assert_eq!(as_raw_string(&s.consume()), String::from("some chars"));
+        assert!(!s.is_complete())
+    }
+
+    #[test]
+    fn it_remains_complete_when_an_esc_sequence_comes_in() {
+        let mut s = given_a_stream_with_chars("some chars");
+        s.push("\x1b[");
+        assert!(s.is_complete())
+    }
+
+    #[test]
+    fn it_buffers_the_esc_sequence_when_an_esc_seq_comes_in() {
+        let mut s = given_a_stream_with_chars("some chars");
+        s.push("\x1b[");
+        let out = s.consume();
+        assert_eq!(as_raw_string(&out), String::from("some chars"))
+    }
+
+    #[test]
+    fn it_releases_the_esc_when_it_is_alone() {
+        let mut s = given_a_stream_with_chars("some chars");
+        s.push("\x1b");
+        let out = s.consume();
+        assert_eq!(as_raw_string(&out), String::from("some chars\x1b"))
+    }
+
+    #[test]
+    fn it_joins_characters_split_across_pushes() {
+        let mut s = given_a_blank_stream();
+        let bytes = "½ off".as_bytes();
+        s.push_bytes(&bytes[..1]);
+        assert_eq!(as_raw_string(&s.consume()), String::new());
+
+        s.push_bytes(&bytes[1..]);
+        assert_eq!(as_raw_string(&s.consume()), String::from("½ off"));
+    }
+
+    #[test]
+    fn it_feeds_bytes_and_takes_the_tokens() {
+        let mut s = given_a_blank_stream();
+        assert_eq!(as_raw_string(&s.feed(b"a\x1b[3")), String::from("a"));
+
+        let out = s.feed(b"1mb");
+        assert_eq!(as_raw_string(&out), String::from("\x1b[31mb"));
+        assert!(matches!(out[0], CSI(VT100::SGR(_))));
+    }
+
+    #[test]
+    fn it_replaces_invalid_utf8() {
+        let mut s = given_a_blank_stream();
+        s.push_bytes(b"caf\xe9!");
+        assert_eq!(as_raw_string(&s.consume()), String::from("caf\u{FFFD}!"));
+    }
+
+    #[test]
+    fn it_treats_8bit_controls_like_their_esc_forms() {
+        let mut s = given_a_blank_stream();
+        s.push_bytes(b"a\x9b31mb\x85c");
+        let out = s.consume();
+        assert_eq!(as_raw_string(&out), String::from("a\x1b[31mb\x1bEc"));
+        assert!(matches!(out[1], CSI(VT100::SGR(_))));
+        assert!(matches!(out[3], CSI(VT100::NextLine(_))));
+    }
+
+    #[test]
+    fn it_reads_a_dcs_as_one_sequence() {
+        let mut s = given_a_stream_with_chars("a\x1bP+q544e\x1b");
+        assert_eq!(as_raw_string(&s.consume()), String::from("a"));
+
+        s.push("\\b\x1bPtmux;\x1b\x1b[1m\x1b\\c");
+        let out = s.consume();
+        assert_eq!(as_raw_string(&out), String::from("\x1bP+q544e\x1b\\b\x1bPtmux;\x1b\x1b[1m\x1b\\c"));
+        assert!(matches!(out[0], CSI(VT100::DeviceControl(_))));
+        assert!(matches!(out[2], CSI(VT100::DeviceControl(_))));
+    }
+
+    #[test]
+    fn it_reads_oscs_ending_in_bel_or_st() {
+        let mut s = given_a_stream_with_chars("\x1b]0;title\x07a\x1b]1337;File=:AAAA\x1b\\b");
+        let out = s.consume();
+        assert_eq!(as_raw_string(&out), String::from("\x1b]0;title\x07a\x1b]1337;File=:AAAA\x1b\\b"));
+        assert!(matches!(out[0], CSI(VT100::OperatingSystem(_))));
+        assert!(matches!(out[2], CSI(VT100::OperatingSystem(_))));
+    }
+
+    #[test]
+    fn it_discards_oversized_dcs_payloads() {
+        let mut s = given_a_blank_stream();
+        s.push("\x1bPq");
+        s.push(&"#".repeat(MAX_STRING_LEN + 1));
+        s.push("\x1b\\ok");
+        assert_eq!(as_raw_string(&s.consume()), String::from("ok"));
+    }
+
+    #[test]
+    fn it_gives_up_on_runaway_sequences() {
+        let mut s = given_a_blank_stream();
+        s.push("\x1b[");
+        s.push(&"\u{1}".repeat(MAX_ESC_SEQ_LEN));
+        s.push("after");
+
+        let out = as_raw_string(&s.consume());
+        assert!(out.ends_with("after"), "{:?}", out);
+    }
+
+    #[test]
+    fn it_releases_an_esc_followed_by_a_wide_char() {
+        let mut s = given_a_stream_with_chars("\x1bé text");
+        assert_eq!(as_raw_string(&s.consume()), String::from("\x1bé text"));
+    }
+
+    #[test]
+    fn it_recognizes_scroll_commands() {
+        let mut s = given_a_stream_with_chars("\x1bM\x1bD");
+        let out = s.consume();
+        assert!(out.iter().all(|s| match s {
+            CSI(_) => { true }
+            _ => { false }
+        }), "not all of {:?} are CSIs!", &out);
+    }
+
+    #[test]
+    fn it_recognizes_unusual_csis() {
+        let mut s = given_a_stream_with_chars("\x1b[>\x1b[=\x1b=\x1b>\x1b\\");
+        let out = s.consume();
+        assert!(out.iter().all(|s| match s {
+            CSI(_) => { true }
+            _ => { false }
+        }), "not all of {:?} are CSIs!", &out);
+    }
+}
\ No newline at end of file
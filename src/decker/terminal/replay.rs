@@ -0,0 +1,104 @@
+use crate::decker::terminal::{Pane, Replay};
+use std::path::Path;
+use std::time::Duration;
+use std::thread;
+use anyhow::{anyhow, bail};
+
+// Untimed captures are fed through in chunks about the size the orchestrator reads
+const CHUNK_SIZE: usize = 1024;
+
+impl Replay {
+    /***
+    A capture without timing information. It plays back as fast as it can be parsed.
+     */
+    pub fn new(raw: &[u8]) -> Replay {
+        Replay { chunks: raw.chunks(CHUNK_SIZE).map(|c| (Duration::from_secs(0), c.to_vec())).collect() }
+    }
+
+    /***
+    A capture with timing, in the format written by `script --timing`:
+    one "<seconds to wait> <byte count>" pair per line.
+     */
+    pub fn with_timing(raw: &[u8], timing: &str) -> anyhow::Result<Replay> {
+        // `script` starts its typescript with a header line that isn't part of the timing
+        let mut offset = if raw.starts_with(b"Script started") {
+            raw.iter().position(|b| *b == b'\n').map(|i| i + 1).unwrap_or(raw.len())
+        } else {
+            0
+        };
+
+        let mut chunks = Vec::new();
+        for (line_no, line) in timing.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+            let mut parts = line.split_whitespace();
+            let (delay, count) = match (parts.next(), parts.next()) {
+                (Some(delay), Some(count)) => { (delay, count) }
+                _ => { bail!("Timing line {} should be \"<seconds> <bytes>\", got '{}'", line_no + 1, line) }
+            };
+            let delay = delay.parse::<f64>().map_err(|_| anyhow!("Timing line {} has a bad delay '{}'", line_no + 1, delay))?;
+            let count = count.parse::<usize>().map_err(|_| anyhow!("Timing line {} has a bad byte count '{}'", line_no + 1, count))?;
+
+            let end = (offset + count).min(raw.len());
+            chunks.push((Duration::from_secs_f64(delay.max(0.0)), raw[offset..end].to_vec()));
+            offset = end;
+        }
+
+        // Anything the timing file doesn't cover comes through at the end
+        if offset < raw.len() {
+            chunks.push((Duration::from_secs(0), raw[offset..].to_vec()));
+        }
+
+        Ok(Replay { chunks })
+    }
+
+    /***
+    Read a capture, and optionally its timing file, from disk
+     */
+    pub fn load(capture: &Path, timing: Option<&Path>) -> anyhow::Result<Replay> {
+        let raw = std::fs::read(capture)?;
+        match timing {
+            None => { Ok(Self::new(&raw)) }
+            Some(timing) => { Self::with_timing(&raw, &std::fs::read_to_string(timing)?) }
+        }
+    }
+
+    /***
+    Feed the capture through `pane`, calling `after_chunk` after each piece so the caller can render.
+    @arg speed: How much faster than the original to play back. Zero skips the waits entirely.
+     */
+    pub fn play<F>(&self, pane: &mut Pane, speed: f64, mut after_chunk: F) -> anyhow::Result<()>
+        where F: FnMut(&mut Pane) -> anyhow::Result<()> {
+        for (delay, bytes) in &self.chunks {
+            if speed > 0.0 && !delay.is_zero() {
+                thread::sleep(delay.div_f64(speed));
+            }
+            pane.push(bytes)?;
+            after_chunk(pane)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replays_a_raw_capture_into_a_pane() {
+        let mut pane = Pane::new("replay", 1, 1, 3, 10);
+        let mut chunks = 0;
+        Replay::new(b"hello\r\nworld").play(&mut pane, 0.0, |_| { chunks += 1; Ok(()) }).unwrap();
+
+        assert_eq!(chunks, 1);
+        assert!(pane.snapshot().starts_with("hello\nworld"));
+    }
+
+    #[test]
+    fn it_splits_captures_by_script_timing() {
+        let replay = Replay::with_timing(b"Script started on today\nabcdef", "0.5 2\n0.25 3\n").unwrap();
+        let chunks = replay.chunks.iter().map(|(d, b)| (d.as_millis(), b.clone())).collect::<Vec<_>>();
+        assert_eq!(chunks, vec![(500, b"ab".to_vec()), (250, b"cde".to_vec()), (0, b"f".to_vec())]);
+
+        assert!(Replay::with_timing(b"abc", "soon 3").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::decker::terminal::Screen;
+
+impl Screen {
+    /***
+    The text on row `y`, without styling. Trailing blanks are kept, so every row is `width` long.
+     */
+    pub fn row_text(&self, y: u16) -> String {
+        self.rows.get(y as usize).map(|row| row.iter().map(|cell| cell.c).collect()).unwrap_or_default()
+    }
+
+    /***
+    Every row's text, one line per row, with trailing blanks trimmed
+     */
+    pub fn text(&self) -> String {
+        (0..self.height).map(|y| self.row_text(y).trim_end().to_string()).collect::<Vec<_>>().join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::terminal::{Color, Pane, PrintStyle};
+
+    #[test]
+    fn it_snapshots_cells_styles_and_cursor() {
+        let mut pane = Pane::new("test", 1, 1, 3, 6);
+        pane.push("hi \x1b[31mred\x1b[0m\r\nok").unwrap();
+        let screen = pane.screen();
+
+        assert_eq!((screen.width, screen.height), (6, 3));
+        assert_eq!(screen.rows.iter().map(|row| row.len()).collect::<Vec<_>>(), vec![6, 6, 6]);
+        assert_eq!(screen.text(), "hi red\nok\n");
+        assert_eq!(screen.row_text(1), "ok    ");
+        assert_eq!(screen.rows[0][3].style.foreground, Color::Red);
+        assert_eq!(screen.rows[0][0].style, PrintStyle::default());
+        assert_eq!(screen.cursor, (2, 1));
+    }
+}
@@ -0,0 +1,16 @@
+/***
+How many minutes it's been since midnight, in the local time zone
+ */
+pub fn minutes_since_midnight() -> u16 {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).
+        map(|d| d.as_secs() as libc::time_t).unwrap_or(0);
+    // Safe: an all-zero tm is valid (its zone is a null pointer), localtime_r only reads `now`
+    // and fills in `tm`, and a failure is checked for
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let converted = unsafe { libc::localtime_r(&now, &mut tm) };
+    if converted.is_null() {
+        // No idea of the time zone - UTC is better than nothing
+        return ((now.rem_euclid(86400)) / 60) as u16;
+    }
+    (tm.tm_hour * 60 + tm.tm_min) as u16
+}
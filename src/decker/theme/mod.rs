@@ -0,0 +1,44 @@
+/***
+* Theme
+* Day and night colors for the dashboard. The real terminal's default foreground and background
+* are swapped with OSC 10 and 11, so every pane, gap and border follows along, at times of day
+* set in the config or whenever a command says it's time.
+***/
+mod local_time;
+mod palette;
+mod schedule;
+
+pub use local_time::minutes_since_midnight;
+pub use palette::THEME_RESET;
+
+use serde::Deserialize;
+
+/***
+The real terminal's default colors, as X color specs, e.g. "#fdf6e3" or "black". Unset leaves
+the terminal's own.
+ */
+#[derive(Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    pub foreground: Option<String>,
+    pub background: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ThemeVariant {
+    Day,
+    Night,
+}
+
+/***
+Which theme to show when: by the clock, or by what `follow` prints
+ */
+#[derive(Clone, Debug)]
+pub struct ThemeSchedule {
+    day: Theme,
+    night: Theme,
+    // Minutes after local midnight
+    day_starts: u16,
+    night_starts: u16,
+    follow: Option<String>,
+}
@@ -0,0 +1,56 @@
+use crate::decker::theme::Theme;
+use anyhow::bail;
+
+// Put the real terminal's default colors back how we found them
+pub const THEME_RESET: &[u8] = b"\x1b]110\x07\x1b]111\x07";
+
+impl Theme {
+    /***
+    Check the colors can go in an OSC 10/11 as they are, so a typo can't send the terminal
+    something it'll take as another escape sequence
+     */
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for color in self.foreground.iter().chain(self.background.iter()) {
+            let allowed = |c: char| c.is_ascii_alphanumeric() || "#:/. ".contains(c);
+            if color.is_empty() || !color.chars().all(allowed) {
+                bail!("'{}' is not a color - expected e.g. \"#fdf6e3\" or \"black\"", color);
+            }
+        }
+        Ok(())
+    }
+
+    /***
+    Append the OSC 10 and 11 sequences that make these the terminal's default colors. Colors
+    left unset go back to the terminal's own.
+     */
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        match &self.foreground {
+            None => { out.extend_from_slice(b"\x1b]110\x07") }
+            Some(color) => { out.extend_from_slice(format!("\x1b]10;{}\x07", color).as_bytes()) }
+        }
+        match &self.background {
+            None => { out.extend_from_slice(b"\x1b]111\x07") }
+            Some(color) => { out.extend_from_slice(format!("\x1b]11;{}\x07", color).as_bytes()) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::theme::Theme;
+
+    #[test]
+    fn it_sets_and_resets_the_default_colors() {
+        let theme = Theme { foreground: None, background: Some(String::from("#1c1c1c")) };
+        let mut out = Vec::new();
+        theme.write_to(&mut out);
+        assert_eq!(out, b"\x1b]110\x07\x1b]11;#1c1c1c\x07");
+    }
+
+    #[test]
+    fn it_refuses_colors_that_could_escape_the_osc() {
+        assert!(Theme { foreground: Some(String::from("rgb:ff/f6/e3")), background: None }.validate().is_ok());
+        assert!(Theme { foreground: Some(String::from("red\x07\x1b[2J")), background: None }.validate().is_err());
+        assert!(Theme { foreground: None, background: Some(String::new()) }.validate().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::decker::config::ThemeConfig;
+use crate::decker::theme::{minutes_since_midnight, Theme, ThemeSchedule, ThemeVariant};
+use anyhow::{anyhow, bail};
+use std::process::Command;
+
+impl ThemeSchedule {
+    /***
+    The schedule the config asks for, or None if it doesn't set any themes
+     */
+    pub fn new(config: &ThemeConfig) -> anyhow::Result<Option<ThemeSchedule>> {
+        if config.day.is_none() && config.night.is_none() {
+            return Ok(None);
+        }
+        let day = config.day.clone().unwrap_or_default();
+        let night = config.night.clone().unwrap_or_default();
+        day.validate()?;
+        night.validate()?;
+
+        Ok(Some(ThemeSchedule {
+            day,
+            night,
+            day_starts: parse_time_of_day(&config.day_starts)?,
+            night_starts: parse_time_of_day(&config.night_starts)?,
+            follow: config.follow.clone(),
+        }))
+    }
+
+    /***
+    Which theme is due now. With a `follow` command, whatever it says, if it says.
+     */
+    pub fn current(&self) -> anyhow::Result<ThemeVariant> {
+        match &self.follow {
+            None => { Ok(self.variant_at(minutes_since_midnight())) }
+            Some(command) => {
+                let output = Command::new("sh").arg("-c").arg(command).output().
+                    map_err(|e| anyhow!("Could not run '{}': {}", command, e))?;
+                read_variant(&String::from_utf8_lossy(&output.stdout)).
+                    ok_or_else(|| anyhow!("'{}' should print day or night", command))
+            }
+        }
+    }
+
+    /***
+    Which theme is due `minutes` after midnight. Night can run past midnight, or day can.
+     */
+    pub fn variant_at(&self, minutes: u16) -> ThemeVariant {
+        let daytime = match self.day_starts <= self.night_starts {
+            true => { minutes >= self.day_starts && minutes < self.night_starts }
+            false => { minutes >= self.day_starts || minutes < self.night_starts }
+        };
+        if daytime { ThemeVariant::Day } else { ThemeVariant::Night }
+    }
+
+    pub fn theme(&self, variant: ThemeVariant) -> &Theme {
+        match variant {
+            ThemeVariant::Day => { &self.day }
+            ThemeVariant::Night => { &self.night }
+        }
+    }
+}
+
+/***
+Read a 24 hour "HH:MM" as minutes after midnight
+ */
+pub fn parse_time_of_day(time: &str) -> anyhow::Result<u16> {
+    let parsed = time.split_once(':').and_then(|(h, m)| Some((h.parse::<u16>().ok()?, m.parse::<u16>().ok()?)));
+    match parsed {
+        Some((hours, minutes)) if hours < 24 && minutes < 60 && time.len() == 5 => { Ok(hours * 60 + minutes) }
+        _ => { bail!("'{}' is not a time of day - expected e.g. \"07:30\"", time) }
+    }
+}
+
+/***
+What a `follow` command's output says: "day" or "light", "night" or "dark"
+ */
+pub fn read_variant(output: &str) -> Option<ThemeVariant> {
+    match output.trim().to_lowercase().as_str() {
+        "day" | "light" => { Some(ThemeVariant::Day) }
+        "night" | "dark" => { Some(ThemeVariant::Night) }
+        _ => { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decker::config::ThemeConfig;
+    use crate::decker::theme::{Theme, ThemeSchedule, ThemeVariant};
+    use crate::decker::theme::schedule::{parse_time_of_day, read_variant};
+
+    fn schedule(day_starts: &str, night_starts: &str) -> ThemeSchedule {
+        let config = ThemeConfig {
+            night: Some(Theme { foreground: Some(String::from("#585858")), background: Some(String::from("black")) }),
+            day_starts: String::from(day_starts),
+            night_starts: String::from(night_starts),
+            ..ThemeConfig::default()
+        };
+        ThemeSchedule::new(&config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn it_goes_dark_at_night() {
+        let schedule = schedule("07:00", "21:30");
+        assert_eq!(schedule.variant_at(parse_time_of_day("06:59").unwrap()), ThemeVariant::Night);
+        assert_eq!(schedule.variant_at(parse_time_of_day("07:00").unwrap()), ThemeVariant::Day);
+        assert_eq!(schedule.variant_at(parse_time_of_day("21:29").unwrap()), ThemeVariant::Day);
+        assert_eq!(schedule.variant_at(parse_time_of_day("21:30").unwrap()), ThemeVariant::Night);
+        assert_eq!(schedule.variant_at(parse_time_of_day("02:00").unwrap()), ThemeVariant::Night);
+        // No day theme set means the terminal's own colors by day
+        assert_eq!(schedule.theme(ThemeVariant::Day), &Theme::default());
+    }
+
+    #[test]
+    fn it_lets_the_day_run_past_midnight() {
+        let schedule = schedule("12:00", "03:00");
+        assert_eq!(schedule.variant_at(parse_time_of_day("01:00").unwrap()), ThemeVariant::Day);
+        assert_eq!(schedule.variant_at(parse_time_of_day("09:00").unwrap()), ThemeVariant::Night);
+    }
+
+    #[test]
+    fn it_only_schedules_themes_that_are_set() {
+        assert!(ThemeSchedule::new(&ThemeConfig::default()).unwrap().is_none());
+    }
+
+    #[test]
+    fn it_parses_times_and_what_commands_say() {
+        assert_eq!(parse_time_of_day("00:00").unwrap(), 0);
+        assert_eq!(parse_time_of_day("23:59").unwrap(), 23 * 60 + 59);
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("7:00").is_err());
+        assert!(parse_time_of_day("noon").is_err());
+
+        assert_eq!(read_variant("dark\n"), Some(ThemeVariant::Night));
+        assert_eq!(read_variant("Light"), Some(ThemeVariant::Day));
+        assert_eq!(read_variant(""), None);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::decker::trigger::FileWatch;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use log::{debug, warn};
+
+impl FileWatch {
+    /***
+    Start watching. Files that already exist aren't changes.
+     */
+    pub fn new(patterns: &[String]) -> FileWatch {
+        let mut watch = FileWatch { patterns: patterns.to_vec(), seen: HashMap::new() };
+        watch.seen = watch.scan();
+        watch
+    }
+
+    /***
+    Has anything matching our patterns changed since the last check?
+     */
+    pub fn changed(&mut self) -> bool {
+        let current = self.scan();
+        let changed = current != self.seen;
+        if changed {
+            debug!("Watched files changed: {:?}", self.patterns);
+        }
+        self.seen = current;
+        changed
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let mut found = HashMap::new();
+        for pattern in &self.patterns {
+            let paths = match glob::glob(pattern) {
+                Ok(paths) => { paths }
+                Err(e) => {
+                    warn!("Can't watch '{}': {}", pattern, e);
+                    continue;
+                }
+            };
+
+            // Files can vanish between listing and reading them - that'll show up next time
+            for path in paths.flatten() {
+                if let Ok(modified) = path.metadata().and_then(|m| m.modified()) {
+                    found.insert(path, modified);
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn it_notices_new_changed_and_deleted_files() {
+        let dir = std::env::temp_dir().join(format!("decker-watch-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.rs"), "fn a() {}").unwrap();
+
+        let mut watch = FileWatch::new(&[format!("{}/*.rs", dir.display())]);
+        assert!(!watch.changed());
+
+        fs::write(dir.join("notes.txt"), "not watched").unwrap();
+        assert!(!watch.changed());
+
+        fs::write(dir.join("b.rs"), "fn b() {}").unwrap();
+        assert!(watch.changed());
+        assert!(!watch.changed());
+
+        // Make sure the new modification time differs from the old one
+        let later = SystemTime::now() + Duration::from_secs(5);
+        fs::File::options().write(true).open(dir.join("a.rs")).unwrap().set_modified(later).unwrap();
+        assert!(watch.changed());
+
+        fs::remove_file(dir.join("b.rs")).unwrap();
+        assert!(watch.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
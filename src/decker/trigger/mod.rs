@@ -0,0 +1,34 @@
+/***
+* Triggers
+* Ways a task can be run besides its period: when files it watches change, and so on.
+***/
+mod file_watch;
+mod output_trigger;
+
+use crate::decker::TaskId;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/***
+* Notices when files matching a set of glob patterns are created, modified or deleted.
+***/
+pub struct FileWatch {
+    patterns: Vec<String>,
+    // Modification time of every matching file as of the last check
+    seen: HashMap<PathBuf, SystemTime>,
+}
+
+/***
+* Fires a task when a line of another task's output matches a pattern.
+***/
+pub struct OutputTrigger {
+    // The task to run
+    pub task: TaskId,
+    // The task whose output we're watching
+    source: TaskId,
+    pattern: Regex,
+    // Streamed output can end mid-line. The start of the line waits here for the rest.
+    partial: String,
+}
@@ -0,0 +1,83 @@
+use crate::decker::trigger::OutputTrigger;
+use crate::decker::{TaskId, TriggerOn};
+use regex::Regex;
+
+// The longest unfinished line we'll hold on to
+const MAX_PARTIAL_LINE: usize = 4096;
+
+impl OutputTrigger {
+    pub fn new(task: &TaskId, trigger_on: &TriggerOn) -> anyhow::Result<OutputTrigger> {
+        Ok(OutputTrigger {
+            task: task.clone(),
+            source: trigger_on.task.clone(),
+            pattern: Regex::new(&trigger_on.pattern)?,
+            partial: String::new(),
+        })
+    }
+
+    /***
+    Check a chunk of streamed output from `source`. Only finished lines are matched - the
+    rest of the last one may still be on its way.
+     */
+    pub fn scan(&mut self, source: &str, output: &[u8]) -> bool {
+        if source != self.source {
+            return false;
+        }
+
+        self.partial.push_str(&String::from_utf8_lossy(output));
+        let (lines, rest) = match self.partial.rfind('\n') {
+            None => { ("", self.partial.as_str()) }
+            Some(end) => { (&self.partial[..end], &self.partial[end + 1..]) }
+        };
+        let matched = lines.lines().any(|line| self.pattern.is_match(line));
+
+        let mut rest = rest.to_string();
+        if rest.len() > MAX_PARTIAL_LINE {
+            // Nobody writes lines this long on purpose - keep the end of it
+            let mut start = rest.len() - MAX_PARTIAL_LINE;
+            while !rest.is_char_boundary(start) { start += 1; }
+            rest = rest.split_off(start);
+        }
+        self.partial = rest;
+        matched
+    }
+
+    /***
+    Check the whole output of a finished run from `source`
+     */
+    pub fn scan_complete(&mut self, source: &str, output: &[u8]) -> bool {
+        if source != self.source {
+            return false;
+        }
+
+        let matched = self.scan(source, output);
+        let rest = std::mem::take(&mut self.partial);
+        matched || self.pattern.is_match(&rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigger(pattern: &str) -> OutputTrigger {
+        OutputTrigger::new(&String::from("deploy"), &TriggerOn { task: String::from("build"), pattern: String::from(pattern) }).unwrap()
+    }
+
+    #[test]
+    fn it_matches_lines_split_across_chunks() {
+        let mut trigger = trigger("^BUILD OK$");
+        assert!(!trigger.scan("build", b"compiling...\nBUILD"));
+        assert!(!trigger.scan("test", b" OK\n"));
+        assert!(trigger.scan("build", b" OK\nmore"));
+        assert!(!trigger.scan("build", b" output\n"));
+    }
+
+    #[test]
+    fn it_matches_the_last_line_of_a_finished_run() {
+        let mut trigger = trigger("BUILD OK");
+        assert!(trigger.scan_complete("build", b"compiling...\nBUILD OK"));
+        assert!(!trigger.scan_complete("build", b"BUILD FAILED"));
+        assert!(!trigger.scan_complete("other", b"BUILD OK"));
+    }
+}
@@ -0,0 +1,12 @@
+/***
+* decker's VT parser, on its own, for fuzzing and for anything else that needs to make sense of
+* terminal output. Panes, and the cell-by-cell `Screen` they show, are here too, so tests can
+* check what gets drawn. The rest of the dashboard is only for the decker binary and the
+* benchmarks to build on.
+***/
+#[path = "decker/terminal/parser/mod.rs"]
+pub mod parser;
+#[doc(hidden)]
+pub mod decker;
+
+pub use decker::terminal::{Cell, Color, Pane, PaneManager, PrintStyle, Screen};
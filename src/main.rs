@@ -1,25 +1,71 @@
-use std::io::{Read, Write, stdout, Stdout, stdin, Stdin};
+use std::io::{Read, Write};
 use log::{info, error};
 use simplelog::{CombinedLogger, WriteLogger, LevelFilter, Config};
-use std::fs::File;
-use termion::raw::{IntoRawMode, RawTerminal};
+use std::path::Path;
+use std::str::FromStr;
+use crate::decker::backend::{keyboard, raw_stdout, MirroredStdout, RenderMirror};
 use std::thread;
-use crate::decker::{MasterControl, TaskId, ProcessOrchestrator, ProcOutput};
-use crate::decker::terminal::{Pane, PaneManager, ScrollMode};
-use crate::decker::config::load_task_config;
+use crate::decker::{MasterControl, TaskId, ProcessOrchestrator, period_to_secs, HealthMap, TaskHealth};
+use crate::decker::output::{output_queue, OutputReceiver};
+use crate::decker::terminal::{set_color_depth, ColorDepth, Focus, Layout, Pane, PaneManager, PaneRequest, Replay, ScrollMode};
+use crate::decker::terminal::Overflow;
+#[cfg(feature = "bench")]
+use crate::decker::terminal::parser::Torture;
+use crate::decker::keys::{KeyAction, KeyBindings, Keystroke};
+use crate::decker::keys::MainInput;
+use crate::decker::clipboard::{read_clipboard_reply, run_paste_command, ClipboardReply, PasteSource};
+use crate::decker::keys::{Edit, LineEditor};
+use crate::decker::config::{load_task_config, start_order, ConfigSource, DeckerConfig, LogConfig, PaneDefinition};
+use crate::decker::logging::{RotatingFile, TaskLogs};
+use crate::decker::cli::{CliArgs, Command, USAGE};
+use crate::decker::systemd;
+use crate::decker::supervisor::{Heartbeat, Supervisor};
+use crate::decker::output::OutputStats;
+use std::sync::{Arc, Mutex};
+use crate::decker::control::{self, ControlServer, GroupCommand, PipePane, RunTask, Status, default_socket_path};
+use crate::decker::session::Session;
+use crate::decker::display::ChangeHighlighter;
+use crate::decker::theme::{ThemeSchedule, THEME_RESET};
+use std::path::PathBuf;
 use std::time::{SystemTime, Duration};
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
-use termion::AsyncReader;
+use std::time::Instant;
+use crossbeam_channel::{unbounded, RecvTimeoutError, Sender};
 
-mod decker;
+use ::decker::decker;
 
-fn run() -> anyhow::Result<()> {
-    init_logging()?;
-    let deck_cfg = load_task_config().unwrap();
+// How long the render thread can go without checking in before we call it stalled
+const RENDER_STALL: Duration = Duration::from_secs(5);
+// How often to check the orchestrator is still answering
+const BACKEND_PING: Duration = Duration::from_secs(1);
+// Shown before what's typed at decker's prompt
+const PROMPT: &str = "run: ";
+// How long a paste helper gets to print the clipboard, and the most of the terminal's answer to
+// a clipboard query we'll wait on
+const PASTE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CLIPBOARD_REPLY: usize = 8 * 1024 * 1024;
+// How much of a pane's history its scrollback_file may hold, unless configured otherwise
+const DEFAULT_SCROLLBACK_FILE_MB: u64 = 64;
+
+fn run(args: &CliArgs, deck_cfg: DeckerConfig, session: Option<Session>) -> anyhow::Result<()> {
+    init_logging(&deck_cfg.logging)?;
+
+    // Panics would otherwise be printed over the dashboard. Log them instead - the
+    // supervisor restarts whatever died.
+    std::panic::set_hook(Box::new(|panic_info| error!("{}", panic_info)));
+
+    // Bad key bindings fail here, before there are processes or a socket to clean up
+    let keys = KeyBindings::new(&deck_cfg.keys)?;
+
+    // Mirror before anything is drawn or started, so the mirror has all of it
+    let mirror = match args.mirror.as_ref().or(deck_cfg.output.mirror.as_ref()) {
+        None => { None }
+        Some(path) => { Some(Arc::new(Mutex::new(RenderMirror::open(Path::new(path))?))) }
+    };
 
     // base-level stdin/out channels
-    let mut stdin = stdin();
-    let stdout = stdout().into_raw_mode()?;
+    // If the config came in on stdin, stdin is spent - read the keyboard from the terminal instead.
+    let mut stdin = keyboard(args.config == ConfigSource::Stdin)?;
+    let _raw_mode = raw_stdout()?; // held until we return
 
     // The channels we need for comms
     // input:  StdIn -> Active Process
@@ -27,8 +73,9 @@ fn run() -> anyhow::Result<()> {
     // cmd:    MCP commands -> Process Orchestrator
     // resp:   Proc. Orc. command response -> MCP
     // output is 'bounded' to create backpressure that prevents overwhelming the rendering thread.
+    // What happens when it fills up is configurable.
     let (input_tx, input_rx) = unbounded();
-    let (output_tx, output_rx) = bounded(50);
+    let (output_tx, output_rx) = output_queue(deck_cfg.output.queue_size, deck_cfg.output.backpressure);
     let (cmd_tx, cmd_rx) = unbounded();
     let (resp_tx, resp_rx) = unbounded();
 
@@ -37,53 +84,379 @@ fn run() -> anyhow::Result<()> {
     let mut pane_manager = PaneManager::new();
 
     // Register all the configured Panes
+    let pane_definitions = deck_cfg.panes.clone();
     for p in deck_cfg.panes {
         let mut new_pane = Pane::new(&p.task_id, p.x, p.y, p.height, p.width);
-        if p.is_main() { new_pane.set_scroll_mode(ScrollMode::Scroll); }
+        if p.title_row {
+            let task_name = deck_cfg.tasks.iter().find(|t| t.id == p.task_id).map(|t| t.name.clone());
+            new_pane.set_title_row(p.title.as_ref().or(task_name.as_ref()).unwrap_or(&p.task_id));
+        }
+        // The main pane is usually a shell, which expects a terminal's usual line wrapping
+        let overflow = if p.is_main() { Overflow::Wrap } else { Overflow::Truncate };
+        new_pane.set_overflow(p.overflow.unwrap_or(overflow));
+        new_pane.set_local_echo(p.local_echo);
+        new_pane.set_align(p.align, p.valign);
+        if p.set_clipboard {
+            new_pane.set_copy_limit(Some(deck_cfg.clipboard.max_copy_kb * 1024));
+        }
+        if p.scroll.unwrap_or(p.is_main()) {
+            new_pane.set_scroll_mode(ScrollMode::Scroll);
+        }
+        if let Some(lines) = p.scrollback { new_pane.set_scrollback_limit(lines); }
+        if let Some(path) = &p.scrollback_file {
+            let max_bytes = p.scrollback_file_mb.unwrap_or(DEFAULT_SCROLLBACK_FILE_MB) * 1024 * 1024;
+            if let Err(e) = new_pane.spill_scrollback(Path::new(path), max_bytes) {
+                error!("main: Could not keep {}'s scrollback in {}: {}", p.task_id, path, e);
+            }
+        }
+        if let Some(columns) = p.tab_width { new_pane.set_tab_width(columns); }
+        if let Some(fps) = p.max_fps { new_pane.set_max_fps(fps); }
+        if let Some(kb) = deck_cfg.tasks.iter().find(|t| t.id == p.task_id).and_then(|t| t.output_buffer_kb) {
+            new_pane.set_frame_cap(kb * 1024);
+        }
         pane_manager.register(p.task_id, new_pane);
     }
 
+    // Put back what a restored session's panes were showing, until their tasks draw over it, and
+    // how far back through it they were scrolled
+    for (task_id, screen) in session.iter().flat_map(|s| s.screens.iter()) {
+        pane_manager.push(task_id.clone(), screen.replace('\n', "\r\n").as_bytes());
+    }
+    for (task_id, lines) in session.iter().flat_map(|s| s.scroll_offsets.iter()) {
+        pane_manager.scroll_back(task_id, *lines);
+    }
+
     let main_pane = pane_manager.find_by_id("main").unwrap();
 
     // Process Orchestrator is in charge of managing all of the processes and forwarding IO
     // It's got to live in a different thread, however, so we communicate with it via the
     // Master Control facade.
-    let orchestrator = ProcessOrchestrator::new(output_tx, cmd_tx.clone(), cmd_rx, resp_tx, input_rx, (main_pane.width(), main_pane.height()));
+    let mut orchestrator = ProcessOrchestrator::new(output_tx, cmd_tx.clone(), cmd_rx, resp_tx, input_rx, (main_pane.width(), main_pane.height()));
+    if let Some(dir) = &deck_cfg.task_logs.dir {
+        let max_age = match &deck_cfg.task_logs.max_age {
+            None => { None }
+            Some(period) => { Some(Duration::from_secs(period_to_secs(period)?)) }
+        };
+        orchestrator.set_task_logs(TaskLogs::new(Path::new(dir), deck_cfg.task_logs.max_size_kb.map(|kb| kb * 1024),
+                                                 max_age, deck_cfg.task_logs.keep));
+    }
+    orchestrator.set_concurrency(deck_cfg.concurrency.max_running, deck_cfg.concurrency.groups.clone());
+    let highlight_fade = Duration::from_secs(period_to_secs(&deck_cfg.output.highlight_fade)?);
+    orchestrator.set_change_highlighter(ChangeHighlighter::new(&deck_cfg.output.highlight_style, highlight_fade)?);
+    let health = orchestrator.health();
+    pane_manager.set_health(health.clone());
+    pane_manager.set_disabled(orchestrator.disabled());
+    pane_manager.set_main_input(input_tx.clone());
+    pane_manager.set_sixel(deck_cfg.output.sixel);
+    pane_manager.set_inline_images(deck_cfg.output.inline_images);
+    let linear = args.screen_reader || deck_cfg.output.screen_reader;
+    pane_manager.set_linear(linear);
+    let color_depth = match args.no_color {
+        true => { ColorDepth::Monochrome }
+        false => { deck_cfg.output.colors.unwrap_or_else(ColorDepth::detect) }
+    };
+    set_color_depth(color_depth);
+    // Themes are all color, so there's nothing to swap without it
+    let themes = match linear || color_depth == ColorDepth::Monochrome {
+        true => { None }
+        false => { ThemeSchedule::new(&deck_cfg.theme)? }
+    };
     start_orchestrator(orchestrator);
 
     // MasterControl is the nice, useful frontend that controls Process Orchestrator.
     // It gives us easy methods for registering and executing tasks, etc.
     let mut mcp = MasterControl::new(cmd_tx, resp_rx);
 
-    //  Now we can register all the configured Tasks
-    for mut task in deck_cfg.tasks {
+    //  Now we can register all the configured Tasks, dependencies first
+    let mut tasks = start_order(deck_cfg.tasks)?;
+    for task in tasks.iter_mut() {
         task.cache_period(); // TODO: This is an ugly solution. We don't call 'Task::new', so we don't have the usual hook to do this sorta call
-        match pane_manager.find_by_id(&task.id) {
-            None => {
-                mcp.register(task, None)?;
-            }
-            Some(p) => {
-                mcp.register(task.clone(), Some((p.width(), p.height())))?;
-                mcp.execute(&task.id)?;
-            }
+        if task.term.is_none() { task.term = deck_cfg.terminal.term.clone(); }
+        if task.colorterm.is_none() { task.colorterm = deck_cfg.terminal.colorterm.clone(); }
+        let size = pane_manager.find_by_id(&task.id).map(|p| (p.width(), p.height()));
+        mcp.register(task.clone(), size)?;
+    }
+    if let Some(session) = &session {
+        mcp.restore(&session.schedules)?;
+    }
+    // ...and start the ones with panes. A restored session's periodic tasks wait until they're due.
+    for task in &tasks {
+        let due = session.as_ref().map(|s| s.is_due(task)).unwrap_or(true);
+        if pane_manager.find_by_id(&task.id).is_some() && due {
+            mcp.execute(&task.id)?;
         }
     }
 
     // TODO: Pull the default main task from the cfg instead of hardcoding it.
-    let task_id: TaskId = TaskId::from("todo");
+    let task_id: TaskId = session.and_then(|s| s.active).unwrap_or_else(|| TaskId::from("todo"));
     mcp.activate_proc(&task_id, pane_manager.find_by_id("main").unwrap())?;
     mcp.execute(&task_id)?;
 
-    println!("\x1b[2J"); // clear screen before we begin
+    // The control socket lets e.g. `decker status` talk to us from another terminal
+    let socket_path = socket_path(args);
+    let pane_requests = pane_manager.pane_requests();
+    let control_server = ControlServer::bind(&socket_path, mcp.clone(), pane_definitions.clone(), pane_manager.pipes(), output_rx.stats(),
+                                             pane_requests.clone())?;
+    start_control_server(control_server);
 
-    start_output_forwarding_thread(output_rx, pane_manager);
-    run_input_forwarding_loop(&mut stdin, input_tx, &mut mcp); // doesn't return until shutdown
+    if !linear {
+        // clear screen before we begin
+        writeln!(MirroredStdout::new(raw_stdout()?, mirror.clone()), "\x1b[2J")?;
+    }
+
+    let output_stats = output_rx.stats();
+    let render_heartbeat = Arc::new(Heartbeat::new());
+    let focus = pane_manager.focus();
+    let keypad = pane_manager.keypad();
+    let layout = pane_manager.layout();
+    pane_manager.set_master_control(mcp.clone());
+    start_output_forwarding_thread(output_rx, pane_manager, deck_cfg.output.status_bar, health, render_heartbeat.clone(), mirror);
+    start_render_monitor(render_heartbeat.clone(), output_stats);
+    start_backend_monitor(mcp.clone());
+
+    // Everything is up - let systemd know, and keep its watchdog fed while we're healthy
+    if let Err(e) = systemd::notify("READY=1") {
+        error!("main: Could not tell systemd we're ready: {}", e);
+    }
+    if let Some(interval) = systemd::watchdog_interval() {
+        start_watchdog_thread(mcp.clone(), interval, render_heartbeat);
+    }
 
+    if let Some(themes) = themes.clone() {
+        let every = Duration::from_secs(period_to_secs(&deck_cfg.theme.check_every)?.max(1));
+        start_theme_thread(themes, pane_requests.clone(), every);
+    }
+
+    if let Some(path) = &deck_cfg.session.checkpoint {
+        let every = Duration::from_secs(period_to_secs(&deck_cfg.session.every)?);
+        start_checkpoint_thread(mcp.clone(), pane_definitions.clone(), pane_requests.clone(), PathBuf::from(path), every);
+    }
+
+    let main_input = MainInput::new(input_tx, keypad.clone(), PasteSource::new(deck_cfg.clipboard.paste_from.as_deref()));
+    run_input_forwarding_loop(stdin.as_mut(), &main_input, &mut mcp, keys, focus, &layout, &pane_requests); // doesn't return until shutdown
+    if keypad.host_app() {
+        print!("\x1b>"); // leave the keypad how we found it
+    }
+    if themes.is_some() {
+        print!("{}", String::from_utf8_lossy(THEME_RESET)); // and the colors
+    }
+
+    // Shutting down matters more than telling systemd about it
+    if let Err(e) = systemd::notify("STOPPING=1") {
+        error!("main: Could not tell systemd we're stopping: {}", e);
+    }
+    if let Some(path) = &deck_cfg.session.checkpoint {
+        if let Err(e) = Session::capture(&mcp, &pane_definitions, &pane_requests).and_then(|s| s.save(Path::new(path))) {
+            error!("main: Could not save the session: {}", e);
+        }
+    }
+    if let Err(e) = mcp.shutdown() {
+        error!("main: Orchestrator did not shut down cleanly: {}", e);
+    }
+    let _ = std::fs::remove_file(&socket_path);
     Ok(())
 }
 
-fn run_input_forwarding_loop(stdin: &mut Stdin, input_tx: Sender<String>, mcp: &mut MasterControl) {
+fn socket_path(args: &CliArgs) -> PathBuf {
+    match &args.socket {
+        None => { default_socket_path() }
+        Some(path) => { PathBuf::from(path) }
+    }
+}
+
+/***
+Print the status of a running decker, either as JSON or a human-friendly summary
+ */
+fn print_status(args: &CliArgs, json: bool) -> anyhow::Result<()> {
+    let response = control::request(&socket_path(args), "status", "")?;
+
+    if json {
+        println!("{}", response);
+        return Ok(());
+    }
+
+    let status: Status = serde_json::from_str(&response)?;
+    println!("TASKS");
+    for task in &status.tasks {
+        let state = if task.running && task.follow_up { "running+1" } else if task.running { "running" }
+                    else if task.queued { "queued" } else if task.active { "active" } else if task.disabled { "disabled" }
+                    else if task.paused { "paused" } else { "" };
+        let health = match task.health {
+            None => { "" }
+            Some(TaskHealth::Healthy) => { "healthy" }
+            Some(TaskHealth::Unhealthy) => { "UNHEALTHY" }
+        };
+        println!("  {:<16} {:<10} {:<8} {:<9} {:<9} {}", task.id, task.group.clone().unwrap_or_default(),
+                 task.period.clone().unwrap_or_default(), state, health, task.command);
+    }
+    println!("OUTPUT");
+    println!("  {}", status.output);
+    println!("PANES");
+    for pane in &status.panes {
+        println!("  {:<16} {}x{} at ({}, {}){}", pane.task_id, pane.width, pane.height, pane.x, pane.y,
+                 if pane.main { " [main]" } else { "" });
+    }
+
+    Ok(())
+}
+
+/***
+Play a captured PTY stream through a pane, drawing it as it goes or saving the final screen
+ */
+fn run_replay(capture: &str, timing: Option<&str>, speed: f64, size: (u16, u16), snapshot: Option<&str>) -> anyhow::Result<()> {
+    let replay = Replay::load(Path::new(capture), timing.map(Path::new))?;
+    let mut pane = Pane::new("replay", 1, 1, size.1, size.0);
+    pane.set_scroll_mode(ScrollMode::Scroll);
+
+    match snapshot {
+        Some(path) => {
+            replay.play(&mut pane, speed, |_| Ok(()))?;
+            std::fs::write(path, pane.screen().text() + "\n")?;
+        }
+        None => {
+            let mut stdout = raw_stdout()?;
+            let mut frame = Vec::new();
+            write!(stdout, "\x1b[2J")?;
+            replay.play(&mut pane, speed, |pane| {
+                frame.clear();
+                pane.write(&mut frame)?;
+                pane.take_cursor(&mut frame)?;
+                stdout.write_all(&frame)?;
+                stdout.flush()?;
+                Ok(())
+            })?;
+            write!(stdout, "\x1b[0m\x1b[{};1H\r\n", size.1)?;
+        }
+    }
+
+    Ok(())
+}
+
+/***
+Time the whole output path - parse, update the pane, draw the frame - on each torture corpus.
+Nothing reaches the terminal; the frames are only counted.
+ */
+#[cfg(feature = "bench")]
+fn run_bench(size: (u16, u16), kb: usize) -> anyhow::Result<()> {
+    println!("{:<14}{:>8}{:>10}{:>10}{:>10}{:>12}", "corpus", "KB", "ms", "MB/s", "frames", "KB drawn");
+    for torture in Torture::ALL {
+        let corpus = torture.generate(kb * 1024, 1, size);
+        let mut pane = Pane::new("bench", 1, 1, size.1, size.0);
+        pane.set_scroll_mode(ScrollMode::Scroll);
+
+        let mut frame = Vec::new();
+        let (mut frames, mut drawn) = (0, 0);
+        let started = Instant::now();
+        // One frame per read, the way the render loop would see a busy task
+        for read in corpus.chunks(1024) {
+            pane.push(read)?;
+            frame.clear();
+            pane.write(&mut frame)?;
+            frames += 1;
+            drawn += frame.len();
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        println!("{:<14}{:>8}{:>10.1}{:>10.2}{:>10}{:>12}", torture.name(), kb, elapsed * 1000.0,
+                 corpus.len() as f64 / elapsed / (1024.0 * 1024.0), frames, drawn / 1024);
+    }
+    Ok(())
+}
+
+/***
+Keep pinging the orchestrator, so MasterControl knows whether it's still answering even when
+nobody else is asking it anything
+ */
+fn start_backend_monitor(mcp: MasterControl) {
+    thread::spawn(move || {
+        let mut responsive = true;
+        loop {
+            if let Err(e) = mcp.ping() {
+                info!("main: Orchestrator missed a ping: {}", e);
+            }
+            if mcp.responsive() != responsive {
+                responsive = !responsive;
+                match responsive {
+                    true => { info!("main: Orchestrator is answering again") }
+                    false => { error!("main: Orchestrator has stopped answering") }
+                }
+            }
+            thread::sleep(BACKEND_PING);
+        }
+    });
+}
+
+/***
+Save the session every so often, so a restart with `--resume` loses as little as possible
+ */
+fn start_checkpoint_thread(mcp: MasterControl, panes: Vec<PaneDefinition>, pane_requests: Sender<PaneRequest>, path: PathBuf, every: Duration) {
+    Supervisor::new("checkpoint").spawn(move || {
+        loop {
+            thread::sleep(every);
+            match Session::capture(&mcp, &panes, &pane_requests).and_then(|s| s.save(&path)) {
+                Ok(_) => { info!("main: Checkpointed the session to {}", path.display()) }
+                Err(e) => { error!("main: Could not checkpoint the session: {}", e) }
+            }
+        }
+    });
+}
+
+/***
+Swap to the day or night theme whenever it's due - by the clock, or the schedule's command
+ */
+fn start_theme_thread(themes: ThemeSchedule, pane_requests: Sender<PaneRequest>, every: Duration) {
+    Supervisor::new("theme").spawn(move || {
+        let mut shown = None;
+        loop {
+            match themes.current() {
+                Ok(variant) if shown != Some(variant) => {
+                    info!("main: Time for the {:?} theme", variant);
+                    pane_requests.send(PaneRequest::Theme(themes.theme(variant).clone()))?;
+                    shown = Some(variant);
+                }
+                Ok(_) => {}
+                Err(e) => { error!("main: Could not tell which theme is due: {}", e) }
+            }
+            thread::sleep(every);
+        }
+    });
+}
+
+/***
+Ping systemd's watchdog twice per interval, but only while the orchestrator is answering.
+If it stops answering, systemd will notice the silence and restart us.
+ */
+fn start_watchdog_thread(mcp: MasterControl, interval: Duration, render_heartbeat: Arc<Heartbeat>) {
+    thread::spawn(move || {
+        info!("main: Feeding systemd watchdog every {:?}", interval / 2);
+        loop {
+            if !mcp.responsive() {
+                error!("main: Orchestrator is unresponsive - not feeding the watchdog");
+            } else if render_heartbeat.since_last_beat() > RENDER_STALL {
+                error!("main: Rendering is stalled - not feeding the watchdog");
+            } else {
+                let _ = systemd::notify("WATCHDOG=1");
+            }
+            thread::sleep(interval / 2);
+        }
+    });
+}
+
+fn start_control_server(mut server: ControlServer) {
+    Supervisor::new("control server").spawn(move || {
+        server.run();
+        Ok(())
+    });
+}
+
+fn run_input_forwarding_loop(stdin: &mut dyn Read, main_input: &MainInput, mcp: &mut MasterControl, mut keys: KeyBindings, focus: Arc<Mutex<Focus>>,
+                             layout: &Mutex<Layout>, pane_requests: &Sender<PaneRequest>) {
     let mut buffer: Vec<u8> = vec![0,0,0,0,0];
+    // While decker's prompt is open, typing goes to it instead
+    let mut prompt: Option<LineEditor> = None;
+    let mut prompt_history = LineEditor::new();
+    // After asking the terminal for its clipboard, what it's answered so far
+    let mut clipboard_reply: Option<Vec<u8>> = None;
 
     loop {
         match stdin.read(&mut buffer) {
@@ -92,47 +465,293 @@ fn run_input_forwarding_loop(stdin: &mut Stdin, input_tx: Sender<String>, mcp: &
                 info!("main: Processing input: '{:?}'", buffer);
                 // TODO: if !mcp.running(), input goes to decker CLI, for launching known tasks from.
 
-                if let Some(3) = buffer.first() { // Ctrl-C
+                if let Some(editor) = prompt.as_mut() {
+                    if !run_prompt_input(editor, &buffer[..count], mcp, pane_requests) {
+                        prompt_history = prompt.take().unwrap_or_default();
+                    }
+                    continue;
+                }
+
+                let mut input = buffer[..count].to_vec();
+                if let Some(mut received) = clipboard_reply.take() {
+                    received.extend_from_slice(&input);
+                    match read_clipboard_reply(&received) {
+                        ClipboardReply::Incomplete if received.len() < MAX_CLIPBOARD_REPLY => {
+                            clipboard_reply = Some(received);
+                            continue;
+                        }
+                        ClipboardReply::Incomplete => {
+                            error!("main: The terminal's clipboard is more than {} bytes - not pasting it", MAX_CLIPBOARD_REPLY);
+                            continue;
+                        }
+                        ClipboardReply::Clipboard(text) => {
+                            paste(text, main_input);
+                            continue;
+                        }
+                        ClipboardReply::NotAReply => {
+                            // It's just typing - the terminal isn't going to answer. What was held
+                            // back in case it was (e.g. an Esc) is typing too.
+                            error!("main: The terminal didn't answer with its clipboard - set paste_from under [clipboard] to use a helper");
+                            input = received;
+                        }
+                    }
+                }
+
+                if let Some(3) = input.first() { // Ctrl-C
                     if !mcp.running().unwrap() {
                         info!("main: ^C means shutdown!");
                         break;
                     };
                 }
 
-                match input_tx.send(String::from_utf8(buffer[..count].to_owned()).unwrap()) {
-                    Ok(_) => {}
-                    Err(err) => {
-                        error!("main: {}", err);
-                        break;
+                let mut closed = false;
+                for stroke in keys.feed(&input) {
+                    match stroke {
+                        Keystroke::Forward(input) => {
+                            let typed_at = Instant::now();
+                            if let Err(e) = pane_requests.send(PaneRequest::Typed(input.clone(), typed_at)) {
+                                error!("main: Could not echo typing: {}", e);
+                            }
+                            // TODO: Only main has a PTY to type into. Once other panes are interactive too, add a
+                            //       tmux-style synchronize-panes toggle that sends this to every one of them.
+                            if let Err(err) = main_input.type_keys(input, typed_at) {
+                                error!("main: {}", err);
+                                closed = true;
+                            }
+                        }
+                        Keystroke::Action(KeyAction::Prompt) => {
+                            let mut editor = std::mem::take(&mut prompt_history);
+                            editor.clear();
+                            let _ = pane_requests.send(PaneRequest::Prompt(Some(editor.render(PROMPT))));
+                            prompt = Some(editor);
+                            break; // the prompt takes whatever's typed next
+                        }
+                        Keystroke::Action(KeyAction::Paste) => {
+                            match main_input.paste_from() {
+                                PasteSource::Command(command) => {
+                                    // Typing carries on while a slow helper runs
+                                    let (command, main_input) = (command.clone(), main_input.clone());
+                                    thread::spawn(move || paste(run_paste_command(&command, PASTE_TIMEOUT), &main_input));
+                                }
+                                PasteSource::Terminal => {
+                                    // The answer comes back as typing
+                                    let _ = pane_requests.send(PaneRequest::QueryClipboard);
+                                    clipboard_reply = Some(Vec::new());
+                                    break;
+                                }
+                            }
+                        }
+                        Keystroke::Action(action) => { run_key_action(action, mcp, &focus, layout, pane_requests) }
                     }
                 }
+                if closed { break; }
             }
             Err(e) => {
                 thread::sleep(Duration::from_millis(30));
             }
         }
     }
-    // TODO: Send shutdown signal to MCP here
     info!("main: Exited top-level input forwarding");
 }
 
-fn start_output_forwarding_thread(output_rx: Receiver<ProcOutput>, mut pane_manager: PaneManager) {
+/***
+Type the clipboard into the main pane
+ */
+fn paste(clipboard: anyhow::Result<String>, main_input: &MainInput) {
+    match clipboard {
+        Ok(text) => {
+            info!("main: Pasting {} bytes", text.len());
+            if let Err(e) = main_input.paste(&text) {
+                error!("main: Could not paste: {}", e);
+            }
+        }
+        Err(e) => { error!("main: Could not read the clipboard: {}", e) }
+    }
+}
+
+/***
+Edit the line at decker's prompt. Enter runs the task named there. Returns whether the prompt's
+still open.
+ */
+fn run_prompt_input(editor: &mut LineEditor, input: &[u8], mcp: &mut MasterControl, pane_requests: &Sender<PaneRequest>) -> bool {
+    let open = match editor.feed(input) {
+        Edit::Editing => { true }
+        Edit::Cancel => { false }
+        Edit::Submit(line) => {
+            let task_id = line.trim().to_string();
+            if !task_id.is_empty() {
+                info!("main: Running {} from the prompt", task_id);
+                if let Err(e) = mcp.refresh(&task_id) {
+                    error!("main: Could not run {}: {}", task_id, e);
+                }
+            }
+            false
+        }
+    };
+
+    let shown = if open { Some(editor.render(PROMPT)) } else { None };
+    if let Err(e) = pane_requests.send(PaneRequest::Prompt(shown)) {
+        error!("main: Could not draw the prompt: {}", e);
+    }
+    open
+}
+
+/***
+Carry out one of decker's own key bindings
+ */
+fn run_key_action(action: KeyAction, mcp: &mut MasterControl, focus: &Arc<Mutex<Focus>>, layout: &Mutex<Layout>,
+                  pane_requests: &Sender<PaneRequest>) {
+    let mut focus = match focus.lock() {
+        Ok(focus) => { focus }
+        Err(_) => { return }
+    };
+
+    match action {
+        KeyAction::Refresh => {
+            match focus.focused() {
+                None => {}
+                Some(task_id) if task_id == "main" => { info!("main: The main pane can't be refreshed") }
+                Some(task_id) => {
+                    info!("main: Refreshing {}", task_id);
+                    if let Err(e) = mcp.refresh(task_id) {
+                        error!("main: Could not refresh {}: {}", task_id, e);
+                    }
+                }
+            }
+        }
+        KeyAction::NextPane => {
+            info!("main: Focused {:?}", focus.cycle());
+        }
+        KeyAction::LastPane => {
+            info!("main: Focused {:?}", focus.last());
+        }
+        KeyAction::NextLayout => {
+            // The render thread notices the change and re-tiles the panes
+            if let Ok(mut layout) = layout.lock() {
+                *layout = layout.next();
+                info!("main: Switching to the {:?} layout", *layout);
+            }
+        }
+        KeyAction::ScrollUp | KeyAction::ScrollDown => {
+            let half_pages = if action == KeyAction::ScrollUp { 1 } else { -1 };
+            if let Some(task_id) = focus.focused() {
+                if let Err(e) = pane_requests.send(PaneRequest::Scroll(task_id.clone(), half_pages)) {
+                    error!("main: Could not scroll {}: {}", task_id, e);
+                }
+            }
+        }
+        KeyAction::Prompt | KeyAction::Paste => {} // the input loop deals with these itself
+        KeyAction::Repaint => {
+            if let Err(e) = pane_requests.send(PaneRequest::Repaint) {
+                error!("main: Could not repaint: {}", e);
+            }
+        }
+        KeyAction::ToggleEcho => {
+            if let Some(task_id) = focus.focused() {
+                if let Err(e) = pane_requests.send(PaneRequest::ToggleEcho(task_id.clone())) {
+                    error!("main: Could not toggle local echo for {}: {}", task_id, e);
+                }
+            }
+        }
+        KeyAction::PanLeft | KeyAction::PanRight => {
+            let half_widths = if action == KeyAction::PanRight { 1 } else { -1 };
+            if let Some(task_id) = focus.focused() {
+                if let Err(e) = pane_requests.send(PaneRequest::Pan(task_id.clone(), half_widths)) {
+                    error!("main: Could not pan {}: {}", task_id, e);
+                }
+            }
+        }
+    }
+}
+
+/***
+Watch the render thread's heartbeat. If it stops, say so, and have it reset the terminal and
+repaint everything once it gets going again.
+ */
+fn start_render_monitor(heartbeat: Arc<Heartbeat>, output_stats: Arc<OutputStats>) {
     thread::spawn(move || {
-        let mut stdout = stdout().into_raw_mode().unwrap();
+        let mut stalled = false;
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let quiet_for = heartbeat.since_last_beat();
+
+            if quiet_for > RENDER_STALL && !stalled {
+                error!("main: Rendering has stalled for {:?} (output {}) - will repaint once it recovers",
+                       quiet_for, output_stats.snapshot());
+                heartbeat.request_recovery();
+                stalled = true;
+            } else if quiet_for <= RENDER_STALL && stalled {
+                info!("main: Rendering resumed");
+                stalled = false;
+            }
+        }
+    });
+}
+
+fn start_output_forwarding_thread(output_rx: OutputReceiver, mut pane_manager: PaneManager, status_bar: bool, health: HealthMap, heartbeat: Arc<Heartbeat>,
+                                  mirror: Option<Arc<Mutex<RenderMirror>>>) {
+    Supervisor::new("output").spawn(move || {
+        let mut stdout = MirroredStdout::new(raw_stdout()?, mirror.clone());
         info!("main: Starting Output caputure thread");
         let last_printed = SystemTime::UNIX_EPOCH;
+        // A restart after a failure may have left the screen half-drawn
+        pane_manager.repaint(&mut stdout)?;
+
         // read stdout and display it
-        while let Ok(pout) = output_rx.recv() {
-            // Capture the output
+        loop {
+            heartbeat.beat();
+            if heartbeat.take_recovery_request() {
+                info!("main: Recovering from a render stall");
+                stdout.reactivate()?;
+                pane_manager.repaint(&mut stdout)?;
+                stdout.flush()?;
+            }
+
+            // Wake up in time to draw any pane its frame rate held back
+            let idle = Duration::from_millis(250);
+            let pout = match output_rx.recv_timeout(pane_manager.redraw_due_in().unwrap_or(idle).min(idle)) {
+                Ok(pout) => { pout }
+                Err(RecvTimeoutError::Timeout) => {
+                    // Nothing to draw, but focus may have moved
+                    if pane_manager.needs_redraw() {
+                        pane_manager.write(&mut stdout)?;
+                        stdout.flush()?;
+                    }
+                    continue
+                }
+                Err(RecvTimeoutError::Disconnected) => { break }
+            };
+
+            // Capture the output. Echoes of typing are drawn straight away, by themselves.
             pane_manager.push(pout.name, &pout.output);
+            if output_rx.echoed() {
+                pane_manager.write_echo(&mut stdout)?;
+                stdout.flush()?;
+                continue;
+            }
+
+            if status_bar {
+                let (queued, capacity) = output_rx.depth();
+                let mut status = format!("queue {}/{} | {}", queued, capacity, output_rx.stats().snapshot());
+                let mut unhealthy = health.read().map(|h| h.iter().
+                    filter(|(_, health)| **health == TaskHealth::Unhealthy).
+                    map(|(id, _)| id.clone()).
+                    collect::<Vec<String>>()).unwrap_or_default();
+                if !unhealthy.is_empty() {
+                    unhealthy.sort();
+                    status.push_str(&format!(" | unhealthy: {}", unhealthy.join(", ")));
+                }
+                pane_manager.set_status_line(Some(status));
+            }
 
             // if it's been more than 30 ms, go ahead and render.
             if SystemTime::now().duration_since(last_printed).unwrap().as_millis() > 30 {
-                pane_manager.write(&mut stdout).unwrap();
-                stdout.flush().unwrap();
+                pane_manager.write(&mut stdout)?;
+                stdout.flush()?;
             }
         }
         info!("main: Exited top-level output forwarding");
+        Ok(())
     });
 }
 
@@ -145,10 +764,21 @@ fn start_orchestrator(mut orchestrator: ProcessOrchestrator) {
     });
 }
 
-fn init_logging() -> anyhow::Result<()> {
+fn init_logging(log_cfg: &LogConfig) -> anyhow::Result<()> {
+    let level = LevelFilter::from_str(&log_cfg.level).
+        map_err(|_| anyhow::anyhow!("'{}' is not a valid log level", log_cfg.level))?;
+    let max_age = match &log_cfg.max_age {
+        None => { None }
+        Some(period) => { Some(Duration::from_secs(period_to_secs(period)?)) }
+    };
+    let log_file = RotatingFile::new(Path::new(&log_cfg.path),
+                                     log_cfg.max_size_kb.map(|kb| kb * 1024),
+                                     max_age,
+                                     log_cfg.keep)?;
+
     CombinedLogger::init(
         vec![
-            WriteLogger::new(LevelFilter::Info, Config::default(), File::create("log/decker.log")?),
+            WriteLogger::new(level, Config::default(), log_file),
         ]
     )?;
 
@@ -156,15 +786,154 @@ fn init_logging() -> anyhow::Result<()> {
 }
 
 fn main() {
-    // Create a master session
-    // Spawn a child process in another thread
-    //   give it the appropriate halves of Input/Output channels
-    // Input Thread: Forward stdin to the child's Input channel
-    // Output Thread: Forward stdout from the child to the Output channel
-    match run() {
-        Ok(_) => {}
-        Err(err) => { error!("Fatal error {:?}", err.to_string()); }
-    }
+    let args = match CliArgs::parse(&std::env::args().skip(1).collect::<Vec<_>>()) {
+        Ok(args) => { args }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    match &args.command {
+        Command::Status { json } => {
+            if let Err(err) = print_status(&args, *json) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::PipePane { pane, target } => {
+            let request = PipePane { pane: pane.clone(), target: target.clone() };
+            let result = serde_json::to_string(&request).map_err(|e| e.into()).
+                and_then(|data| control::request(&socket_path(&args), "pipe-pane", &data));
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::SetEnabled { task_id, enabled } => {
+            let command = if *enabled { "enable" } else { "disable" };
+            if let Err(err) = control::request(&socket_path(&args), command, task_id) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Cancel { task_id } => {
+            if let Err(err) = control::request(&socket_path(&args), "cancel", task_id) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Repaint => {
+            if let Err(err) = control::request(&socket_path(&args), "repaint", "") {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Attach { task_id } => {
+            if let Err(err) = control::request(&socket_path(&args), "attach", task_id) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Run { argv, id, pane, linger } => {
+            let request = RunTask {
+                argv: argv.clone(), path: std::env::current_dir().map(|d| d.to_string_lossy().to_string()).unwrap_or_else(|_| String::from(".")),
+                id: id.clone(), pane: pane.clone(), linger: linger.clone(),
+            };
+            let result = serde_json::to_string(&request).map_err(|e| e.into()).
+                and_then(|data| control::request(&socket_path(&args), "run", &data));
+            match result {
+                Ok(task_id) => { println!("{}", task_id) }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Group { group, action } => {
+            let request = GroupCommand { group: group.clone(), action: *action };
+            let result = serde_json::to_string(&request).map_err(|e| e.into()).
+                and_then(|data| control::request(&socket_path(&args), "group", &data));
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Replay { capture, timing, speed, size, snapshot } => {
+            set_color_depth(if args.no_color { ColorDepth::Monochrome } else { ColorDepth::detect() });
+            if let Err(err) = run_replay(capture, timing.as_deref(), *speed, *size, snapshot.as_deref()) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Bench { size, kb } => {
+            #[cfg(feature = "bench")]
+            {
+                set_color_depth(ColorDepth::detect());
+                if let Err(err) = run_bench(*size, *kb) {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            #[cfg(not(feature = "bench"))]
+            {
+                let _ = (size, kb);
+                eprintln!("This decker was built without benchmarks - rebuild it with `--features bench`");
+                std::process::exit(1);
+            }
+        }
+        Command::ExportHtml { pane, path } => {
+            let result = control::request(&socket_path(&args), "export-html", pane).
+                and_then(|html| Ok(std::fs::write(path, html)?));
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::ExportSession { path } => {
+            let result = control::request(&socket_path(&args), "session", "").
+                and_then(|data| Ok(serde_json::from_str::<Session>(&data)?)).
+                and_then(|session| session.save(Path::new(path)));
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        Command::Dashboard { session, resume } => {
+            // Create a master session
+            // Spawn a child process in another thread
+            //   give it the appropriate halves of Input/Output channels
+            // Input Thread: Forward stdin to the child's Input channel
+            // Output Thread: Forward stdout from the child to the Output channel
 
-    println!("\x1B[0m{}", "Shutdown!");
+            // Report config problems before we take over the terminal
+            let mut deck_cfg = match load_task_config(&args.config, args.profile.as_deref()) {
+                Ok(cfg) => { cfg }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(2);
+                }
+            };
+            // Resuming is best effort - with no checkpoint yet, start afresh
+            let checkpoint = deck_cfg.session.checkpoint.clone().filter(|path| *resume && Path::new(path).exists());
+            let session = match session.clone().or(checkpoint).map(|path| Session::load(Path::new(&path))) {
+                None => { None }
+                Some(Ok(session)) => {
+                    session.apply(&mut deck_cfg);
+                    Some(session)
+                }
+                Some(Err(err)) => {
+                    eprintln!("{:#}", err);
+                    std::process::exit(2);
+                }
+            };
+
+            match run(&args, deck_cfg, session) {
+                Ok(_) => {}
+                Err(err) => { error!("Fatal error {:?}", err.to_string()); }
+            }
+
+            println!("\x1B[0m{}", "Shutdown!");
+        }
+    }
 }